@@ -1,9 +1,15 @@
 // Note: This is business logic; tested through the integration tests in
 // rosenpass/tests/
 
-use std::{borrow::BorrowMut, collections::VecDeque, os::fd::OwnedFd};
+use std::{
+    borrow::BorrowMut,
+    collections::VecDeque,
+    os::fd::OwnedFd,
+    time::{Duration, Instant},
+};
 
-use anyhow::Context;
+use anyhow::{bail, ensure, Context};
+use rosenpass_secret_memory::Public;
 use rosenpass_to::{ops::copy_slice, To};
 use rosenpass_util::{
     fd::FdIo,
@@ -13,12 +19,25 @@ use rosenpass_util::{
     mio::UnixStreamExt,
     result::OkExt,
 };
-use rosenpass_wireguard_broker::brokers::mio_client::MioBrokerClient;
+use rosenpass_wireguard_broker::brokers::{
+    mio_client::MioBrokerClient, native_unix::NativeUnixBrokerConfigBaseBuilder,
+};
+use zerocopy::AsBytes;
 
 use crate::{
-    api::{add_listen_socket_response_status, add_psk_broker_response_status},
-    app_server::AppServer,
-    protocol::BuildCryptoServer,
+    api::{
+        add_listen_socket_response_status, add_peer_protocol_version, add_peer_response_status,
+        add_psk_broker_response_status, get_peer_status_response_status, key_sink_reason,
+        list_peers_response_status, register_key_sink_response_status, rekey_response_status,
+        reload_config_response_status, remove_peer_response_status, set_text_field,
+        shutdown_key_policy, shutdown_response_status, subscribe_events_event_kind,
+        subscribe_events_response_status, version_features, version_response_status, EVENT_NO_PEER,
+        LIST_PEERS_PAGE_LEN, PEER_STATUS_NEVER,
+    },
+    app_server::{AppPeerPtr, AppServer, BrokerPeer, BrokerStorePtr, DoSOperation, Endpoint},
+    config::{ProtocolVersion, ShutdownPolicy},
+    protocol::{BuildCryptoServer, SPk, SymKey},
+    reload::ReloadError,
 };
 
 use super::{supply_keypair_response_status, Server as ApiServer};
@@ -31,14 +50,56 @@ use super::{supply_keypair_response_status, Server as ApiServer};
 /// [ApiHandlerContext] is what actually contains the API handler functions.
 #[derive(Debug)]
 pub struct ApiHandler {
-    _dummy: (),
+    /// Whether the client sent a [crate::api::SubscribeEventsRequest]
+    events_subscribed: bool,
+    /// The peer whose keys the client receives; see [crate::api::RegisterKeySinkRequest]
+    key_sink: Option<AppPeerPtr>,
+    /// A [crate::api::RekeyRequest] that is still waiting for its key exchange
+    rekey_wait: Option<RekeyWait>,
+}
+
+/// Longest time a [crate::api::RekeyRequest] may wait for its key exchange
+const MAX_REKEY_WAIT_MS: u64 = 60 * 60 * 1000;
+
+/// A [crate::api::RekeyRequest] waiting for a key to be exchanged with the peer
+#[derive(Debug, Clone, Copy)]
+pub struct RekeyWait {
+    /// The peer a key should be exchanged with
+    pub peer: AppPeerPtr,
+    /// When to give up and respond with [crate::api::rekey_response_status::TIMEOUT]
+    pub deadline: Instant,
 }
 
 impl ApiHandler {
     /// Construct an [Self]
     #[allow(clippy::new_without_default)]
     pub fn new() -> Self {
-        Self { _dummy: () }
+        Self {
+            events_subscribed: false,
+            key_sink: None,
+            rekey_wait: None,
+        }
+    }
+
+    /// Whether the connection was turned into an event subscription
+    pub fn events_subscribed(&self) -> bool {
+        self.events_subscribed
+    }
+
+    /// The peer whose keys are sent over the connection, if the client registered as key sink
+    pub fn key_sink(&self) -> Option<AppPeerPtr> {
+        self.key_sink
+    }
+
+    /// The [crate::api::RekeyRequest] whose response is still being held back, if any
+    pub fn rekey_wait(&self) -> Option<RekeyWait> {
+        self.rekey_wait
+    }
+
+    /// Stop waiting for the key exchange of the [crate::api::RekeyRequest]; done right before
+    /// the response is sent
+    pub fn take_rekey_wait(&mut self) -> Option<RekeyWait> {
+        self.rekey_wait.take()
     }
 }
 
@@ -121,6 +182,12 @@ impl<T, E: Into<anyhow::Error>> SupplyKeypairErrorExt<T> for Result<T, E> {
     }
 }
 
+/// Read a zero padded UTF-8 text field from an API message
+fn text_field(field: &[u8]) -> anyhow::Result<&str> {
+    let len = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    std::str::from_utf8(&field[..len]).context("Text field is not valid UTF-8")
+}
+
 impl<T> ApiServer for T
 where
     T: ?Sized + ApiHandlerContext,
@@ -338,4 +405,435 @@ where
         res.payload.status = add_psk_broker_response_status::OK;
         Ok(())
     }
+
+    fn reload_config(
+        &mut self,
+        _req: &super::boilerplate::ReloadConfigRequest,
+        _req_fds: &mut VecDeque<OwnedFd>,
+        res: &mut super::boilerplate::ReloadConfigResponse,
+    ) -> anyhow::Result<()> {
+        use reload_config_response_status as status;
+
+        let summary = match self.app_server_mut().reload_config() {
+            Ok(summary) => summary,
+            Err(e) => {
+                res.payload.status = match e {
+                    ReloadError::NotSupported => status::NOT_SUPPORTED,
+                    ReloadError::InvalidConfig(_) => status::INVALID_CONFIG,
+                    ReloadError::Apply(_) => status::INTERNAL_ERROR,
+                };
+                log::warn!("Error processing ReloadConfig API request: {e}");
+                return Ok(());
+            }
+        };
+
+        log::info!("Configuration reloaded through the API; {summary}");
+        let r = &mut res.payload;
+        r.peers_added = summary.peers_added as u64;
+        r.peers_removed = summary.peers_removed as u64;
+        r.peers_updated = summary.peers_updated as u64;
+        r.peers_replaced = summary.peers_replaced as u64;
+        r.peers_unchanged = summary.peers_unchanged as u64;
+        r.listen_added = summary.listen_added as u64;
+        r.listen_removed = summary.listen_removed as u64;
+        r.status = status::OK;
+        Ok(())
+    }
+
+    fn add_peer(
+        &mut self,
+        req: &super::boilerplate::AddPeerRequest,
+        req_fds: &mut VecDeque<OwnedFd>,
+        res: &mut super::boilerplate::AddPeerResponse,
+    ) -> anyhow::Result<()> {
+        use add_peer_response_status as status;
+
+        // Parse the request and read the keys
+        let parsed = run(|| -> anyhow::Result<_> {
+            let req = &req.payload;
+
+            let version = req.protocol_version;
+            let protocol_version = match version {
+                add_peer_protocol_version::V02 => ProtocolVersion::V02,
+                add_peer_protocol_version::V03 => ProtocolVersion::V03,
+                v => bail!("Unknown protocol version {v}"),
+            };
+
+            let mut pk = SPk::zero();
+            FdIo(
+                req_fds
+                    .front()
+                    .context("First file descriptor, public key, missing.")?,
+            )
+            .read_exact_til_end(pk.borrow_mut())?;
+
+            let psk = match req_fds.get(1) {
+                Some(fd) => {
+                    let mut psk = SymKey::zero();
+                    FdIo(fd).read_exact_til_end(psk.secret_mut())?;
+                    Some(psk)
+                }
+                None => None,
+            };
+
+            let endpoint = match text_field(&req.endpoint)? {
+                "" => None,
+                // Looked up in the background once the peer is added
+                host => Some(Endpoint::discovery_from_hostname_deferred(host.to_owned())?),
+            };
+
+            let broker_peer = match text_field(&req.wg_device)? {
+                "" => None,
+                device => {
+                    let broker = req.broker;
+                    let ptr = BrokerStorePtr(Public::from_slice(broker.as_bytes()));
+                    ensure!(
+                        self.app_server().brokers.store.contains_key(&ptr.0),
+                        "There is no PSK broker {broker}"
+                    );
+                    let peer_cfg = NativeUnixBrokerConfigBaseBuilder::default()
+                        .peer_id(Public::from_slice(&req.wg_peer))
+                        .interface(device.to_owned())
+                        .extra_params_ser(&Vec::new())?
+                        .build()
+                        .map_err(|e| anyhow::Error::msg(format!("{e:?}")))?;
+                    Some(BrokerPeer::new(ptr, Box::new(peer_cfg)))
+                }
+            };
+
+            Ok((pk, psk, endpoint, broker_peer, protocol_version))
+        });
+
+        let (pk, psk, endpoint, broker_peer, protocol_version) = match parsed {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                log::debug!(
+                    "Request found to be invalid while processing AddPeer API request: {e:?}"
+                );
+                res.payload.status = status::INVALID_REQUEST;
+                return Ok(());
+            }
+        };
+
+        // Peers are identified by their public key
+        let srv = self.app_server_mut();
+        use rosenpass_util::build::ConstructionSite as C;
+        let exists = match &srv.crypto_site {
+            C::Product(crypto) => crypto.peers.iter().any(|p| !p.removed && p.spkt == pk),
+            C::Builder(builder) => builder.peers.iter().any(|p| p.pk == pk),
+            C::Void => false,
+        };
+        if exists {
+            log::debug!("AddPeer API request for a peer that exists already");
+            res.payload.status = status::PEER_ALREADY_EXISTS;
+            return Ok(());
+        }
+
+        let ptr = match srv.add_peer_with_endpoint(
+            psk,
+            pk,
+            None,
+            broker_peer,
+            endpoint,
+            protocol_version,
+        ) {
+            Ok(ptr) => ptr,
+            Err(e) => {
+                log::warn!("Internal error while processing AddPeer API request: {e:?}");
+                res.payload.status = status::INTERNAL_ERROR;
+                return Ok(());
+            }
+        };
+
+        srv.resolve_endpoint(ptr);
+        log::info!("Peer {} added through the API", ptr.0);
+        res.payload.peer_id = ptr.0 as u64;
+        res.payload.status = status::OK;
+        Ok(())
+    }
+
+    fn remove_peer(
+        &mut self,
+        req: &super::boilerplate::RemovePeerRequest,
+        _req_fds: &mut VecDeque<OwnedFd>,
+        res: &mut super::boilerplate::RemovePeerResponse,
+    ) -> anyhow::Result<()> {
+        use remove_peer_response_status as status;
+
+        let srv = self.app_server_mut();
+        let Some(crypto) = srv.crypto_site.product_ref() else {
+            log::debug!("RemovePeer API request before the server keypair was supplied");
+            res.payload.status = status::KEYPAIR_MISSING;
+            return Ok(());
+        };
+
+        let peer_id = req.payload.peer_id;
+        let ptr = usize::try_from(peer_id)
+            .ok()
+            .filter(|&i| i < srv.peers.len())
+            .map(AppPeerPtr)
+            .filter(|ptr| !ptr.lower().get(crypto).removed);
+        let Some(ptr) = ptr else {
+            log::debug!("RemovePeer API request for unknown peer {peer_id}");
+            res.payload.status = status::NO_SUCH_PEER;
+            return Ok(());
+        };
+
+        if let Err(e) = srv.remove_peer(ptr) {
+            log::warn!("Internal error while processing RemovePeer API request: {e:?}");
+            res.payload.status = status::INTERNAL_ERROR;
+            return Ok(());
+        }
+        if let Some(reload) = srv.config_reload.as_mut() {
+            reload.forget_peer(ptr);
+        }
+
+        log::info!("Peer {} removed through the API", ptr.0);
+        res.payload.status = status::OK;
+        Ok(())
+    }
+
+    fn list_peers(
+        &mut self,
+        req: &super::boilerplate::ListPeersRequest,
+        _req_fds: &mut VecDeque<OwnedFd>,
+        res: &mut super::boilerplate::ListPeersResponse,
+    ) -> anyhow::Result<()> {
+        let peers = self.app_server().live_peers();
+        let offset = usize::try_from(req.payload.offset).unwrap_or(usize::MAX);
+
+        let mut peer_ids = [0u64; LIST_PEERS_PAGE_LEN];
+        let mut count = 0;
+        for (slot, peer) in peer_ids.iter_mut().zip(peers.iter().skip(offset)) {
+            *slot = peer.0 as u64;
+            count += 1;
+        }
+
+        let r = &mut res.payload;
+        r.peer_ids = peer_ids;
+        r.count = count;
+        r.total = peers.len() as u64;
+        r.status = list_peers_response_status::OK;
+        Ok(())
+    }
+
+    fn get_peer_status(
+        &mut self,
+        req: &super::boilerplate::GetPeerStatusRequest,
+        _req_fds: &mut VecDeque<OwnedFd>,
+        res: &mut super::boilerplate::GetPeerStatusResponse,
+    ) -> anyhow::Result<()> {
+        use get_peer_status_response_status as status;
+
+        let peer_id = req.payload.peer_id;
+        let peer_status = usize::try_from(peer_id)
+            .ok()
+            .and_then(|i| self.app_server().peer_status(AppPeerPtr(i)));
+        let Some(peer_status) = peer_status else {
+            log::debug!("GetPeerStatus API request for unknown peer {peer_id}");
+            res.payload.status = status::NO_SUCH_PEER;
+            return Ok(());
+        };
+
+        let millis = |d: Option<std::time::Duration>| match d {
+            Some(d) => u64::try_from(d.as_millis()).unwrap_or(u64::MAX - 1),
+            None => PEER_STATUS_NEVER,
+        };
+
+        let r = &mut res.payload;
+        r.peer_id = peer_id;
+        r.protocol_version = match peer_status.protocol_version {
+            ProtocolVersion::V02 => add_peer_protocol_version::V02,
+            ProtocolVersion::V03 => add_peer_protocol_version::V03,
+        };
+        set_text_field(&mut r.name, peer_status.name.as_deref().unwrap_or(""));
+        set_text_field(
+            &mut r.configured_endpoint,
+            peer_status.configured_endpoint.as_deref().unwrap_or(""),
+        );
+        set_text_field(
+            &mut r.current_endpoint,
+            peer_status.current_endpoint.as_deref().unwrap_or(""),
+        );
+        r.session_age_ms = millis(peer_status.session_age);
+        r.since_key_exchange_ms = millis(peer_status.since_key_exchange);
+        r.handshake_in_progress = peer_status.handshake_in_progress as u8;
+        r.retransmissions = peer_status.counters.retransmissions;
+        r.broker_failures = peer_status.counters.broker_failures;
+        r.keys_stale = peer_status.counters.keys_stale;
+        r.status = status::OK;
+        Ok(())
+    }
+
+    fn subscribe_events(
+        &mut self,
+        _req: &super::boilerplate::SubscribeEventsRequest,
+        _req_fds: &mut VecDeque<OwnedFd>,
+        res: &mut super::boilerplate::SubscribeEventsResponse,
+    ) -> anyhow::Result<()> {
+        self.api_handler_mut().events_subscribed = true;
+
+        let under_load = self.app_server().under_load == DoSOperation::UnderLoad;
+        let r = &mut res.payload;
+        r.event = subscribe_events_event_kind::SUBSCRIBED;
+        r.peer_id = EVENT_NO_PEER;
+        r.under_load = under_load as u8;
+        r.status = subscribe_events_response_status::OK;
+        Ok(())
+    }
+
+    fn register_key_sink(
+        &mut self,
+        req: &super::boilerplate::RegisterKeySinkRequest,
+        _req_fds: &mut VecDeque<OwnedFd>,
+        res: &mut super::boilerplate::RegisterKeySinkResponse,
+    ) -> anyhow::Result<()> {
+        use register_key_sink_response_status as status;
+
+        let peer_id = req.payload.peer_id;
+        let peer = usize::try_from(peer_id)
+            .ok()
+            .map(AppPeerPtr)
+            .filter(|p| self.app_server().live_peers().iter().any(|q| q.0 == p.0));
+        let Some(peer) = peer else {
+            log::debug!("RegisterKeySink API request for unknown peer {peer_id}");
+            res.payload.status = status::NO_SUCH_PEER;
+            return Ok(());
+        };
+
+        self.api_handler_mut().key_sink = Some(peer);
+
+        let r = &mut res.payload;
+        r.peer_id = peer_id;
+        r.reason = key_sink_reason::REGISTERED;
+        r.status = status::OK;
+        Ok(())
+    }
+
+    fn rekey(
+        &mut self,
+        req: &super::boilerplate::RekeyRequest,
+        _req_fds: &mut VecDeque<OwnedFd>,
+        res: &mut super::boilerplate::RekeyResponse,
+    ) -> anyhow::Result<()> {
+        use rekey_response_status as status;
+
+        let (peer_id, wait_ms) = (req.payload.peer_id, req.payload.wait_ms);
+        res.payload.peer_id = peer_id;
+
+        let srv = self.app_server_mut();
+        let Some(crypto) = srv.crypto_site.product_mut() else {
+            log::debug!("Rekey API request before the server keypair was supplied");
+            res.payload.status = status::KEYPAIR_MISSING;
+            return Ok(());
+        };
+
+        let ptr = usize::try_from(peer_id)
+            .ok()
+            .filter(|&i| i < srv.peers.len())
+            .map(AppPeerPtr)
+            .filter(|ptr| !ptr.lower().get(crypto).removed);
+        let Some(ptr) = ptr else {
+            log::debug!("Rekey API request for unknown peer {peer_id}");
+            res.payload.status = status::NO_SUCH_PEER;
+            return Ok(());
+        };
+
+        if srv.peers[ptr.0].endpoint().is_none() {
+            log::debug!("Rekey API request for peer {peer_id} without endpoint");
+            res.payload.status = status::NO_ENDPOINT;
+            return Ok(());
+        }
+
+        crypto.initiate_now(ptr.lower())?;
+        log::info!("Key exchange with peer {peer_id} requested through the API");
+
+        if wait_ms > 0 {
+            // The connection holds back the response until the key is exchanged or time is up
+            self.api_handler_mut().rekey_wait = Some(RekeyWait {
+                peer: ptr,
+                deadline: Instant::now() + Duration::from_millis(wait_ms.min(MAX_REKEY_WAIT_MS)),
+            });
+        }
+
+        res.payload.status = status::OK;
+        Ok(())
+    }
+
+    fn shutdown(
+        &mut self,
+        req: &super::boilerplate::ShutdownRequest,
+        _req_fds: &mut VecDeque<OwnedFd>,
+        res: &mut super::boilerplate::ShutdownResponse,
+    ) -> anyhow::Result<()> {
+        use shutdown_response_status as status;
+
+        let srv = self.app_server_mut();
+        srv.on_shutdown = match req.payload.key_policy {
+            shutdown_key_policy::CONFIGURED => srv.on_shutdown,
+            shutdown_key_policy::KEEP => ShutdownPolicy::Keep,
+            shutdown_key_policy::RETIRE => ShutdownPolicy::Retire,
+            other => {
+                log::debug!("Shutdown API request with unknown key policy {other}");
+                res.payload.status = status::INVALID_REQUEST;
+                return Ok(());
+            }
+        };
+
+        // The event loop shuts down on its next iteration, after this response was sent
+        srv.shutdown_requested = true;
+        log::info!("Shutdown requested through the API");
+
+        res.payload.status = status::OK;
+        Ok(())
+    }
+
+    fn version(
+        &mut self,
+        _req: &super::boilerplate::VersionRequest,
+        _req_fds: &mut VecDeque<OwnedFd>,
+        res: &mut super::boilerplate::VersionResponse,
+    ) -> anyhow::Result<()> {
+        use version_features as f;
+
+        let libcrux_all = cfg!(feature = "experiment_libcrux_all");
+        let features = [
+            (cfg!(feature = "experiment_api"), f::EXPERIMENT_API),
+            (
+                cfg!(feature = "experiment_cookie_dos_mitigation"),
+                f::EXPERIMENT_COOKIE_DOS_MITIGATION,
+            ),
+            (
+                cfg!(feature = "experiment_memfd_secret"),
+                f::EXPERIMENT_MEMFD_SECRET,
+            ),
+            (
+                libcrux_all || cfg!(feature = "experiment_libcrux_blake2"),
+                f::EXPERIMENT_LIBCRUX_BLAKE2,
+            ),
+            (
+                libcrux_all || cfg!(feature = "experiment_libcrux_chachapoly"),
+                f::EXPERIMENT_LIBCRUX_CHACHAPOLY,
+            ),
+            (
+                libcrux_all || cfg!(feature = "experiment_libcrux_kyber"),
+                f::EXPERIMENT_LIBCRUX_KYBER,
+            ),
+        ];
+
+        let r = &mut res.payload;
+        set_text_field(&mut r.version, env!("CARGO_PKG_VERSION"));
+        r.features = features
+            .iter()
+            .filter(|(enabled, _)| *enabled)
+            .fold(0, |acc, (_, bit)| acc | bit);
+        r.protocol_versions = [
+            add_peer_protocol_version::V02,
+            add_peer_protocol_version::V03,
+        ]
+        .iter()
+        .fold(0, |acc, v| acc | 1 << v);
+        r.status = version_response_status::OK;
+        Ok(())
+    }
 }
@@ -257,6 +257,404 @@ pub trait ByteSliceRefExt: ByteSlice {
     ) -> anyhow::Result<Ref<Self, super::AddPskBrokerResponse>> {
         self.zk_parse_suffix()
     }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse].
+    fn reload_config_request(self) -> anyhow::Result<Ref<Self, super::ReloadConfigRequest>> {
+        self.zk_parse()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_prefix].
+    fn reload_config_request_from_prefix(
+        self,
+    ) -> anyhow::Result<Ref<Self, super::ReloadConfigRequest>> {
+        self.zk_parse_prefix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_suffix].
+    fn reload_config_request_from_suffix(
+        self,
+    ) -> anyhow::Result<Ref<Self, super::ReloadConfigRequest>> {
+        self.zk_parse_suffix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_ref_maker].
+    fn reload_config_response_maker(self) -> RefMaker<Self, super::ReloadConfigResponse> {
+        self.zk_ref_maker()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse].
+    fn reload_config_response(self) -> anyhow::Result<Ref<Self, super::ReloadConfigResponse>> {
+        self.zk_parse()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_prefix].
+    fn reload_config_response_from_prefix(
+        self,
+    ) -> anyhow::Result<Ref<Self, super::ReloadConfigResponse>> {
+        self.zk_parse_prefix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_suffix].
+    fn reload_config_response_from_suffix(
+        self,
+    ) -> anyhow::Result<Ref<Self, super::ReloadConfigResponse>> {
+        self.zk_parse_suffix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse].
+    fn add_peer_request(self) -> anyhow::Result<Ref<Self, super::AddPeerRequest>> {
+        self.zk_parse()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_prefix].
+    fn add_peer_request_from_prefix(self) -> anyhow::Result<Ref<Self, super::AddPeerRequest>> {
+        self.zk_parse_prefix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_suffix].
+    fn add_peer_request_from_suffix(self) -> anyhow::Result<Ref<Self, super::AddPeerRequest>> {
+        self.zk_parse_suffix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_ref_maker].
+    fn add_peer_response_maker(self) -> RefMaker<Self, super::AddPeerResponse> {
+        self.zk_ref_maker()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse].
+    fn add_peer_response(self) -> anyhow::Result<Ref<Self, super::AddPeerResponse>> {
+        self.zk_parse()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_prefix].
+    fn add_peer_response_from_prefix(self) -> anyhow::Result<Ref<Self, super::AddPeerResponse>> {
+        self.zk_parse_prefix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_suffix].
+    fn add_peer_response_from_suffix(self) -> anyhow::Result<Ref<Self, super::AddPeerResponse>> {
+        self.zk_parse_suffix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse].
+    fn remove_peer_request(self) -> anyhow::Result<Ref<Self, super::RemovePeerRequest>> {
+        self.zk_parse()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_prefix].
+    fn remove_peer_request_from_prefix(
+        self,
+    ) -> anyhow::Result<Ref<Self, super::RemovePeerRequest>> {
+        self.zk_parse_prefix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_suffix].
+    fn remove_peer_request_from_suffix(
+        self,
+    ) -> anyhow::Result<Ref<Self, super::RemovePeerRequest>> {
+        self.zk_parse_suffix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_ref_maker].
+    fn remove_peer_response_maker(self) -> RefMaker<Self, super::RemovePeerResponse> {
+        self.zk_ref_maker()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse].
+    fn remove_peer_response(self) -> anyhow::Result<Ref<Self, super::RemovePeerResponse>> {
+        self.zk_parse()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_prefix].
+    fn remove_peer_response_from_prefix(
+        self,
+    ) -> anyhow::Result<Ref<Self, super::RemovePeerResponse>> {
+        self.zk_parse_prefix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_suffix].
+    fn remove_peer_response_from_suffix(
+        self,
+    ) -> anyhow::Result<Ref<Self, super::RemovePeerResponse>> {
+        self.zk_parse_suffix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse].
+    fn list_peers_request(self) -> anyhow::Result<Ref<Self, super::ListPeersRequest>> {
+        self.zk_parse()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_prefix].
+    fn list_peers_request_from_prefix(self) -> anyhow::Result<Ref<Self, super::ListPeersRequest>> {
+        self.zk_parse_prefix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_suffix].
+    fn list_peers_request_from_suffix(self) -> anyhow::Result<Ref<Self, super::ListPeersRequest>> {
+        self.zk_parse_suffix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_ref_maker].
+    fn list_peers_response_maker(self) -> RefMaker<Self, super::ListPeersResponse> {
+        self.zk_ref_maker()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse].
+    fn list_peers_response(self) -> anyhow::Result<Ref<Self, super::ListPeersResponse>> {
+        self.zk_parse()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_prefix].
+    fn list_peers_response_from_prefix(
+        self,
+    ) -> anyhow::Result<Ref<Self, super::ListPeersResponse>> {
+        self.zk_parse_prefix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_suffix].
+    fn list_peers_response_from_suffix(
+        self,
+    ) -> anyhow::Result<Ref<Self, super::ListPeersResponse>> {
+        self.zk_parse_suffix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse].
+    fn get_peer_status_request(self) -> anyhow::Result<Ref<Self, super::GetPeerStatusRequest>> {
+        self.zk_parse()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_prefix].
+    fn get_peer_status_request_from_prefix(
+        self,
+    ) -> anyhow::Result<Ref<Self, super::GetPeerStatusRequest>> {
+        self.zk_parse_prefix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_suffix].
+    fn get_peer_status_request_from_suffix(
+        self,
+    ) -> anyhow::Result<Ref<Self, super::GetPeerStatusRequest>> {
+        self.zk_parse_suffix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_ref_maker].
+    fn get_peer_status_response_maker(self) -> RefMaker<Self, super::GetPeerStatusResponse> {
+        self.zk_ref_maker()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse].
+    fn get_peer_status_response(self) -> anyhow::Result<Ref<Self, super::GetPeerStatusResponse>> {
+        self.zk_parse()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_prefix].
+    fn get_peer_status_response_from_prefix(
+        self,
+    ) -> anyhow::Result<Ref<Self, super::GetPeerStatusResponse>> {
+        self.zk_parse_prefix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_suffix].
+    fn get_peer_status_response_from_suffix(
+        self,
+    ) -> anyhow::Result<Ref<Self, super::GetPeerStatusResponse>> {
+        self.zk_parse_suffix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse].
+    fn subscribe_events_request(self) -> anyhow::Result<Ref<Self, super::SubscribeEventsRequest>> {
+        self.zk_parse()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_prefix].
+    fn subscribe_events_request_from_prefix(
+        self,
+    ) -> anyhow::Result<Ref<Self, super::SubscribeEventsRequest>> {
+        self.zk_parse_prefix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_suffix].
+    fn subscribe_events_request_from_suffix(
+        self,
+    ) -> anyhow::Result<Ref<Self, super::SubscribeEventsRequest>> {
+        self.zk_parse_suffix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_ref_maker].
+    fn subscribe_events_response_maker(self) -> RefMaker<Self, super::SubscribeEventsResponse> {
+        self.zk_ref_maker()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse].
+    fn subscribe_events_response(
+        self,
+    ) -> anyhow::Result<Ref<Self, super::SubscribeEventsResponse>> {
+        self.zk_parse()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_prefix].
+    fn subscribe_events_response_from_prefix(
+        self,
+    ) -> anyhow::Result<Ref<Self, super::SubscribeEventsResponse>> {
+        self.zk_parse_prefix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_suffix].
+    fn subscribe_events_response_from_suffix(
+        self,
+    ) -> anyhow::Result<Ref<Self, super::SubscribeEventsResponse>> {
+        self.zk_parse_suffix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse].
+    fn register_key_sink_request(self) -> anyhow::Result<Ref<Self, super::RegisterKeySinkRequest>> {
+        self.zk_parse()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_prefix].
+    fn register_key_sink_request_from_prefix(
+        self,
+    ) -> anyhow::Result<Ref<Self, super::RegisterKeySinkRequest>> {
+        self.zk_parse_prefix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_suffix].
+    fn register_key_sink_request_from_suffix(
+        self,
+    ) -> anyhow::Result<Ref<Self, super::RegisterKeySinkRequest>> {
+        self.zk_parse_suffix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_ref_maker].
+    fn register_key_sink_response_maker(self) -> RefMaker<Self, super::RegisterKeySinkResponse> {
+        self.zk_ref_maker()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse].
+    fn register_key_sink_response(
+        self,
+    ) -> anyhow::Result<Ref<Self, super::RegisterKeySinkResponse>> {
+        self.zk_parse()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_prefix].
+    fn register_key_sink_response_from_prefix(
+        self,
+    ) -> anyhow::Result<Ref<Self, super::RegisterKeySinkResponse>> {
+        self.zk_parse_prefix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_suffix].
+    fn register_key_sink_response_from_suffix(
+        self,
+    ) -> anyhow::Result<Ref<Self, super::RegisterKeySinkResponse>> {
+        self.zk_parse_suffix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse].
+    fn rekey_request(self) -> anyhow::Result<Ref<Self, super::RekeyRequest>> {
+        self.zk_parse()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_prefix].
+    fn rekey_request_from_prefix(self) -> anyhow::Result<Ref<Self, super::RekeyRequest>> {
+        self.zk_parse_prefix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_suffix].
+    fn rekey_request_from_suffix(self) -> anyhow::Result<Ref<Self, super::RekeyRequest>> {
+        self.zk_parse_suffix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_ref_maker].
+    fn rekey_response_maker(self) -> RefMaker<Self, super::RekeyResponse> {
+        self.zk_ref_maker()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse].
+    fn rekey_response(self) -> anyhow::Result<Ref<Self, super::RekeyResponse>> {
+        self.zk_parse()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_prefix].
+    fn rekey_response_from_prefix(self) -> anyhow::Result<Ref<Self, super::RekeyResponse>> {
+        self.zk_parse_prefix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_suffix].
+    fn rekey_response_from_suffix(self) -> anyhow::Result<Ref<Self, super::RekeyResponse>> {
+        self.zk_parse_suffix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse].
+    fn shutdown_request(self) -> anyhow::Result<Ref<Self, super::ShutdownRequest>> {
+        self.zk_parse()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_prefix].
+    fn shutdown_request_from_prefix(self) -> anyhow::Result<Ref<Self, super::ShutdownRequest>> {
+        self.zk_parse_prefix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_suffix].
+    fn shutdown_request_from_suffix(self) -> anyhow::Result<Ref<Self, super::ShutdownRequest>> {
+        self.zk_parse_suffix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_ref_maker].
+    fn shutdown_response_maker(self) -> RefMaker<Self, super::ShutdownResponse> {
+        self.zk_ref_maker()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse].
+    fn shutdown_response(self) -> anyhow::Result<Ref<Self, super::ShutdownResponse>> {
+        self.zk_parse()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_prefix].
+    fn shutdown_response_from_prefix(self) -> anyhow::Result<Ref<Self, super::ShutdownResponse>> {
+        self.zk_parse_prefix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_suffix].
+    fn shutdown_response_from_suffix(self) -> anyhow::Result<Ref<Self, super::ShutdownResponse>> {
+        self.zk_parse_suffix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse].
+    fn version_request(self) -> anyhow::Result<Ref<Self, super::VersionRequest>> {
+        self.zk_parse()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_prefix].
+    fn version_request_from_prefix(self) -> anyhow::Result<Ref<Self, super::VersionRequest>> {
+        self.zk_parse_prefix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_suffix].
+    fn version_request_from_suffix(self) -> anyhow::Result<Ref<Self, super::VersionRequest>> {
+        self.zk_parse_suffix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_ref_maker].
+    fn version_response_maker(self) -> RefMaker<Self, super::VersionResponse> {
+        self.zk_ref_maker()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse].
+    fn version_response(self) -> anyhow::Result<Ref<Self, super::VersionResponse>> {
+        self.zk_parse()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_prefix].
+    fn version_response_from_prefix(self) -> anyhow::Result<Ref<Self, super::VersionResponse>> {
+        self.zk_parse_prefix()
+    }
+
+    /// Shorthand for the typed use of [ZerocopySliceExt::zk_parse_suffix].
+    fn version_response_from_suffix(self) -> anyhow::Result<Ref<Self, super::VersionResponse>> {
+        self.zk_parse_suffix()
+    }
 }
 
 impl<B: ByteSlice> ByteSliceRefExt for B {}
@@ -35,6 +35,76 @@ const ADD_PSK_BROKER_REQUEST: RawMsgType =
 const ADD_PSK_BROKER_RESPONSE: RawMsgType =
     RawMsgType::from_le_bytes(hex!("bd25 e418 ffb0 6930    248b 217e 2fae e353"));
 
+// hash domain hash of: Rosenpass IPC API -> Rosenpass Protocol Server -> Reload Config Request
+const RELOAD_CONFIG_REQUEST: RawMsgType =
+    RawMsgType::from_le_bytes(hex!("c4a7 6c64 5154 5809    519c bc66 847b 4896"));
+// hash domain hash of: Rosenpass IPC API -> Rosenpass Protocol Server -> Reload Config Response
+const RELOAD_CONFIG_RESPONSE: RawMsgType =
+    RawMsgType::from_le_bytes(hex!("33c8 1b51 2dad ee84    6047 0118 cc24 5b0f"));
+
+// hash domain hash of: Rosenpass IPC API -> Rosenpass Protocol Server -> Add Peer Request
+const ADD_PEER_REQUEST: RawMsgType =
+    RawMsgType::from_le_bytes(hex!("69ac 7483 ce72 b30b    cf7f 37ad 40da a4a0"));
+// hash domain hash of: Rosenpass IPC API -> Rosenpass Protocol Server -> Add Peer Response
+const ADD_PEER_RESPONSE: RawMsgType =
+    RawMsgType::from_le_bytes(hex!("ff13 d033 8575 b2eb    3615 d0ad 81c7 4b34"));
+
+// hash domain hash of: Rosenpass IPC API -> Rosenpass Protocol Server -> Remove Peer Request
+const REMOVE_PEER_REQUEST: RawMsgType =
+    RawMsgType::from_le_bytes(hex!("d33c 778c 7434 40ab    d3cb 115c 5fd5 a18c"));
+// hash domain hash of: Rosenpass IPC API -> Rosenpass Protocol Server -> Remove Peer Response
+const REMOVE_PEER_RESPONSE: RawMsgType =
+    RawMsgType::from_le_bytes(hex!("c45e 842e 9c1f 377c    3bb3 3f0a d037 c867"));
+
+// hash domain hash of: Rosenpass IPC API -> Rosenpass Protocol Server -> List Peers Request
+const LIST_PEERS_REQUEST: RawMsgType =
+    RawMsgType::from_le_bytes(hex!("97b8 a3ca 8d8c 924f    02c8 b967 1bfc 3775"));
+// hash domain hash of: Rosenpass IPC API -> Rosenpass Protocol Server -> List Peers Response
+const LIST_PEERS_RESPONSE: RawMsgType =
+    RawMsgType::from_le_bytes(hex!("16d6 30ea 2182 edcd    3158 461c 928b 886c"));
+
+// hash domain hash of: Rosenpass IPC API -> Rosenpass Protocol Server -> Get Peer Status Request
+const GET_PEER_STATUS_REQUEST: RawMsgType =
+    RawMsgType::from_le_bytes(hex!("7048 4da2 8178 2207    46ea 0db7 ee93 1272"));
+// hash domain hash of: Rosenpass IPC API -> Rosenpass Protocol Server -> Get Peer Status Response
+const GET_PEER_STATUS_RESPONSE: RawMsgType =
+    RawMsgType::from_le_bytes(hex!("de5e da3b 7a58 ef8f    6a5e 604b b859 6e7f"));
+
+// hash domain hash of: Rosenpass IPC API -> Rosenpass Protocol Server -> Subscribe Events Request
+const SUBSCRIBE_EVENTS_REQUEST: RawMsgType =
+    RawMsgType::from_le_bytes(hex!("661a d283 1dfd c064    ea12 9f13 f9e4 247b"));
+// hash domain hash of: Rosenpass IPC API -> Rosenpass Protocol Server -> Subscribe Events Response
+const SUBSCRIBE_EVENTS_RESPONSE: RawMsgType =
+    RawMsgType::from_le_bytes(hex!("6a95 4367 0f7b 81e3    b66c dde0 d9a6 2cd8"));
+
+// hash domain hash of: Rosenpass IPC API -> Rosenpass Protocol Server -> Register Key Sink Request
+const REGISTER_KEY_SINK_REQUEST: RawMsgType =
+    RawMsgType::from_le_bytes(hex!("cc5e 6c35 2dbb a189    f998 0cbe c86d a8f3"));
+// hash domain hash of: Rosenpass IPC API -> Rosenpass Protocol Server -> Register Key Sink Response
+const REGISTER_KEY_SINK_RESPONSE: RawMsgType =
+    RawMsgType::from_le_bytes(hex!("4e80 a9c1 61c6 6ffc    2852 bd4e 6acb e9ec"));
+
+// hash domain hash of: Rosenpass IPC API -> Rosenpass Protocol Server -> Rekey Request
+const REKEY_REQUEST: RawMsgType =
+    RawMsgType::from_le_bytes(hex!("b19e 885b 732a 2d47    d76b 454d fba2 f11b"));
+// hash domain hash of: Rosenpass IPC API -> Rosenpass Protocol Server -> Rekey Response
+const REKEY_RESPONSE: RawMsgType =
+    RawMsgType::from_le_bytes(hex!("9a1a 951b b055 fd05    3e4a 03ed 2a59 1e90"));
+
+// hash domain hash of: Rosenpass IPC API -> Rosenpass Protocol Server -> Shutdown Request
+const SHUTDOWN_REQUEST: RawMsgType =
+    RawMsgType::from_le_bytes(hex!("b742 7432 361a 353c    b422 e3cc 3ff7 ed31"));
+// hash domain hash of: Rosenpass IPC API -> Rosenpass Protocol Server -> Shutdown Response
+const SHUTDOWN_RESPONSE: RawMsgType =
+    RawMsgType::from_le_bytes(hex!("4ae7 4525 035d 7c4c    4691 f45c 44a9 4591"));
+
+// hash domain hash of: Rosenpass IPC API -> Rosenpass Protocol Server -> Version Request
+const VERSION_REQUEST: RawMsgType =
+    RawMsgType::from_le_bytes(hex!("e226 1a65 1b2f 72a8    b964 ee83 2855 68b5"));
+// hash domain hash of: Rosenpass IPC API -> Rosenpass Protocol Server -> Version Response
+const VERSION_RESPONSE: RawMsgType =
+    RawMsgType::from_le_bytes(hex!("0b8b 633b 0984 2417    7365 5a16 6d32 b4fd"));
+
 /// Message properties global to the message type
 pub trait MessageAttributes {
     /// Get the size of the message
@@ -50,6 +120,16 @@ pub enum RequestMsgType {
     SupplyKeypair,
     AddListenSocket,
     AddPskBroker,
+    ReloadConfig,
+    AddPeer,
+    RemovePeer,
+    ListPeers,
+    GetPeerStatus,
+    SubscribeEvents,
+    RegisterKeySink,
+    Rekey,
+    Shutdown,
+    Version,
 }
 
 /// API response messages types as an enum
@@ -59,6 +139,16 @@ pub enum ResponseMsgType {
     SupplyKeypair,
     AddListenSocket,
     AddPskBroker,
+    ReloadConfig,
+    AddPeer,
+    RemovePeer,
+    ListPeers,
+    GetPeerStatus,
+    SubscribeEvents,
+    RegisterKeySink,
+    Rekey,
+    Shutdown,
+    Version,
 }
 
 impl MessageAttributes for RequestMsgType {
@@ -68,6 +158,16 @@ impl MessageAttributes for RequestMsgType {
             Self::SupplyKeypair => std::mem::size_of::<super::SupplyKeypairRequest>(),
             Self::AddListenSocket => std::mem::size_of::<super::AddListenSocketRequest>(),
             Self::AddPskBroker => std::mem::size_of::<super::AddPskBrokerRequest>(),
+            Self::ReloadConfig => std::mem::size_of::<super::ReloadConfigRequest>(),
+            Self::AddPeer => std::mem::size_of::<super::AddPeerRequest>(),
+            Self::RemovePeer => std::mem::size_of::<super::RemovePeerRequest>(),
+            Self::ListPeers => std::mem::size_of::<super::ListPeersRequest>(),
+            Self::GetPeerStatus => std::mem::size_of::<super::GetPeerStatusRequest>(),
+            Self::SubscribeEvents => std::mem::size_of::<super::SubscribeEventsRequest>(),
+            Self::RegisterKeySink => std::mem::size_of::<super::RegisterKeySinkRequest>(),
+            Self::Rekey => std::mem::size_of::<super::RekeyRequest>(),
+            Self::Shutdown => std::mem::size_of::<super::ShutdownRequest>(),
+            Self::Version => std::mem::size_of::<super::VersionRequest>(),
         }
     }
 }
@@ -79,6 +179,16 @@ impl MessageAttributes for ResponseMsgType {
             Self::SupplyKeypair => std::mem::size_of::<super::SupplyKeypairResponse>(),
             Self::AddListenSocket => std::mem::size_of::<super::AddListenSocketResponse>(),
             Self::AddPskBroker => std::mem::size_of::<super::AddPskBrokerResponse>(),
+            Self::ReloadConfig => std::mem::size_of::<super::ReloadConfigResponse>(),
+            Self::AddPeer => std::mem::size_of::<super::AddPeerResponse>(),
+            Self::RemovePeer => std::mem::size_of::<super::RemovePeerResponse>(),
+            Self::ListPeers => std::mem::size_of::<super::ListPeersResponse>(),
+            Self::GetPeerStatus => std::mem::size_of::<super::GetPeerStatusResponse>(),
+            Self::SubscribeEvents => std::mem::size_of::<super::SubscribeEventsResponse>(),
+            Self::RegisterKeySink => std::mem::size_of::<super::RegisterKeySinkResponse>(),
+            Self::Rekey => std::mem::size_of::<super::RekeyResponse>(),
+            Self::Shutdown => std::mem::size_of::<super::ShutdownResponse>(),
+            Self::Version => std::mem::size_of::<super::VersionResponse>(),
         }
     }
 }
@@ -93,6 +203,16 @@ impl TryFrom<RawMsgType> for RequestMsgType {
             self::SUPPLY_KEYPAIR_REQUEST => E::SupplyKeypair,
             self::ADD_LISTEN_SOCKET_REQUEST => E::AddListenSocket,
             self::ADD_PSK_BROKER_REQUEST => E::AddPskBroker,
+            self::RELOAD_CONFIG_REQUEST => E::ReloadConfig,
+            self::ADD_PEER_REQUEST => E::AddPeer,
+            self::REMOVE_PEER_REQUEST => E::RemovePeer,
+            self::LIST_PEERS_REQUEST => E::ListPeers,
+            self::GET_PEER_STATUS_REQUEST => E::GetPeerStatus,
+            self::SUBSCRIBE_EVENTS_REQUEST => E::SubscribeEvents,
+            self::REGISTER_KEY_SINK_REQUEST => E::RegisterKeySink,
+            self::REKEY_REQUEST => E::Rekey,
+            self::SHUTDOWN_REQUEST => E::Shutdown,
+            self::VERSION_REQUEST => E::Version,
             _ => return Err(InvalidApiMessageType(value)),
         })
     }
@@ -106,6 +226,16 @@ impl From<RequestMsgType> for RawMsgType {
             E::SupplyKeypair => self::SUPPLY_KEYPAIR_REQUEST,
             E::AddListenSocket => self::ADD_LISTEN_SOCKET_REQUEST,
             E::AddPskBroker => self::ADD_PSK_BROKER_REQUEST,
+            E::ReloadConfig => self::RELOAD_CONFIG_REQUEST,
+            E::AddPeer => self::ADD_PEER_REQUEST,
+            E::RemovePeer => self::REMOVE_PEER_REQUEST,
+            E::ListPeers => self::LIST_PEERS_REQUEST,
+            E::GetPeerStatus => self::GET_PEER_STATUS_REQUEST,
+            E::SubscribeEvents => self::SUBSCRIBE_EVENTS_REQUEST,
+            E::RegisterKeySink => self::REGISTER_KEY_SINK_REQUEST,
+            E::Rekey => self::REKEY_REQUEST,
+            E::Shutdown => self::SHUTDOWN_REQUEST,
+            E::Version => self::VERSION_REQUEST,
         }
     }
 }
@@ -120,6 +250,16 @@ impl TryFrom<RawMsgType> for ResponseMsgType {
             self::SUPPLY_KEYPAIR_RESPONSE => E::SupplyKeypair,
             self::ADD_LISTEN_SOCKET_RESPONSE => E::AddListenSocket,
             self::ADD_PSK_BROKER_RESPONSE => E::AddPskBroker,
+            self::RELOAD_CONFIG_RESPONSE => E::ReloadConfig,
+            self::ADD_PEER_RESPONSE => E::AddPeer,
+            self::REMOVE_PEER_RESPONSE => E::RemovePeer,
+            self::LIST_PEERS_RESPONSE => E::ListPeers,
+            self::GET_PEER_STATUS_RESPONSE => E::GetPeerStatus,
+            self::SUBSCRIBE_EVENTS_RESPONSE => E::SubscribeEvents,
+            self::REGISTER_KEY_SINK_RESPONSE => E::RegisterKeySink,
+            self::REKEY_RESPONSE => E::Rekey,
+            self::SHUTDOWN_RESPONSE => E::Shutdown,
+            self::VERSION_RESPONSE => E::Version,
             _ => return Err(InvalidApiMessageType(value)),
         })
     }
@@ -133,6 +273,16 @@ impl From<ResponseMsgType> for RawMsgType {
             E::SupplyKeypair => self::SUPPLY_KEYPAIR_RESPONSE,
             E::AddListenSocket => self::ADD_LISTEN_SOCKET_RESPONSE,
             E::AddPskBroker => self::ADD_PSK_BROKER_RESPONSE,
+            E::ReloadConfig => self::RELOAD_CONFIG_RESPONSE,
+            E::AddPeer => self::ADD_PEER_RESPONSE,
+            E::RemovePeer => self::REMOVE_PEER_RESPONSE,
+            E::ListPeers => self::LIST_PEERS_RESPONSE,
+            E::GetPeerStatus => self::GET_PEER_STATUS_RESPONSE,
+            E::SubscribeEvents => self::SUBSCRIBE_EVENTS_RESPONSE,
+            E::RegisterKeySink => self::REGISTER_KEY_SINK_RESPONSE,
+            E::Rekey => self::REKEY_RESPONSE,
+            E::Shutdown => self::SHUTDOWN_RESPONSE,
+            E::Version => self::VERSION_RESPONSE,
         }
     }
 }
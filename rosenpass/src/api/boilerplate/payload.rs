@@ -9,6 +9,8 @@ pub const MAX_REQUEST_LEN: usize = 2500; // TODO fix this
 pub const MAX_RESPONSE_LEN: usize = 2500; // TODO fix this
 /// Maximum number of file descriptors that can be sent in a request.
 pub const MAX_REQUEST_FDS: usize = 2;
+/// Maximum number of file descriptors that can come with a response.
+pub const MAX_RESPONSE_FDS: usize = 1;
 
 /// Message envelope for API messages
 #[repr(packed)]
@@ -396,3 +398,1163 @@ impl Message for AddPskBrokerResponse {
         self.msg_type = Self::MESSAGE_TYPE.into();
     }
 }
+
+#[allow(missing_docs)]
+#[repr(packed)]
+#[derive(Debug, Copy, Clone, Hash, AsBytes, FromBytes, FromZeroes, PartialEq, Eq)]
+pub struct ReloadConfigRequestPayload {}
+
+#[allow(missing_docs)]
+pub type ReloadConfigRequest = RequestEnvelope<ReloadConfigRequestPayload>;
+
+impl Default for ReloadConfigRequest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReloadConfigRequest {
+    #[allow(missing_docs)]
+    pub fn new() -> Self {
+        Self::from_payload(ReloadConfigRequestPayload {})
+    }
+}
+
+impl Message for ReloadConfigRequest {
+    type Payload = ReloadConfigRequestPayload;
+    type MessageClass = RequestMsgType;
+    const MESSAGE_TYPE: Self::MessageClass = RequestMsgType::ReloadConfig;
+
+    fn from_payload(payload: Self::Payload) -> Self {
+        Self {
+            msg_type: Self::MESSAGE_TYPE.into(),
+            payload,
+        }
+    }
+
+    fn setup<B: ByteSliceMut>(buf: B) -> anyhow::Result<Ref<B, Self>> {
+        let mut r: Ref<B, Self> = buf.zk_zeroized()?;
+        r.init();
+        Ok(r)
+    }
+
+    fn init(&mut self) {
+        self.msg_type = Self::MESSAGE_TYPE.into();
+    }
+}
+
+#[allow(missing_docs)]
+pub mod reload_config_response_status {
+    #[allow(missing_docs)]
+    pub const OK: u128 = 0;
+    #[allow(missing_docs)]
+    pub const NOT_SUPPORTED: u128 = 1;
+    #[allow(missing_docs)]
+    pub const INVALID_CONFIG: u128 = 2;
+    #[allow(missing_docs)]
+    pub const INTERNAL_ERROR: u128 = 3;
+}
+
+/// Response to [ReloadConfigRequest]; the counters mirror [crate::reload::ReloadSummary]
+/// and are only set if the status is [reload_config_response_status::OK]
+#[repr(packed)]
+#[derive(Debug, Copy, Clone, Hash, AsBytes, FromBytes, FromZeroes, PartialEq, Eq)]
+pub struct ReloadConfigResponsePayload {
+    #[allow(missing_docs)]
+    pub status: u128,
+    #[allow(missing_docs)]
+    pub peers_added: u64,
+    #[allow(missing_docs)]
+    pub peers_removed: u64,
+    #[allow(missing_docs)]
+    pub peers_updated: u64,
+    #[allow(missing_docs)]
+    pub peers_replaced: u64,
+    #[allow(missing_docs)]
+    pub peers_unchanged: u64,
+    #[allow(missing_docs)]
+    pub listen_added: u64,
+    #[allow(missing_docs)]
+    pub listen_removed: u64,
+}
+
+#[allow(missing_docs)]
+pub type ReloadConfigResponse = ResponseEnvelope<ReloadConfigResponsePayload>;
+
+impl ReloadConfigResponse {
+    /// Construct a response without a summary; used for error statuses
+    pub fn new(status: u128) -> Self {
+        Self::from_payload(ReloadConfigResponsePayload {
+            status,
+            ..ReloadConfigResponsePayload::new_zeroed()
+        })
+    }
+}
+
+impl Message for ReloadConfigResponse {
+    type Payload = ReloadConfigResponsePayload;
+    type MessageClass = ResponseMsgType;
+    const MESSAGE_TYPE: Self::MessageClass = ResponseMsgType::ReloadConfig;
+
+    fn from_payload(payload: Self::Payload) -> Self {
+        Self {
+            msg_type: Self::MESSAGE_TYPE.into(),
+            payload,
+        }
+    }
+
+    fn setup<B: ByteSliceMut>(buf: B) -> anyhow::Result<Ref<B, Self>> {
+        let mut r: Ref<B, Self> = buf.zk_zeroized()?;
+        r.init();
+        Ok(r)
+    }
+
+    fn init(&mut self) {
+        self.msg_type = Self::MESSAGE_TYPE.into();
+    }
+}
+
+/// Values of [AddPeerRequestPayload::protocol_version]
+#[allow(missing_docs)]
+pub mod add_peer_protocol_version {
+    pub const V02: u64 = 2;
+    pub const V03: u64 = 3;
+}
+
+/// Request to add a peer; its public key and pre-shared key are passed as file descriptors
+///
+/// Text fields are UTF-8, padded with zero bytes; an empty field is not set.
+#[repr(packed)]
+#[derive(Debug, Copy, Clone, Hash, AsBytes, FromBytes, FromZeroes, PartialEq, Eq)]
+pub struct AddPeerRequestPayload {
+    /// Protocol version to use with the peer; see [add_peer_protocol_version]
+    pub protocol_version: u64,
+    /// Where to send handshakes to, as `host:port`; without it, rosenpass only responds
+    ///
+    /// Host names are looked up in the background, so lookup failures only show in the log.
+    pub endpoint: [u8; 256],
+    /// WireGuard device the exchanged keys are handed to through the PSK broker
+    pub wg_device: [u8; 16],
+    /// Public key of the peer on [Self::wg_device]
+    pub wg_peer: [u8; 32],
+    /// Index of the PSK broker to use for [Self::wg_device]; the first broker is zero
+    pub broker: u64,
+}
+
+#[allow(missing_docs)]
+pub type AddPeerRequest = RequestEnvelope<AddPeerRequestPayload>;
+
+impl AddPeerRequest {
+    /// Construct a request for a peer without endpoint and WireGuard device
+    pub fn new(protocol_version: crate::config::ProtocolVersion) -> Self {
+        use crate::config::ProtocolVersion as V;
+        let protocol_version = match protocol_version {
+            V::V02 => add_peer_protocol_version::V02,
+            V::V03 => add_peer_protocol_version::V03,
+        };
+        Self::from_payload(AddPeerRequestPayload {
+            protocol_version,
+            ..FromZeroes::new_zeroed()
+        })
+    }
+
+    /// Set [AddPeerRequestPayload::endpoint]
+    pub fn with_endpoint(mut self, endpoint: &str) -> anyhow::Result<Self> {
+        copy_text(&mut self.payload.endpoint, endpoint)?;
+        Ok(self)
+    }
+
+    /// Set [AddPeerRequestPayload::wg_device], [AddPeerRequestPayload::wg_peer] and
+    /// [AddPeerRequestPayload::broker]
+    pub fn with_wireguard(
+        mut self,
+        device: &str,
+        peer: [u8; 32],
+        broker: u64,
+    ) -> anyhow::Result<Self> {
+        copy_text(&mut self.payload.wg_device, device)?;
+        self.payload.wg_peer = peer;
+        self.payload.broker = broker;
+        Ok(self)
+    }
+}
+
+/// Write `text` into the zero padded field `dst`
+fn copy_text(dst: &mut [u8], text: &str) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        text.len() <= dst.len(),
+        "{text:?} is longer than {} bytes",
+        dst.len()
+    );
+    dst.fill(0);
+    dst[..text.len()].copy_from_slice(text.as_bytes());
+    Ok(())
+}
+
+/// Write `text` into the zero padded field `dst` of a response, truncating it at a character
+/// boundary if needed
+pub fn set_text_field(dst: &mut [u8], text: &str) {
+    let mut len = text.len().min(dst.len());
+    while !text.is_char_boundary(len) {
+        len -= 1;
+    }
+    dst.fill(0);
+    dst[..len].copy_from_slice(&text.as_bytes()[..len]);
+}
+
+impl Message for AddPeerRequest {
+    type Payload = AddPeerRequestPayload;
+    type MessageClass = RequestMsgType;
+    const MESSAGE_TYPE: Self::MessageClass = RequestMsgType::AddPeer;
+
+    fn from_payload(payload: Self::Payload) -> Self {
+        Self {
+            msg_type: Self::MESSAGE_TYPE.into(),
+            payload,
+        }
+    }
+
+    fn setup<B: ByteSliceMut>(buf: B) -> anyhow::Result<Ref<B, Self>> {
+        let mut r: Ref<B, Self> = buf.zk_zeroized()?;
+        r.init();
+        Ok(r)
+    }
+
+    fn init(&mut self) {
+        self.msg_type = Self::MESSAGE_TYPE.into();
+    }
+}
+
+#[allow(missing_docs)]
+pub mod add_peer_response_status {
+    #[allow(missing_docs)]
+    pub const OK: u128 = 0;
+    #[allow(missing_docs)]
+    pub const INVALID_REQUEST: u128 = 1;
+    #[allow(missing_docs)]
+    pub const PEER_ALREADY_EXISTS: u128 = 2;
+    #[allow(missing_docs)]
+    pub const INTERNAL_ERROR: u128 = 3;
+}
+
+/// Response to [AddPeerRequest]
+#[repr(packed)]
+#[derive(Debug, Copy, Clone, Hash, AsBytes, FromBytes, FromZeroes, PartialEq, Eq)]
+pub struct AddPeerResponsePayload {
+    #[allow(missing_docs)]
+    pub status: u128,
+    /// Id of the new peer, used e.g. in [RemovePeerRequest]; only set with
+    /// [add_peer_response_status::OK]
+    pub peer_id: u64,
+}
+
+#[allow(missing_docs)]
+pub type AddPeerResponse = ResponseEnvelope<AddPeerResponsePayload>;
+
+impl AddPeerResponse {
+    #[allow(missing_docs)]
+    pub fn new(status: u128, peer_id: u64) -> Self {
+        Self::from_payload(AddPeerResponsePayload { status, peer_id })
+    }
+}
+
+impl Message for AddPeerResponse {
+    type Payload = AddPeerResponsePayload;
+    type MessageClass = ResponseMsgType;
+    const MESSAGE_TYPE: Self::MessageClass = ResponseMsgType::AddPeer;
+
+    fn from_payload(payload: Self::Payload) -> Self {
+        Self {
+            msg_type: Self::MESSAGE_TYPE.into(),
+            payload,
+        }
+    }
+
+    fn setup<B: ByteSliceMut>(buf: B) -> anyhow::Result<Ref<B, Self>> {
+        let mut r: Ref<B, Self> = buf.zk_zeroized()?;
+        r.init();
+        Ok(r)
+    }
+
+    fn init(&mut self) {
+        self.msg_type = Self::MESSAGE_TYPE.into();
+    }
+}
+
+#[allow(missing_docs)]
+#[repr(packed)]
+#[derive(Debug, Copy, Clone, Hash, AsBytes, FromBytes, FromZeroes, PartialEq, Eq)]
+pub struct RemovePeerRequestPayload {
+    /// Id of the peer, as returned in [AddPeerResponsePayload::peer_id]
+    pub peer_id: u64,
+}
+
+#[allow(missing_docs)]
+pub type RemovePeerRequest = RequestEnvelope<RemovePeerRequestPayload>;
+
+impl RemovePeerRequest {
+    #[allow(missing_docs)]
+    pub fn new(peer_id: u64) -> Self {
+        Self::from_payload(RemovePeerRequestPayload { peer_id })
+    }
+}
+
+impl Message for RemovePeerRequest {
+    type Payload = RemovePeerRequestPayload;
+    type MessageClass = RequestMsgType;
+    const MESSAGE_TYPE: Self::MessageClass = RequestMsgType::RemovePeer;
+
+    fn from_payload(payload: Self::Payload) -> Self {
+        Self {
+            msg_type: Self::MESSAGE_TYPE.into(),
+            payload,
+        }
+    }
+
+    fn setup<B: ByteSliceMut>(buf: B) -> anyhow::Result<Ref<B, Self>> {
+        let mut r: Ref<B, Self> = buf.zk_zeroized()?;
+        r.init();
+        Ok(r)
+    }
+
+    fn init(&mut self) {
+        self.msg_type = Self::MESSAGE_TYPE.into();
+    }
+}
+
+#[allow(missing_docs)]
+pub mod remove_peer_response_status {
+    #[allow(missing_docs)]
+    pub const OK: u128 = 0;
+    #[allow(missing_docs)]
+    pub const NO_SUCH_PEER: u128 = 1;
+    #[allow(missing_docs)]
+    pub const KEYPAIR_MISSING: u128 = 2;
+    #[allow(missing_docs)]
+    pub const INTERNAL_ERROR: u128 = 3;
+}
+
+#[allow(missing_docs)]
+#[repr(packed)]
+#[derive(Debug, Copy, Clone, Hash, AsBytes, FromBytes, FromZeroes, PartialEq, Eq)]
+pub struct RemovePeerResponsePayload {
+    #[allow(missing_docs)]
+    pub status: u128,
+}
+
+#[allow(missing_docs)]
+pub type RemovePeerResponse = ResponseEnvelope<RemovePeerResponsePayload>;
+
+impl RemovePeerResponse {
+    #[allow(missing_docs)]
+    pub fn new(status: u128) -> Self {
+        Self::from_payload(RemovePeerResponsePayload { status })
+    }
+}
+
+impl Message for RemovePeerResponse {
+    type Payload = RemovePeerResponsePayload;
+    type MessageClass = ResponseMsgType;
+    const MESSAGE_TYPE: Self::MessageClass = ResponseMsgType::RemovePeer;
+
+    fn from_payload(payload: Self::Payload) -> Self {
+        Self {
+            msg_type: Self::MESSAGE_TYPE.into(),
+            payload,
+        }
+    }
+
+    fn setup<B: ByteSliceMut>(buf: B) -> anyhow::Result<Ref<B, Self>> {
+        let mut r: Ref<B, Self> = buf.zk_zeroized()?;
+        r.init();
+        Ok(r)
+    }
+
+    fn init(&mut self) {
+        self.msg_type = Self::MESSAGE_TYPE.into();
+    }
+}
+
+/// Number of peer ids in one [ListPeersResponse]
+pub const LIST_PEERS_PAGE_LEN: usize = 256;
+
+#[allow(missing_docs)]
+#[repr(packed)]
+#[derive(Debug, Copy, Clone, Hash, AsBytes, FromBytes, FromZeroes, PartialEq, Eq)]
+pub struct ListPeersRequestPayload {
+    /// How many peers to skip; for listing more than [LIST_PEERS_PAGE_LEN] peers
+    pub offset: u64,
+}
+
+#[allow(missing_docs)]
+pub type ListPeersRequest = RequestEnvelope<ListPeersRequestPayload>;
+
+impl ListPeersRequest {
+    #[allow(missing_docs)]
+    pub fn new(offset: u64) -> Self {
+        Self::from_payload(ListPeersRequestPayload { offset })
+    }
+}
+
+impl Message for ListPeersRequest {
+    type Payload = ListPeersRequestPayload;
+    type MessageClass = RequestMsgType;
+    const MESSAGE_TYPE: Self::MessageClass = RequestMsgType::ListPeers;
+
+    fn from_payload(payload: Self::Payload) -> Self {
+        Self {
+            msg_type: Self::MESSAGE_TYPE.into(),
+            payload,
+        }
+    }
+
+    fn setup<B: ByteSliceMut>(buf: B) -> anyhow::Result<Ref<B, Self>> {
+        let mut r: Ref<B, Self> = buf.zk_zeroized()?;
+        r.init();
+        Ok(r)
+    }
+
+    fn init(&mut self) {
+        self.msg_type = Self::MESSAGE_TYPE.into();
+    }
+}
+
+#[allow(missing_docs)]
+pub mod list_peers_response_status {
+    #[allow(missing_docs)]
+    pub const OK: u128 = 0;
+}
+
+/// Response to [ListPeersRequest]
+#[repr(packed)]
+#[derive(Debug, Copy, Clone, Hash, AsBytes, FromBytes, FromZeroes, PartialEq, Eq)]
+pub struct ListPeersResponsePayload {
+    #[allow(missing_docs)]
+    pub status: u128,
+    /// Number of peers on the server
+    pub total: u64,
+    /// Number of valid entries in [Self::peer_ids]
+    pub count: u64,
+    /// Ids of the peers, starting at [ListPeersRequestPayload::offset]
+    pub peer_ids: [u64; LIST_PEERS_PAGE_LEN],
+}
+
+#[allow(missing_docs)]
+pub type ListPeersResponse = ResponseEnvelope<ListPeersResponsePayload>;
+
+impl ListPeersResponse {
+    /// The valid peer ids in this response
+    pub fn peer_ids(&self) -> Vec<u64> {
+        let (ids, count) = (self.payload.peer_ids, self.payload.count);
+        ids.into_iter()
+            .take(count.min(LIST_PEERS_PAGE_LEN as u64) as usize)
+            .collect()
+    }
+}
+
+impl Message for ListPeersResponse {
+    type Payload = ListPeersResponsePayload;
+    type MessageClass = ResponseMsgType;
+    const MESSAGE_TYPE: Self::MessageClass = ResponseMsgType::ListPeers;
+
+    fn from_payload(payload: Self::Payload) -> Self {
+        Self {
+            msg_type: Self::MESSAGE_TYPE.into(),
+            payload,
+        }
+    }
+
+    fn setup<B: ByteSliceMut>(buf: B) -> anyhow::Result<Ref<B, Self>> {
+        let mut r: Ref<B, Self> = buf.zk_zeroized()?;
+        r.init();
+        Ok(r)
+    }
+
+    fn init(&mut self) {
+        self.msg_type = Self::MESSAGE_TYPE.into();
+    }
+}
+
+#[allow(missing_docs)]
+#[repr(packed)]
+#[derive(Debug, Copy, Clone, Hash, AsBytes, FromBytes, FromZeroes, PartialEq, Eq)]
+pub struct GetPeerStatusRequestPayload {
+    /// Id of the peer, as returned in [ListPeersResponsePayload::peer_ids]
+    pub peer_id: u64,
+}
+
+#[allow(missing_docs)]
+pub type GetPeerStatusRequest = RequestEnvelope<GetPeerStatusRequestPayload>;
+
+impl GetPeerStatusRequest {
+    #[allow(missing_docs)]
+    pub fn new(peer_id: u64) -> Self {
+        Self::from_payload(GetPeerStatusRequestPayload { peer_id })
+    }
+}
+
+impl Message for GetPeerStatusRequest {
+    type Payload = GetPeerStatusRequestPayload;
+    type MessageClass = RequestMsgType;
+    const MESSAGE_TYPE: Self::MessageClass = RequestMsgType::GetPeerStatus;
+
+    fn from_payload(payload: Self::Payload) -> Self {
+        Self {
+            msg_type: Self::MESSAGE_TYPE.into(),
+            payload,
+        }
+    }
+
+    fn setup<B: ByteSliceMut>(buf: B) -> anyhow::Result<Ref<B, Self>> {
+        let mut r: Ref<B, Self> = buf.zk_zeroized()?;
+        r.init();
+        Ok(r)
+    }
+
+    fn init(&mut self) {
+        self.msg_type = Self::MESSAGE_TYPE.into();
+    }
+}
+
+#[allow(missing_docs)]
+pub mod get_peer_status_response_status {
+    #[allow(missing_docs)]
+    pub const OK: u128 = 0;
+    #[allow(missing_docs)]
+    pub const NO_SUCH_PEER: u128 = 1;
+}
+
+/// Value of the durations in [GetPeerStatusResponsePayload] for things that did not happen
+pub const PEER_STATUS_NEVER: u64 = u64::MAX;
+
+/// Response to [GetPeerStatusRequest]; mirrors [crate::app_server::PeerStatus]
+///
+/// Text fields are UTF-8, padded with zero bytes and truncated if they do not fit. Durations are
+/// in milliseconds.
+#[repr(packed)]
+#[derive(Debug, Copy, Clone, Hash, AsBytes, FromBytes, FromZeroes, PartialEq, Eq)]
+pub struct GetPeerStatusResponsePayload {
+    #[allow(missing_docs)]
+    pub status: u128,
+    #[allow(missing_docs)]
+    pub peer_id: u64,
+    /// See [add_peer_protocol_version]
+    pub protocol_version: u64,
+    #[allow(missing_docs)]
+    pub name: [u8; 64],
+    #[allow(missing_docs)]
+    pub configured_endpoint: [u8; 128],
+    #[allow(missing_docs)]
+    pub current_endpoint: [u8; 128],
+    /// Age of the current session or [PEER_STATUS_NEVER] without one
+    pub session_age_ms: u64,
+    /// Time since the last key exchange or [PEER_STATUS_NEVER]
+    pub since_key_exchange_ms: u64,
+    /// One if we initiated a handshake that has not completed yet
+    pub handshake_in_progress: u8,
+    #[allow(missing_docs)]
+    pub retransmissions: u64,
+    #[allow(missing_docs)]
+    pub broker_failures: u64,
+    #[allow(missing_docs)]
+    pub keys_stale: u64,
+}
+
+#[allow(missing_docs)]
+pub type GetPeerStatusResponse = ResponseEnvelope<GetPeerStatusResponsePayload>;
+
+impl GetPeerStatusResponse {
+    /// Construct a response without a peer status; used for error statuses
+    pub fn new(status: u128) -> Self {
+        Self::from_payload(GetPeerStatusResponsePayload {
+            status,
+            ..FromZeroes::new_zeroed()
+        })
+    }
+}
+
+impl Message for GetPeerStatusResponse {
+    type Payload = GetPeerStatusResponsePayload;
+    type MessageClass = ResponseMsgType;
+    const MESSAGE_TYPE: Self::MessageClass = ResponseMsgType::GetPeerStatus;
+
+    fn from_payload(payload: Self::Payload) -> Self {
+        Self {
+            msg_type: Self::MESSAGE_TYPE.into(),
+            payload,
+        }
+    }
+
+    fn setup<B: ByteSliceMut>(buf: B) -> anyhow::Result<Ref<B, Self>> {
+        let mut r: Ref<B, Self> = buf.zk_zeroized()?;
+        r.init();
+        Ok(r)
+    }
+
+    fn init(&mut self) {
+        self.msg_type = Self::MESSAGE_TYPE.into();
+    }
+}
+
+#[allow(missing_docs)]
+#[repr(packed)]
+#[derive(Debug, Copy, Clone, Hash, AsBytes, FromBytes, FromZeroes, PartialEq, Eq)]
+pub struct SubscribeEventsRequestPayload {}
+
+#[allow(missing_docs)]
+pub type SubscribeEventsRequest = RequestEnvelope<SubscribeEventsRequestPayload>;
+
+impl Default for SubscribeEventsRequest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SubscribeEventsRequest {
+    #[allow(missing_docs)]
+    pub fn new() -> Self {
+        Self::from_payload(SubscribeEventsRequestPayload {})
+    }
+}
+
+impl Message for SubscribeEventsRequest {
+    type Payload = SubscribeEventsRequestPayload;
+    type MessageClass = RequestMsgType;
+    const MESSAGE_TYPE: Self::MessageClass = RequestMsgType::SubscribeEvents;
+
+    fn from_payload(payload: Self::Payload) -> Self {
+        Self {
+            msg_type: Self::MESSAGE_TYPE.into(),
+            payload,
+        }
+    }
+
+    fn setup<B: ByteSliceMut>(buf: B) -> anyhow::Result<Ref<B, Self>> {
+        let mut r: Ref<B, Self> = buf.zk_zeroized()?;
+        r.init();
+        Ok(r)
+    }
+
+    fn init(&mut self) {
+        self.msg_type = Self::MESSAGE_TYPE.into();
+    }
+}
+
+#[allow(missing_docs)]
+pub mod subscribe_events_response_status {
+    #[allow(missing_docs)]
+    pub const OK: u128 = 0;
+}
+
+/// Values of [SubscribeEventsResponsePayload::event]
+pub mod subscribe_events_event_kind {
+    /// The answer to the [super::SubscribeEventsRequest] itself; no event happened
+    pub const SUBSCRIBED: u64 = 0;
+    /// A key was exchanged with the peer
+    pub const KEY_EXCHANGED: u64 = 1;
+    /// The key of the peer went stale and was erased
+    pub const KEY_STALE: u64 = 2;
+    /// A handshake message from the endpoint could not be processed; sent at most once per second
+    pub const HANDSHAKE_FAILED: u64 = 3;
+    /// The peer is now reached through a different endpoint
+    pub const ENDPOINT_CHANGED: u64 = 4;
+    /// The server entered or left the under-load state; see
+    /// [SubscribeEventsResponsePayload::under_load]
+    pub const UNDER_LOAD_CHANGED: u64 = 5;
+}
+
+/// Value of [SubscribeEventsResponsePayload::peer_id] for events that do not concern a
+/// particular peer
+pub const EVENT_NO_PEER: u64 = u64::MAX;
+
+/// Response to [SubscribeEventsRequest] and every event pushed on the connection afterwards
+#[repr(packed)]
+#[derive(Debug, Copy, Clone, Hash, AsBytes, FromBytes, FromZeroes, PartialEq, Eq)]
+pub struct SubscribeEventsResponsePayload {
+    #[allow(missing_docs)]
+    pub status: u128,
+    /// What happened; one of the constants in [subscribe_events_event_kind]
+    pub event: u64,
+    /// The peer the event concerns or [EVENT_NO_PEER]
+    pub peer_id: u64,
+    /// Number of events dropped right before this one because the client did not read them
+    /// quickly enough
+    pub dropped: u64,
+    /// Whether the server is currently under load
+    pub under_load: u8,
+    /// Endpoint of the peer for [subscribe_events_event_kind::ENDPOINT_CHANGED] and
+    /// [subscribe_events_event_kind::HANDSHAKE_FAILED]; UTF-8, padded with zero bytes
+    pub endpoint: [u8; 128],
+}
+
+#[allow(missing_docs)]
+pub type SubscribeEventsResponse = ResponseEnvelope<SubscribeEventsResponsePayload>;
+
+impl SubscribeEventsResponsePayload {
+    /// Set [Self::endpoint], truncating `text` at a character boundary if needed
+    pub fn set_endpoint(&mut self, text: &str) {
+        set_text_field(&mut self.endpoint, text);
+    }
+}
+
+impl Message for SubscribeEventsResponse {
+    type Payload = SubscribeEventsResponsePayload;
+    type MessageClass = ResponseMsgType;
+    const MESSAGE_TYPE: Self::MessageClass = ResponseMsgType::SubscribeEvents;
+
+    fn from_payload(payload: Self::Payload) -> Self {
+        Self {
+            msg_type: Self::MESSAGE_TYPE.into(),
+            payload,
+        }
+    }
+
+    fn setup<B: ByteSliceMut>(buf: B) -> anyhow::Result<Ref<B, Self>> {
+        let mut r: Ref<B, Self> = buf.zk_zeroized()?;
+        r.init();
+        Ok(r)
+    }
+
+    fn init(&mut self) {
+        self.msg_type = Self::MESSAGE_TYPE.into();
+    }
+}
+
+#[allow(missing_docs)]
+#[repr(packed)]
+#[derive(Debug, Copy, Clone, Hash, AsBytes, FromBytes, FromZeroes, PartialEq, Eq)]
+pub struct RegisterKeySinkRequestPayload {
+    /// Id of the peer whose keys should be sent, as returned in
+    /// [ListPeersResponsePayload::peer_ids]
+    pub peer_id: u64,
+}
+
+#[allow(missing_docs)]
+pub type RegisterKeySinkRequest = RequestEnvelope<RegisterKeySinkRequestPayload>;
+
+impl RegisterKeySinkRequest {
+    #[allow(missing_docs)]
+    pub fn new(peer_id: u64) -> Self {
+        Self::from_payload(RegisterKeySinkRequestPayload { peer_id })
+    }
+}
+
+impl Message for RegisterKeySinkRequest {
+    type Payload = RegisterKeySinkRequestPayload;
+    type MessageClass = RequestMsgType;
+    const MESSAGE_TYPE: Self::MessageClass = RequestMsgType::RegisterKeySink;
+
+    fn from_payload(payload: Self::Payload) -> Self {
+        Self {
+            msg_type: Self::MESSAGE_TYPE.into(),
+            payload,
+        }
+    }
+
+    fn setup<B: ByteSliceMut>(buf: B) -> anyhow::Result<Ref<B, Self>> {
+        let mut r: Ref<B, Self> = buf.zk_zeroized()?;
+        r.init();
+        Ok(r)
+    }
+
+    fn init(&mut self) {
+        self.msg_type = Self::MESSAGE_TYPE.into();
+    }
+}
+
+#[allow(missing_docs)]
+pub mod register_key_sink_response_status {
+    #[allow(missing_docs)]
+    pub const OK: u128 = 0;
+    #[allow(missing_docs)]
+    pub const NO_SUCH_PEER: u128 = 1;
+}
+
+/// Values of [RegisterKeySinkResponsePayload::reason]
+pub mod key_sink_reason {
+    /// The answer to the [super::RegisterKeySinkRequest] itself; no key is attached
+    pub const REGISTERED: u64 = 0;
+    /// A key was exchanged with the peer
+    pub const EXCHANGED: u64 = 1;
+    /// No key could be exchanged in time; the attached key is random and replaces the stale one
+    pub const STALE: u64 = 2;
+}
+
+/// Response to [RegisterKeySinkRequest] and every key sent on the connection afterwards
+#[repr(packed)]
+#[derive(Debug, Copy, Clone, Hash, AsBytes, FromBytes, FromZeroes, PartialEq, Eq)]
+pub struct RegisterKeySinkResponsePayload {
+    #[allow(missing_docs)]
+    pub status: u128,
+    /// The peer the key belongs to
+    pub peer_id: u64,
+    /// Why the key was output; one of the constants in [key_sink_reason]
+    pub reason: u64,
+    /// Number of keys dropped right before this one because the client did not read them
+    /// quickly enough
+    pub dropped: u64,
+}
+
+#[allow(missing_docs)]
+pub type RegisterKeySinkResponse = ResponseEnvelope<RegisterKeySinkResponsePayload>;
+
+impl RegisterKeySinkResponse {
+    #[allow(missing_docs)]
+    pub fn new(status: u128, peer_id: u64, reason: u64) -> Self {
+        Self::from_payload(RegisterKeySinkResponsePayload {
+            status,
+            peer_id,
+            reason,
+            dropped: 0,
+        })
+    }
+}
+
+impl Message for RegisterKeySinkResponse {
+    type Payload = RegisterKeySinkResponsePayload;
+    type MessageClass = ResponseMsgType;
+    const MESSAGE_TYPE: Self::MessageClass = ResponseMsgType::RegisterKeySink;
+
+    fn from_payload(payload: Self::Payload) -> Self {
+        Self {
+            msg_type: Self::MESSAGE_TYPE.into(),
+            payload,
+        }
+    }
+
+    fn setup<B: ByteSliceMut>(buf: B) -> anyhow::Result<Ref<B, Self>> {
+        let mut r: Ref<B, Self> = buf.zk_zeroized()?;
+        r.init();
+        Ok(r)
+    }
+
+    fn init(&mut self) {
+        self.msg_type = Self::MESSAGE_TYPE.into();
+    }
+}
+
+#[allow(missing_docs)]
+#[repr(packed)]
+#[derive(Debug, Copy, Clone, Hash, AsBytes, FromBytes, FromZeroes, PartialEq, Eq)]
+pub struct RekeyRequestPayload {
+    /// Id of the peer to exchange a key with, as returned in
+    /// [ListPeersResponsePayload::peer_ids]
+    pub peer_id: u64,
+    /// How long to wait for the key exchange before responding, in milliseconds; zero to
+    /// respond right away. The server waits for an hour at most.
+    pub wait_ms: u64,
+}
+
+#[allow(missing_docs)]
+pub type RekeyRequest = RequestEnvelope<RekeyRequestPayload>;
+
+impl RekeyRequest {
+    #[allow(missing_docs)]
+    pub fn new(peer_id: u64, wait_ms: u64) -> Self {
+        Self::from_payload(RekeyRequestPayload { peer_id, wait_ms })
+    }
+}
+
+impl Message for RekeyRequest {
+    type Payload = RekeyRequestPayload;
+    type MessageClass = RequestMsgType;
+    const MESSAGE_TYPE: Self::MessageClass = RequestMsgType::Rekey;
+
+    fn from_payload(payload: Self::Payload) -> Self {
+        Self {
+            msg_type: Self::MESSAGE_TYPE.into(),
+            payload,
+        }
+    }
+
+    fn setup<B: ByteSliceMut>(buf: B) -> anyhow::Result<Ref<B, Self>> {
+        let mut r: Ref<B, Self> = buf.zk_zeroized()?;
+        r.init();
+        Ok(r)
+    }
+
+    fn init(&mut self) {
+        self.msg_type = Self::MESSAGE_TYPE.into();
+    }
+}
+
+#[allow(missing_docs)]
+pub mod rekey_response_status {
+    #[allow(missing_docs)]
+    pub const OK: u128 = 0;
+    #[allow(missing_docs)]
+    pub const NO_SUCH_PEER: u128 = 1;
+    /// The peer has no endpoint, so the server can not initiate a handshake with it
+    pub const NO_ENDPOINT: u128 = 2;
+    #[allow(missing_docs)]
+    pub const KEYPAIR_MISSING: u128 = 3;
+    /// No key was exchanged within [super::RekeyRequestPayload::wait_ms]; the server keeps
+    /// trying
+    pub const TIMEOUT: u128 = 4;
+}
+
+/// Response to [RekeyRequest]
+#[repr(packed)]
+#[derive(Debug, Copy, Clone, Hash, AsBytes, FromBytes, FromZeroes, PartialEq, Eq)]
+pub struct RekeyResponsePayload {
+    #[allow(missing_docs)]
+    pub status: u128,
+    /// The peer from the request
+    pub peer_id: u64,
+}
+
+#[allow(missing_docs)]
+pub type RekeyResponse = ResponseEnvelope<RekeyResponsePayload>;
+
+impl RekeyResponse {
+    #[allow(missing_docs)]
+    pub fn new(status: u128, peer_id: u64) -> Self {
+        Self::from_payload(RekeyResponsePayload { status, peer_id })
+    }
+}
+
+impl Message for RekeyResponse {
+    type Payload = RekeyResponsePayload;
+    type MessageClass = ResponseMsgType;
+    const MESSAGE_TYPE: Self::MessageClass = ResponseMsgType::Rekey;
+
+    fn from_payload(payload: Self::Payload) -> Self {
+        Self {
+            msg_type: Self::MESSAGE_TYPE.into(),
+            payload,
+        }
+    }
+
+    fn setup<B: ByteSliceMut>(buf: B) -> anyhow::Result<Ref<B, Self>> {
+        let mut r: Ref<B, Self> = buf.zk_zeroized()?;
+        r.init();
+        Ok(r)
+    }
+
+    fn init(&mut self) {
+        self.msg_type = Self::MESSAGE_TYPE.into();
+    }
+}
+
+#[allow(missing_docs)]
+pub mod shutdown_key_policy {
+    /// Do with the exported keys what the `on_shutdown` option of the configuration says
+    pub const CONFIGURED: u64 = 0;
+    /// Leave the exported keys in place
+    pub const KEEP: u64 = 1;
+    /// Overwrite the exported keys with random ones; see [crate::app_server::AppServer::shutdown]
+    pub const RETIRE: u64 = 2;
+}
+
+#[allow(missing_docs)]
+#[repr(packed)]
+#[derive(Debug, Copy, Clone, Hash, AsBytes, FromBytes, FromZeroes, PartialEq, Eq)]
+pub struct ShutdownRequestPayload {
+    /// What to do with the exported keys; see [shutdown_key_policy]
+    pub key_policy: u64,
+}
+
+#[allow(missing_docs)]
+pub type ShutdownRequest = RequestEnvelope<ShutdownRequestPayload>;
+
+impl ShutdownRequest {
+    #[allow(missing_docs)]
+    pub fn new(key_policy: u64) -> Self {
+        Self::from_payload(ShutdownRequestPayload { key_policy })
+    }
+}
+
+impl Message for ShutdownRequest {
+    type Payload = ShutdownRequestPayload;
+    type MessageClass = RequestMsgType;
+    const MESSAGE_TYPE: Self::MessageClass = RequestMsgType::Shutdown;
+
+    fn from_payload(payload: Self::Payload) -> Self {
+        Self {
+            msg_type: Self::MESSAGE_TYPE.into(),
+            payload,
+        }
+    }
+
+    fn setup<B: ByteSliceMut>(buf: B) -> anyhow::Result<Ref<B, Self>> {
+        let mut r: Ref<B, Self> = buf.zk_zeroized()?;
+        r.init();
+        Ok(r)
+    }
+
+    fn init(&mut self) {
+        self.msg_type = Self::MESSAGE_TYPE.into();
+    }
+}
+
+#[allow(missing_docs)]
+pub mod shutdown_response_status {
+    #[allow(missing_docs)]
+    pub const OK: u128 = 0;
+    /// The request named an unknown [super::shutdown_key_policy]
+    pub const INVALID_REQUEST: u128 = 1;
+}
+
+/// Response to [ShutdownRequest]
+#[repr(packed)]
+#[derive(Debug, Copy, Clone, Hash, AsBytes, FromBytes, FromZeroes, PartialEq, Eq)]
+pub struct ShutdownResponsePayload {
+    #[allow(missing_docs)]
+    pub status: u128,
+}
+
+#[allow(missing_docs)]
+pub type ShutdownResponse = ResponseEnvelope<ShutdownResponsePayload>;
+
+impl ShutdownResponse {
+    #[allow(missing_docs)]
+    pub fn new(status: u128) -> Self {
+        Self::from_payload(ShutdownResponsePayload { status })
+    }
+}
+
+impl Message for ShutdownResponse {
+    type Payload = ShutdownResponsePayload;
+    type MessageClass = ResponseMsgType;
+    const MESSAGE_TYPE: Self::MessageClass = ResponseMsgType::Shutdown;
+
+    fn from_payload(payload: Self::Payload) -> Self {
+        Self {
+            msg_type: Self::MESSAGE_TYPE.into(),
+            payload,
+        }
+    }
+
+    fn setup<B: ByteSliceMut>(buf: B) -> anyhow::Result<Ref<B, Self>> {
+        let mut r: Ref<B, Self> = buf.zk_zeroized()?;
+        r.init();
+        Ok(r)
+    }
+
+    fn init(&mut self) {
+        self.msg_type = Self::MESSAGE_TYPE.into();
+    }
+}
+
+#[allow(missing_docs)]
+#[repr(packed)]
+#[derive(Debug, Copy, Clone, Hash, AsBytes, FromBytes, FromZeroes, PartialEq, Eq)]
+pub struct VersionRequestPayload {}
+
+#[allow(missing_docs)]
+pub type VersionRequest = RequestEnvelope<VersionRequestPayload>;
+
+impl Default for VersionRequest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VersionRequest {
+    #[allow(missing_docs)]
+    pub fn new() -> Self {
+        Self::from_payload(VersionRequestPayload {})
+    }
+}
+
+impl Message for VersionRequest {
+    type Payload = VersionRequestPayload;
+    type MessageClass = RequestMsgType;
+    const MESSAGE_TYPE: Self::MessageClass = RequestMsgType::Version;
+
+    fn from_payload(payload: Self::Payload) -> Self {
+        Self {
+            msg_type: Self::MESSAGE_TYPE.into(),
+            payload,
+        }
+    }
+
+    fn setup<B: ByteSliceMut>(buf: B) -> anyhow::Result<Ref<B, Self>> {
+        let mut r: Ref<B, Self> = buf.zk_zeroized()?;
+        r.init();
+        Ok(r)
+    }
+
+    fn init(&mut self) {
+        self.msg_type = Self::MESSAGE_TYPE.into();
+    }
+}
+
+#[allow(missing_docs)]
+pub mod version_response_status {
+    #[allow(missing_docs)]
+    pub const OK: u128 = 0;
+}
+
+/// Cargo features the server was built with; bits of [super::VersionResponsePayload::features]
+pub mod version_features {
+    /// `experiment_api`
+    pub const EXPERIMENT_API: u64 = 1 << 0;
+    /// `experiment_cookie_dos_mitigation`
+    pub const EXPERIMENT_COOKIE_DOS_MITIGATION: u64 = 1 << 1;
+    /// `experiment_memfd_secret`
+    pub const EXPERIMENT_MEMFD_SECRET: u64 = 1 << 2;
+    /// `experiment_libcrux_blake2`, also set by `experiment_libcrux_all`
+    pub const EXPERIMENT_LIBCRUX_BLAKE2: u64 = 1 << 3;
+    /// `experiment_libcrux_chachapoly`, also set by `experiment_libcrux_all`
+    pub const EXPERIMENT_LIBCRUX_CHACHAPOLY: u64 = 1 << 4;
+    /// `experiment_libcrux_kyber`, also set by `experiment_libcrux_all`
+    pub const EXPERIMENT_LIBCRUX_KYBER: u64 = 1 << 5;
+}
+
+/// Response to [VersionRequest]
+///
+/// Text fields are UTF-8, padded with zero bytes.
+#[repr(packed)]
+#[derive(Debug, Copy, Clone, Hash, AsBytes, FromBytes, FromZeroes, PartialEq, Eq)]
+pub struct VersionResponsePayload {
+    #[allow(missing_docs)]
+    pub status: u128,
+    /// Version of the rosenpass crate the server was built from
+    pub version: [u8; 32],
+    /// Enabled cargo features; see [version_features]
+    pub features: u64,
+    /// Supported protocol versions; bit `n` is set if the server supports version `n` from
+    /// [add_peer_protocol_version]
+    pub protocol_versions: u64,
+}
+
+#[allow(missing_docs)]
+pub type VersionResponse = ResponseEnvelope<VersionResponsePayload>;
+
+impl VersionResponsePayload {
+    /// The crate version from [Self::version]
+    pub fn version_str(&self) -> anyhow::Result<&str> {
+        let len = self.version.iter().position(|&b| b == 0);
+        let bytes = &self.version[..len.unwrap_or(self.version.len())];
+        Ok(std::str::from_utf8(bytes)?)
+    }
+
+    /// Whether the server supports the protocol version `v` from [add_peer_protocol_version]
+    pub fn supports_protocol_version(&self, v: u64) -> bool {
+        v < 64 && self.protocol_versions & (1 << v) != 0
+    }
+}
+
+impl Message for VersionResponse {
+    type Payload = VersionResponsePayload;
+    type MessageClass = ResponseMsgType;
+    const MESSAGE_TYPE: Self::MessageClass = ResponseMsgType::Version;
+
+    fn from_payload(payload: Self::Payload) -> Self {
+        Self {
+            msg_type: Self::MESSAGE_TYPE.into(),
+            payload,
+        }
+    }
+
+    fn setup<B: ByteSliceMut>(buf: B) -> anyhow::Result<Ref<B, Self>> {
+        let mut r: Ref<B, Self> = buf.zk_zeroized()?;
+        r.init();
+        Ok(r)
+    }
+
+    fn init(&mut self) {
+        self.msg_type = Self::MESSAGE_TYPE.into();
+    }
+}
@@ -67,6 +67,16 @@ impl<B: ByteSlice> RequestRef<B> {
             Self::SupplyKeypair(_) => RequestMsgType::SupplyKeypair,
             Self::AddListenSocket(_) => RequestMsgType::AddListenSocket,
             Self::AddPskBroker(_) => RequestMsgType::AddPskBroker,
+            Self::ReloadConfig(_) => RequestMsgType::ReloadConfig,
+            Self::AddPeer(_) => RequestMsgType::AddPeer,
+            Self::RemovePeer(_) => RequestMsgType::RemovePeer,
+            Self::ListPeers(_) => RequestMsgType::ListPeers,
+            Self::GetPeerStatus(_) => RequestMsgType::GetPeerStatus,
+            Self::SubscribeEvents(_) => RequestMsgType::SubscribeEvents,
+            Self::RegisterKeySink(_) => RequestMsgType::RegisterKeySink,
+            Self::Rekey(_) => RequestMsgType::Rekey,
+            Self::Shutdown(_) => RequestMsgType::Shutdown,
+            Self::Version(_) => RequestMsgType::Version,
         }
     }
 }
@@ -95,6 +105,66 @@ impl<B> From<Ref<B, super::AddPskBrokerRequest>> for RequestRef<B> {
     }
 }
 
+impl<B> From<Ref<B, super::ReloadConfigRequest>> for RequestRef<B> {
+    fn from(v: Ref<B, super::ReloadConfigRequest>) -> Self {
+        Self::ReloadConfig(v)
+    }
+}
+
+impl<B> From<Ref<B, super::AddPeerRequest>> for RequestRef<B> {
+    fn from(v: Ref<B, super::AddPeerRequest>) -> Self {
+        Self::AddPeer(v)
+    }
+}
+
+impl<B> From<Ref<B, super::RemovePeerRequest>> for RequestRef<B> {
+    fn from(v: Ref<B, super::RemovePeerRequest>) -> Self {
+        Self::RemovePeer(v)
+    }
+}
+
+impl<B> From<Ref<B, super::ListPeersRequest>> for RequestRef<B> {
+    fn from(v: Ref<B, super::ListPeersRequest>) -> Self {
+        Self::ListPeers(v)
+    }
+}
+
+impl<B> From<Ref<B, super::GetPeerStatusRequest>> for RequestRef<B> {
+    fn from(v: Ref<B, super::GetPeerStatusRequest>) -> Self {
+        Self::GetPeerStatus(v)
+    }
+}
+
+impl<B> From<Ref<B, super::SubscribeEventsRequest>> for RequestRef<B> {
+    fn from(v: Ref<B, super::SubscribeEventsRequest>) -> Self {
+        Self::SubscribeEvents(v)
+    }
+}
+
+impl<B> From<Ref<B, super::RegisterKeySinkRequest>> for RequestRef<B> {
+    fn from(v: Ref<B, super::RegisterKeySinkRequest>) -> Self {
+        Self::RegisterKeySink(v)
+    }
+}
+
+impl<B> From<Ref<B, super::RekeyRequest>> for RequestRef<B> {
+    fn from(v: Ref<B, super::RekeyRequest>) -> Self {
+        Self::Rekey(v)
+    }
+}
+
+impl<B> From<Ref<B, super::ShutdownRequest>> for RequestRef<B> {
+    fn from(v: Ref<B, super::ShutdownRequest>) -> Self {
+        Self::Shutdown(v)
+    }
+}
+
+impl<B> From<Ref<B, super::VersionRequest>> for RequestRef<B> {
+    fn from(v: Ref<B, super::VersionRequest>) -> Self {
+        Self::Version(v)
+    }
+}
+
 impl<B: ByteSlice> RequestRefMaker<B> {
     fn new(buf: B) -> anyhow::Result<Self> {
         let msg_type = buf.deref().request_msg_type_from_prefix()?;
@@ -117,6 +187,24 @@ impl<B: ByteSlice> RequestRefMaker<B> {
             RequestMsgType::AddPskBroker => {
                 RequestRef::AddPskBroker(self.buf.add_psk_broker_request()?)
             }
+            RequestMsgType::ReloadConfig => {
+                RequestRef::ReloadConfig(self.buf.reload_config_request()?)
+            }
+            RequestMsgType::AddPeer => RequestRef::AddPeer(self.buf.add_peer_request()?),
+            RequestMsgType::RemovePeer => RequestRef::RemovePeer(self.buf.remove_peer_request()?),
+            RequestMsgType::ListPeers => RequestRef::ListPeers(self.buf.list_peers_request()?),
+            RequestMsgType::GetPeerStatus => {
+                RequestRef::GetPeerStatus(self.buf.get_peer_status_request()?)
+            }
+            RequestMsgType::SubscribeEvents => {
+                RequestRef::SubscribeEvents(self.buf.subscribe_events_request()?)
+            }
+            RequestMsgType::RegisterKeySink => {
+                RequestRef::RegisterKeySink(self.buf.register_key_sink_request()?)
+            }
+            RequestMsgType::Rekey => RequestRef::Rekey(self.buf.rekey_request()?),
+            RequestMsgType::Shutdown => RequestRef::Shutdown(self.buf.shutdown_request()?),
+            RequestMsgType::Version => RequestRef::Version(self.buf.version_request()?),
         })
     }
 
@@ -155,6 +243,16 @@ pub enum RequestRef<B> {
     SupplyKeypair(Ref<B, super::SupplyKeypairRequest>),
     AddListenSocket(Ref<B, super::AddListenSocketRequest>),
     AddPskBroker(Ref<B, super::AddPskBrokerRequest>),
+    ReloadConfig(Ref<B, super::ReloadConfigRequest>),
+    AddPeer(Ref<B, super::AddPeerRequest>),
+    RemovePeer(Ref<B, super::RemovePeerRequest>),
+    ListPeers(Ref<B, super::ListPeersRequest>),
+    GetPeerStatus(Ref<B, super::GetPeerStatusRequest>),
+    SubscribeEvents(Ref<B, super::SubscribeEventsRequest>),
+    RegisterKeySink(Ref<B, super::RegisterKeySinkRequest>),
+    Rekey(Ref<B, super::RekeyRequest>),
+    Shutdown(Ref<B, super::ShutdownRequest>),
+    Version(Ref<B, super::VersionRequest>),
 }
 
 impl<B> RequestRef<B>
@@ -172,6 +270,16 @@ where
             Self::SupplyKeypair(r) => r.bytes(),
             Self::AddListenSocket(r) => r.bytes(),
             Self::AddPskBroker(r) => r.bytes(),
+            Self::ReloadConfig(r) => r.bytes(),
+            Self::AddPeer(r) => r.bytes(),
+            Self::RemovePeer(r) => r.bytes(),
+            Self::ListPeers(r) => r.bytes(),
+            Self::GetPeerStatus(r) => r.bytes(),
+            Self::SubscribeEvents(r) => r.bytes(),
+            Self::RegisterKeySink(r) => r.bytes(),
+            Self::Rekey(r) => r.bytes(),
+            Self::Shutdown(r) => r.bytes(),
+            Self::Version(r) => r.bytes(),
         }
     }
 }
@@ -187,6 +295,16 @@ where
             Self::SupplyKeypair(r) => r.bytes_mut(),
             Self::AddListenSocket(r) => r.bytes_mut(),
             Self::AddPskBroker(r) => r.bytes_mut(),
+            Self::ReloadConfig(r) => r.bytes_mut(),
+            Self::AddPeer(r) => r.bytes_mut(),
+            Self::RemovePeer(r) => r.bytes_mut(),
+            Self::ListPeers(r) => r.bytes_mut(),
+            Self::GetPeerStatus(r) => r.bytes_mut(),
+            Self::SubscribeEvents(r) => r.bytes_mut(),
+            Self::RegisterKeySink(r) => r.bytes_mut(),
+            Self::Rekey(r) => r.bytes_mut(),
+            Self::Shutdown(r) => r.bytes_mut(),
+            Self::Version(r) => r.bytes_mut(),
         }
     }
 }
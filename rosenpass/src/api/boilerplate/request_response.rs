@@ -73,6 +73,86 @@ impl ResponseMsg for super::AddPskBrokerResponse {
     type RequestMsg = super::AddPskBrokerRequest;
 }
 
+impl RequestMsg for super::ReloadConfigRequest {
+    type ResponseMsg = super::ReloadConfigResponse;
+}
+
+impl ResponseMsg for super::ReloadConfigResponse {
+    type RequestMsg = super::ReloadConfigRequest;
+}
+
+impl RequestMsg for super::AddPeerRequest {
+    type ResponseMsg = super::AddPeerResponse;
+}
+
+impl ResponseMsg for super::AddPeerResponse {
+    type RequestMsg = super::AddPeerRequest;
+}
+
+impl RequestMsg for super::RemovePeerRequest {
+    type ResponseMsg = super::RemovePeerResponse;
+}
+
+impl ResponseMsg for super::RemovePeerResponse {
+    type RequestMsg = super::RemovePeerRequest;
+}
+
+impl RequestMsg for super::ListPeersRequest {
+    type ResponseMsg = super::ListPeersResponse;
+}
+
+impl ResponseMsg for super::ListPeersResponse {
+    type RequestMsg = super::ListPeersRequest;
+}
+
+impl RequestMsg for super::GetPeerStatusRequest {
+    type ResponseMsg = super::GetPeerStatusResponse;
+}
+
+impl ResponseMsg for super::GetPeerStatusResponse {
+    type RequestMsg = super::GetPeerStatusRequest;
+}
+
+impl RequestMsg for super::SubscribeEventsRequest {
+    type ResponseMsg = super::SubscribeEventsResponse;
+}
+
+impl ResponseMsg for super::SubscribeEventsResponse {
+    type RequestMsg = super::SubscribeEventsRequest;
+}
+
+impl RequestMsg for super::RegisterKeySinkRequest {
+    type ResponseMsg = super::RegisterKeySinkResponse;
+}
+
+impl ResponseMsg for super::RegisterKeySinkResponse {
+    type RequestMsg = super::RegisterKeySinkRequest;
+}
+
+impl RequestMsg for super::RekeyRequest {
+    type ResponseMsg = super::RekeyResponse;
+}
+
+impl ResponseMsg for super::RekeyResponse {
+    type RequestMsg = super::RekeyRequest;
+}
+
+impl RequestMsg for super::ShutdownRequest {
+    type ResponseMsg = super::ShutdownResponse;
+}
+
+impl ResponseMsg for super::ShutdownResponse {
+    type RequestMsg = super::ShutdownRequest;
+}
+
+impl RequestMsg for super::VersionRequest {
+    type ResponseMsg = super::VersionResponse;
+}
+
+impl ResponseMsg for super::VersionResponse {
+    type RequestMsg = super::VersionRequest;
+}
+
 /// Request and response for the [crate::api::RequestMsgType::Ping] message type
 pub type PingPair<B1, B2> = (Ref<B1, PingRequest>, Ref<B2, PingResponse>);
 /// Request and response for the [crate::api::RequestMsgType::SupplyKeypair] message type
@@ -90,6 +170,53 @@ pub type AddPskBrokerPair<B1, B2> = (
     Ref<B1, super::AddPskBrokerRequest>,
     Ref<B2, super::AddPskBrokerResponse>,
 );
+/// Request and response for the [crate::api::RequestMsgType::ReloadConfig] message type
+pub type ReloadConfigPair<B1, B2> = (
+    Ref<B1, super::ReloadConfigRequest>,
+    Ref<B2, super::ReloadConfigResponse>,
+);
+/// Request and response for the [crate::api::RequestMsgType::AddPeer] message type
+pub type AddPeerPair<B1, B2> = (
+    Ref<B1, super::AddPeerRequest>,
+    Ref<B2, super::AddPeerResponse>,
+);
+/// Request and response for the [crate::api::RequestMsgType::RemovePeer] message type
+pub type RemovePeerPair<B1, B2> = (
+    Ref<B1, super::RemovePeerRequest>,
+    Ref<B2, super::RemovePeerResponse>,
+);
+/// Request and response for the [crate::api::RequestMsgType::ListPeers] message type
+pub type ListPeersPair<B1, B2> = (
+    Ref<B1, super::ListPeersRequest>,
+    Ref<B2, super::ListPeersResponse>,
+);
+/// Request and response for the [crate::api::RequestMsgType::GetPeerStatus] message type
+pub type GetPeerStatusPair<B1, B2> = (
+    Ref<B1, super::GetPeerStatusRequest>,
+    Ref<B2, super::GetPeerStatusResponse>,
+);
+/// Request and response for the [crate::api::RequestMsgType::SubscribeEvents] message type
+pub type SubscribeEventsPair<B1, B2> = (
+    Ref<B1, super::SubscribeEventsRequest>,
+    Ref<B2, super::SubscribeEventsResponse>,
+);
+/// Request and response for the [crate::api::RequestMsgType::RegisterKeySink] message type
+pub type RegisterKeySinkPair<B1, B2> = (
+    Ref<B1, super::RegisterKeySinkRequest>,
+    Ref<B2, super::RegisterKeySinkResponse>,
+);
+/// Request and response for the [crate::api::RequestMsgType::Rekey] message type
+pub type RekeyPair<B1, B2> = (Ref<B1, super::RekeyRequest>, Ref<B2, super::RekeyResponse>);
+/// Request and response for the [crate::api::RequestMsgType::Shutdown] message type
+pub type ShutdownPair<B1, B2> = (
+    Ref<B1, super::ShutdownRequest>,
+    Ref<B2, super::ShutdownResponse>,
+);
+/// Request and response for the [crate::api::RequestMsgType::Version] message type
+pub type VersionPair<B1, B2> = (
+    Ref<B1, super::VersionRequest>,
+    Ref<B2, super::VersionResponse>,
+);
 
 /// A pair of references to messages; request and response each.
 pub enum RequestResponsePair<B1, B2> {
@@ -97,6 +224,16 @@ pub enum RequestResponsePair<B1, B2> {
     SupplyKeypair(SupplyKeypairPair<B1, B2>),
     AddListenSocket(AddListenSocketPair<B1, B2>),
     AddPskBroker(AddPskBrokerPair<B1, B2>),
+    ReloadConfig(ReloadConfigPair<B1, B2>),
+    AddPeer(AddPeerPair<B1, B2>),
+    RemovePeer(RemovePeerPair<B1, B2>),
+    ListPeers(ListPeersPair<B1, B2>),
+    GetPeerStatus(GetPeerStatusPair<B1, B2>),
+    SubscribeEvents(SubscribeEventsPair<B1, B2>),
+    RegisterKeySink(RegisterKeySinkPair<B1, B2>),
+    Rekey(RekeyPair<B1, B2>),
+    Shutdown(ShutdownPair<B1, B2>),
+    Version(VersionPair<B1, B2>),
 }
 
 impl<B1, B2> From<PingPair<B1, B2>> for RequestResponsePair<B1, B2> {
@@ -123,6 +260,66 @@ impl<B1, B2> From<AddPskBrokerPair<B1, B2>> for RequestResponsePair<B1, B2> {
     }
 }
 
+impl<B1, B2> From<ReloadConfigPair<B1, B2>> for RequestResponsePair<B1, B2> {
+    fn from(v: ReloadConfigPair<B1, B2>) -> Self {
+        RequestResponsePair::ReloadConfig(v)
+    }
+}
+
+impl<B1, B2> From<AddPeerPair<B1, B2>> for RequestResponsePair<B1, B2> {
+    fn from(v: AddPeerPair<B1, B2>) -> Self {
+        RequestResponsePair::AddPeer(v)
+    }
+}
+
+impl<B1, B2> From<RemovePeerPair<B1, B2>> for RequestResponsePair<B1, B2> {
+    fn from(v: RemovePeerPair<B1, B2>) -> Self {
+        RequestResponsePair::RemovePeer(v)
+    }
+}
+
+impl<B1, B2> From<ListPeersPair<B1, B2>> for RequestResponsePair<B1, B2> {
+    fn from(v: ListPeersPair<B1, B2>) -> Self {
+        RequestResponsePair::ListPeers(v)
+    }
+}
+
+impl<B1, B2> From<GetPeerStatusPair<B1, B2>> for RequestResponsePair<B1, B2> {
+    fn from(v: GetPeerStatusPair<B1, B2>) -> Self {
+        RequestResponsePair::GetPeerStatus(v)
+    }
+}
+
+impl<B1, B2> From<SubscribeEventsPair<B1, B2>> for RequestResponsePair<B1, B2> {
+    fn from(v: SubscribeEventsPair<B1, B2>) -> Self {
+        RequestResponsePair::SubscribeEvents(v)
+    }
+}
+
+impl<B1, B2> From<RegisterKeySinkPair<B1, B2>> for RequestResponsePair<B1, B2> {
+    fn from(v: RegisterKeySinkPair<B1, B2>) -> Self {
+        RequestResponsePair::RegisterKeySink(v)
+    }
+}
+
+impl<B1, B2> From<RekeyPair<B1, B2>> for RequestResponsePair<B1, B2> {
+    fn from(v: RekeyPair<B1, B2>) -> Self {
+        RequestResponsePair::Rekey(v)
+    }
+}
+
+impl<B1, B2> From<ShutdownPair<B1, B2>> for RequestResponsePair<B1, B2> {
+    fn from(v: ShutdownPair<B1, B2>) -> Self {
+        RequestResponsePair::Shutdown(v)
+    }
+}
+
+impl<B1, B2> From<VersionPair<B1, B2>> for RequestResponsePair<B1, B2> {
+    fn from(v: VersionPair<B1, B2>) -> Self {
+        RequestResponsePair::Version(v)
+    }
+}
+
 impl<B1, B2> RequestResponsePair<B1, B2>
 where
     B1: ByteSlice,
@@ -151,6 +348,56 @@ where
                 let res = ResponseRef::AddPskBroker(res.emancipate());
                 (req, res)
             }
+            Self::ReloadConfig((req, res)) => {
+                let req = RequestRef::ReloadConfig(req.emancipate());
+                let res = ResponseRef::ReloadConfig(res.emancipate());
+                (req, res)
+            }
+            Self::AddPeer((req, res)) => {
+                let req = RequestRef::AddPeer(req.emancipate());
+                let res = ResponseRef::AddPeer(res.emancipate());
+                (req, res)
+            }
+            Self::RemovePeer((req, res)) => {
+                let req = RequestRef::RemovePeer(req.emancipate());
+                let res = ResponseRef::RemovePeer(res.emancipate());
+                (req, res)
+            }
+            Self::ListPeers((req, res)) => {
+                let req = RequestRef::ListPeers(req.emancipate());
+                let res = ResponseRef::ListPeers(res.emancipate());
+                (req, res)
+            }
+            Self::GetPeerStatus((req, res)) => {
+                let req = RequestRef::GetPeerStatus(req.emancipate());
+                let res = ResponseRef::GetPeerStatus(res.emancipate());
+                (req, res)
+            }
+            Self::SubscribeEvents((req, res)) => {
+                let req = RequestRef::SubscribeEvents(req.emancipate());
+                let res = ResponseRef::SubscribeEvents(res.emancipate());
+                (req, res)
+            }
+            Self::RegisterKeySink((req, res)) => {
+                let req = RequestRef::RegisterKeySink(req.emancipate());
+                let res = ResponseRef::RegisterKeySink(res.emancipate());
+                (req, res)
+            }
+            Self::Rekey((req, res)) => {
+                let req = RequestRef::Rekey(req.emancipate());
+                let res = ResponseRef::Rekey(res.emancipate());
+                (req, res)
+            }
+            Self::Shutdown((req, res)) => {
+                let req = RequestRef::Shutdown(req.emancipate());
+                let res = ResponseRef::Shutdown(res.emancipate());
+                (req, res)
+            }
+            Self::Version((req, res)) => {
+                let req = RequestRef::Version(req.emancipate());
+                let res = ResponseRef::Version(res.emancipate());
+                (req, res)
+            }
         }
     }
 
@@ -193,6 +440,56 @@ where
                 let res = ResponseRef::AddPskBroker(res.emancipate_mut());
                 (req, res)
             }
+            Self::ReloadConfig((req, res)) => {
+                let req = RequestRef::ReloadConfig(req.emancipate_mut());
+                let res = ResponseRef::ReloadConfig(res.emancipate_mut());
+                (req, res)
+            }
+            Self::AddPeer((req, res)) => {
+                let req = RequestRef::AddPeer(req.emancipate_mut());
+                let res = ResponseRef::AddPeer(res.emancipate_mut());
+                (req, res)
+            }
+            Self::RemovePeer((req, res)) => {
+                let req = RequestRef::RemovePeer(req.emancipate_mut());
+                let res = ResponseRef::RemovePeer(res.emancipate_mut());
+                (req, res)
+            }
+            Self::ListPeers((req, res)) => {
+                let req = RequestRef::ListPeers(req.emancipate_mut());
+                let res = ResponseRef::ListPeers(res.emancipate_mut());
+                (req, res)
+            }
+            Self::GetPeerStatus((req, res)) => {
+                let req = RequestRef::GetPeerStatus(req.emancipate_mut());
+                let res = ResponseRef::GetPeerStatus(res.emancipate_mut());
+                (req, res)
+            }
+            Self::SubscribeEvents((req, res)) => {
+                let req = RequestRef::SubscribeEvents(req.emancipate_mut());
+                let res = ResponseRef::SubscribeEvents(res.emancipate_mut());
+                (req, res)
+            }
+            Self::RegisterKeySink((req, res)) => {
+                let req = RequestRef::RegisterKeySink(req.emancipate_mut());
+                let res = ResponseRef::RegisterKeySink(res.emancipate_mut());
+                (req, res)
+            }
+            Self::Rekey((req, res)) => {
+                let req = RequestRef::Rekey(req.emancipate_mut());
+                let res = ResponseRef::Rekey(res.emancipate_mut());
+                (req, res)
+            }
+            Self::Shutdown((req, res)) => {
+                let req = RequestRef::Shutdown(req.emancipate_mut());
+                let res = ResponseRef::Shutdown(res.emancipate_mut());
+                (req, res)
+            }
+            Self::Version((req, res)) => {
+                let req = RequestRef::Version(req.emancipate_mut());
+                let res = ResponseRef::Version(res.emancipate_mut());
+                (req, res)
+            }
         }
     }
 
@@ -71,6 +71,16 @@ impl<B: ByteSlice> ResponseRef<B> {
             Self::SupplyKeypair(_) => ResponseMsgType::SupplyKeypair,
             Self::AddListenSocket(_) => ResponseMsgType::AddListenSocket,
             Self::AddPskBroker(_) => ResponseMsgType::AddPskBroker,
+            Self::ReloadConfig(_) => ResponseMsgType::ReloadConfig,
+            Self::AddPeer(_) => ResponseMsgType::AddPeer,
+            Self::RemovePeer(_) => ResponseMsgType::RemovePeer,
+            Self::ListPeers(_) => ResponseMsgType::ListPeers,
+            Self::GetPeerStatus(_) => ResponseMsgType::GetPeerStatus,
+            Self::SubscribeEvents(_) => ResponseMsgType::SubscribeEvents,
+            Self::RegisterKeySink(_) => ResponseMsgType::RegisterKeySink,
+            Self::Rekey(_) => ResponseMsgType::Rekey,
+            Self::Shutdown(_) => ResponseMsgType::Shutdown,
+            Self::Version(_) => ResponseMsgType::Version,
         }
     }
 }
@@ -99,6 +109,66 @@ impl<B> From<Ref<B, super::AddPskBrokerResponse>> for ResponseRef<B> {
     }
 }
 
+impl<B> From<Ref<B, super::ReloadConfigResponse>> for ResponseRef<B> {
+    fn from(v: Ref<B, super::ReloadConfigResponse>) -> Self {
+        Self::ReloadConfig(v)
+    }
+}
+
+impl<B> From<Ref<B, super::AddPeerResponse>> for ResponseRef<B> {
+    fn from(v: Ref<B, super::AddPeerResponse>) -> Self {
+        Self::AddPeer(v)
+    }
+}
+
+impl<B> From<Ref<B, super::RemovePeerResponse>> for ResponseRef<B> {
+    fn from(v: Ref<B, super::RemovePeerResponse>) -> Self {
+        Self::RemovePeer(v)
+    }
+}
+
+impl<B> From<Ref<B, super::ListPeersResponse>> for ResponseRef<B> {
+    fn from(v: Ref<B, super::ListPeersResponse>) -> Self {
+        Self::ListPeers(v)
+    }
+}
+
+impl<B> From<Ref<B, super::GetPeerStatusResponse>> for ResponseRef<B> {
+    fn from(v: Ref<B, super::GetPeerStatusResponse>) -> Self {
+        Self::GetPeerStatus(v)
+    }
+}
+
+impl<B> From<Ref<B, super::SubscribeEventsResponse>> for ResponseRef<B> {
+    fn from(v: Ref<B, super::SubscribeEventsResponse>) -> Self {
+        Self::SubscribeEvents(v)
+    }
+}
+
+impl<B> From<Ref<B, super::RegisterKeySinkResponse>> for ResponseRef<B> {
+    fn from(v: Ref<B, super::RegisterKeySinkResponse>) -> Self {
+        Self::RegisterKeySink(v)
+    }
+}
+
+impl<B> From<Ref<B, super::RekeyResponse>> for ResponseRef<B> {
+    fn from(v: Ref<B, super::RekeyResponse>) -> Self {
+        Self::Rekey(v)
+    }
+}
+
+impl<B> From<Ref<B, super::ShutdownResponse>> for ResponseRef<B> {
+    fn from(v: Ref<B, super::ShutdownResponse>) -> Self {
+        Self::Shutdown(v)
+    }
+}
+
+impl<B> From<Ref<B, super::VersionResponse>> for ResponseRef<B> {
+    fn from(v: Ref<B, super::VersionResponse>) -> Self {
+        Self::Version(v)
+    }
+}
+
 impl<B: ByteSlice> ResponseRefMaker<B> {
     fn new(buf: B) -> anyhow::Result<Self> {
         let msg_type = buf.deref().response_msg_type_from_prefix()?;
@@ -121,6 +191,26 @@ impl<B: ByteSlice> ResponseRefMaker<B> {
             ResponseMsgType::AddPskBroker => {
                 ResponseRef::AddPskBroker(self.buf.add_psk_broker_response()?)
             }
+            ResponseMsgType::ReloadConfig => {
+                ResponseRef::ReloadConfig(self.buf.reload_config_response()?)
+            }
+            ResponseMsgType::AddPeer => ResponseRef::AddPeer(self.buf.add_peer_response()?),
+            ResponseMsgType::RemovePeer => {
+                ResponseRef::RemovePeer(self.buf.remove_peer_response()?)
+            }
+            ResponseMsgType::ListPeers => ResponseRef::ListPeers(self.buf.list_peers_response()?),
+            ResponseMsgType::GetPeerStatus => {
+                ResponseRef::GetPeerStatus(self.buf.get_peer_status_response()?)
+            }
+            ResponseMsgType::SubscribeEvents => {
+                ResponseRef::SubscribeEvents(self.buf.subscribe_events_response()?)
+            }
+            ResponseMsgType::RegisterKeySink => {
+                ResponseRef::RegisterKeySink(self.buf.register_key_sink_response()?)
+            }
+            ResponseMsgType::Rekey => ResponseRef::Rekey(self.buf.rekey_response()?),
+            ResponseMsgType::Shutdown => ResponseRef::Shutdown(self.buf.shutdown_response()?),
+            ResponseMsgType::Version => ResponseRef::Version(self.buf.version_response()?),
         })
     }
 
@@ -159,6 +249,16 @@ pub enum ResponseRef<B> {
     SupplyKeypair(Ref<B, super::SupplyKeypairResponse>),
     AddListenSocket(Ref<B, super::AddListenSocketResponse>),
     AddPskBroker(Ref<B, super::AddPskBrokerResponse>),
+    ReloadConfig(Ref<B, super::ReloadConfigResponse>),
+    AddPeer(Ref<B, super::AddPeerResponse>),
+    RemovePeer(Ref<B, super::RemovePeerResponse>),
+    ListPeers(Ref<B, super::ListPeersResponse>),
+    GetPeerStatus(Ref<B, super::GetPeerStatusResponse>),
+    SubscribeEvents(Ref<B, super::SubscribeEventsResponse>),
+    RegisterKeySink(Ref<B, super::RegisterKeySinkResponse>),
+    Rekey(Ref<B, super::RekeyResponse>),
+    Shutdown(Ref<B, super::ShutdownResponse>),
+    Version(Ref<B, super::VersionResponse>),
 }
 
 impl<B> ResponseRef<B>
@@ -176,6 +276,16 @@ where
             Self::SupplyKeypair(r) => r.bytes(),
             Self::AddListenSocket(r) => r.bytes(),
             Self::AddPskBroker(r) => r.bytes(),
+            Self::ReloadConfig(r) => r.bytes(),
+            Self::AddPeer(r) => r.bytes(),
+            Self::RemovePeer(r) => r.bytes(),
+            Self::ListPeers(r) => r.bytes(),
+            Self::GetPeerStatus(r) => r.bytes(),
+            Self::SubscribeEvents(r) => r.bytes(),
+            Self::RegisterKeySink(r) => r.bytes(),
+            Self::Rekey(r) => r.bytes(),
+            Self::Shutdown(r) => r.bytes(),
+            Self::Version(r) => r.bytes(),
         }
     }
 }
@@ -191,6 +301,16 @@ where
             Self::SupplyKeypair(r) => r.bytes_mut(),
             Self::AddListenSocket(r) => r.bytes_mut(),
             Self::AddPskBroker(r) => r.bytes_mut(),
+            Self::ReloadConfig(r) => r.bytes_mut(),
+            Self::AddPeer(r) => r.bytes_mut(),
+            Self::RemovePeer(r) => r.bytes_mut(),
+            Self::ListPeers(r) => r.bytes_mut(),
+            Self::GetPeerStatus(r) => r.bytes_mut(),
+            Self::SubscribeEvents(r) => r.bytes_mut(),
+            Self::RegisterKeySink(r) => r.bytes_mut(),
+            Self::Rekey(r) => r.bytes_mut(),
+            Self::Shutdown(r) => r.bytes_mut(),
+            Self::Version(r) => r.bytes_mut(),
         }
     }
 }
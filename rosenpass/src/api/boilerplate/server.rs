@@ -141,6 +141,345 @@ pub trait Server {
         res: &mut super::AddPskBrokerResponse,
     ) -> anyhow::Result<()>;
 
+    /// Re-read the configuration file and apply the changes to the running server
+    ///
+    /// This implements the handler for the [crate::api::RequestMsgType::ReloadConfig] API message.
+    ///
+    /// # File descriptors
+    ///
+    /// None
+    ///
+    /// # API Return Status
+    ///
+    /// 1. [crate::api::reload_config_response_status::OK] - Indicates success; the response
+    ///    payload contains a summary of what changed
+    /// 2. [crate::api::reload_config_response_status::NOT_SUPPORTED] – The server was not started
+    ///    from a configuration file
+    /// 3. [crate::api::reload_config_response_status::INVALID_CONFIG] – The configuration file
+    ///    could not be loaded or does not apply to the running server; nothing was changed
+    /// 4. [crate::api::reload_config_response_status::INTERNAL_ERROR] – Applying the configuration
+    ///    failed midway. Check the logs
+    ///
+    /// # Description
+    ///
+    /// This is the API equivalent of sending SIGHUP to the rosenpass process; see
+    /// [crate::reload] for how the new configuration is applied.
+    ///
+    /// # Examples
+    ///
+    /// See the example of how to use the API in [crate::api].
+    fn reload_config(
+        &mut self,
+        req: &super::ReloadConfigRequest,
+        req_fds: &mut VecDeque<OwnedFd>,
+        res: &mut super::ReloadConfigResponse,
+    ) -> anyhow::Result<()>;
+
+    /// Add a protocol peer at runtime
+    ///
+    /// This implements the handler for the [crate::api::RequestMsgType::AddPeer] API message.
+    ///
+    /// # File descriptors
+    ///
+    /// 1. The public key of the peer (size must match exactly)
+    /// 2. Optionally, the pre-shared key for the peer as raw bytes (size must match exactly)
+    ///
+    /// # API Return Status
+    ///
+    /// 1. [crate::api::add_peer_response_status::OK] - Indicates success; the response contains
+    ///    the id of the new peer
+    /// 2. [crate::api::add_peer_response_status::INVALID_REQUEST] – Malformed request; could be:
+    ///     - Missing file descriptor for the public key
+    ///     - File descriptors contain data of invalid length
+    ///     - Unknown protocol version or broker
+    ///     - Endpoint that can not be resolved
+    /// 3. [crate::api::add_peer_response_status::PEER_ALREADY_EXISTS] – A peer with this public
+    ///    key is configured already
+    /// 4. [crate::api::add_peer_response_status::INTERNAL_ERROR] – Some other, non-fatal error
+    ///    occured. Check the logs
+    ///
+    /// # Description
+    ///
+    /// The peer is set up just like a peer from the configuration file, except that its keys are
+    /// only handed to the PSK broker: there is no `key_out` file for peers added through the API.
+    /// Peers can be added before the server keypair is supplied.
+    ///
+    /// # Examples
+    ///
+    /// See the example of how to use the API in [crate::api].
+    fn add_peer(
+        &mut self,
+        req: &super::AddPeerRequest,
+        req_fds: &mut VecDeque<OwnedFd>,
+        res: &mut super::AddPeerResponse,
+    ) -> anyhow::Result<()>;
+
+    /// Remove a protocol peer at runtime
+    ///
+    /// This implements the handler for the [crate::api::RequestMsgType::RemovePeer] API message.
+    ///
+    /// # File descriptors
+    ///
+    /// None
+    ///
+    /// # API Return Status
+    ///
+    /// 1. [crate::api::remove_peer_response_status::OK] - Indicates success
+    /// 2. [crate::api::remove_peer_response_status::NO_SUCH_PEER] – There is no peer with this
+    ///    id or it was removed already
+    /// 3. [crate::api::remove_peer_response_status::KEYPAIR_MISSING] – Peers can only be removed
+    ///    once the server keypair is known
+    /// 4. [crate::api::remove_peer_response_status::INTERNAL_ERROR] – Some other, non-fatal error
+    ///    occured. Check the logs
+    ///
+    /// # Description
+    ///
+    /// Works for peers from the configuration file as well as for those added through
+    /// [Self::add_peer]. The key of the peer is overwritten with a random one first, just like
+    /// when it goes stale; see [crate::app_server::AppServer::remove_peer].
+    ///
+    /// A peer from the configuration file that is removed this way comes back when the
+    /// configuration is reloaded.
+    ///
+    /// # Examples
+    ///
+    /// See the example of how to use the API in [crate::api].
+    fn remove_peer(
+        &mut self,
+        req: &super::RemovePeerRequest,
+        req_fds: &mut VecDeque<OwnedFd>,
+        res: &mut super::RemovePeerResponse,
+    ) -> anyhow::Result<()>;
+
+    /// List the ids of the peers of the server
+    ///
+    /// This implements the handler for the [crate::api::RequestMsgType::ListPeers] API message.
+    ///
+    /// # File descriptors
+    ///
+    /// None
+    ///
+    /// # API Return Status
+    ///
+    /// 1. [crate::api::list_peers_response_status::OK] - Always
+    ///
+    /// # Description
+    ///
+    /// Returns up to [crate::api::LIST_PEERS_PAGE_LEN] peer ids, skipping the first `offset`
+    /// peers, together with the total number of peers. Removed peers are not listed. Use
+    /// [Self::get_peer_status] to find out more about a peer.
+    ///
+    /// # Examples
+    ///
+    /// See the example of how to use the API in [crate::api].
+    fn list_peers(
+        &mut self,
+        req: &super::ListPeersRequest,
+        req_fds: &mut VecDeque<OwnedFd>,
+        res: &mut super::ListPeersResponse,
+    ) -> anyhow::Result<()>;
+
+    /// Describe the state of one peer
+    ///
+    /// This implements the handler for the [crate::api::RequestMsgType::GetPeerStatus] API
+    /// message.
+    ///
+    /// # File descriptors
+    ///
+    /// None
+    ///
+    /// # API Return Status
+    ///
+    /// 1. [crate::api::get_peer_status_response_status::OK] - Indicates success; the response
+    ///    holds the status of the peer
+    /// 2. [crate::api::get_peer_status_response_status::NO_SUCH_PEER] – There is no peer with
+    ///    this id or it was removed
+    ///
+    /// # Description
+    ///
+    /// Reports the endpoints, protocol version, session and handshake state, time of the last key
+    /// exchange and error counters of the peer; see [crate::app_server::PeerStatus].
+    ///
+    /// # Examples
+    ///
+    /// See the example of how to use the API in [crate::api].
+    fn get_peer_status(
+        &mut self,
+        req: &super::GetPeerStatusRequest,
+        req_fds: &mut VecDeque<OwnedFd>,
+        res: &mut super::GetPeerStatusResponse,
+    ) -> anyhow::Result<()>;
+
+    /// Turn the connection into an event subscription
+    ///
+    /// This implements the handler for the [crate::api::RequestMsgType::SubscribeEvents] API
+    /// message.
+    ///
+    /// # File descriptors
+    ///
+    /// None
+    ///
+    /// # API Return Status
+    ///
+    /// 1. [crate::api::subscribe_events_response_status::OK] - Indicates success; the event kind
+    ///    of the response is [crate::api::subscribe_events_event_kind::SUBSCRIBED]
+    ///
+    /// # Description
+    ///
+    /// After the response, the server stops processing requests on this connection and instead
+    /// sends a further [crate::api::SubscribeEventsResponse] whenever a key is exchanged or goes
+    /// stale, a handshake fails, the endpoint of a peer changes or the server enters or leaves
+    /// the under-load state. Use a separate connection for other requests.
+    ///
+    /// Events are queued for clients that read slowly; once the queue is full, the oldest events
+    /// are dropped and [crate::api::SubscribeEventsResponsePayload::dropped] of the next event
+    /// sent says how many.
+    ///
+    /// # Examples
+    ///
+    /// See the example of how to use the API in [crate::api].
+    fn subscribe_events(
+        &mut self,
+        req: &super::SubscribeEventsRequest,
+        req_fds: &mut VecDeque<OwnedFd>,
+        res: &mut super::SubscribeEventsResponse,
+    ) -> anyhow::Result<()>;
+
+    /// Have the keys of a peer sent to this connection
+    ///
+    /// This implements the handler for the [crate::api::RequestMsgType::RegisterKeySink] API
+    /// message.
+    ///
+    /// # File descriptors
+    ///
+    /// None in the request. Every key sent afterwards comes with a sealed memfd holding the key.
+    ///
+    /// # API Return Status
+    ///
+    /// 1. [crate::api::register_key_sink_response_status::OK] - Indicates success; the reason
+    ///    of the response is [crate::api::key_sink_reason::REGISTERED]
+    /// 2. [crate::api::register_key_sink_response_status::NO_SUCH_PEER] – There is no peer with
+    ///    this id or it was removed
+    ///
+    /// # Description
+    ///
+    /// After a successful response, the server stops processing requests on this connection.
+    /// Whenever [crate::app_server::AppServer::output_key] outputs a key for the peer, the
+    /// server sends a further [crate::api::RegisterKeySinkResponse] with the reason and attaches
+    /// a sealed memfd with the key, encoded like all other key outputs of the peer (see
+    /// [crate::key_out::KeyOutFormat]). The key never touches the file system.
+    ///
+    /// Keys are queued for clients that read slowly; once the queue is full, the oldest keys are
+    /// dropped and [crate::api::RegisterKeySinkResponsePayload::dropped] of the next key sent
+    /// says how many.
+    ///
+    /// # Examples
+    ///
+    /// See the example of how to use the API in [crate::api].
+    fn register_key_sink(
+        &mut self,
+        req: &super::RegisterKeySinkRequest,
+        req_fds: &mut VecDeque<OwnedFd>,
+        res: &mut super::RegisterKeySinkResponse,
+    ) -> anyhow::Result<()>;
+
+    /// Exchange a new key with a peer right away
+    ///
+    /// This implements the handler for the [crate::api::RequestMsgType::Rekey] API message.
+    ///
+    /// # File descriptors
+    ///
+    /// None
+    ///
+    /// # API Return Status
+    ///
+    /// 1. [crate::api::rekey_response_status::OK] - Indicates success; the server initiated a
+    ///    handshake or, if the request asked to wait, a key was exchanged with the peer
+    /// 2. [crate::api::rekey_response_status::NO_SUCH_PEER] – There is no peer with this id or
+    ///    it was removed
+    /// 3. [crate::api::rekey_response_status::NO_ENDPOINT] – The peer has no endpoint to send
+    ///    the handshake to
+    /// 4. [crate::api::rekey_response_status::KEYPAIR_MISSING] – The server has no keypair yet
+    /// 5. [crate::api::rekey_response_status::TIMEOUT] – No key was exchanged within
+    ///    [crate::api::RekeyRequestPayload::wait_ms]
+    ///
+    /// # Description
+    ///
+    /// Asks the server to initiate a handshake with the peer through
+    /// [crate::protocol::CryptoServer::initiate_now], even if the current key is still fresh.
+    ///
+    /// With a non-zero [crate::api::RekeyRequestPayload::wait_ms], the response is held back
+    /// until a key is exchanged with the peer or the time is up. Other requests on the
+    /// connection are only processed after the response.
+    ///
+    /// # Examples
+    ///
+    /// See the example of how to use the API in [crate::api].
+    fn rekey(
+        &mut self,
+        req: &super::RekeyRequest,
+        req_fds: &mut VecDeque<OwnedFd>,
+        res: &mut super::RekeyResponse,
+    ) -> anyhow::Result<()>;
+
+    /// Stop the server
+    ///
+    /// This implements the handler for the [crate::api::RequestMsgType::Shutdown] API message.
+    ///
+    /// # File descriptors
+    ///
+    /// None
+    ///
+    /// # API Return Status
+    ///
+    /// 1. [crate::api::shutdown_response_status::OK] - Indicates success; the server shuts down
+    ///    after sending the response
+    /// 2. [crate::api::shutdown_response_status::INVALID_REQUEST] – The request named an unknown
+    ///    [crate::api::shutdown_key_policy]
+    ///
+    /// # Description
+    ///
+    /// Makes the event loop return through [crate::app_server::AppServer::shutdown], just like
+    /// SIGTERM does. [crate::api::ShutdownRequestPayload::key_policy] decides whether the exported
+    /// keys are retired first, overriding the `on_shutdown` option of the configuration.
+    ///
+    /// # Examples
+    ///
+    /// See the example of how to use the API in [crate::api].
+    fn shutdown(
+        &mut self,
+        req: &super::ShutdownRequest,
+        req_fds: &mut VecDeque<OwnedFd>,
+        res: &mut super::ShutdownResponse,
+    ) -> anyhow::Result<()>;
+
+    /// Report which Rosenpass build the server runs
+    ///
+    /// This implements the handler for the [crate::api::RequestMsgType::Version] API message.
+    ///
+    /// # File descriptors
+    ///
+    /// None
+    ///
+    /// # API Return Status
+    ///
+    /// 1. [crate::api::version_response_status::OK] - Indicates success
+    ///
+    /// # Description
+    ///
+    /// Responds with the crate version, the enabled cargo features and the supported protocol
+    /// versions, so clients can find out what to expect from the server before relying on it.
+    ///
+    /// # Examples
+    ///
+    /// See the example of how to use the API in [crate::api].
+    fn version(
+        &mut self,
+        req: &super::VersionRequest,
+        req_fds: &mut VecDeque<OwnedFd>,
+        res: &mut super::VersionResponse,
+    ) -> anyhow::Result<()>;
+
     /// Similar to [Self::handle_message], but takes a [RequestResponsePair]
     /// instead of taking to separate byte buffers.
     ///
@@ -164,6 +503,22 @@ pub trait Server {
                 self.add_listen_socket(req, req_fds, res)
             }
             RequestResponsePair::AddPskBroker((req, res)) => self.add_psk_broker(req, req_fds, res),
+            RequestResponsePair::ReloadConfig((req, res)) => self.reload_config(req, req_fds, res),
+            RequestResponsePair::AddPeer((req, res)) => self.add_peer(req, req_fds, res),
+            RequestResponsePair::RemovePeer((req, res)) => self.remove_peer(req, req_fds, res),
+            RequestResponsePair::ListPeers((req, res)) => self.list_peers(req, req_fds, res),
+            RequestResponsePair::GetPeerStatus((req, res)) => {
+                self.get_peer_status(req, req_fds, res)
+            }
+            RequestResponsePair::SubscribeEvents((req, res)) => {
+                self.subscribe_events(req, req_fds, res)
+            }
+            RequestResponsePair::RegisterKeySink((req, res)) => {
+                self.register_key_sink(req, req_fds, res)
+            }
+            RequestResponsePair::Rekey((req, res)) => self.rekey(req, req_fds, res),
+            RequestResponsePair::Shutdown((req, res)) => self.shutdown(req, req_fds, res),
+            RequestResponsePair::Version((req, res)) => self.version(req, req_fds, res),
         }
     }
 
@@ -208,6 +563,56 @@ pub trait Server {
                 res.init();
                 RequestResponsePair::AddPskBroker((req, res))
             }
+            RequestRef::ReloadConfig(req) => {
+                let mut res = res.reload_config_response_from_prefix()?;
+                res.init();
+                RequestResponsePair::ReloadConfig((req, res))
+            }
+            RequestRef::AddPeer(req) => {
+                let mut res = res.add_peer_response_from_prefix()?;
+                res.init();
+                RequestResponsePair::AddPeer((req, res))
+            }
+            RequestRef::RemovePeer(req) => {
+                let mut res = res.remove_peer_response_from_prefix()?;
+                res.init();
+                RequestResponsePair::RemovePeer((req, res))
+            }
+            RequestRef::ListPeers(req) => {
+                let mut res = res.list_peers_response_from_prefix()?;
+                res.init();
+                RequestResponsePair::ListPeers((req, res))
+            }
+            RequestRef::GetPeerStatus(req) => {
+                let mut res = res.get_peer_status_response_from_prefix()?;
+                res.init();
+                RequestResponsePair::GetPeerStatus((req, res))
+            }
+            RequestRef::SubscribeEvents(req) => {
+                let mut res = res.subscribe_events_response_from_prefix()?;
+                res.init();
+                RequestResponsePair::SubscribeEvents((req, res))
+            }
+            RequestRef::RegisterKeySink(req) => {
+                let mut res = res.register_key_sink_response_from_prefix()?;
+                res.init();
+                RequestResponsePair::RegisterKeySink((req, res))
+            }
+            RequestRef::Rekey(req) => {
+                let mut res = res.rekey_response_from_prefix()?;
+                res.init();
+                RequestResponsePair::Rekey((req, res))
+            }
+            RequestRef::Shutdown(req) => {
+                let mut res = res.shutdown_response_from_prefix()?;
+                res.init();
+                RequestResponsePair::Shutdown((req, res))
+            }
+            RequestRef::Version(req) => {
+                let mut res = res.version_response_from_prefix()?;
+                res.init();
+                RequestResponsePair::Version((req, res))
+            }
         };
         self.dispatch(&mut pair, req_fds)?;
 
@@ -0,0 +1,48 @@
+//! Small client for the Rosenpass API, used by the command line interface
+
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use rosenpass_util::length_prefix_encoding::{
+    decoder::LengthPrefixDecoder, encoder::LengthPrefixEncoder,
+};
+use rosenpass_util::zerocopy::ZerocopySliceExt;
+use zerocopy::{AsBytes, FromBytes};
+
+use super::{rekey_response_status, RekeyRequest, RekeyResponse, MAX_RESPONSE_LEN};
+
+/// Send one request to the server listening on the API socket `api` and return its response
+fn request<Res: FromBytes + Copy>(api: &Path, req: &[u8]) -> anyhow::Result<Res> {
+    let mut sock = UnixStream::connect(api)
+        .with_context(|| format!("Could not connect to the API socket {api:?}"))?;
+    LengthPrefixEncoder::from_message(req).write_all_to_stdio(&mut sock)?;
+
+    let mut decoder = LengthPrefixDecoder::new([0u8; MAX_RESPONSE_LEN]);
+    let res = decoder.read_all_from_stdio(&mut sock)?;
+    Ok(*res.zk_parse::<Res>()?)
+}
+
+/// Make the server behind `api` exchange a new key with the peer `peer_id` right away
+///
+/// With `wait`, this returns once the exchange succeeded and fails if it does not within the
+/// given time. See [super::Server::rekey].
+pub fn rekey(api: &Path, peer_id: u64, wait: Option<Duration>) -> anyhow::Result<()> {
+    use rekey_response_status as status;
+
+    let wait_ms = wait.map_or(0, |w| w.as_millis().try_into().unwrap_or(u64::MAX));
+    let res: RekeyResponse = request(api, RekeyRequest::new(peer_id, wait_ms).as_bytes())?;
+
+    match res.payload.status {
+        status::OK if wait_ms > 0 => println!("Exchanged a new key with peer {peer_id}"),
+        status::OK => println!("Started a key exchange with peer {peer_id}"),
+        status::NO_SUCH_PEER => bail!("The server has no peer {peer_id}"),
+        status::NO_ENDPOINT => bail!("Peer {peer_id} has no endpoint to start a key exchange with"),
+        status::KEYPAIR_MISSING => bail!("The server has no keypair to exchange keys with"),
+        status::TIMEOUT => bail!("No key was exchanged with peer {peer_id} in time"),
+        other => bail!("Unexpected response status {other} from the API"),
+    }
+
+    Ok(())
+}
@@ -1,10 +1,12 @@
 use std::borrow::{Borrow, BorrowMut};
 use std::collections::VecDeque;
+use std::io::Read;
 use std::os::fd::OwnedFd;
+use std::time::Instant;
 
 use mio::net::UnixStream;
 use rosenpass_secret_memory::Secret;
-use rosenpass_util::mio::ReadWithFileDescriptors;
+use rosenpass_util::mio::{ReadWithFileDescriptors, WriteWithFileDescriptors};
 use rosenpass_util::{
     io::{IoResultKindHintExt, TryIoResultKindHintExt},
     length_prefix_encoding::{
@@ -15,7 +17,16 @@ use rosenpass_util::{
 };
 use zeroize::Zeroize;
 
-use crate::api::MAX_REQUEST_FDS;
+use crate::api::{
+    key_sink_reason, register_key_sink_response_status, rekey_response_status,
+    subscribe_events_event_kind, subscribe_events_response_status, ByteSliceRefExt, Message,
+    RegisterKeySinkResponse, RekeyResponse, SubscribeEventsResponse, EVENT_NO_PEER,
+    MAX_REQUEST_FDS,
+};
+use crate::app_server::{AppPeerPtr, DoSOperation, KeyOutputReason, ServerEvent};
+#[cfg(target_os = "linux")]
+use crate::key_out::sealed_memfd;
+use crate::key_out::EncodedKey;
 use crate::{api::Server, app_server::AppServer};
 
 use super::super::{ApiHandler, ApiHandlerContext};
@@ -44,12 +55,23 @@ impl<const N: usize> BorrowMut<[u8]> for SecretBuffer<N> {
 type ReadBuffer = LengthPrefixDecoder<SecretBuffer<4096>>;
 type WriteBuffer = LengthPrefixEncoder<SecretBuffer<4096>>;
 type ReadFdBuffer = VecDeque<OwnedFd>;
+type WriteFdBuffer = VecDeque<OwnedFd>;
+
+/// How many events may wait for a subscriber that does not read them before the oldest ones
+/// get dropped
+const MAX_QUEUED_EVENTS: usize = 256;
+
+/// How many keys may wait for a key sink that does not read them before the oldest ones get
+/// dropped; only the latest key of a peer is of use anyway
+const MAX_QUEUED_KEYS: usize = 16;
 
 #[derive(Debug)]
 struct MioConnectionBuffers {
     read_buffer: ReadBuffer,
     write_buffer: WriteBuffer,
     read_fd_buffer: ReadFdBuffer,
+    /// File descriptors sent along with the message in [Self::write_buffer]
+    write_fd_buffer: WriteFdBuffer,
 }
 
 #[derive(Debug)]
@@ -62,6 +84,14 @@ pub struct MioConnection {
     invalid_read: bool,
     buffers: Option<MioConnectionBuffers>,
     api_handler: ApiHandler,
+    /// Events not yet sent to a client subscribed to them
+    events: VecDeque<ServerEvent>,
+    /// Events dropped from [Self::events] since the last event was sent
+    events_dropped: u64,
+    /// Keys not yet sent to a client registered as key sink, as sealed memfds
+    keys: VecDeque<(KeyOutputReason, OwnedFd)>,
+    /// Keys dropped from [Self::keys] since the last key was sent
+    keys_dropped: u64,
 }
 
 impl MioConnection {
@@ -78,10 +108,12 @@ impl MioConnection {
         let read_buffer = LengthPrefixDecoder::new(SecretBuffer::new());
         let write_buffer = LengthPrefixEncoder::from_buffer(SecretBuffer::new());
         let read_fd_buffer = VecDeque::new();
+        let write_fd_buffer = VecDeque::new();
         let buffers = Some(MioConnectionBuffers {
             read_buffer,
             write_buffer,
             read_fd_buffer,
+            write_fd_buffer,
         });
         let api_state = ApiHandler::new();
         Ok(Self {
@@ -90,9 +122,101 @@ impl MioConnection {
             invalid_read,
             buffers,
             api_handler: api_state,
+            events: VecDeque::new(),
+            events_dropped: 0,
+            keys: VecDeque::new(),
+            keys_dropped: 0,
         })
     }
 
+    /// Queue an event to be sent if the client subscribed to events.
+    ///
+    /// If the client does not keep up with reading, the oldest queued event is dropped instead
+    /// of blocking the server.
+    ///
+    /// Returns whether the client subscribed to events.
+    pub fn queue_event(&mut self, event: &ServerEvent) -> bool {
+        if !self.api_handler.events_subscribed() {
+            return false;
+        }
+
+        if self.events.len() >= MAX_QUEUED_EVENTS {
+            self.events.pop_front();
+            self.events_dropped += 1;
+        }
+        self.events.push_back(event.clone());
+        true
+    }
+
+    /// When the [crate::api::RekeyRequest] the client waits on times out, if any
+    pub fn rekey_deadline(&self) -> Option<Instant> {
+        self.api_handler.rekey_wait().map(|w| w.deadline)
+    }
+
+    /// Send the held back response to the [crate::api::RekeyRequest] the client waits on, if any
+    pub fn answer_rekey_wait(&mut self, status: u128) -> anyhow::Result<()> {
+        let Some(wait) = self.api_handler.take_rekey_wait() else {
+            return Ok(());
+        };
+
+        // The response to the request was never written, so the write buffer is free
+        let write_buf = &mut self.buffers.as_mut().unwrap().write_buffer;
+        let len = encode_rekey_response(write_buf.buffer_bytes_mut(), wait.peer, status)?;
+        write_buf.restart_write_with_new_message(len)?;
+        Ok(())
+    }
+
+    /// Answer the [crate::api::RekeyRequest] the client waits on if `event` is the key exchange
+    /// it waits for.
+    ///
+    /// Returns whether the request was answered.
+    pub fn complete_rekey_wait(&mut self, event: &ServerEvent) -> anyhow::Result<bool> {
+        let Some(wait) = self.api_handler.rekey_wait() else {
+            return Ok(false);
+        };
+        match event {
+            ServerEvent::KeyOutput {
+                peer,
+                why: KeyOutputReason::Exchanged,
+            } if peer.0 == wait.peer.0 => {
+                self.answer_rekey_wait(rekey_response_status::OK)?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Queue a key of `peer` to be sent if the client registered as its key sink.
+    ///
+    /// Like [Self::queue_event], this drops the oldest queued key rather than blocking the
+    /// server.
+    ///
+    /// Returns whether the client registered as key sink for `peer`.
+    pub fn queue_key(
+        &mut self,
+        peer: AppPeerPtr,
+        why: KeyOutputReason,
+        key: &EncodedKey,
+    ) -> anyhow::Result<bool> {
+        if self.api_handler.key_sink().map(|p| p.0) != Some(peer.0) {
+            return Ok(false);
+        }
+
+        if self.keys.len() >= MAX_QUEUED_KEYS {
+            self.keys.pop_front();
+            self.keys_dropped += 1;
+        }
+        self.keys.push_back((why, sealed_memfd(key)?.into()));
+        Ok(true)
+    }
+
+    /// Checks if part of a message still waits to be written to the client
+    pub fn write_pending(&self) -> bool {
+        self.buffers
+            .as_ref()
+            .is_some_and(|b| !b.write_buffer.exhausted())
+    }
+
     /// Checks if this unix stream should be closed by the enclosing
     /// structure
     pub fn should_close(&self) -> bool {
@@ -140,7 +264,31 @@ pub trait MioConnectionContext {
 
         // All of these functions return an error, None ("operation incomplete")
         // or some ("operation complete, keep processing")
+
+        // Clients receiving events or keys never send requests; reading from them only tells
+        // whether they hung up
+        let api_handler = &self.mio_connection().api_handler;
+        if api_handler.events_subscribed() || api_handler.key_sink().is_some() {
+            short!(self.detect_hangup()?);
+        }
+
         short!(self.flush_write_buffer()?); // Flush last message
+
+        // Subscribers only receive events from here on
+        if self.mio_connection().api_handler.events_subscribed() {
+            short!(self.send_events()?);
+            return Ok(());
+        }
+        if self.mio_connection().api_handler.key_sink().is_some() {
+            short!(self.send_keys()?);
+            return Ok(());
+        }
+
+        // No new requests until the held back response was sent
+        if self.mio_connection().api_handler.rekey_wait().is_some() {
+            return Ok(());
+        }
+
         short!(self.recv()?); // Receive new message
         short!(self.handle_incoming_message()?); // Process new message with API
         short!(self.flush_write_buffer()?); // Begin flushing response
@@ -163,8 +311,12 @@ pub trait MioConnectionContext {
             // Transitive trait implementations: MioConnectionContext -> ApiHandlerContext -> as ApiServer
             let response_len = this.handle_message(req, req_fds, res)?;
 
-            bufs.write_buffer
-                .restart_write_with_new_message(response_len)?;
+            // A request waiting for something is answered later; see
+            // [MioConnection::answer_rekey_wait]
+            if this.mio_connection().api_handler.rekey_wait().is_none() {
+                bufs.write_buffer
+                    .restart_write_with_new_message(response_len)?;
+            }
             bufs.read_buffer.zeroize(); // clear for new message to read
             bufs.read_fd_buffer.clear();
 
@@ -187,8 +339,12 @@ pub trait MioConnectionContext {
 
             let sock = &conn.io;
             let write_buf = &mut bufs.write_buffer;
+            let fd_passing_sock = WriteWithFileDescriptors::<UnixStream, _, _, _>::new(
+                sock,
+                &mut bufs.write_fd_buffer,
+            );
 
-            match write_buf.write_to_stdio(sock).io_err_kind_hint() {
+            match write_buf.write_to_stdio(fd_passing_sock).io_err_kind_hint() {
                 // Done
                 Ok(Ret { done: true, .. }) => {
                     write_buf.zeroize(); // clear for new message to write
@@ -211,6 +367,80 @@ pub trait MioConnectionContext {
         }
     }
 
+    /// Called by [Self::poll] to send queued events to a client subscribed to them
+    fn send_events(&mut self) -> anyhow::Result<Option<()>> {
+        let under_load =
+            MioConnectionContext::app_server(self).under_load == DoSOperation::UnderLoad;
+        loop {
+            let conn = self.mio_connection_mut();
+            let Some(event) = conn.events.pop_front() else {
+                return Ok(Some(()));
+            };
+            let dropped = std::mem::take(&mut conn.events_dropped);
+
+            let write_buf = &mut conn.buffers.as_mut().unwrap().write_buffer;
+            let len = encode_event(write_buf.buffer_bytes_mut(), &event, dropped, under_load)?;
+            write_buf.restart_write_with_new_message(len)?;
+
+            if self.flush_write_buffer()?.is_none() {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Called by [Self::poll] to send queued keys to a client registered as key sink
+    fn send_keys(&mut self) -> anyhow::Result<Option<()>> {
+        loop {
+            let conn = self.mio_connection_mut();
+            let peer = conn.api_handler.key_sink().unwrap();
+            let Some((why, memfd)) = conn.keys.pop_front() else {
+                return Ok(Some(()));
+            };
+            let dropped = std::mem::take(&mut conn.keys_dropped);
+
+            let bufs = conn.buffers.as_mut().unwrap();
+            let len = encode_key(bufs.write_buffer.buffer_bytes_mut(), peer, why, dropped)?;
+            bufs.write_fd_buffer.push_back(memfd);
+            bufs.write_buffer.restart_write_with_new_message(len)?;
+
+            if self.flush_write_buffer()?.is_none() {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Called by [Self::poll] on clients subscribed to events or registered as key sink, which
+    /// do not send requests anymore, to notice when they hang up
+    ///
+    /// Whatever the client sends is discarded. Once it hung up, nothing more is sent to it and
+    /// the connection gets closed.
+    fn detect_hangup(&mut self) -> anyhow::Result<Option<()>> {
+        use std::io::ErrorKind as K;
+
+        let conn = self.mio_connection_mut();
+        let mut discard = [0u8; 64];
+        let hangup = loop {
+            match (&conn.io).read(&mut discard) {
+                Ok(0) => break None,
+                Ok(_) => continue,
+                Err(e) if e.kind() == K::WouldBlock => return Ok(Some(())),
+                Err(e) if e.kind() == K::Interrupted => continue,
+                Err(e) => break Some(e),
+            }
+        };
+
+        if let Some(e) = hangup {
+            log::warn!("IO error on API connection; the client hung up: {e:?}");
+        }
+        conn.invalid_read = true; // closed later by mio_manager
+        conn.events.clear();
+        conn.keys.clear();
+        let bufs = conn.buffers.as_mut().unwrap();
+        bufs.write_buffer.zeroize();
+        bufs.write_fd_buffer.clear();
+        Ok(None)
+    }
+
     /// Called by [Self::poll] to check for messages to receive
     fn recv(&mut self) -> anyhow::Result<Option<()>> {
         if !self.write_buf_mut().exhausted() || self.mio_connection().invalid_read {
@@ -286,6 +516,87 @@ pub trait MioConnectionContext {
     }
 }
 
+/// Write `event` as a [SubscribeEventsResponse] to `buf`, returning the length of the message
+fn encode_event(
+    buf: &mut [u8],
+    event: &ServerEvent,
+    dropped: u64,
+    under_load: bool,
+) -> anyhow::Result<usize> {
+    use subscribe_events_event_kind as kind;
+
+    let mut res = buf.subscribe_events_response_from_prefix()?;
+    res.init();
+
+    let r = &mut res.payload;
+    r.status = subscribe_events_response_status::OK;
+    r.peer_id = EVENT_NO_PEER;
+    r.dropped = dropped;
+    r.under_load = under_load as u8;
+    r.set_endpoint("");
+    match event {
+        ServerEvent::KeyOutput { peer, why } => {
+            r.event = match why {
+                KeyOutputReason::Exchanged => kind::KEY_EXCHANGED,
+                KeyOutputReason::Stale => kind::KEY_STALE,
+            };
+            r.peer_id = peer.0 as u64;
+        }
+        ServerEvent::HandshakeFailed { endpoint } => {
+            r.event = kind::HANDSHAKE_FAILED;
+            r.set_endpoint(endpoint);
+        }
+        ServerEvent::EndpointChanged { peer, endpoint } => {
+            r.event = kind::ENDPOINT_CHANGED;
+            r.peer_id = peer.0 as u64;
+            r.set_endpoint(endpoint);
+        }
+        ServerEvent::UnderLoadChanged { under_load } => {
+            r.event = kind::UNDER_LOAD_CHANGED;
+            r.under_load = *under_load as u8;
+        }
+    }
+
+    Ok(std::mem::size_of::<SubscribeEventsResponse>())
+}
+
+/// Write the header for a key sent to a key sink to `buf`, returning the length of the message
+fn encode_key(
+    buf: &mut [u8],
+    peer: AppPeerPtr,
+    why: KeyOutputReason,
+    dropped: u64,
+) -> anyhow::Result<usize> {
+    let mut res = buf.register_key_sink_response_from_prefix()?;
+    res.init();
+
+    let r = &mut res.payload;
+    r.status = register_key_sink_response_status::OK;
+    r.peer_id = peer.0 as u64;
+    r.reason = match why {
+        KeyOutputReason::Exchanged => key_sink_reason::EXCHANGED,
+        KeyOutputReason::Stale => key_sink_reason::STALE,
+    };
+    r.dropped = dropped;
+
+    Ok(std::mem::size_of::<RegisterKeySinkResponse>())
+}
+
+/// Key sinks receive their keys as sealed memfds, which only exist on Linux
+#[cfg(not(target_os = "linux"))]
+fn sealed_memfd(_key: &EncodedKey) -> anyhow::Result<std::fs::File> {
+    anyhow::bail!("Passing keys to API clients is only supported on Linux")
+}
+
+/// Write the response to a [crate::api::RekeyRequest] to `buf`, returning its length
+fn encode_rekey_response(buf: &mut [u8], peer: AppPeerPtr, status: u128) -> anyhow::Result<usize> {
+    let mut res = buf.rekey_response_from_prefix()?;
+    res.init();
+    res.payload.status = status;
+    res.payload.peer_id = peer.0 as u64;
+    Ok(std::mem::size_of::<RekeyResponse>())
+}
+
 trait MioConnectionContextPrivate: MioConnectionContext {
     fn steal_buffers(&mut self) -> MioConnectionBuffers {
         self.mio_connection_mut().buffers.take().unwrap()
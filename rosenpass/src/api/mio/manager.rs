@@ -1,4 +1,8 @@
-use std::{borrow::BorrowMut, io};
+use std::{
+    borrow::BorrowMut,
+    io,
+    time::{Duration, Instant},
+};
 
 use mio::net::{UnixListener, UnixStream};
 
@@ -6,7 +10,9 @@ use rosenpass_util::{
     functional::ApplyExt, io::nonblocking_handle_io_errors, mio::interest::RW as MIO_RW,
 };
 
-use crate::app_server::{AppServer, AppServerIoSource};
+use crate::api::rekey_response_status;
+use crate::app_server::{AppPeerPtr, AppServer, AppServerIoSource, KeyOutputReason, ServerEvent};
+use crate::key_out::EncodedKey;
 
 use super::{MioConnection, MioConnectionContext};
 
@@ -88,15 +94,20 @@ pub trait MioManagerContext {
         let mio_token = connection.mio_token();
         let conns: &mut Vec<Option<MioConnection>> =
             self.mio_manager_mut().connections.borrow_mut();
-        let idx = conns
-            .iter_mut()
-            .enumerate()
-            .find(|(_, slot)| slot.is_some())
-            .map(|(idx, _)| idx)
-            .unwrap_or(conns.len());
-        conns.insert(idx, Some(connection));
+        // Reuse the slot of a closed connection; the slots of the others must not move, as their
+        // index is their IO source
+        let idx = match conns.iter().position(Option::is_none) {
+            Some(idx) => {
+                conns[idx] = Some(connection);
+                idx
+            }
+            None => {
+                conns.push(Some(connection));
+                conns.len() - 1
+            }
+        };
         let io_source = idx
-            .apply(MioManagerIoSource::Listener)
+            .apply(MioManagerIoSource::Connection)
             .apply(AppServerIoSource::MioManager);
         self.app_server_mut()
             .register_io_source(mio_token, io_source);
@@ -153,6 +164,82 @@ pub trait MioManagerContext {
         Ok(())
     }
 
+    /// Queue an event on all connections subscribed to events and send as much as the
+    /// sockets take without blocking
+    fn push_event(&mut self, event: &ServerEvent) {
+        for idx in 0..self.mio_manager().connections.len() {
+            let Some(conn) = self.mio_manager_mut().connections[idx].as_mut() else {
+                continue;
+            };
+            let answered = conn.complete_rekey_wait(event).unwrap_or_else(|e| {
+                log::warn!("Could not answer rekey request on API connection {e:?}");
+                false
+            });
+
+            // Subscribed connections do not process requests, so polling them just sends events
+            let res = match conn.queue_event(event) {
+                true => self.poll_particular_connection(idx),
+                false if answered => self.flush_particular_connection(idx),
+                false => Ok(()),
+            };
+            if let Err(e) = res {
+                log::warn!("Error while sending event to API connection {e:?}");
+            }
+        }
+    }
+
+    /// Send a key to all connections registered as key sink for `peer`, as far as the sockets
+    /// take it without blocking
+    fn push_key(&mut self, peer: AppPeerPtr, why: KeyOutputReason, key: &EncodedKey) {
+        for idx in 0..self.mio_manager().connections.len() {
+            let registered = match self.mio_manager_mut().connections[idx].as_mut() {
+                Some(conn) => conn.queue_key(peer, why, key),
+                None => Ok(false),
+            };
+            match registered {
+                Ok(false) => {}
+                // Key sinks do not process requests, so polling them just sends keys
+                Ok(true) => {
+                    if let Err(e) = self.poll_particular_connection(idx) {
+                        log::warn!("Error while sending key to API connection {e:?}");
+                    }
+                }
+                Err(e) => log::warn!("Could not pass key to API connection {e:?}"),
+            }
+        }
+    }
+
+    /// Answer the [crate::api::RekeyRequest]s that waited in vain for their key exchange
+    ///
+    /// Returns how long until the next one times out.
+    fn expire_rekey_waits(&mut self) -> Option<Duration> {
+        let now = Instant::now();
+        let mut next_due: Option<Duration> = None;
+        for idx in 0..self.mio_manager().connections.len() {
+            let Some(conn) = self.mio_manager_mut().connections[idx].as_mut() else {
+                continue;
+            };
+            let Some(deadline) = conn.rekey_deadline() else {
+                continue;
+            };
+            if deadline > now {
+                let due = deadline - now;
+                next_due = Some(next_due.map_or(due, |d| d.min(due)));
+                continue;
+            }
+
+            match conn.answer_rekey_wait(rekey_response_status::TIMEOUT) {
+                Ok(()) => {
+                    if let Err(e) = self.flush_particular_connection(idx) {
+                        log::warn!("Error while answering rekey request on API connection {e:?}");
+                    }
+                }
+                Err(e) => log::warn!("Could not answer rekey request on API connection {e:?}"),
+            }
+        }
+        next_due
+    }
+
     /// Call [MioConnectionContext::poll] on a particular connection
     fn poll_particular_connection(&mut self, idx: usize) -> anyhow::Result<()> {
         if self.mio_manager().connections[idx].is_none() {
@@ -163,16 +250,79 @@ pub trait MioManagerContext {
         conn.poll()?;
 
         if conn.should_close() {
-            let conn = self.mio_manager_mut().connections[idx].take().unwrap();
-            let mio_token = conn.mio_token();
-            if let Err(e) = conn.close(self.app_server_mut()) {
-                log::warn!("Error while closing API connection {e:?}");
-            };
-            self.app_server_mut().unregister_io_source(mio_token);
+            self.close_connection(idx);
         }
 
         Ok(())
     }
+
+    /// Send whatever is waiting in the write buffers of all connections without processing any
+    /// requests; connections that fail are closed
+    fn flush_connections(&mut self) {
+        for idx in 0..self.mio_manager().connections.len() {
+            if let Err(e) = self.flush_particular_connection(idx) {
+                log::warn!("Error while flushing API connection {e:?}");
+                self.close_connection(idx);
+            }
+        }
+    }
+
+    /// Like [Self::flush_connections], but keep trying until everything is written or `timeout`
+    /// passed
+    ///
+    /// Used when the server goes away, so clients still get e.g. the response to their
+    /// [crate::api::ShutdownRequest]. Clients that do not read are given up on after `timeout`.
+    fn flush_connections_within(&mut self, timeout: Duration) {
+        let deadline = Instant::now() + timeout;
+        loop {
+            self.flush_connections();
+
+            let pending = self
+                .mio_manager()
+                .connections
+                .iter()
+                .flatten()
+                .any(MioConnection::write_pending);
+            if !pending {
+                return;
+            }
+            if Instant::now() >= deadline {
+                log::warn!("Giving up on API clients that do not read their messages");
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    /// Send whatever is waiting in the write buffer of a particular connection, without
+    /// processing any requests
+    ///
+    /// Unlike [Self::poll_particular_connection], this is safe to call while the [AppServer] is
+    /// in the middle of something.
+    fn flush_particular_connection(&mut self, idx: usize) -> anyhow::Result<()> {
+        if self.mio_manager().connections[idx].is_none() {
+            return Ok(());
+        }
+
+        let mut conn = MioConnectionFocus::new(self, idx);
+        conn.flush_write_buffer()?;
+
+        if conn.should_close() {
+            self.close_connection(idx);
+        }
+
+        Ok(())
+    }
+
+    /// Close and remove a particular connection
+    fn close_connection(&mut self, idx: usize) {
+        let conn = self.mio_manager_mut().connections[idx].take().unwrap();
+        let mio_token = conn.mio_token();
+        if let Err(e) = conn.close(self.app_server_mut()) {
+            log::warn!("Error while closing API connection {e:?}");
+        };
+        self.app_server_mut().unregister_io_source(mio_token);
+    }
 }
 
 impl<T: ?Sized + MioManagerContext> MioConnectionContext for MioConnectionFocus<'_, T> {
@@ -2,6 +2,7 @@
 /// the actual cryptographic code lives in the [crate::protocol] module
 use anyhow::bail;
 
+use anyhow::ensure;
 use anyhow::Context;
 use anyhow::Result;
 use derive_builder::Builder;
@@ -11,7 +12,7 @@ use mio::Token;
 use rosenpass_secret_memory::Public;
 use rosenpass_secret_memory::Secret;
 use rosenpass_util::build::ConstructionSite;
-use rosenpass_util::file::StoreValueB64;
+use rosenpass_util::file::{remove_previous, rotate_previous, store_atomic, Visibility};
 use rosenpass_util::functional::run;
 use rosenpass_util::functional::ApplyExt;
 use rosenpass_util::io::IoResultKindHintExt;
@@ -22,7 +23,7 @@ use rosenpass_wireguard_broker::WireguardBrokerMio;
 use rosenpass_wireguard_broker::{WireguardBrokerCfg, WG_KEY_LEN};
 use zerocopy::AsBytes;
 
-use std::cell::Cell;
+use std::cell::RefCell;
 
 use std::collections::HashMap;
 use std::collections::VecDeque;
@@ -39,12 +40,24 @@ use std::net::SocketAddrV6;
 use std::net::ToSocketAddrs;
 use std::path::PathBuf;
 use std::slice;
+use std::sync::Arc;
 use std::time::Duration;
 use std::time::Instant;
 
 use crate::config::ProtocolVersion;
+use crate::config::ShutdownPolicy;
+use crate::happy_eyeballs::{Path, PathSelector};
+use crate::hooks::{HookRunner, Hooks};
+use crate::key_oracle::KeyOracleFailed;
+use crate::key_out::{EncodedKey, KeyOutFormat, KeySink};
+use crate::logging::LogSpan;
+use crate::metrics::{LoopEvent, PeerCounters, RejectReason};
+use crate::pktinfo::LocalAddr;
 use crate::protocol::BuildCryptoServer;
 use crate::protocol::HostIdentification;
+use crate::resolve::Resolver;
+use crate::socket_options::SocketOptions;
+use crate::systemd::SystemdNotifier;
 use crate::{
     config::Verbosity,
     protocol::{CryptoServer, MsgBuf, PeerPtr, SPk, SSk, SymKey, Timing},
@@ -69,6 +82,12 @@ const IPV6_ANY_ADDR: Ipv6Addr = Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0);
 const UNDER_LOAD_RATIO: f64 = 0.5;
 /// Period at which the DOS detection code updates whether there is an "under load" status
 const DURATION_UPDATE_UNDER_LOAD_STATUS: Duration = Duration::from_millis(500);
+/// Minimum time between two [ServerEvent::HandshakeFailed] events; anyone can send bad packets,
+/// so these must not flood the API clients
+const HANDSHAKE_FAILED_EVENT_INTERVAL: Duration = Duration::from_secs(1);
+/// How long [AppServer::shutdown] waits for API clients to take their last messages
+#[cfg(feature = "experiment_api")]
+const SHUTDOWN_API_FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
 
 pub const BROKER_ID_BYTES: usize = 8;
 
@@ -194,6 +213,9 @@ pub struct AppPeer {
     /// to a file configured here and produce information on standard out to
     /// notify the calling process that
     pub outfile: Option<PathBuf>,
+    /// Number of previous keys to keep next to [Self::outfile]; see
+    /// [crate::config::RosenpassPeer::key_out_previous]
+    pub outfile_previous: usize,
     /// If this option is set, then [AppServer::output_key] will send generated output
     /// keys to the broker configured here
     pub broker_peer: Option<BrokerPeer>,
@@ -212,6 +234,14 @@ pub struct AppPeer {
     /// If another peer successfully connects to this one from any address, then this field will
     /// be updated to reflect which address this was.
     pub current_endpoint: Option<Endpoint>,
+    /// Human readable name of the peer, used in the logs
+    pub name: Option<String>,
+    /// Programs to run when a key is output for this peer; see [crate::hooks]
+    pub hooks: Hooks,
+    /// Further destinations for the keys of this peer; see [crate::key_out]
+    pub key_sinks: Vec<KeySink>,
+    /// How the keys of this peer are encoded for all outputs but the broker
+    pub key_format: KeyOutFormat,
 }
 
 impl AppPeer {
@@ -227,9 +257,14 @@ impl AppPeer {
     ///
     /// let mut peer = AppPeer {
     ///   outfile: None,
+    ///   outfile_previous: 0,
     ///   broker_peer: None,
     ///   initial_endpoint: Some(Endpoint::discovery_from_hostname("0.0.0.0:0".to_string())?),
     ///   current_endpoint: Some(Endpoint::discovery_from_hostname("0.0.0.0:1".to_string())?),
+    ///   name: None,
+    ///   hooks: Default::default(),
+    ///   key_sinks: vec![],
+    ///   key_format: Default::default(),
     /// };
     ///
     /// fn same(a: Option<&Endpoint>, b: Option<&Endpoint>) -> bool {
@@ -281,13 +316,14 @@ pub enum DoSOperation {
     UnderLoad,
     Normal,
 }
-/// Integration test helpers for AppServer
+/// Integration test hooks for AppServer
 ///
-/// TODO: Remove; this is no way to write integration tests
+/// These are the knobs `crate::harness` uses to observe and control an [AppServer]
+/// running in-process; prefer the harness over using this struct directly.
 ///
 /// # Examples
 ///
-/// See [AppServer]
+/// See [AppServer] and `crate::harness`.
 #[derive(Debug, Builder)]
 #[builder(pattern = "owned")]
 pub struct AppServerTest {
@@ -295,8 +331,58 @@ pub struct AppServerTest {
     #[builder(default = "false")]
     pub enable_dos_permanently: bool,
     /// Terminate application signal
+    ///
+    /// Checked by [AppServer::poll]; combine with [AppServer::register_waker] to
+    /// make termination take effect while the server is blocked waiting for IO.
     #[builder(default = "None")]
     pub termination_handler: Option<std::sync::mpsc::Receiver<()>>,
+    /// Receives an [AppServerTestEvent] whenever something notable happens in the server
+    #[builder(default = "None")]
+    pub event_sink: Option<std::sync::mpsc::Sender<AppServerTestEvent>>,
+}
+
+/// Events reported through [AppServerTest::event_sink]
+#[derive(Debug, Clone, Copy)]
+pub enum AppServerTestEvent {
+    /// [AppServer::output_key] was called for the given peer; the key has already
+    /// been handed to the outfile and the broker when this event is issued
+    KeyOutput {
+        /// The peer the key belongs to
+        peer: AppPeerPtr,
+        /// Whether the key was freshly exchanged or is a random key erasing a stale one
+        why: KeyOutputReason,
+    },
+}
+
+/// Events pushed to API clients that subscribed to them; see [AppServer::emit_event]
+#[derive(Debug, Clone)]
+pub enum ServerEvent {
+    /// [AppServer::output_key] handed a key for the peer to its consumers
+    KeyOutput {
+        /// The peer the key belongs to
+        peer: AppPeerPtr,
+        /// Whether the key was freshly exchanged or is a random key erasing a stale one
+        why: KeyOutputReason,
+    },
+    /// A handshake message could not be processed
+    ///
+    /// Emitted at most once per second; further failures in that time are not reported.
+    HandshakeFailed {
+        /// Where the message came from
+        endpoint: String,
+    },
+    /// A key exchange with the peer went through a different endpoint than before
+    EndpointChanged {
+        /// The peer whose endpoint changed
+        peer: AppPeerPtr,
+        /// The new endpoint
+        endpoint: String,
+    },
+    /// [AppServer::under_load] changed
+    UnderLoadChanged {
+        /// Whether the server is under load now
+        under_load: bool,
+    },
 }
 
 /// This represents a some source of IO operations in the context of the Rosenpass server
@@ -312,6 +398,12 @@ pub enum AppServerIoSource {
     /// see [AppServer::api_manager]
     #[cfg(feature = "experiment_api")]
     MioManager(crate::api::mio::MioManagerIoSource),
+    /// IO source refers to a [mio::Waker] created through [AppServer::register_waker]
+    Waker,
+    /// IO source refers to [AppServer::signal_handler]
+    Signals,
+    /// IO source refers to the HTTP listener or a connection of [AppServer::metrics_exporter]
+    Metrics(crate::metrics::MetricsIoSource),
 }
 
 /// Number of epoll(7) events Rosenpass can receive at a time
@@ -337,6 +429,8 @@ pub struct AppServer {
     pub crypto_site: ConstructionSite<BuildCryptoServer, CryptoServer>,
     /// The UDP sockets used to send and receive protocol messages
     pub sockets: Vec<mio::net::UdpSocket>,
+    /// Socket options applied to all of [Self::sockets]; see [crate::socket_options]
+    pub listen_options: SocketOptions,
     /// Buffer for [mio] (epoll(7), async IO handling) IO events
     pub events: mio::Events,
     /// Supplemental buffer for [mio] events. See the inline documentation of [AppServer::try_recv]
@@ -363,6 +457,24 @@ pub struct AppServer {
     /// If set to [Verbosity::Verbose], then some extra information will be printed
     /// at the info log level
     pub verbosity: Verbosity,
+    /// What [Self::shutdown] does with the exported keys
+    pub on_shutdown: ShutdownPolicy,
+    /// Set to make the event loop return through [Self::shutdown], e.g. by a
+    /// [crate::api::ShutdownRequest]
+    pub shutdown_requested: bool,
+    /// Statistics about this server; see [crate::metrics]
+    pub metrics: crate::metrics::Metrics,
+    /// Makes [Self::metrics] available to monitoring systems
+    pub metrics_exporter: crate::metrics::MetricsExporter,
+    /// Programs to run when a key is output for a peer without hooks of its own
+    pub hooks: Hooks,
+    /// Hooks running in the background
+    pub hook_runner: HookRunner,
+    /// Host name lookups for peer endpoints running in the background; see [crate::resolve]
+    pub resolver: Resolver,
+    /// Notifications to the service manager, if rosenpass was started by systemd; see
+    /// [crate::systemd]
+    pub systemd: Option<SystemdNotifier>,
     /// Used by [AppServer::try_recv] to ensure that all packages have been read
     /// from the UDP sockets
     pub all_sockets_drained: bool,
@@ -376,6 +488,8 @@ pub struct AppServer {
     pub unpolled_count: usize,
     /// State kept by the [AppServer::try_recv] for polling
     pub last_update_time: Instant,
+    /// When the last [ServerEvent::HandshakeFailed] was emitted; used to rate limit these
+    pub last_handshake_failed_event: Option<Instant>,
     /// Used by integration tests to force [Self] into DoS condition
     /// and to terminate the AppServer after the test is complete
     pub test_helpers: Option<AppServerTest>,
@@ -389,6 +503,10 @@ pub struct AppServer {
     /// See <https://github.com/rosenpass/rosenpass/issues/385>
     #[cfg(feature = "internal_signal_handling_for_coverage_reports")]
     pub term_signal: terminate::TerminateRequested,
+    /// Process signal handling; set up by [Self::enable_signal_handling]
+    pub signal_handler: Option<crate::signals::SignalHandler>,
+    /// The configuration file the server was set up from; used by [Self::reload_config]
+    pub config_reload: Option<crate::reload::ConfigReload>,
     #[cfg(feature = "experiment_api")]
     /// The Rosenpass unix socket API handler; this is an experimental
     /// feature that can be used to embed Rosenpass in external applications
@@ -425,6 +543,27 @@ impl SocketPtr {
     }
 }
 
+/// What a peer is up to; produced by [AppServer::peer_status]
+#[derive(Debug, Clone)]
+pub struct PeerStatus {
+    /// See [AppPeer::name]
+    pub name: Option<String>,
+    /// See [AppPeer::initial_endpoint]
+    pub configured_endpoint: Option<String>,
+    /// See [AppPeer::current_endpoint]
+    pub current_endpoint: Option<String>,
+    /// Protocol version used with the peer
+    pub protocol_version: ProtocolVersion,
+    /// Age of the current session, if there is one
+    pub session_age: Option<Duration>,
+    /// Whether we initiated a handshake that has not completed yet
+    pub handshake_in_progress: bool,
+    /// Time since the last key exchange, if there was one
+    pub since_key_exchange: Option<Duration>,
+    /// Error counters; see [crate::metrics::PeerCounters]
+    pub counters: PeerCounters,
+}
+
 /// Index based pointer to a Peer
 ///
 /// This allows retrieving both the io-oriented and the cryptographic information
@@ -465,7 +604,12 @@ impl AppPeerPtr {
         if let Some(broker) = server.peers[self.0].broker_peer.as_ref() {
             let config = broker.peer_cfg.create_config(psk);
             let broker = server.brokers.store.get_mut(&broker.ptr().0).unwrap();
-            broker.set_psk(config)?;
+            let res = broker.set_psk(config);
+            if res.is_err() {
+                server.metrics.broker_failures += 1;
+                server.metrics.peer_mut(*self).broker_failures += 1;
+            }
+            res?;
         } else if server.peers[self.0].outfile.is_none() {
             log::warn!("No broker peer found for peer {}", self.0);
         }
@@ -487,12 +631,22 @@ pub enum AppPollResult {
     SendRetransmission(AppPeerPtr),
     /// Received a network message.
     ///
-    /// This is the only case without a correspondence in [crate::protocol::PollResult]
+    /// This case has no correspondence in [crate::protocol::PollResult]
     ReceivedMessage(usize, Endpoint),
+    /// Termination was requested through SIGTERM, SIGINT, [AppServer::shutdown_requested] or
+    /// [AppServerTest::termination_handler]; the event loop should run [AppServer::shutdown] and
+    /// return.
+    ///
+    /// This case has no correspondence in [crate::protocol::PollResult]
+    Terminate,
+    /// SIGHUP was received; the configuration file should be reloaded.
+    ///
+    /// This case has no correspondence in [crate::protocol::PollResult]
+    ReloadConfig,
 }
 
 /// The reason why we are outputting a key
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum KeyOutputReason {
     /// The reason is that a new key for the given peer was successfully exchanged
     Exchanged,
@@ -504,6 +658,16 @@ pub enum KeyOutputReason {
     Stale,
 }
 
+impl KeyOutputReason {
+    /// Name of the reason in the `output-key` lines on standard out and for hooks
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Exchanged => "exchanged",
+            Self::Stale => "stale",
+        }
+    }
+}
+
 /// Represents a communication partner rosenpass may be sending packets to
 ///
 /// Generally at the start of Rosenpass either no address or a Hostname is known;
@@ -550,6 +714,9 @@ pub struct SocketBoundEndpoint {
     socket: SocketPtr,
     /// The network address
     addr: SocketAddr,
+    /// The local address the peer sent its message to; messages to the peer are sent from
+    /// this address (see [crate::pktinfo])
+    local: Option<LocalAddr>,
     /// Byte representation of this socket bound network address.
     /// Generated through [SocketBoundEndpoint::to_bytes].
     ///
@@ -585,10 +752,36 @@ impl SocketBoundEndpoint {
         Self {
             socket,
             addr,
+            local: None,
             bytes,
         }
     }
 
+    /// Send messages to the peer from the local address `local`
+    pub fn with_local(mut self, local: Option<LocalAddr>) -> Self {
+        self.local = local;
+        self
+    }
+
+    /// The local address messages to the peer are sent from, if it is pinned
+    pub fn local(&self) -> Option<&LocalAddr> {
+        self.local.as_ref()
+    }
+
+    /// Send a message to the peer
+    pub fn send(&self, srv: &AppServer, buf: &[u8]) -> anyhow::Result<()> {
+        crate::pktinfo::send_to(self.socket.get(srv), buf, self.addr, self.local.as_ref())?;
+        Ok(())
+    }
+
+    /// The address and socket of this endpoint
+    pub fn path(&self) -> Path {
+        Path {
+            addr: self.addr,
+            socket: self.socket.0,
+        }
+    }
+
     /// Computes [HostIdentification::encode] for [Self]. Value cached in [Self::bytes].
     fn to_bytes(
         socket: &SocketPtr,
@@ -641,6 +834,26 @@ impl Endpoint {
         Ok(Endpoint::Discovery(host))
     }
 
+    /// Like [Self::discovery_from_hostname], but without blocking on the lookup
+    ///
+    /// Socket addresses are used right away; host names are only checked for a port and
+    /// need to be looked up with [AppServer::resolve_endpoint] once the peer was added.
+    pub fn discovery_from_hostname_deferred(hostname: String) -> anyhow::Result<Self> {
+        if let Ok(addr) = hostname.parse::<SocketAddr>() {
+            return Ok(Self::discovery_from_addresses(vec![addr]));
+        }
+        let has_port = hostname
+            .rsplit_once(':')
+            .is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok());
+        ensure!(
+            has_port,
+            "Endpoint {hostname:?} is not of the form host:port"
+        );
+        Ok(Endpoint::Discovery(HostPathDiscoveryEndpoint::unresolved(
+            hostname,
+        )))
+    }
+
     // Restart discovery; joining two sources of (potential) addresses
     //
     // This is used when the connection to an endpoint is lost in order
@@ -663,7 +876,16 @@ impl Endpoint {
                 addrs.push(*a);
             }
         }
-        Some(Self::discovery_from_addresses(addrs))
+
+        // Keep what was learned about the paths to the peer
+        let host = HostPathDiscoveryEndpoint::from_addresses(addrs);
+        for ep in [a, b].into_iter().flatten() {
+            match ep {
+                Endpoint::SocketBoundAddress(bound) => host.paths.borrow_mut().prefer(bound.path()),
+                Endpoint::Discovery(other) => host.paths.borrow_mut().absorb(&other.paths.borrow()),
+            }
+        }
+        Some(Endpoint::Discovery(host))
     }
 
     /// Send a message to the address referenced by this endpoint or to one of
@@ -671,7 +893,7 @@ impl Endpoint {
     pub fn send(&self, srv: &AppServer, buf: &[u8]) -> anyhow::Result<()> {
         use Endpoint::*;
         match self {
-            SocketBoundAddress(host) => host.socket.send_to(srv, buf, host.addr),
+            SocketBoundAddress(host) => host.send(srv, buf),
             Discovery(host) => host.send_scouting(srv, buf),
         }
     }
@@ -679,7 +901,7 @@ impl Endpoint {
     /// List of addresses this endpoint may be associated with.
     ///
     /// During peer discovery, this can be multiple addresses.
-    fn addresses(&self) -> &[SocketAddr] {
+    pub fn addresses(&self) -> &[SocketAddr] {
         use Endpoint::*;
         match self {
             SocketBoundAddress(host) => slice::from_ref(&host.addr),
@@ -704,42 +926,51 @@ impl Endpoint {
 ///
 /// In contrast to TCP, UDP has no mechanism to ensure packets actually arrive.
 ///
-/// To robustly handle host path discovery, we try each socket-ip-combination in a round
-/// robin fashion; the struct stores the offset of the last used combination internally and
-/// and will continue with the next combination on every call.
+/// To robustly handle host path discovery, each socket-ip-combination is a separate path;
+/// [PathSelector] decides which path to try next, preferring the one that last worked and
+/// racing IPv6 against IPv4 otherwise. See [crate::happy_eyeballs] for details.
 ///
 /// Retransmission handling will continue normally; i.e. increasing the distance between
 /// retransmissions on every retransmission, until it is long enough to bore a human. Therefor
 /// it is important to avoid having a large number of sockets drop packets not just for efficiency
 /// but to avoid latency issues too.
-///
-// TODO: We might consider adjusting the retransmission handling to account for host-path discovery
 #[derive(Debug)]
 pub struct HostPathDiscoveryEndpoint {
-    /// Round robin index the next [Self::send_scouting] call should send packets to
-    ///
-    /// (address offset, socket offset)
-    ///
-    /// Including the socket here accounts for the fact that some network addresses may be
-    /// reachable only through particular UDP sockets
-    scouting_state: Cell<(usize, usize)>,
+    /// Which paths worked and failed so far, and which path to try next
+    paths: RefCell<PathSelector>,
     /// List of addresses fir oeer discovery
     addresses: Vec<SocketAddr>,
+    /// The host name the addresses were looked up from, if any; see [crate::resolve]
+    hostname: Option<String>,
 }
 
 impl std::fmt::Display for HostPathDiscoveryEndpoint {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self.addresses)
+        write!(f, "{:?}", self.addresses)?;
+        if let Some(path) = self.paths.borrow().preferred() {
+            write!(f, " (using {path})")?;
+        }
+        Ok(())
     }
 }
 
 impl HostPathDiscoveryEndpoint {
     /// Initiate a peer discovery process through a list of potential addresses
     pub fn from_addresses(addresses: Vec<SocketAddr>) -> Self {
-        let scouting_state = Cell::new((0, 0));
         Self {
             addresses,
-            scouting_state,
+            paths: RefCell::default(),
+            hostname: None,
+        }
+    }
+
+    /// Initiate a peer discovery process for a host name that [crate::resolve] looks up in the
+    /// background; there are no addresses to try until then
+    pub fn unresolved(hostname: String) -> Self {
+        Self {
+            addresses: Vec::new(),
+            paths: RefCell::default(),
+            hostname: Some(hostname),
         }
     }
 
@@ -747,7 +978,8 @@ impl HostPathDiscoveryEndpoint {
     pub fn lookup(hostname: String) -> anyhow::Result<Self> {
         Ok(Self {
             addresses: ToSocketAddrs::to_socket_addrs(&hostname)?.collect(),
-            scouting_state: Cell::new((0, 0)),
+            paths: RefCell::default(),
+            hostname: Some(hostname),
         })
     }
 
@@ -756,59 +988,86 @@ impl HostPathDiscoveryEndpoint {
         &self.addresses
     }
 
-    /// Calculates and stores the next value for [Self::scouting_state]
-    /// given the address and socket we just sent a scouting [crate::msgs::InitHello] message
-    /// to
-    fn insert_next_scout_offset(&self, srv: &AppServer, addr_no: usize, sock_no: usize) {
-        self.scouting_state.set((
-            (addr_no + 1) % self.addresses.len(),
-            (sock_no + 1) % srv.sockets.len(),
-        ));
+    /// The host name passed to [Self::lookup]
+    pub fn hostname(&self) -> Option<&str> {
+        self.hostname.as_deref()
+    }
+
+    /// Replace the address candidates, e.g. after the host name was resolved again
+    ///
+    /// What was learned about paths to addresses that remain is kept.
+    pub fn set_addresses(&mut self, addresses: Vec<SocketAddr>) {
+        self.addresses = addresses;
+    }
+
+    /// The state of path selection for this endpoint
+    pub fn paths(&self) -> &RefCell<PathSelector> {
+        &self.paths
+    }
+
+    /// Send `buf` through `path`
+    ///
+    /// Errors are logged, unless they merely indicate that the socket cannot reach addresses
+    /// of this family.
+    fn try_send(srv: &AppServer, path: &Path, buf: &[u8]) -> bool {
+        let Some(sock) = srv.sockets.get(path.socket) else {
+            return false;
+        };
+        let err = match sock.send_to(buf, path.addr) {
+            Ok(_) => return true,
+            Err(e) => e,
+        };
+
+        // TODO: replace this by
+        // e.kind() == io::ErrorKind::NetworkUnreachable
+        // once https://github.com/rust-lang/rust/issues/86442 lands
+        let ignore = err
+            .to_string()
+            .starts_with("Address family not supported by protocol");
+        if !ignore {
+            warn!(
+                "Socket #{} refusing to send to {}: {}",
+                path.socket, path.addr, err
+            );
+        }
+        false
     }
 
     /// Attempt to reach the host
     ///
-    /// Will round-robin-try different socket-ip-combinations on each call.
+    /// Tries the paths in the order [PathSelector::candidates] puts them in until sending
+    /// through one of them works.
     pub fn send_scouting(&self, srv: &AppServer, buf: &[u8]) -> anyhow::Result<()> {
-        let (addr_off, sock_off) = self.scouting_state.get();
+        let now = Instant::now();
+        let mut paths = self.paths.borrow_mut();
+        paths.settle(now);
 
-        let mut addrs = (self.addresses)
+        let candidates = paths.candidates(&self.addresses, srv.sockets.len(), now);
+        let Some(sent) = candidates
             .iter()
-            .enumerate()
-            .cycle()
-            .skip(addr_off)
-            .take(self.addresses.len());
-        let mut sockets = (srv.sockets)
-            .iter()
-            .enumerate()
-            .cycle()
-            .skip(sock_off)
-            .take(srv.sockets.len());
-
-        for (addr_no, addr) in addrs.by_ref() {
-            for (sock_no, sock) in sockets.by_ref() {
-                let res = sock.send_to(buf, *addr);
-                let err = match res {
-                    Ok(_) => {
-                        self.insert_next_scout_offset(srv, addr_no, sock_no);
-                        return Ok(());
-                    }
-                    Err(e) => e,
-                };
+            .position(|path| Self::try_send(srv, path, buf))
+        else {
+            bail!("Unable to send message: All sockets returned errors.")
+        };
+        paths.attempted(&candidates, sent, buf, now);
+        Ok(())
+    }
 
-                // TODO: replace this by
-                // e.kind() == io::ErrorKind::NetworkUnreachable
-                // once https://github.com/rust-lang/rust/issues/86442 lands
-                let ignore = err
-                    .to_string()
-                    .starts_with("Address family not supported by protocol");
-                if !ignore {
-                    warn!("Socket #{} refusing to send to {}: {}", sock_no, addr, err);
-                }
-            }
+    /// Send the message raced through the other address family, if it is due
+    ///
+    /// Returns when the race is due, if it is pending still.
+    pub fn send_due_race(&self, srv: &AppServer, now: Instant) -> Option<Instant> {
+        let mut paths = self.paths.borrow_mut();
+        let Some((candidates, msg)) = paths.take_due_race(now) else {
+            return paths.race_due();
+        };
+        if let Some(path) = candidates
+            .into_iter()
+            .find(|path| Self::try_send(srv, path, &msg))
+        {
+            paths.raced(path);
         }
-
-        bail!("Unable to send message: All sockets returned errors.")
+        None
     }
 }
 
@@ -823,6 +1082,30 @@ impl AppServer {
         addrs: Vec<SocketAddr>,
         verbosity: Verbosity,
         test_helpers: Option<AppServerTest>,
+    ) -> anyhow::Result<Self> {
+        Self::with_sockets(
+            keypair,
+            addrs,
+            Vec::new(),
+            SocketOptions::default(),
+            verbosity,
+            test_helpers,
+        )
+    }
+
+    /// Construct a new AppServer, like [Self::new], that also listens on the already bound
+    /// `sockets`
+    ///
+    /// The sockets for `addrs` come first in [Self::sockets], followed by `sockets`. Only if
+    /// both are empty, sockets on all interfaces are opened. `listen_options` are applied to
+    /// all of them.
+    pub fn with_sockets(
+        keypair: Option<(SSk, SPk)>,
+        addrs: Vec<SocketAddr>,
+        bound: Vec<mio::net::UdpSocket>,
+        listen_options: SocketOptions,
+        verbosity: Verbosity,
+        test_helpers: Option<AppServerTest>,
     ) -> anyhow::Result<Self> {
         // setup mio
         let mio_poll = mio::Poll::new()?;
@@ -833,6 +1116,7 @@ impl AppServer {
         let maybe_sockets: Result<Vec<_>, _> =
             addrs.into_iter().map(mio::net::UdpSocket::bind).collect();
         let mut sockets = maybe_sockets?;
+        sockets.extend(bound);
 
         // When no socket is specified, rosenpass should open one port on all
         // available interfaces best-effort. Here are the cases how this can possibly go:
@@ -903,6 +1187,7 @@ impl AppServer {
         // register all sockets to mio
         let mut io_source_index = HashMap::new();
         for (idx, socket) in sockets.iter_mut().enumerate() {
+            Self::prepare_listen_socket(socket, &listen_options)?;
             let mio_token = mio_token_dispenser.dispense();
             mio_poll
                 .registry()
@@ -922,7 +1207,16 @@ impl AppServer {
             crypto_site,
             peers: Vec::new(),
             verbosity,
+            on_shutdown: ShutdownPolicy::default(),
+            shutdown_requested: false,
+            metrics: Default::default(),
+            metrics_exporter: Default::default(),
+            hooks: Hooks::default(),
+            hook_runner: HookRunner::default(),
+            resolver: Resolver::default(),
+            systemd: None,
             sockets,
+            listen_options,
             events,
             short_poll_queue: Default::default(),
             performed_long_poll: false,
@@ -936,7 +1230,10 @@ impl AppServer {
             non_blocking_polls_count: 0,
             unpolled_count: 0,
             last_update_time: Instant::now(),
+            last_handshake_failed_event: None,
             test_helpers,
+            signal_handler: None,
+            config_reload: None,
             #[cfg(feature = "experiment_api")]
             api_manager: crate::api::mio::MioManager::default(),
         })
@@ -972,8 +1269,48 @@ impl AppServer {
         matches!(self.verbosity, Verbosity::Verbose)
     }
 
+    /// Set up a socket before it is used as a listen socket
+    ///
+    /// Applies `listen_options` (see [crate::socket_options]) and enables reporting the local
+    /// addresses of received messages (see [crate::pktinfo]).
+    fn prepare_listen_socket(
+        sock: &mio::net::UdpSocket,
+        listen_options: &SocketOptions,
+    ) -> anyhow::Result<()> {
+        listen_options.apply(sock)?;
+        if let Err(e) = crate::pktinfo::enable(sock) {
+            warn!("Replies on this listen socket may come from the wrong source address: {e}");
+        }
+        Ok(())
+    }
+
+    /// Apply new [Self::listen_options] to all listen sockets
+    ///
+    /// Options that were removed are reset. If the options cannot be applied to one of the
+    /// sockets, the sockets are set back to the previous options as far as possible.
+    pub fn set_listen_options(&mut self, listen_options: SocketOptions) -> anyhow::Result<()> {
+        for (no, sock) in self.sockets.iter().enumerate() {
+            let Err(e) = listen_options.reapply(&self.listen_options, sock) else {
+                continue;
+            };
+            for (no, sock) in self.sockets[..=no].iter().enumerate() {
+                if let Err(e) = self.listen_options.reapply(&listen_options, sock) {
+                    warn!("Could not restore the listen options of socket #{no}: {e:?}");
+                }
+            }
+            return Err(e.context(format!(
+                "Could not apply the listen options to socket #{no}"
+            )));
+        }
+        self.listen_options = listen_options;
+        Ok(())
+    }
+
     /// Used by [Self::new] to register a new udp listen source
+    ///
+    /// [Self::listen_options] are applied to the socket first.
     pub fn register_listen_socket(&mut self, mut sock: mio::net::UdpSocket) -> anyhow::Result<()> {
+        Self::prepare_listen_socket(&sock, &self.listen_options)?;
         let mio_token = self.mio_token_dispenser.dispense();
         self.mio_poll
             .registry()
@@ -984,6 +1321,64 @@ impl AppServer {
         Ok(())
     }
 
+    /// Close the listen socket at index `idx` in [Self::sockets]
+    ///
+    /// The sockets after `idx` move down by one; endpoints bound to the removed socket
+    /// fall back to host-path discovery on the remaining sockets, and discovery forgets the
+    /// paths through it.
+    pub fn remove_listen_socket(&mut self, idx: usize) -> anyhow::Result<()> {
+        ensure!(
+            idx < self.sockets.len(),
+            "No listen socket with index {idx}"
+        );
+        ensure!(
+            self.sockets.len() > 1,
+            "Refusing to close the last listen socket"
+        );
+
+        let token = self
+            .io_source_index
+            .iter()
+            .find(|(_, src)| **src == AppServerIoSource::Socket(idx))
+            .map(|(token, _)| *token)
+            .context("Listen socket is not registered with mio")?;
+        let mut sock = self.sockets.remove(idx);
+        self.mio_poll.registry().deregister(&mut sock)?;
+        self.unregister_io_source(token);
+        self.short_poll_queue.retain(|ev| ev.token() != token);
+
+        for src in self.io_source_index.values_mut() {
+            if let AppServerIoSource::Socket(no) = src {
+                if *no > idx {
+                    *no -= 1;
+                }
+            }
+        }
+
+        for peer in self.peers.iter_mut() {
+            for ep in [&mut peer.initial_endpoint, &mut peer.current_endpoint] {
+                if let Some(Endpoint::Discovery(host)) = ep {
+                    host.paths().borrow_mut().forget_socket(idx);
+                }
+                let Some(Endpoint::SocketBoundAddress(bound)) = ep else {
+                    continue;
+                };
+                let (no, addr, local) = (bound.socket.0, bound.addr, bound.local);
+                *ep = match no.cmp(&idx) {
+                    std::cmp::Ordering::Less => continue,
+                    std::cmp::Ordering::Equal => {
+                        Some(Endpoint::discovery_from_addresses(vec![addr]))
+                    }
+                    std::cmp::Ordering::Greater => Some(Endpoint::SocketBoundAddress(
+                        SocketBoundEndpoint::new(SocketPtr(no - 1), addr).with_local(local),
+                    )),
+                };
+            }
+        }
+
+        Ok(())
+    }
+
     /// Used to register a source of IO such as a listen socket with [Self::io_source_index]
     pub fn register_io_source(&mut self, token: mio::Token, io_source: AppServerIoSource) {
         let prev = self.io_source_index.insert(token, io_source);
@@ -996,6 +1391,38 @@ impl AppServer {
         assert!(value.is_some(), "Removed IO source that does not exist");
     }
 
+    /// Create a [mio::Waker] that can interrupt [Self::poll] from another thread
+    /// while it is blocked waiting for IO.
+    ///
+    /// Waking the server on its own does nothing but make it re-check its state; this
+    /// is used by `crate::harness` to deliver [AppServerTest::termination_handler]
+    /// requests without having to wait for the next protocol timer to expire.
+    pub fn register_waker(&mut self) -> anyhow::Result<Arc<mio::Waker>> {
+        let mio_token = self.mio_token_dispenser.dispense();
+        let waker = mio::Waker::new(self.mio_poll.registry(), mio_token)?;
+        self.register_io_source(mio_token, AppServerIoSource::Waker);
+        Ok(Arc::new(waker))
+    }
+
+    /// Install signal handlers, so SIGHUP triggers [Self::reload_config] and SIGTERM or SIGINT
+    /// make the event loop return after [Self::shutdown]
+    ///
+    /// Signal handlers are process-wide, so this is opt-in; the `exchange-config` command
+    /// enables it, while servers embedded in other programs (e.g. through `crate::harness`)
+    /// leave signal handling to their host.
+    pub fn enable_signal_handling(&mut self) -> anyhow::Result<()> {
+        ensure!(
+            self.signal_handler.is_none(),
+            "Signal handling is already enabled"
+        );
+        let mut handler = crate::signals::SignalHandler::new()?;
+        let mio_token = self.mio_token_dispenser.dispense();
+        handler.register(self.mio_poll.registry(), mio_token)?;
+        self.register_io_source(mio_token, AppServerIoSource::Signals);
+        self.signal_handler = Some(handler);
+        Ok(())
+    }
+
     /// Register a new WireGuard PSK broker
     pub fn register_broker(
         &mut self,
@@ -1044,6 +1471,29 @@ impl AppServer {
         broker_peer: Option<BrokerPeer>,
         hostname: Option<String>,
         protocol_version: ProtocolVersion,
+    ) -> anyhow::Result<AppPeerPtr> {
+        let initial_endpoint = hostname
+            .map(Endpoint::discovery_from_hostname)
+            .transpose()?;
+        self.add_peer_with_endpoint(
+            psk,
+            pk,
+            outfile,
+            broker_peer,
+            initial_endpoint,
+            protocol_version,
+        )
+    }
+
+    /// Register a new protocol peer, like [Self::add_peer], with an already resolved endpoint
+    pub fn add_peer_with_endpoint(
+        &mut self,
+        psk: Option<SymKey>,
+        pk: SPk,
+        outfile: Option<PathBuf>,
+        broker_peer: Option<BrokerPeer>,
+        initial_endpoint: Option<Endpoint>,
+        protocol_version: ProtocolVersion,
     ) -> anyhow::Result<AppPeerPtr> {
         let PeerPtr(pn) = match &mut self.crypto_site {
             ConstructionSite::Void => bail!("Crypto server construction site is void"),
@@ -1052,19 +1502,142 @@ impl AppServer {
         };
         assert!(pn == self.peers.len());
 
-        let initial_endpoint = hostname
-            .map(Endpoint::discovery_from_hostname)
-            .transpose()?;
         let current_endpoint = None;
         self.peers.push(AppPeer {
             outfile,
+            outfile_previous: 0,
             broker_peer,
             initial_endpoint,
             current_endpoint,
+            name: None,
+            hooks: Hooks::default(),
+            key_sinks: Vec::new(),
+            key_format: KeyOutFormat::default(),
         });
         Ok(AppPeerPtr(pn))
     }
 
+    /// Remove a peer added through [Self::add_peer]
+    ///
+    /// Before the peer is removed from the [CryptoServer] (see [CryptoServer::remove_peer]),
+    /// its key outputs are overwritten with a random key just like when the key goes stale.
+    /// The [AppPeerPtr] stays valid, but refers to an empty peer from then on.
+    pub fn remove_peer(&mut self, peer: AppPeerPtr) -> anyhow::Result<()> {
+        let crypto = self
+            .crypto_server()
+            .context("Peers can only be removed once the server keypair is known")?;
+        ensure!(
+            !peer.lower().get(crypto).removed,
+            "Peer {} has already been removed",
+            peer.0
+        );
+
+        // The peer goes away either way; a broker that is not reachable right now
+        // should not keep it alive
+        if let Err(e) = self.output_key(peer, KeyOutputReason::Stale, &SymKey::random()) {
+            warn!("Could not erase the key of removed peer {}: {e:?}", peer.0);
+        }
+
+        self.crypto_server_mut()?.remove_peer(peer.lower())?;
+        *peer.get_app_mut(self) = AppPeer::default();
+        self.metrics.forget_peer(peer);
+        Ok(())
+    }
+
+    /// The peers that have not been removed, see [Self::remove_peer]
+    pub fn live_peers(&self) -> Vec<AppPeerPtr> {
+        let crypto = self.crypto_site.product_ref();
+        (0..self.peers.len())
+            .map(AppPeerPtr)
+            .filter(|p| crypto.map_or(true, |crypto| !p.lower().get(crypto).removed))
+            .collect()
+    }
+
+    /// Describe the state of `peer`, or `None` if there is no such peer or it has been removed
+    pub fn peer_status(&self, peer: AppPeerPtr) -> Option<PeerStatus> {
+        let ap = self.peers.get(peer.0)?;
+
+        let (protocol_version, session_age, handshake_in_progress) = match &self.crypto_site {
+            ConstructionSite::Product(crypto) => {
+                let p = peer.lower().get(crypto);
+                if p.removed {
+                    return None;
+                }
+                let version = match p.protocol_version {
+                    crate::protocol::ProtocolVersion::V02 => ProtocolVersion::V02,
+                    crate::protocol::ProtocolVersion::V03 => ProtocolVersion::V03,
+                };
+                let age = p.session.as_ref().map(|s| {
+                    Duration::from_secs_f64((crypto.timebase.now() - s.created_at).max(0.0))
+                });
+                (version, age, p.handshake.is_some())
+            }
+            ConstructionSite::Builder(builder) => {
+                (builder.peers.get(peer.0)?.protocol_version, None, false)
+            }
+            ConstructionSite::Void => return None,
+        };
+
+        Some(PeerStatus {
+            name: ap.name.clone(),
+            configured_endpoint: ap.initial_endpoint.as_ref().map(|e| e.to_string()),
+            current_endpoint: ap.current_endpoint.as_ref().map(|e| e.to_string()),
+            protocol_version,
+            session_age,
+            handshake_in_progress,
+            since_key_exchange: self
+                .metrics
+                .last_key_exchange
+                .get(&peer.0)
+                .map(|t| t.elapsed()),
+            counters: self.metrics.peers.get(&peer.0).copied().unwrap_or_default(),
+        })
+    }
+
+    /// Wind the server down before the event loop returns
+    ///
+    /// With [ShutdownPolicy::Retire], the keys of all peers are overwritten with random ones,
+    /// exactly as with [KeyOutputReason::Stale]: through the broker, in the `key_out` file and
+    /// with a final `output-key … stale` notification. Afterwards, the [CryptoServer] is dropped,
+    /// which zeroizes all secrets it holds.
+    ///
+    /// Failing to retire the key of one peer does not stop the others from being retired.
+    pub fn shutdown(&mut self) -> anyhow::Result<()> {
+        self.notify_systemd_stopping();
+
+        if self.on_shutdown == ShutdownPolicy::Retire {
+            if let Some(crypto) = self.crypto_site.product_ref() {
+                let peers = (0..self.peers.len())
+                    .map(AppPeerPtr)
+                    .filter(|p| !p.lower().get(crypto).removed)
+                    .collect::<Vec<_>>();
+                for peer in peers {
+                    if let Err(e) = self.output_key(peer, KeyOutputReason::Stale, &SymKey::random())
+                    {
+                        warn!("Could not retire the key of peer {}: {e:?}", peer.0);
+                    }
+                }
+            }
+
+            // Give the brokers a chance to deliver the keys before we go away
+            for (_, broker) in self.brokers.store.iter_mut() {
+                if let Err(e) = broker.process_poll() {
+                    warn!("Could not flush the WireGuard broker: {e:?}");
+                }
+            }
+        }
+
+        // Send API clients what is still waiting for them, e.g. the response to a Shutdown request
+        #[cfg(feature = "experiment_api")]
+        {
+            use crate::api::mio::MioManagerContext;
+            MioManagerFocus(self).flush_connections_within(SHUTDOWN_API_FLUSH_TIMEOUT);
+        }
+
+        drop(self.crypto_site.take());
+        Ok(())
+    }
+
     /// Main IO handler; this generally does not terminate
     ///
     /// # Examples
@@ -1082,6 +1655,12 @@ impl AppServer {
                 Err(e) => e,
             };
 
+            // Retrying does not help; the server has to be restarted
+            if err.is::<KeyOracleFailed>() {
+                self.shutdown()?;
+                return Err(err);
+            }
+
             #[cfg(feature = "internal_signal_handling_for_coverage_reports")]
             {
                 let terminated_by_signal = err
@@ -1151,22 +1730,20 @@ impl AppServer {
             use AppPollResult::*;
             use KeyOutputReason::*;
 
-            if let Some(AppServerTest {
-                termination_handler: Some(terminate),
-                ..
-            }) = &self.test_helpers
-            {
-                if terminate.try_recv().is_ok() {
-                    return Ok(());
-                }
-            }
-
             enum CryptoSrv {
                 Avail,
                 Missing,
             }
 
             let poll_result = self.poll(&mut *rx)?;
+            let started = Instant::now();
+            let loop_event = match poll_result {
+                ReceivedMessage(_, _) => LoopEvent::Message,
+                SendInitiation(_) => LoopEvent::Initiation,
+                SendRetransmission(_) => LoopEvent::Retransmission,
+                DeleteKey(_) => LoopEvent::DeleteKey,
+                _ => LoopEvent::Other,
+            };
             let have_crypto = match self.crypto_site.is_available() {
                 true => CryptoSrv::Avail,
                 false => CryptoSrv::Missing,
@@ -1174,18 +1751,27 @@ impl AppServer {
 
             #[allow(clippy::redundant_closure_call)]
             match (have_crypto, poll_result) {
+                (_, Terminate) => return self.shutdown(),
+                (_, ReloadConfig) => self.reload_config_and_log(),
+
                 (CryptoSrv::Missing, SendInitiation(_)) => {}
                 (CryptoSrv::Avail, SendInitiation(peer)) => tx_maybe_with!(peer, || self
                     .crypto_server_mut()?
                     .initiate_handshake(peer.lower(), &mut *tx))?,
 
                 (CryptoSrv::Missing, SendRetransmission(_)) => {}
-                (CryptoSrv::Avail, SendRetransmission(peer)) => tx_maybe_with!(peer, || self
-                    .crypto_server_mut()?
-                    .retransmit_handshake(peer.lower(), &mut *tx))?,
+                (CryptoSrv::Avail, SendRetransmission(peer)) => {
+                    self.metrics.retransmissions += 1;
+                    self.metrics.peer_mut(peer).retransmissions += 1;
+                    self.resolver.note_retransmission(peer);
+                    tx_maybe_with!(peer, || self
+                        .crypto_server_mut()?
+                        .retransmit_handshake(peer.lower(), &mut *tx))?
+                }
 
                 (CryptoSrv::Missing, DeleteKey(_)) => {}
                 (CryptoSrv::Avail, DeleteKey(peer)) => {
+                    self.metrics.peer_mut(peer).keys_stale += 1;
                     self.output_key(peer, Stale, &SymKey::random())?;
 
                     // There was a loss of connection apparently; restart host discovery
@@ -1201,6 +1787,13 @@ impl AppServer {
 
                 (CryptoSrv::Missing, ReceivedMessage(_, _)) => {}
                 (CryptoSrv::Avail, ReceivedMessage(len, endpoint)) => {
+                    let msg_type = self.metrics.record_message(&rx[..len]);
+                    let span = LogSpan::enter("handshake")
+                        .with("endpoint", &endpoint)
+                        .with(
+                            "msg_type",
+                            msg_type.map_or("unknown".to_owned(), |t| format!("{t:?}")),
+                        );
                     let msg_result = match self.under_load {
                         DoSOperation::UnderLoad => {
                             self.handle_msg_under_load(&endpoint, &rx[..len], &mut *tx)
@@ -1209,16 +1802,28 @@ impl AppServer {
                             self.crypto_server_mut()?.handle_msg(&rx[..len], &mut *tx)
                         }
                     };
+                    self.metrics.record_message_result(
+                        msg_type,
+                        self.under_load,
+                        &msg_result,
+                        &tx[..],
+                    );
                     match msg_result {
                         Err(ref e) => {
                             self.verbose().then(|| {
+                                let kind = RejectReason::classify(msg_type, self.under_load, e);
                                 info!(
+                                    error_kind = kind.label();
                                     "error processing incoming message from {}: {:?} {}",
                                     endpoint,
                                     e,
                                     e.backtrace()
                                 );
                             });
+                            self.emit_handshake_failed(&endpoint);
+                            if e.is::<KeyOracleFailed>() {
+                                return Err(KeyOracleFailed.into());
+                            }
                         }
 
                         Ok(HandleMsgResult {
@@ -1232,7 +1837,21 @@ impl AppServer {
 
                             if let Some(p) = exchanged_with {
                                 let ap = AppPeerPtr::lift(p);
-                                ap.get_app_mut(self).current_endpoint = Some(endpoint);
+                                span.record("peer", self.peer_log_id(ap)?);
+                                if let Some(name) = ap.get_app(self).name.as_ref() {
+                                    span.record("peer_name", name);
+                                }
+                                self.record_path_success(ap, &endpoint);
+                                let new_endpoint = endpoint.to_string();
+                                let previous =
+                                    ap.get_app_mut(self).current_endpoint.replace(endpoint);
+                                if previous.map(|e| e.to_string()).as_ref() != Some(&new_endpoint) {
+                                    self.emit_event(ServerEvent::EndpointChanged {
+                                        peer: ap,
+                                        endpoint: new_endpoint,
+                                    });
+                                }
+                                self.resolver.note_exchange(ap);
 
                                 // TODO: Maybe we should rather call the key "rosenpass output"?
                                 let osk = &self.crypto_server_mut()?.osk(p)?;
@@ -1242,6 +1861,8 @@ impl AppServer {
                     }
                 }
             };
+
+            self.metrics.record_latency(loop_event, started.elapsed());
         }
     }
 
@@ -1271,31 +1892,34 @@ impl AppServer {
         why: KeyOutputReason,
         key: &SymKey,
     ) -> anyhow::Result<()> {
-        let peerid = peer.lower().get(self.crypto_server()?).pidt()?;
+        let peerid = self.peer_log_id(peer)?;
+        let ap = peer.get_app(self);
+        let encoded = EncodedKey::new(key, ap.key_format, &peerid, why)?;
 
         if self.verbose() {
             let msg = match why {
                 KeyOutputReason::Exchanged => "Exchanged key with peer",
                 KeyOutputReason::Stale => "Erasing outdated key from peer",
             };
-            info!("{} {}", msg, peerid.fmt_b64::<MAX_B64_PEER_ID_SIZE>());
+            info!(
+                peer = peerid,
+                peer_name = ap.name,
+                reason = why.as_str();
+                "{msg} {peerid}"
+            );
         }
 
-        let ap = peer.get_app(self);
-
         if let Some(of) = ap.outfile.as_ref() {
-            // This might leave some fragments of the secret on the stack;
-            // in practice this is likely not a problem because the stack likely
-            // will be overwritten by something else soon but this is not exactly
-            // guaranteed. It would be possible to remedy this, but since the secret
-            // data will linger in the linux page cache anyways with the current
-            // implementation, going to great length to erase the secret here is
-            // not worth it right now.
-            key.store_b64::<MAX_B64_KEY_SIZE, _>(of)?;
-            let why = match why {
-                KeyOutputReason::Exchanged => "exchanged",
-                KeyOutputReason::Stale => "stale",
-            };
+            match why {
+                KeyOutputReason::Exchanged => rotate_previous(of, ap.outfile_previous)?,
+                // Once a key is outdated, the keys before it are even more so
+                KeyOutputReason::Stale => remove_previous(of, ap.outfile_previous)?,
+            }
+
+            // Consumers polling the file must never see a partially written key, so the file
+            // is replaced rather than overwritten. The secret data will still linger in the
+            // page cache; use a key sink (see [crate::key_out]) to avoid that.
+            store_atomic(of, Visibility::Secret, |f| f.write_all(encoded.as_bytes()))?;
 
             // this is intentionally writing to stdout instead of stderr, because
             // it is meant to allow external detection of a successful key-exchange
@@ -1303,17 +1927,47 @@ impl AppServer {
             let mut stdout = stdout.lock();
             writeln!(
                 stdout,
-                "output-key peer {} key-file {of:?} {why}",
-                peerid.fmt_b64::<MAX_B64_PEER_ID_SIZE>()
+                "output-key peer {peerid} key-file {of:?} {}",
+                why.as_str()
             )?;
             stdout.flush()?;
         }
 
+        self.write_key_sinks(peer, why, &encoded)?;
         peer.set_psk(self, key)?;
+        self.run_key_hook(peer, why, &encoded);
+
+        if let Some(AppServerTest {
+            event_sink: Some(sink),
+            ..
+        }) = &self.test_helpers
+        {
+            // The receiving end going away just means nobody is listening anymore
+            let _ = sink.send(AppServerTestEvent::KeyOutput { peer, why });
+        }
+
+        self.emit_event(ServerEvent::KeyOutput { peer, why });
 
         Ok(())
     }
 
+    /// The peer id of `peer` in base64, as used to identify peers in the logs
+    pub fn peer_log_id(&self, peer: AppPeerPtr) -> anyhow::Result<String> {
+        let peerid = peer.lower().get(self.crypto_server()?).pidt()?;
+        Ok(peerid.fmt_b64::<MAX_B64_PEER_ID_SIZE>().to_string())
+    }
+
+    /// Checks whether termination was requested through [AppServerTest::termination_handler]
+    fn termination_requested(&self) -> bool {
+        match &self.test_helpers {
+            Some(AppServerTest {
+                termination_handler: Some(terminate),
+                ..
+            }) => terminate.try_recv().is_ok(),
+            _ => false,
+        }
+    }
+
     /// Poll for events from the cryptographic server ([Self::crypto_server()])
     /// and for IO events through [Self::poll].
     ///
@@ -1322,6 +1976,18 @@ impl AppServer {
         use crate::protocol::PollResult as C;
         use AppPollResult as A;
         let res = loop {
+            if self.shutdown_requested || self.termination_requested() {
+                break A::Terminate;
+            }
+            if let Some(signals) = &self.signal_handler {
+                if signals.terminate_requested() {
+                    break A::Terminate;
+                }
+                if signals.take_reload_request() {
+                    break A::ReloadConfig;
+                }
+            }
+
             // Call CryptoServer's poll (if available)
             let crypto_poll = self
                 .crypto_site
@@ -1340,6 +2006,43 @@ impl AppServer {
                 None => crate::protocol::UNENDING,  // Crypto server is uninitialized, do IO
             };
 
+            // Wake up in time for the next metrics textfile
+            let io_poll_timeout = match self.write_metrics_textfile_if_due() {
+                Some(due) => io_poll_timeout.min(due.as_secs_f64()),
+                None => io_poll_timeout,
+            };
+
+            // Check on running hooks regularly
+            let io_poll_timeout = match self.hook_runner.reap() {
+                Some(due) => io_poll_timeout.min(due.as_secs_f64()),
+                None => io_poll_timeout,
+            };
+
+            // Race handshake messages through the other address family when it is time
+            let io_poll_timeout = match self.send_due_races() {
+                Some(due) => io_poll_timeout.min(due.as_secs_f64()),
+                None => io_poll_timeout,
+            };
+
+            // Answer API clients that waited in vain for a key exchange
+            #[cfg(feature = "experiment_api")]
+            let io_poll_timeout = match self.expire_api_rekey_waits() {
+                Some(due) => io_poll_timeout.min(due.as_secs_f64()),
+                None => io_poll_timeout,
+            };
+
+            // Look up host names of peers again, and pick up the results
+            let io_poll_timeout = match self.resolve_endpoints_if_due() {
+                Some(due) => io_poll_timeout.min(due.as_secs_f64()),
+                None => io_poll_timeout,
+            };
+
+            // Keep the service manager posted and its watchdog happy
+            let io_poll_timeout = match self.notify_systemd_if_due() {
+                Some(due) => io_poll_timeout.min(due.as_secs_f64()),
+                None => io_poll_timeout,
+            };
+
             // Perform IO (look for a message)
             if let Some((len, addr)) = self.try_recv(rx_buf, io_poll_timeout)? {
                 break A::ReceivedMessage(len, addr);
@@ -1414,6 +2117,7 @@ impl AppServer {
             Ok(())
         })?;
 
+        let was_under_load = self.under_load;
         if let Some(AppServerTest {
             enable_dos_permanently: true,
             ..
@@ -1446,6 +2150,11 @@ impl AppServer {
                 self.unpolled_count = 0;
             }
         }
+        if self.under_load != was_under_load {
+            self.emit_event(ServerEvent::UnderLoadChanged {
+                under_load: self.under_load == DoSOperation::UnderLoad,
+            });
+        }
 
         // Focused polling – i.e. actually using mio::Token – is experimental for now.
         // The reason for this is that we need to figure out how to integrate load detection
@@ -1507,7 +2216,10 @@ impl AppServer {
 
         // Process brokers poll
         for (_, broker) in self.brokers.store.iter_mut() {
-            broker.process_poll()?;
+            if let Err(e) = broker.process_poll() {
+                self.metrics.broker_failures += 1;
+                return Err(e);
+            }
         }
 
         // API poll
@@ -1518,6 +2230,8 @@ impl AppServer {
             MioManagerFocus(self).poll()?;
         }
 
+        self.poll_metrics()?;
+
         self.performed_long_poll = true;
 
         Ok(None)
@@ -1525,7 +2239,14 @@ impl AppServer {
 
     /// Internal helper for [Self::try_recv]
     fn perform_mio_poll_and_register_events(&mut self, timeout: Duration) -> io::Result<()> {
-        self.mio_poll.poll(&mut self.events, Some(timeout))?;
+        match self.mio_poll.poll(&mut self.events, Some(timeout)) {
+            // Interrupted by a signal; the signals we handle wake us up again
+            // through AppServerIoSource::Signals
+            Err(e) if e.kind() == ErrorKind::Interrupted && !self.terminated_by_signal() => {
+                return Ok(())
+            }
+            r => r?,
+        }
         // Fill the short poll buffer with the acquired events
         self.events
             .iter()
@@ -1534,6 +2255,16 @@ impl AppServer {
         Ok(())
     }
 
+    /// Whether an interrupted poll should be treated as termination; see [Self::term_signal]
+    ///
+    /// With [Self::signal_handler] installed, termination goes through [Self::shutdown] instead.
+    fn terminated_by_signal(&self) -> bool {
+        #[cfg(feature = "internal_signal_handling_for_coverage_reports")]
+        return self.signal_handler.is_none() && self.term_signal.value();
+        #[cfg(not(feature = "internal_signal_handling_for_coverage_reports"))]
+        return false;
+    }
+
     /// Internal helper for [Self::try_recv]
     fn try_recv_from_mio_token(
         &mut self,
@@ -1563,13 +2294,18 @@ impl AppServer {
                 .substitute_for_ioerr_wouldblock(None)?
                 .ok(),
 
-            AppServerIoSource::PskBroker(key) => self
-                .brokers
-                .store
-                .get_mut(&key)
-                .with_context(|| format!("No PSK broker under key {key:?}"))?
-                .process_poll()
-                .map(|_| None),
+            AppServerIoSource::PskBroker(key) => {
+                let res = self
+                    .brokers
+                    .store
+                    .get_mut(&key)
+                    .with_context(|| format!("No PSK broker under key {key:?}"))?
+                    .process_poll();
+                if res.is_err() {
+                    self.metrics.broker_failures += 1;
+                }
+                res.map(|_| None)
+            }
 
             #[cfg(feature = "experiment_api")]
             AppServerIoSource::MioManager(mmio_src) => {
@@ -1579,6 +2315,21 @@ impl AppServer {
                     .poll_particular(mmio_src)
                     .map(|_| None)
             }
+
+            // Waking up is the whole point; the caller re-checks everything anyway
+            AppServerIoSource::Waker => Ok(None),
+
+            AppServerIoSource::Signals => {
+                if let Some(signals) = self.signal_handler.as_mut() {
+                    signals.drain()?;
+                }
+                Ok(None)
+            }
+
+            AppServerIoSource::Metrics(metrics_src) => {
+                self.poll_metrics_io_source(metrics_src)?;
+                Ok(None)
+            }
         }
     }
 
@@ -1589,21 +2340,72 @@ impl AppServer {
         idx: usize,
     ) -> io::Result<Option<(usize, Endpoint)>> {
         use std::io::ErrorKind as K;
-        let (n, addr) = loop {
-            match self.sockets[idx].recv_from(buf).io_err_kind_hint() {
+        let (n, addr, local) = loop {
+            match crate::pktinfo::recv_from(&self.sockets[idx], buf).io_err_kind_hint() {
                 Ok(v) => break v,
                 Err((_, K::Interrupted)) => continue,
                 Err((e, _)) => return Err(e)?,
             }
         };
         SocketPtr(idx)
-            .apply(|sp| SocketBoundEndpoint::new(sp, addr))
+            .apply(|sp| SocketBoundEndpoint::new(sp, addr).with_local(local))
             .apply(Endpoint::SocketBoundAddress)
             .apply(|ep| (n, ep))
             .some()
             .ok()
     }
 
+    /// Push an event to the API clients that subscribed to events
+    ///
+    /// The event is queued on each subscribed connection and written as far as the sockets
+    /// accept it without blocking; the rest follows whenever the API is polled.
+    pub fn emit_event(&mut self, event: ServerEvent) {
+        #[cfg(feature = "experiment_api")]
+        {
+            use crate::api::mio::MioManagerContext;
+            MioManagerFocus(self).push_event(&event);
+        }
+        #[cfg(not(feature = "experiment_api"))]
+        let _ = event;
+    }
+
+    /// Emit [ServerEvent::HandshakeFailed] for a message from `endpoint`, unless one was emitted
+    /// less than [HANDSHAKE_FAILED_EVENT_INTERVAL] ago
+    fn emit_handshake_failed(&mut self, endpoint: &Endpoint) {
+        let now = Instant::now();
+        let recent = self
+            .last_handshake_failed_event
+            .is_some_and(|t| now.duration_since(t) < HANDSHAKE_FAILED_EVENT_INTERVAL);
+        if recent {
+            return;
+        }
+        self.last_handshake_failed_event = Some(now);
+        self.emit_event(ServerEvent::HandshakeFailed {
+            endpoint: endpoint.to_string(),
+        });
+    }
+
+    /// Send `key` to the API clients registered as key sink for `peer`; see
+    /// [crate::api::Server::register_key_sink]
+    #[cfg(feature = "experiment_api")]
+    pub fn send_key_to_api(
+        &mut self,
+        peer: AppPeerPtr,
+        why: KeyOutputReason,
+        key: &crate::key_out::EncodedKey,
+    ) {
+        use crate::api::mio::MioManagerContext;
+        MioManagerFocus(self).push_key(peer, why, key);
+    }
+
+    /// Answer rekey requests on the API whose time is up; returns how long until the next one
+    /// times out
+    #[cfg(feature = "experiment_api")]
+    fn expire_api_rekey_waits(&mut self) -> Option<Duration> {
+        use crate::api::mio::MioManagerContext;
+        MioManagerFocus(self).expire_rekey_waits()
+    }
+
     #[cfg(feature = "experiment_api")]
     pub fn add_api_connection(&mut self, connection: mio::net::UnixStream) -> std::io::Result<()> {
         use crate::api::mio::MioManagerContext;
@@ -88,6 +88,26 @@ fn main() -> Result<()> {
                 Tree::Leaf("Add Listen Socket Response".to_owned()),
                 Tree::Leaf("Add Psk Broker Request".to_owned()),
                 Tree::Leaf("Add Psk Broker Response".to_owned()),
+                Tree::Leaf("Reload Config Request".to_owned()),
+                Tree::Leaf("Reload Config Response".to_owned()),
+                Tree::Leaf("Add Peer Request".to_owned()),
+                Tree::Leaf("Add Peer Response".to_owned()),
+                Tree::Leaf("Remove Peer Request".to_owned()),
+                Tree::Leaf("Remove Peer Response".to_owned()),
+                Tree::Leaf("List Peers Request".to_owned()),
+                Tree::Leaf("List Peers Response".to_owned()),
+                Tree::Leaf("Get Peer Status Request".to_owned()),
+                Tree::Leaf("Get Peer Status Response".to_owned()),
+                Tree::Leaf("Subscribe Events Request".to_owned()),
+                Tree::Leaf("Subscribe Events Response".to_owned()),
+                Tree::Leaf("Register Key Sink Request".to_owned()),
+                Tree::Leaf("Register Key Sink Response".to_owned()),
+                Tree::Leaf("Rekey Request".to_owned()),
+                Tree::Leaf("Rekey Response".to_owned()),
+                Tree::Leaf("Shutdown Request".to_owned()),
+                Tree::Leaf("Shutdown Response".to_owned()),
+                Tree::Leaf("Version Request".to_owned()),
+                Tree::Leaf("Version Response".to_owned()),
             ],
         )],
     );
@@ -8,16 +8,20 @@ use clap::{Parser, Subcommand};
 use rosenpass_cipher_traits::primitives::Kem;
 use rosenpass_ciphers::StaticKem;
 use rosenpass_secret_memory::file::StoreSecret;
-use rosenpass_util::file::{LoadValue, LoadValueB64, StoreValue};
-use rosenpass_wireguard_broker::brokers::native_unix::{
-    NativeUnixBroker, NativeUnixBrokerConfigBaseBuilder, NativeUnixBrokerConfigBaseBuilderError,
-};
+use rosenpass_util::file::{LoadValue, StoreValue};
+use rosenpass_wireguard_broker::brokers::native_unix::NativeUnixBroker;
 use std::ops::DerefMut;
 use std::path::PathBuf;
 
+use crate::app_server::AppServer;
 use crate::app_server::AppServerTest;
-use crate::app_server::{AppServer, BrokerPeer};
-use crate::protocol::{SPk, SSk, SymKey};
+use crate::credentials::load_secret;
+use crate::key_oracle::KeyOracleClient;
+use crate::landlock::Confinement;
+use crate::privileges::{Capability, PrivilegeDrop};
+use crate::protocol::{SPk, SSk};
+use crate::reload::ConfigReload;
+use crate::systemd::{ListenFds, SystemdNotifier};
 
 use super::config;
 
@@ -32,7 +36,7 @@ use {
     rustix::net::{socketpair, AddressFamily, SocketFlags, SocketType},
     std::os::fd::AsRawFd,
     std::os::unix::net,
-    std::process::Command,
+    std::process::{Child, Command},
     std::thread,
 };
 
@@ -70,6 +74,10 @@ pub struct CliArgs {
     #[arg(short, long, group = "log-level")]
     quiet: bool,
 
+    /// Format of the log output; overrides the `log_format` from the configuration file
+    #[arg(long = "log-format", value_name = "FORMAT")]
+    log_format: Option<crate::logging::LogFormat>,
+
     #[command(flatten)]
     #[cfg(feature = "experiment_api")]
     api: crate::api::cli::ApiCli,
@@ -118,9 +126,12 @@ impl CliArgs {
     ///
     /// Generally the flow of control here is that all the command line parameters
     /// are merged into the configuration file to avoid much code duplication.
-    pub fn apply_to_config(&self, _cfg: &mut config::Rosenpass) -> anyhow::Result<()> {
+    pub fn apply_to_config(&self, cfg: &mut config::Rosenpass) -> anyhow::Result<()> {
+        if let Some(log_format) = self.log_format {
+            cfg.log_format = log_format;
+        }
         #[cfg(feature = "experiment_api")]
-        self.api.apply_to_config(_cfg)?;
+        self.api.apply_to_config(cfg)?;
         Ok(())
     }
 
@@ -143,6 +154,12 @@ impl CliArgs {
         None
     }
 
+    /// returns the log format set by CLI args
+    /// returns `None` if the user did not specify a log format via CLI
+    pub fn get_log_format(&self) -> Option<crate::logging::LogFormat> {
+        self.log_format
+    }
+
     /// Return the WireGuard PSK broker interface configured.
     ///
     /// Returns `None` if the `experiment_api` feature is disabled.
@@ -254,6 +271,43 @@ pub enum CliCommand {
     /// Defined secret & public keys are checked for existence and validity.
     Validate { config_files: Vec<PathBuf> },
 
+    /// Run a key oracle holding the secret key of a Rosenpass server
+    ///
+    /// The server sends the oracle the ciphertexts that need the secret key
+    /// and never loads the key itself. See the `key_oracle` option of the
+    /// configuration file.
+    KeyOracle {
+        /// Where to read the secret key from
+        #[clap(short, long)]
+        secret_key: PathBuf,
+
+        /// Listen for servers on this unix socket
+        #[clap(long, group = "oracle-socket")]
+        listen: Option<PathBuf>,
+
+        /// Serve the server on this inherited socket; used by `key_oracle = "spawn"`
+        #[clap(long, group = "oracle-socket", hide = true)]
+        stream_fd: Option<i32>,
+    },
+
+    /// Make a running Rosenpass server exchange a new key with a peer right away
+    ///
+    /// Talks to the server through its API socket. Peers are numbered in the
+    /// order they were added, starting with the ones from the configuration file.
+    #[cfg(feature = "experiment_api")]
+    Rekey {
+        /// The unix socket the server listens for API connections on
+        #[clap(long)]
+        api: PathBuf,
+
+        /// Number of the peer to exchange a key with
+        peer_id: u64,
+
+        /// Wait up to this many seconds for the key exchange to succeed
+        #[clap(long)]
+        wait: Option<u64>,
+    },
+
     /// DEPRECATED - use the gen-keys command instead
     #[allow(rustdoc::broken_intra_doc_links)]
     #[allow(rustdoc::invalid_html_tags)]
@@ -407,6 +461,20 @@ impl CliArgs {
                 Self::event_loop(config, broker_interface, test_helpers)?;
             }
 
+            Some(KeyOracle {
+                secret_key,
+                listen,
+                stream_fd,
+            }) => {
+                crate::key_oracle::run(secret_key, listen.as_deref(), *stream_fd)?;
+            }
+
+            #[cfg(feature = "experiment_api")]
+            Some(Rekey { api, peer_id, wait }) => {
+                let wait = wait.map(std::time::Duration::from_secs);
+                crate::api::client::rekey(api, *peer_id, wait)?;
+            }
+
             Some(Validate { config_files }) => {
                 for file in config_files {
                     match config::Rosenpass::load(file) {
@@ -434,66 +502,100 @@ impl CliArgs {
         broker_interface: Option<BrokerInterface>,
         test_helpers: Option<AppServerTest>,
     ) -> anyhow::Result<()> {
-        const MAX_PSK_SIZE: usize = 1000;
+        crate::logging::set_format(config.log_format);
 
-        // load own keys
+        // start the key oracle first, so it is not affected by the sandbox set up below
+        let mut key_oracle = match (&config.key_oracle, &config.keypair) {
+            (Some(oracle), Some(kp)) => Some(KeyOracleClient::from_config(oracle, &kp.secret_key)?),
+            _ => None,
+        };
+
+        // load own keys; with a key oracle, only the public key
         let keypair = config
             .keypair
             .as_ref()
             .map(|kp| -> anyhow::Result<_> {
-                let sk = SSk::load(&kp.secret_key)?;
                 let pk = SPk::load(&kp.public_key)?;
+                let sk: SSk = match key_oracle.as_mut() {
+                    Some(oracle) => {
+                        oracle.verify(&pk)?;
+                        SSk::zero()
+                    }
+                    None => load_secret(&kp.secret_key)?,
+                };
                 Ok((sk, pk))
             })
             .transpose()?;
 
+        // who to switch to once everything is set up; the built-in broker runs `wg` itself and
+        // needs to keep the right to configure WireGuard devices
+        let native_broker = cfg!(not(feature = "experiment_api")) || broker_interface.is_none();
+        let runs_wg = native_broker && config.peers.iter().any(|p| p.wg.is_some());
+        let privileges = config
+            .user
+            .as_deref()
+            .map(|user| {
+                let retain = match runs_wg {
+                    true => vec![Capability::NetAdmin],
+                    false => vec![],
+                };
+                PrivilegeDrop::lookup(user, config.group.as_deref(), retain)
+            })
+            .transpose()?;
+
+        // the sandbox is set up last, right before entering the event loop
+        let confinement = match config.landlock {
+            true => Some(Confinement::from_config(&config, runs_wg)?),
+            false => None,
+        };
+        let seccomp = config.seccomp;
+        let starts_programs = native_broker || config.has_hooks();
+
+        // take over the sockets systemd passed in through socket activation, if any
+        let mut activated = ListenFds::from_env()?;
+
         // start an application server
-        let mut srv = std::boxed::Box::<AppServer>::new(AppServer::new(
+        let mut srv = std::boxed::Box::<AppServer>::new(AppServer::with_sockets(
             keypair,
             config.listen.clone(),
+            std::mem::take(&mut activated.udp),
+            config.listen_options.clone(),
             config.verbosity,
             test_helpers,
         )?);
 
+        if let Some(oracle) = key_oracle {
+            srv.crypto_server_mut()?.static_key_oracle = Some(Box::new(oracle));
+        }
         config.apply_to_app_server(&mut srv)?;
+        activated.apply_to_app_server(&mut srv)?;
+        srv.systemd = SystemdNotifier::from_env()?;
 
-        let broker = Self::create_broker(broker_interface)?;
+        let (broker, broker_process) = Self::create_broker(broker_interface)?;
         let broker_store_ptr = srv.register_broker(broker)?;
 
-        fn cfg_err_map(e: NativeUnixBrokerConfigBaseBuilderError) -> anyhow::Error {
-            anyhow::Error::msg(format!("NativeUnixBrokerConfigBaseBuilderError: {:?}", e))
-        }
-
-        for cfg_peer in config.peers {
-            let broker_peer = if let Some(wg) = &cfg_peer.wg {
-                let peer_cfg = NativeUnixBrokerConfigBaseBuilder::default()
-                    .peer_id_b64(&wg.peer)?
-                    .interface(wg.device.clone())
-                    .extra_params_ser(&wg.extra_params)?
-                    .build()
-                    .map_err(cfg_err_map)?;
+        let reload = ConfigReload::apply(&mut srv, config, broker_store_ptr)?;
+        srv.config_reload = Some(reload);
 
-                let broker_peer = BrokerPeer::new(broker_store_ptr.clone(), Box::new(peer_cfg));
-
-                Some(broker_peer)
-            } else {
-                None
-            };
-
-            srv.add_peer(
-                // psk, pk, outfile, outwg, tx_addr
-                cfg_peer
-                    .pre_shared_key
-                    .map(SymKey::load_b64::<MAX_PSK_SIZE, _>)
-                    .transpose()?,
-                SPk::load(&cfg_peer.public_key)?,
-                cfg_peer.key_out,
-                broker_peer,
-                cfg_peer.endpoint.clone(),
-                cfg_peer.protocol_version.into(),
-            )?;
+        if let Some(privileges) = privileges {
+            privileges
+                .apply()
+                .context("Could not drop privileges; refusing to continue")?;
         }
+        srv.enable_signal_handling()?;
 
+        if let Some(confinement) = confinement {
+            confinement.apply()?;
+        }
+        // Landlock only confines threads started after it was applied, so all threads that
+        // outlive the setup are started here
+        #[cfg(feature = "experiment_api")]
+        if let Some(child) = broker_process {
+            Self::watch_broker_process(child);
+        }
+        #[cfg(not(feature = "experiment_api"))]
+        let _ = broker_process;
+        crate::seccomp::install(seccomp, starts_programs)?;
         srv.event_loop()
     }
 
@@ -510,18 +612,25 @@ impl CliArgs {
     /// feature flag is not set, then this returns a [NativeUnixBroker],
     /// sending pre-shared keys directly to WireGuard from within this
     /// process.
+    ///
+    /// Also returns the PSK broker process, if it was spawned; see
+    /// [Self::watch_broker_process].
     #[cfg(feature = "experiment_api")]
+    #[allow(clippy::type_complexity)]
     fn create_broker(
         broker_interface: Option<BrokerInterface>,
     ) -> Result<
-        Box<dyn WireguardBrokerMio<MioError = anyhow::Error, Error = anyhow::Error>>,
+        (
+            Box<dyn WireguardBrokerMio<MioError = anyhow::Error, Error = anyhow::Error>>,
+            Option<Child>,
+        ),
         anyhow::Error,
     > {
         if let Some(interface) = broker_interface {
-            let socket = Self::get_broker_socket(interface)?;
-            Ok(Box::new(MioBrokerClient::new(socket)))
+            let (socket, child) = Self::get_broker_socket(interface)?;
+            Ok((Box::new(MioBrokerClient::new(socket)), child))
         } else {
-            Ok(Box::new(NativeUnixBroker::new()))
+            Ok((Box::new(NativeUnixBroker::new()), None))
         }
     }
 
@@ -541,25 +650,29 @@ impl CliArgs {
     #[cfg(not(feature = "experiment_api"))]
     fn create_broker(
         _broker_interface: Option<BrokerInterface>,
-    ) -> Result<Box<NativeUnixBroker>, anyhow::Error> {
-        Ok(Box::new(NativeUnixBroker::new()))
+    ) -> Result<(Box<NativeUnixBroker>, Option<std::process::Child>), anyhow::Error> {
+        Ok((Box::new(NativeUnixBroker::new()), None))
     }
 
     /// Used by [Self::create_broker] if the `experiment_api` is configured
     /// to set up the connection with the PSK broker process as configured
     /// via the `psk_broker_path`, `psk_broker_fd`, and `psk_broker_spawn`
     /// fields.
+    ///
+    /// Also returns the PSK broker process, if it was spawned.
     #[cfg(feature = "experiment_api")]
-    fn get_broker_socket(broker_interface: BrokerInterface) -> Result<UnixStream, anyhow::Error> {
+    fn get_broker_socket(
+        broker_interface: BrokerInterface,
+    ) -> Result<(UnixStream, Option<Child>), anyhow::Error> {
         // Connect to the psk broker unix socket if one was specified
         // OR OTHERWISE spawn the psk broker and use socketpair(2) to connect with them
         match broker_interface {
-            BrokerInterface::Socket(broker_path) => Ok(UnixStream::connect(broker_path)?),
+            BrokerInterface::Socket(broker_path) => Ok((UnixStream::connect(broker_path)?, None)),
             BrokerInterface::FileDescriptor(broker_fd) => {
                 // mio::net::UnixStream doesn't implement From<OwnedFd>, so we have to go through std
                 let sock = net::UnixStream::from(claim_fd(broker_fd)?);
                 sock.set_nonblocking(true)?;
-                Ok(UnixStream::from_std(sock))
+                Ok((UnixStream::from_std(sock), None))
             }
             BrokerInterface::SocketPair => {
                 // Form a socketpair for communicating to the broker
@@ -575,7 +688,7 @@ impl CliArgs {
                 ours.set_nonblocking(true)?;
 
                 // Start the PSK broker
-                let mut child = Command::new("rosenpass-wireguard-broker-socket-handler")
+                let child = Command::new("rosenpass-wireguard-broker-socket-handler")
                     .args(["--stream-fd", "3"])
                     .fd_mappings(vec![FdMapping {
                         parent_fd: theirs.as_raw_fd(),
@@ -583,26 +696,33 @@ impl CliArgs {
                     }])?
                     .spawn()?;
 
-                // Handle the PSK broker crashing
-                thread::spawn(move || {
-                    let status = child.wait();
-
-                    if let Ok(status) = status {
-                        if status.success() {
-                            // Maybe they are doing double forking?
-                            info!("PSK broker exited.");
-                        } else {
-                            error!("PSK broker exited with an error ({status:?})");
-                        }
-                    } else {
-                        error!("Wait on PSK broker process failed ({status:?})");
-                    }
-                });
-
-                Ok(UnixStream::from_std(ours))
+                Ok((UnixStream::from_std(ours), Some(child)))
             }
         }
     }
+
+    /// Log when the PSK broker process spawned by [Self::get_broker_socket] exits
+    ///
+    /// Waits on a separate thread; called only once the sandbox is set up, so the thread is
+    /// confined as well.
+    #[cfg(feature = "experiment_api")]
+    fn watch_broker_process(mut child: Child) {
+        // Handle the PSK broker crashing
+        thread::spawn(move || {
+            let status = child.wait();
+
+            if let Ok(status) = status {
+                if status.success() {
+                    // Maybe they are doing double forking?
+                    info!("PSK broker exited.");
+                } else {
+                    error!("PSK broker exited with an error ({status:?})");
+                }
+            } else {
+                error!("Wait on PSK broker process failed ({status:?})");
+            }
+        });
+    }
 }
 
 /// generate secret and public keys, store in files according to the paths passed as arguments
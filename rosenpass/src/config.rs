@@ -17,11 +17,25 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use anyhow::{bail, ensure};
+use anyhow::{bail, ensure, Context};
 use rosenpass_util::file::{fopen_w, Visibility};
 use serde::{Deserialize, Serialize};
 
 use crate::app_server::AppServer;
+use crate::credentials::{load_secret, SecretSource};
+use crate::hooks::Hooks;
+use crate::key_oracle::KeyOracleConfig;
+use crate::key_out::{KeyOutFormat, KeySinkConfig};
+use crate::logging::LogFormat;
+use crate::metrics::config::MetricsConfig;
+use crate::privileges::PrivilegeDrop;
+use crate::seccomp::SeccompMode;
+use crate::socket_options::SocketOptions;
+
+/// Used to leave counts that are zero out of stored configuration files
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
 
 #[cfg(feature = "experiment_api")]
 fn empty_api_config() -> crate::api::config::ApiConfig {
@@ -56,12 +70,70 @@ pub struct Rosenpass {
     /// - `[::]:4476` – Listen on any IPv4 or IPv6 interface, port 4476
     pub listen: Vec<SocketAddr>,
 
+    /// socket options for all listen sockets
+    ///
+    /// See [`crate::socket_options`] for details.
+    #[serde(default, skip_serializing_if = "SocketOptions::is_default")]
+    pub listen_options: SocketOptions,
+
+    /// user to switch to once the server is set up
+    ///
+    /// A user name or numeric id. See [`crate::privileges`] for details.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+
+    /// group to switch to together with [`Self::user`]; defaults to the primary group of the user
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+
+    /// whether to restrict the system calls the server may make
+    ///
+    /// See [`crate::seccomp`] for details.
+    #[serde(default, skip_serializing_if = "SeccompMode::is_off")]
+    pub seccomp: SeccompMode,
+
+    /// whether to restrict file system access to the paths in this configuration
+    ///
+    /// See [`crate::landlock`] for details.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub landlock: bool,
+
+    /// where to decapsulate with the secret key, instead of in the server process
+    ///
+    /// See [`crate::key_oracle`] for details.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_oracle: Option<KeyOracleConfig>,
+
     /// log verbosity
     ///
     /// This is subject to change. See [`Verbosity`] for details.
     #[serde(default)]
     pub verbosity: Verbosity,
 
+    /// format of the log output
+    ///
+    /// See [`LogFormat`] for details.
+    #[serde(default, skip_serializing_if = "LogFormat::is_text")]
+    pub log_format: LogFormat,
+
+    /// what to do with the exported keys when Rosenpass is stopped
+    ///
+    /// See [`ShutdownPolicy`] for details.
+    #[serde(default, skip_serializing_if = "ShutdownPolicy::is_keep")]
+    pub on_shutdown: ShutdownPolicy,
+
+    /// where to export metrics to
+    ///
+    /// See [`crate::metrics::config::MetricsConfig`] for details.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<MetricsConfig>,
+
+    /// programs to run when keys are exchanged or go stale, for peers without hooks of their own
+    ///
+    /// See [`crate::hooks`] for details.
+    #[serde(flatten)]
+    pub hooks: Hooks,
+
     /// list of peers
     ///
     /// See the [`RosenpassPeer`] type for more information and examples.
@@ -109,6 +181,25 @@ pub enum Verbosity {
     Verbose,
 }
 
+/// What [crate::app_server::AppServer::shutdown] does with the keys exported to WireGuard and
+/// to `key_out` files when Rosenpass receives SIGTERM or SIGINT
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Copy, Clone, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ShutdownPolicy {
+    /// Leave the last exchanged keys in place
+    #[default]
+    Keep,
+    /// Overwrite the exported keys with random ones, just like a key that went stale
+    Retire,
+}
+
+impl ShutdownPolicy {
+    /// Whether the exported keys are left in place
+    pub fn is_keep(&self) -> bool {
+        *self == Self::Keep
+    }
+}
+
 /// The protocol version to be used by a peer.
 #[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Copy, Clone, Default)]
 pub enum ProtocolVersion {
@@ -123,6 +214,10 @@ pub struct RosenpassPeer {
     /// path to the public key of the peer
     pub public_key: PathBuf,
 
+    /// A human readable name for the peer, used in the logs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
     /// The hostname and port to connect to
     ///
     /// Can be a
@@ -143,10 +238,36 @@ pub struct RosenpassPeer {
     #[serde(default)]
     pub key_out: Option<PathBuf>,
 
+    /// Number of previous keys to keep next to [Self::key_out]
+    ///
+    /// The most recent previous key is kept in `<key_out>.prev`, older ones in
+    /// `<key_out>.prev.2` and so on, so consumers switching over to a new key can still read the
+    /// old one for a while. All of them are removed once the key goes stale.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub key_out_previous: usize,
+
+    /// How the exchanged keys are encoded for [Self::key_out], [Self::key_sinks] and hooks
+    ///
+    /// See [KeyOutFormat] for the available formats; defaults to base64.
+    #[serde(default, skip_serializing_if = "KeyOutFormat::is_base64")]
+    pub key_out_format: KeyOutFormat,
+
+    /// Further destinations for the exchanged keys, e.g. file descriptors or named pipes
+    ///
+    /// See [crate::key_out] for details.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub key_sinks: Vec<KeySinkConfig>,
+
     /// Information for supplying exchanged keys directly to WireGuard
     #[serde(flatten)]
     pub wg: Option<WireGuard>,
 
+    /// Programs to run when keys are exchanged with this peer or go stale
+    ///
+    /// These take the place of the global hooks; see [crate::hooks] for details.
+    #[serde(flatten)]
+    pub hooks: Hooks,
+
     #[serde(default)]
     /// The protocol version to use for the exchange
     pub protocol_version: ProtocolVersion,
@@ -251,10 +372,20 @@ impl Rosenpass {
         self.store(&self.config_file_path)
     }
 
+    /// Whether any hooks are configured, for the server or for peers
+    pub fn has_hooks(&self) -> bool {
+        !self.hooks.is_empty() || self.peers.iter().any(|peer| !peer.hooks.is_empty())
+    }
+
     /// Apply the configuration in this object to the given [crate::app_server::AppServer]
-    pub fn apply_to_app_server(&self, _srv: &mut AppServer) -> anyhow::Result<()> {
+    pub fn apply_to_app_server(&self, srv: &mut AppServer) -> anyhow::Result<()> {
+        srv.on_shutdown = self.on_shutdown;
+        srv.hooks = self.hooks.clone();
+        if let Some(metrics) = self.metrics.as_ref() {
+            metrics.apply_to_app_server(srv)?;
+        }
         #[cfg(feature = "experiment_api")]
-        self.api.apply_to_app_server(_srv)?;
+        self.api.apply_to_app_server(srv)?;
         Ok(())
     }
 
@@ -267,6 +398,20 @@ impl Rosenpass {
     #[doc = include_str!("../tests/config_Rosenpass_validate.rs")]
     #[doc = "```"]
     pub fn validate(&self) -> anyhow::Result<()> {
+        self.listen_options
+            .validate()
+            .context("invalid listen_options")?;
+
+        if let Some(ref user) = self.user {
+            PrivilegeDrop::lookup(user, self.group.as_deref(), vec![])
+                .context("invalid user or group")?;
+        } else {
+            ensure!(
+                self.group.is_none(),
+                "group is only used together with user"
+            );
+        }
+
         if let Some(ref keypair) = self.keypair {
             // check the public key file exists
             ensure!(
@@ -282,19 +427,18 @@ impl Rosenpass {
                 keypair.public_key
             );
 
-            // check the secret-key file exists
-            ensure!(
-                keypair.secret_key.is_file(),
-                "could not find secret-key file {:?}: no such file. Consider running `rosenpass gen-keys` to generate a new keypair.",
-                keypair.secret_key
-            );
-
-            // check the secret-key file is a valid key
-            ensure!(
-                SSk::load(&keypair.secret_key).is_ok(),
-                "could not load public-key file {:?}: invalid key",
-                keypair.secret_key
-            );
+            // an oracle behind a socket holds the secret key; it is checked on startup instead
+            if !matches!(self.key_oracle, Some(KeyOracleConfig::Socket(_))) {
+                // check the secret key is available, be it in a file or elsewhere
+                let sk = SecretSource::parse(&keypair.secret_key)?;
+                sk.check().with_context(|| {
+                    format!("could not find secret key ({sk}). Consider running `rosenpass gen-keys` to generate a new keypair.")
+                })?;
+
+                // check the secret key is valid
+                let loaded: anyhow::Result<SSk> = load_secret(&keypair.secret_key);
+                loaded.with_context(|| format!("could not load secret key ({sk}): invalid key"))?;
+            }
         }
 
         for (i, peer) in self.peers.iter().enumerate() {
@@ -312,6 +456,18 @@ impl Rosenpass {
                 peer.public_key
             );
 
+            // check the peer's pre-shared key is available
+            if let Some(psk) = peer.pre_shared_key.as_ref() {
+                let psk = SecretSource::parse(psk)
+                    .with_context(|| format!("peer {i} has an invalid pre-shared key reference"))?;
+                psk.check()
+                    .with_context(|| format!("peer {i} pre-shared key ({psk}) is not available"))?;
+            }
+
+            peer.hooks
+                .validate()
+                .with_context(|| format!("peer {i} has an invalid hook"))?;
+
             // check endpoint is usable
             if let Some(addr) = peer.endpoint.as_ref() {
                 ensure!(
@@ -321,8 +477,13 @@ impl Rosenpass {
                 );
             }
 
-            // check if `key_out` or `device` and `peer` are defined
-            if peer.key_out.is_none() {
+            ensure!(
+                peer.key_out.is_some() || peer.key_out_previous == 0,
+                "peer {i} keeps previous keys, but has no `key_out`"
+            );
+
+            // check if `key_out`, `key_sinks` or `device` and `peer` are defined
+            if peer.key_out.is_none() && peer.key_sinks.is_empty() {
                 if let Some(wg) = &peer.wg {
                     if wg.device.is_empty() || wg.peer.is_empty() {
                         ensure!(
@@ -339,6 +500,11 @@ impl Rosenpass {
             }
         }
 
+        if let Some(metrics) = self.metrics.as_ref() {
+            metrics.validate()?;
+        }
+        self.hooks.validate()?;
+
         Ok(())
     }
 
@@ -408,9 +574,19 @@ impl Rosenpass {
         Self {
             keypair,
             listen: vec![],
+            listen_options: SocketOptions::default(),
+            user: None,
+            group: None,
+            seccomp: SeccompMode::default(),
+            landlock: false,
+            key_oracle: None,
             #[cfg(feature = "experiment_api")]
             api: crate::api::config::ApiConfig::default(),
             verbosity: Verbosity::Quiet,
+            log_format: LogFormat::default(),
+            on_shutdown: ShutdownPolicy::default(),
+            metrics: None,
+            hooks: Hooks::default(),
             peers: vec![],
             config_file_path: PathBuf::new(),
         }
@@ -649,22 +825,54 @@ impl Default for Verbosity {
 
 /// Example configuration generated by the command `rosenpass gen-config <TOML-FILE>`.
 pub static EXAMPLE_CONFIG: &str = r###"public_key = "/path/to/rp-public-key"
-secret_key = "/path/to/rp-secret-key"
+secret_key = "/path/to/rp-secret-key" # or e.g. "credential:rp-sk", "env:RP_SK", "fd:3"
 listen = []
 verbosity = "Verbose"
+# Write the logs as human readable "text" or as one "json" object per line
+# log_format = "json"
+# Overwrite the exported keys with random ones on SIGTERM/SIGINT ("retire") or leave them ("keep")
+# on_shutdown = "retire"
+# Run a program whenever a key is exchanged or goes stale; peers can have hooks of their own
+# on_key_exchange = { command = ["/path/to/program", "--argument"], timeout = 10 }
+# on_key_stale = { command = ["/path/to/program"] }
+# Switch to an unprivileged user once the listen sockets are bound and the keys are loaded
+# user = "rosenpass"
+# group = "rosenpass" # defaults to the primary group of the user
+# Restrict the system calls rosenpass may make; "log" reports the ones the filter would block
+# seccomp = "enforce"
+# Restrict file system access to the key outputs, keys and other paths in this file
+# landlock = true
+# Keep the secret key in a separate process, started by rosenpass or listening on a socket
+# key_oracle = "spawn" # or { socket = "/run/rosenpass/oracle.sock" }
+
+# Socket options for all listen sockets, e.g. to keep handshakes out of the WireGuard tunnel
+# [listen_options]
+# bind_to_device = "eth0"
+# fwmark = 0xca6c
+# dscp = 46
+
+# Export metrics for Prometheus over HTTP and/or to a file for the node exporter
+# [metrics]
+# listen = "127.0.0.1:9464"
+# textfile = "/var/lib/prometheus/node-exporter/rosenpass.prom"
 
 [[peers]]
 # Commented out fields are optional
 public_key = "/path/to/rp-peer-public-key"
+# name = "alice" # used in the logs
 endpoint = "127.0.0.1:9998"
 # pre_shared_key = "/path/to/preshared-key"
 
 # Choose to store the key in a file via `key_out` or pass it to WireGuard by
 # defining `device` and `peer`. You may choose to do both.
 key_out = "/path/to/rp-key-out.txt" # path to store the key
+# key_out_previous = 1 # keep the previous key in /path/to/rp-key-out.txt.prev
+# key_out_format = "base64" # or "hex", "raw", "wireguard", "json"
 # device = "wg0" # WireGuard interface
 #peer = "RULdRAtUw7SFfVfGD..." # WireGuard public key
 # extra_params = [] # passed to WireGuard `wg set`
+# key_sinks = [{ fd = 3 }, { fifo = "/path/to/fifo" }] # further places to write the key to
+# on_key_exchange = { command = ["/path/to/program"], pass_key = true } # key on an inherited fd
 "###;
 
 #[cfg(test)]
@@ -782,6 +990,20 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn configs_without_on_shutdown_keep_the_keys() -> anyhow::Result<()> {
+        let config: Rosenpass = toml::from_str("listen = []\npeers = []\n")?;
+        assert_eq!(config.on_shutdown, ShutdownPolicy::Keep);
+        Ok(())
+    }
+
+    #[test]
+    fn configs_without_log_format_log_text() -> anyhow::Result<()> {
+        let config: Rosenpass = toml::from_str("listen = []\npeers = []\n")?;
+        assert_eq!(config.log_format, LogFormat::Text);
+        Ok(())
+    }
+
     #[test]
     fn test_protocol_version() {
         let mut rosenpass = Rosenpass::empty();
@@ -0,0 +1,275 @@
+//! Loading secrets from other sources than plain files
+//!
+//! Wherever the configuration expects the path of a secret – [Keypair::secret_key] and
+//! [RosenpassPeer::pre_shared_key] – it also accepts references to other sources:
+//!
+//! - `credential:<name>` reads the systemd credential `<name>`, i.e. the file of that name in
+//!   `$CREDENTIALS_DIRECTORY`. This works with `LoadCredential=` as well as with
+//!   `LoadCredentialEncrypted=`, so secret keys need not be stored on disk in plaintext.
+//! - `env:<VAR>` reads the environment variable `<VAR>`. Environment variables cannot hold binary
+//!   data, so secret keys are base64 encoded here; pre-shared keys are base64 encoded anyway.
+//! - `fd:<N>` reads the inherited file descriptor `<N>` (e.g. a pipe) until its end.
+//!
+//! Environment variables and file descriptors are read once; afterwards, the variable is removed
+//! from the environment (so hooks do not inherit it) and the file descriptor is closed. Their
+//! contents are kept in secret memory (see [rosenpass_secret_memory::alloc]), so reloading the
+//! configuration keeps working.
+//!
+//! A file whose name starts with one of the prefixes can still be used by writing it as
+//! `./credential:…`.
+//!
+//! # Examples
+//!
+//! ```toml
+//! secret_key = "credential:rp-sk"
+//!
+//! [[peers]]
+//! public_key = "/path/to/rp-peer-public-key"
+//! pre_shared_key = "env:RP_PEER_PSK"
+//! ```
+//!
+//! [Keypair::secret_key]: crate::config::Keypair::secret_key
+//! [RosenpassPeer::pre_shared_key]: crate::config::RosenpassPeer::pre_shared_key
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+use std::os::fd::{BorrowedFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::{ensure, Context};
+use rosenpass_secret_memory::alloc::{secret_vec, SecretVec};
+use rosenpass_secret_memory::Secret;
+use rosenpass_util::b64::b64_decode;
+use rosenpass_util::fd::{claim_fd, FdIo};
+use rosenpass_util::file::{LoadValue, LoadValueB64};
+use zeroize::Zeroizing;
+
+/// Where a secret referenced in the configuration comes from; see [crate::credentials]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecretSource {
+    /// A plain file
+    File(PathBuf),
+    /// A systemd credential
+    Credential(String),
+    /// An environment variable
+    Env(String),
+    /// An inherited file descriptor
+    Fd(RawFd),
+}
+
+impl SecretSource {
+    /// Interpret a secret path from the configuration
+    pub fn parse(path: &Path) -> anyhow::Result<Self> {
+        let Some(text) = path.to_str() else {
+            return Ok(Self::File(path.to_owned()));
+        };
+
+        if let Some(name) = text.strip_prefix("credential:") {
+            ensure!(
+                !name.is_empty() && !name.contains('/'),
+                "Invalid credential name {name:?}"
+            );
+            Ok(Self::Credential(name.to_owned()))
+        } else if let Some(var) = text.strip_prefix("env:") {
+            ensure!(
+                !var.is_empty() && !var.contains('='),
+                "Invalid environment variable name {var:?}"
+            );
+            Ok(Self::Env(var.to_owned()))
+        } else if let Some(fd) = text.strip_prefix("fd:") {
+            let fd = fd
+                .parse()
+                .with_context(|| format!("Invalid file descriptor {fd:?}"))?;
+            ensure!(fd > 2, "Refusing to read secrets from standard IO");
+            Ok(Self::Fd(fd))
+        } else {
+            Ok(Self::File(path.to_owned()))
+        }
+    }
+
+    /// The file holding the secret, for sources that are files
+    pub fn file(&self) -> anyhow::Result<Option<PathBuf>> {
+        match self {
+            Self::File(path) => Ok(Some(path.clone())),
+            Self::Credential(name) => {
+                let dir = std::env::var_os("CREDENTIALS_DIRECTORY")
+                    .context("CREDENTIALS_DIRECTORY is not set; was rosenpass started by systemd with LoadCredential=?")?;
+                Ok(Some(Path::new(&dir).join(name)))
+            }
+            Self::Env(_) | Self::Fd(_) => Ok(None),
+        }
+    }
+
+    /// Check that the secret is available, without reading it
+    pub fn check(&self) -> anyhow::Result<()> {
+        if let Some(path) = self.file()? {
+            ensure!(path.is_file(), "{path:?} does not exist");
+            return Ok(());
+        }
+        if consumed().lock().unwrap().contains_key(&self.to_string()) {
+            return Ok(());
+        }
+
+        match self {
+            Self::Env(var) => {
+                ensure!(std::env::var_os(var).is_some(), "The variable is not set");
+            }
+            Self::Fd(fd) => {
+                // Safety: the file descriptor is only borrowed to check that it is open
+                let fd = unsafe { BorrowedFd::borrow_raw(*fd) };
+                rustix::io::fcntl_getfd(fd).context("The file descriptor is not open")?;
+            }
+            Self::File(_) | Self::Credential(_) => unreachable!(),
+        }
+        Ok(())
+    }
+
+    /// The contents of an environment variable or file descriptor
+    ///
+    /// The first read consumes the source; later reads see the same contents. The contents are
+    /// only lent to `f`, so they never leave secret memory.
+    fn consume<R>(&self, f: impl FnOnce(&[u8]) -> anyhow::Result<R>) -> anyhow::Result<R> {
+        let mut consumed = consumed().lock().unwrap();
+        let key = self.to_string();
+        if let Some(contents) = consumed.get(&key) {
+            return f(contents);
+        }
+
+        let mut contents = secret_vec();
+        match self {
+            Self::Env(var) => {
+                let value = Zeroizing::new(std::env::var(var)?);
+                std::env::remove_var(var);
+                contents.extend_from_slice(value.as_bytes());
+            }
+            Self::Fd(fd) => {
+                let mut io = FdIo(claim_fd(*fd)?);
+                let mut buf = Zeroizing::new([0u8; 1024]);
+                loop {
+                    match io.read(&mut buf[..]) {
+                        Ok(0) => break,
+                        Ok(len) => contents.extend_from_slice(&buf[..len]),
+                        Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+            }
+            Self::File(_) | Self::Credential(_) => unreachable!(),
+        };
+        let contents = consumed.entry(key).or_insert(contents);
+        f(contents)
+    }
+}
+
+impl fmt::Display for SecretSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "file {path:?}"),
+            Self::Credential(name) => write!(f, "credential:{name}"),
+            Self::Env(var) => write!(f, "env:{var}"),
+            Self::Fd(fd) => write!(f, "fd:{fd}"),
+        }
+    }
+}
+
+/// Contents of the environment variables and file descriptors read so far
+///
+/// The contents live in secret memory, which is wiped when it is freed.
+fn consumed() -> &'static Mutex<HashMap<String, SecretVec<u8>>> {
+    static CONSUMED: OnceLock<Mutex<HashMap<String, SecretVec<u8>>>> = OnceLock::new();
+    CONSUMED.get_or_init(Default::default)
+}
+
+/// Decode base64 data that must encode exactly `N` bytes
+fn decode_b64<const N: usize>(data: &[u8]) -> anyhow::Result<Secret<N>> {
+    let start = data
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(data.len());
+    let end = data
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map_or(start, |i| i + 1);
+    let data = &data[start..end];
+    ensure!(
+        data.len() == N.div_ceil(3) * 4,
+        "Expected {N} base64 encoded bytes"
+    );
+    let mut secret = Secret::zero();
+    b64_decode(data, secret.secret_mut())?;
+    Ok(secret)
+}
+
+/// Load a secret stored as raw bytes, such as [crate::protocol::SSk]
+///
+/// `path` may refer to any source described in [crate::credentials]; in environment variables,
+/// the secret is base64 encoded.
+pub fn load_secret<const N: usize>(path: &Path) -> anyhow::Result<Secret<N>> {
+    let source = SecretSource::parse(path)?;
+    let res = match (&source, source.file()?) {
+        (_, Some(path)) => Secret::load(path),
+        (SecretSource::Env(_), None) => source.consume(decode_b64),
+        (_, None) => source.consume(|contents| match contents.len() {
+            len if len == N => Ok(Secret::from_slice(contents)),
+            len => Err(anyhow::anyhow!("Expected {N} bytes, got {len}")),
+        }),
+    };
+    res.with_context(|| format!("Could not load secret from {source}"))
+}
+
+/// Load a base64 encoded secret, such as a pre-shared key
+///
+/// `F` is the maximum size of the encoded secret; `path` may refer to any source described in
+/// [crate::credentials].
+pub fn load_secret_b64<const F: usize, const N: usize>(path: &Path) -> anyhow::Result<Secret<N>> {
+    let source = SecretSource::parse(path)?;
+    let res = match source.file()? {
+        Some(path) => Secret::load_b64::<F, _>(path),
+        None => source.consume(decode_b64),
+    };
+    res.with_context(|| format!("Could not load secret from {source}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sources_are_parsed_from_paths() -> anyhow::Result<()> {
+        let parse = |p: &str| SecretSource::parse(Path::new(p));
+        assert_eq!(
+            parse("credential:rp-sk")?,
+            SecretSource::Credential("rp-sk".to_owned())
+        );
+        assert_eq!(parse("env:RP_PSK")?, SecretSource::Env("RP_PSK".to_owned()));
+        assert_eq!(parse("fd:7")?, SecretSource::Fd(7));
+        assert_eq!(
+            parse("./fd:7")?,
+            SecretSource::File(PathBuf::from("./fd:7"))
+        );
+        assert!(parse("fd:1").is_err());
+        assert!(parse("credential:../sk").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn secrets_are_loaded_from_the_environment_once() -> anyhow::Result<()> {
+        rosenpass_secret_memory::secret_policy_use_only_malloc_secrets();
+        let var = "ROSENPASS_TEST_CREDENTIALS_PSK";
+        let path = PathBuf::from(format!("env:{var}"));
+        std::env::set_var(var, "q6urq6urq6urq6urq6urq6urq6urq6urq6urq6urq6s=\n");
+
+        let psk = load_secret_b64::<64, 32>(&path)?;
+        assert_eq!(psk.secret(), &[0xab; 32]);
+        assert!(std::env::var_os(var).is_none());
+
+        // The contents were kept, so loading again (e.g. on reload) works
+        SecretSource::parse(&path)?.check()?;
+        let again = load_secret_b64::<64, 32>(&path)?;
+        assert_eq!(again.secret(), psk.secret());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,377 @@
+//! Choosing the path to a peer during host-path discovery
+//!
+//! A peer that is reachable over IPv6 and IPv4 can have several addresses, and rosenpass may
+//! listen on several sockets. [HostPathDiscoveryEndpoint::send_scouting] picks one combination
+//! of address and socket – a [Path] – per handshake message, in the spirit of Happy Eyeballs
+//! (RFC 8305):
+//!
+//! - The path a handshake last completed on is tried first.
+//! - Without such a path, addresses are tried alternating between IPv6 and IPv4, IPv6 first.
+//!   [RACE_DELAY] after each message, the message is sent once more through the first path of
+//!   the other address family, unless a response arrived in the meantime. A broken IPv6 path
+//!   thus delays the handshake by a fraction of a second instead of a whole retransmission.
+//! - A path that did not lead to a response before the next message was sent counts as failed
+//!   and is put into exponential backoff (see [BACKOFF_BASE] and [BACKOFF_MAX]); paths in backoff
+//!   are only tried when there is nothing else left.
+//!
+//! The chosen path is logged when it changes and shown next to the addresses of the endpoint.
+//!
+//! [HostPathDiscoveryEndpoint::send_scouting]: crate::app_server::HostPathDiscoveryEndpoint::send_scouting
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use log::info;
+
+use crate::app_server::{AppPeerPtr, AppServer, Endpoint};
+
+/// How long to wait for a response before racing the other address family
+pub const RACE_DELAY: Duration = Duration::from_millis(250);
+
+/// How long a path is avoided after its first failure; doubles with every further failure
+pub const BACKOFF_BASE: Duration = Duration::from_secs(1);
+
+/// The longest a path is avoided after failing
+pub const BACKOFF_MAX: Duration = Duration::from_secs(60);
+
+/// A network address of a peer together with the socket used to reach it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Path {
+    /// The network address
+    pub addr: SocketAddr,
+    /// Index of the socket in [AppServer::sockets]
+    pub socket: usize,
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} via socket #{}", self.addr, self.socket)
+    }
+}
+
+/// What is known about how well a path works
+#[derive(Debug, Default, Clone)]
+struct PathStats {
+    /// Failures since the path last worked
+    failures: u32,
+    /// Until when the path is avoided
+    retry_after: Option<Instant>,
+}
+
+/// A message that is sent once more through the other address family, unless a response
+/// arrives first
+#[derive(Debug)]
+struct Race {
+    /// The paths to try, in order, until sending through one of them works
+    paths: Vec<Path>,
+    due: Instant,
+    msg: Vec<u8>,
+}
+
+/// The state of path selection for one [HostPathDiscoveryEndpoint]; see [crate::happy_eyeballs]
+///
+/// [HostPathDiscoveryEndpoint]: crate::app_server::HostPathDiscoveryEndpoint
+#[derive(Debug, Default)]
+pub struct PathSelector {
+    /// The path a handshake last completed on
+    preferred: Option<Path>,
+    stats: HashMap<Path, PathStats>,
+    /// Paths messages were sent through since the last response or message
+    outstanding: Vec<Path>,
+    race: Option<Race>,
+}
+
+impl PathSelector {
+    /// The path a handshake last completed on, if it still seems to work
+    pub fn preferred(&self) -> Option<Path> {
+        self.preferred
+    }
+
+    /// Take over what `other` learned; the preferred path of `self` stays, if it has one
+    pub fn absorb(&mut self, other: &PathSelector) {
+        self.preferred = self.preferred.or(other.preferred);
+        for (path, stats) in other.stats.iter() {
+            let own = self.stats.entry(*path).or_default();
+            if stats.failures > own.failures {
+                *own = stats.clone();
+            }
+        }
+    }
+
+    /// Prefer `path` until it fails
+    pub fn prefer(&mut self, path: Path) {
+        self.preferred.get_or_insert(path);
+    }
+
+    /// Count the paths that were tried since the last call without getting a response as
+    /// failed; called before each new handshake message
+    pub fn settle(&mut self, now: Instant) {
+        for path in self.outstanding.drain(..) {
+            let stats = self.stats.entry(path).or_default();
+            stats.failures += 1;
+            let backoff = BACKOFF_BASE.saturating_mul(1 << (stats.failures - 1).min(16));
+            stats.retry_after = Some(now + backoff.min(BACKOFF_MAX));
+            if self.preferred == Some(path) {
+                self.preferred = None;
+            }
+        }
+        self.race = None;
+    }
+
+    /// Until when `path` is avoided, if it currently is
+    fn backoff(&self, path: &Path, now: Instant) -> Option<Instant> {
+        self.stats
+            .get(path)
+            .and_then(|stats| stats.retry_after)
+            .filter(|&until| until > now)
+    }
+
+    /// All paths to `addresses` through `sockets` sockets, in the order they should be tried
+    pub fn candidates(&self, addresses: &[SocketAddr], sockets: usize, now: Instant) -> Vec<Path> {
+        let (mut v6, mut v4): (Vec<_>, Vec<_>) = addresses.iter().partition(|a| a.is_ipv6());
+        v6.reverse();
+        v4.reverse();
+        let mut interleaved = Vec::with_capacity(addresses.len());
+        while let Some(addr) = v6.pop().or_else(|| v4.pop()) {
+            interleaved.push(*addr);
+            if let Some(addr) = v4.pop() {
+                interleaved.push(*addr);
+            }
+        }
+
+        let mut paths = interleaved
+            .into_iter()
+            .flat_map(|addr| (0..sockets).map(move |socket| Path { addr, socket }))
+            .collect::<Vec<_>>();
+        // Paths in backoff go last, those that may be retried soonest first
+        paths.sort_by_key(|path| self.backoff(path, now));
+        if let Some(idx) = self
+            .preferred
+            .and_then(|pref| paths.iter().position(|p| *p == pref))
+        {
+            let pref = paths.remove(idx);
+            paths.insert(0, pref);
+        }
+        paths
+    }
+
+    /// Record that `msg` was sent through `candidates[sent]`
+    ///
+    /// Unless there is a preferred path, the message is raced through the other address family.
+    pub fn attempted(&mut self, candidates: &[Path], sent: usize, msg: &[u8], now: Instant) {
+        let path = candidates[sent];
+        self.outstanding.push(path);
+        if self.preferred.is_some() {
+            return;
+        }
+
+        let paths = candidates[sent + 1..]
+            .iter()
+            .filter(|p| p.addr.is_ipv6() != path.addr.is_ipv6())
+            .copied()
+            .collect::<Vec<_>>();
+        if !paths.is_empty() {
+            log::debug!("Sending handshake message to {path}, racing the other address family");
+            self.race = Some(Race {
+                paths,
+                due: now + RACE_DELAY,
+                msg: msg.to_vec(),
+            });
+        }
+    }
+
+    /// When the pending race is due, if there is one
+    pub fn race_due(&self) -> Option<Instant> {
+        self.race.as_ref().map(|race| race.due)
+    }
+
+    /// The paths and message of the pending race, if it is due
+    pub fn take_due_race(&mut self, now: Instant) -> Option<(Vec<Path>, Vec<u8>)> {
+        match self.race_due() {
+            Some(due) if due <= now => {}
+            _ => return None,
+        }
+        let race = self.race.take()?;
+        Some((race.paths, race.msg))
+    }
+
+    /// Record that a message was sent through `path` as part of a race
+    pub fn raced(&mut self, path: Path) {
+        self.outstanding.push(path);
+    }
+
+    /// Record that a handshake completed through `path`
+    ///
+    /// Returns whether `path` was not the preferred path before.
+    pub fn record_success(&mut self, path: Path) -> bool {
+        self.outstanding.clear();
+        self.race = None;
+        self.stats.remove(&path);
+        self.preferred.replace(path) != Some(path)
+    }
+
+    /// Forget the paths through the listen socket `socket`, which was closed; the paths through
+    /// the sockets after it move down by one, like the sockets themselves
+    pub fn forget_socket(&mut self, socket: usize) {
+        let renumber = |path: Path| match path.socket.cmp(&socket) {
+            Ordering::Less => Some(path),
+            Ordering::Equal => None,
+            Ordering::Greater => Some(Path {
+                socket: path.socket - 1,
+                ..path
+            }),
+        };
+        self.preferred = self.preferred.and_then(renumber);
+        self.stats = std::mem::take(&mut self.stats)
+            .into_iter()
+            .filter_map(|(path, stats)| Some((renumber(path)?, stats)))
+            .collect();
+        self.outstanding = self.outstanding.drain(..).filter_map(renumber).collect();
+        if let Some(race) = self.race.as_mut() {
+            race.paths = race.paths.drain(..).filter_map(renumber).collect();
+        }
+    }
+}
+
+impl AppServer {
+    /// Send the handshake messages raced through the other address family that are due; see
+    /// [crate::happy_eyeballs]
+    ///
+    /// Returns how long it is until the next race is due.
+    pub fn send_due_races(&self) -> Option<Duration> {
+        let now = Instant::now();
+        let mut next: Option<Instant> = None;
+        for peer in self.peers.iter() {
+            let Some(Endpoint::Discovery(ep)) = peer.endpoint() else {
+                continue;
+            };
+            if let Some(due) = ep.send_due_race(self, now) {
+                next = Some(next.map_or(due, |next| next.min(due)));
+            }
+        }
+        next.map(|due| due.saturating_duration_since(now))
+    }
+
+    /// Remember that a handshake with `peer` completed through `via`, so the path is preferred
+    /// from then on
+    pub fn record_path_success(&self, peer: AppPeerPtr, via: &Endpoint) {
+        let Endpoint::SocketBoundAddress(bound) = via else {
+            return;
+        };
+        let path = bound.path();
+
+        let ap = peer.get_app(self);
+        let mut changed = false;
+        for ep in [&ap.initial_endpoint, &ap.current_endpoint] {
+            if let Some(Endpoint::Discovery(ep)) = ep {
+                changed |= ep.paths().borrow_mut().record_success(path);
+            }
+        }
+
+        if changed {
+            let peer_id = self
+                .peer_log_id(peer)
+                .unwrap_or_else(|_| peer.0.to_string());
+            info!(peer = peer_id; "Reaching peer {peer_id} at {path}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    fn path(s: &str, socket: usize) -> Path {
+        Path {
+            addr: addr(s),
+            socket,
+        }
+    }
+
+    #[test]
+    fn address_families_are_interleaved_ipv6_first() {
+        let sel = PathSelector::default();
+        let addrs = [
+            addr("192.0.2.1:9999"),
+            addr("192.0.2.2:9999"),
+            addr("[2001:db8::1]:9999"),
+        ];
+        let order = sel
+            .candidates(&addrs, 1, Instant::now())
+            .into_iter()
+            .map(|p| p.addr)
+            .collect::<Vec<_>>();
+        assert_eq!(order, [addrs[2], addrs[0], addrs[1]]);
+    }
+
+    #[test]
+    fn failing_paths_back_off_and_races_stop_on_success() {
+        let mut sel = PathSelector::default();
+        let addrs = [addr("[2001:db8::1]:9999"), addr("192.0.2.1:9999")];
+        let now = Instant::now();
+
+        // Fresh discovery: IPv6 first, IPv4 raced shortly after
+        let candidates = sel.candidates(&addrs, 1, now);
+        assert_eq!(candidates[0], path("[2001:db8::1]:9999", 0));
+        sel.attempted(&candidates, 0, b"msg", now);
+        assert_eq!(sel.race_due(), Some(now + RACE_DELAY));
+        assert!(sel.take_due_race(now).is_none());
+        let (paths, msg) = sel.take_due_race(now + RACE_DELAY).unwrap();
+        assert_eq!(paths, [path("192.0.2.1:9999", 0)]);
+        assert_eq!(msg, b"msg");
+
+        // No response through IPv6: it is avoided until its backoff ends
+        sel.settle(now + RACE_DELAY);
+        assert_eq!(
+            sel.candidates(&addrs, 1, now + RACE_DELAY)[0],
+            path("192.0.2.1:9999", 0)
+        );
+        let later = now + BACKOFF_MAX * 2;
+        assert_eq!(
+            sel.candidates(&addrs, 1, later)[0],
+            path("[2001:db8::1]:9999", 0)
+        );
+
+        // A response makes the IPv4 path preferred; no more racing
+        assert!(sel.record_success(path("192.0.2.1:9999", 0)));
+        assert!(!sel.record_success(path("192.0.2.1:9999", 0)));
+        let candidates = sel.candidates(&addrs, 1, later);
+        assert_eq!(candidates[0], path("192.0.2.1:9999", 0));
+        sel.attempted(&candidates, 0, b"msg", later);
+        assert_eq!(sel.race_due(), None);
+
+        // Once the preferred path fails, it is no longer preferred
+        sel.settle(later);
+        assert_eq!(sel.preferred(), None);
+    }
+
+    #[test]
+    fn paths_through_closed_sockets_are_forgotten() {
+        let mut sel = PathSelector::default();
+        let addrs = [addr("[2001:db8::1]:9999"), addr("192.0.2.1:9999")];
+        let now = Instant::now();
+
+        // The race through IPv4 covers sockets #0 to #2; closing #1 renumbers #2
+        let candidates = sel.candidates(&addrs, 3, now);
+        sel.attempted(&candidates, 0, b"msg", now);
+        sel.forget_socket(1);
+        let (paths, _) = sel.take_due_race(now + RACE_DELAY).unwrap();
+        assert_eq!(
+            paths,
+            [path("192.0.2.1:9999", 0), path("192.0.2.1:9999", 1)]
+        );
+
+        sel.record_success(path("192.0.2.1:9999", 1));
+        sel.forget_socket(0);
+        assert_eq!(sel.preferred(), Some(path("192.0.2.1:9999", 0)));
+        sel.forget_socket(0);
+        assert_eq!(sel.preferred(), None);
+    }
+}
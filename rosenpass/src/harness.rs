@@ -0,0 +1,576 @@
+//! In-process integration test harness for [AppServer]
+//!
+//! The harness runs several [AppServer]s on the IPv6 loopback interface, each in its own
+//! thread. Output keys are captured through an [InMemoryBroker] instead of being written to
+//! files and tests can wait for particular events – such as a key exchange between two
+//! servers – with a timeout. Dropping the [Harness] (or calling [Harness::shutdown])
+//! terminates all servers and joins their threads.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::time::Duration;
+//! use rosenpass::config::ProtocolVersion;
+//! use rosenpass::harness::{HarnessBuilder, HarnessLink, HarnessServerOptions};
+//!
+//! rosenpass_secret_memory::policy::secret_policy_use_only_malloc_secrets();
+//!
+//! let mut builder = HarnessBuilder::default();
+//! let alice = builder.add_server(HarnessServerOptions::default());
+//! let bob = builder.add_server(HarnessServerOptions::default());
+//! builder.add_link(HarnessLink::new(alice, bob).with_protocol_version(ProtocolVersion::V03));
+//!
+//! let mut harness = builder.start()?;
+//!
+//! // Both servers output the same key once the handshake is complete
+//! let key = harness.await_mutual_key_exchange(alice, bob, Duration::from_secs(30))?;
+//! assert!(rosenpass_constant_time::memcmp(
+//!     key.secret(),
+//!     harness.key(bob, alice).unwrap().secret()
+//! ));
+//!
+//! harness.shutdown()?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use std::collections::HashMap;
+use std::net::{Ipv6Addr, SocketAddr};
+use std::ops::DerefMut;
+use std::os::unix::net::UnixStream;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, ensure, Context};
+use rosenpass_cipher_traits::primitives::Kem;
+use rosenpass_ciphers::StaticKem;
+use rosenpass_secret_memory::{Public, Secret};
+use rosenpass_util::result::OkExt;
+use rosenpass_wireguard_broker::{
+    SerializedBrokerConfig, WireGuardBroker, WireguardBrokerCfg, WireguardBrokerMio, WG_KEY_LEN,
+    WG_PEER_LEN,
+};
+
+use crate::app_server::{
+    AppServer, AppServerTest, AppServerTestEvent, BrokerPeer, KeyOutputReason,
+};
+use crate::config::{ProtocolVersion, ShutdownPolicy, Verbosity};
+use crate::key_oracle::KeyOracleClient;
+use crate::protocol::{SPk, SSk, SymKey};
+
+/// Interface name reported to the [InMemoryBroker] by [InMemoryBrokerCfg]
+pub const IN_MEMORY_BROKER_INTERFACE: &[u8] = b"rosenpass-harness";
+
+/// Shared storage for the keys captured by an [InMemoryBroker], indexed by WireGuard peer id
+pub type InMemoryKeyStore = Arc<Mutex<HashMap<Public<WG_PEER_LEN>, Secret<WG_KEY_LEN>>>>;
+
+/// A WireGuard PSK broker that just remembers the latest key for each peer
+///
+/// The keys can be inspected from other threads through [Self::keys].
+#[derive(Debug, Default, Clone)]
+pub struct InMemoryBroker {
+    keys: InMemoryKeyStore,
+    mio_token: Option<mio::Token>,
+}
+
+impl InMemoryBroker {
+    /// Create a broker with an empty key store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The key store this broker writes to
+    pub fn keys(&self) -> &InMemoryKeyStore {
+        &self.keys
+    }
+}
+
+impl WireGuardBroker for InMemoryBroker {
+    type Error = anyhow::Error;
+
+    fn set_psk(&mut self, config: SerializedBrokerConfig<'_>) -> Result<(), Self::Error> {
+        self.keys
+            .lock()
+            .map_err(|_| anyhow::anyhow!("In-memory key store lock is poisoned"))?
+            .insert(*config.peer_id, config.psk.clone());
+        Ok(())
+    }
+}
+
+impl WireguardBrokerMio for InMemoryBroker {
+    type MioError = anyhow::Error;
+
+    fn register(
+        &mut self,
+        _registry: &mio::Registry,
+        token: mio::Token,
+    ) -> Result<(), Self::MioError> {
+        self.mio_token = Some(token);
+        Ok(())
+    }
+
+    fn mio_token(&self) -> Option<mio::Token> {
+        self.mio_token
+    }
+
+    fn process_poll(&mut self) -> Result<(), Self::MioError> {
+        Ok(())
+    }
+
+    fn unregister(&mut self, _registry: &mio::Registry) -> Result<(), Self::MioError> {
+        self.mio_token = None;
+        Ok(())
+    }
+}
+
+/// Peer configuration for the [InMemoryBroker]
+#[derive(Debug, Clone)]
+pub struct InMemoryBrokerCfg {
+    /// The key under which [InMemoryBroker] stores the keys for this peer
+    pub peer_id: Public<WG_PEER_LEN>,
+}
+
+impl WireguardBrokerCfg for InMemoryBrokerCfg {
+    fn create_config<'a>(&'a self, psk: &'a Secret<WG_KEY_LEN>) -> SerializedBrokerConfig<'a> {
+        SerializedBrokerConfig {
+            interface: IN_MEMORY_BROKER_INTERFACE,
+            peer_id: &self.peer_id,
+            psk,
+            additional_params: &[],
+        }
+    }
+}
+
+/// Index of a server in a [Harness]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ServerId(pub usize);
+
+impl ServerId {
+    /// The WireGuard peer id under which other servers' [InMemoryBroker]s store
+    /// keys exchanged with this server
+    pub fn broker_peer_id(&self) -> Public<WG_PEER_LEN> {
+        let mut id = Public::zero();
+        id.value[..8].copy_from_slice(&(self.0 as u64).to_le_bytes());
+        id
+    }
+}
+
+/// Options for a single server started by the [Harness]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HarnessServerOptions {
+    /// Force the server into DoS mode; see [AppServerTest::enable_dos_permanently]
+    pub enable_dos_permanently: bool,
+    /// Verbosity of the server
+    pub verbosity: Verbosity,
+    /// What the server does with its keys when it is stopped; see [AppServer::shutdown]
+    pub on_shutdown: ShutdownPolicy,
+    /// Leave the secret key to a key oracle on another thread; see [crate::key_oracle]
+    pub key_oracle: bool,
+}
+
+/// Two servers in the [Harness] that know each other as peers
+///
+/// The initiator is told the responder's address; the responder learns the initiator's
+/// address once the initiator connects.
+#[derive(Debug, Clone)]
+pub struct HarnessLink {
+    /// The server that knows the endpoint of its peer
+    pub initiator: ServerId,
+    /// The server without a configured endpoint
+    pub responder: ServerId,
+    /// Pre-shared key used by both sides
+    pub psk: Option<SymKey>,
+    /// The protocol version used by both sides
+    pub protocol_version: ProtocolVersion,
+}
+
+impl HarnessLink {
+    /// Link two servers without a pre-shared key using the default protocol version
+    pub fn new(initiator: ServerId, responder: ServerId) -> Self {
+        Self {
+            initiator,
+            responder,
+            psk: None,
+            protocol_version: ProtocolVersion::default(),
+        }
+    }
+
+    /// Use the given pre-shared key on both sides
+    pub fn with_psk(mut self, psk: SymKey) -> Self {
+        self.psk = Some(psk);
+        self
+    }
+
+    /// Use the given protocol version on both sides
+    pub fn with_protocol_version(mut self, protocol_version: ProtocolVersion) -> Self {
+        self.protocol_version = protocol_version;
+        self
+    }
+}
+
+/// Event produced by a server running in the [Harness]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HarnessEvent {
+    /// The server that produced the event
+    pub server: ServerId,
+    /// The server that is the peer in question
+    pub peer: ServerId,
+    /// Whether a key was exchanged or a stale key was erased
+    pub why: KeyOutputReason,
+}
+
+/// Collects servers and links for a [Harness]; see the [module documentation](self)
+#[derive(Debug, Default)]
+pub struct HarnessBuilder {
+    servers: Vec<HarnessServerOptions>,
+    links: Vec<HarnessLink>,
+}
+
+impl HarnessBuilder {
+    /// Add a server to the harness
+    pub fn add_server(&mut self, opts: HarnessServerOptions) -> ServerId {
+        self.servers.push(opts);
+        ServerId(self.servers.len() - 1)
+    }
+
+    /// Make two servers peers of each other
+    pub fn add_link(&mut self, link: HarnessLink) -> &mut Self {
+        self.links.push(link);
+        self
+    }
+
+    /// Start all servers, each in its own thread
+    pub fn start(self) -> anyhow::Result<Harness> {
+        for (no, link) in self.links.iter().enumerate() {
+            ensure!(
+                link.initiator != link.responder,
+                "Link {no} connects server {} to itself",
+                link.initiator.0
+            );
+            for srv in [link.initiator, link.responder] {
+                ensure!(
+                    srv.0 < self.servers.len(),
+                    "Link {no} refers to server {}, which does not exist",
+                    srv.0
+                );
+            }
+        }
+
+        let keypairs = self
+            .servers
+            .iter()
+            .map(|_| {
+                let (mut sk, mut pk) = (SSk::zero(), SPk::zero());
+                StaticKem.keygen(sk.secret_mut(), pk.deref_mut())?;
+                Ok((sk, pk))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let public_keys: Vec<SPk> = keypairs.iter().map(|(_, pk)| pk.clone()).collect();
+
+        let mut harness = Harness {
+            servers: Vec::new(),
+        };
+        let mut peer_setups = Vec::new();
+        for (no, ((sk, pk), opts)) in keypairs.into_iter().zip(self.servers).enumerate() {
+            let (srv, peer_tx) = HarnessServer::spawn(ServerId(no), sk, pk, opts)?;
+            harness.servers.push(srv);
+            peer_setups.push((peer_tx, Vec::new()));
+        }
+
+        for link in self.links.iter() {
+            let responder_port = harness.servers[link.responder.0].port;
+            let sides = [
+                (link.initiator, link.responder, Some(responder_port)),
+                (link.responder, link.initiator, None),
+            ];
+            for (local, remote, port) in sides {
+                peer_setups[local.0].1.push(HarnessPeerSetup {
+                    remote,
+                    pk: public_keys[remote.0].clone(),
+                    psk: link.psk.clone(),
+                    endpoint: port.map(|port| format!("[::1]:{port}")),
+                    protocol_version: link.protocol_version,
+                });
+                harness.servers[local.0].peers.push(remote);
+            }
+        }
+
+        for (no, (peer_tx, peers)) in peer_setups.into_iter().enumerate() {
+            if peer_tx.send(peers).is_err() {
+                harness.servers[no].join()?;
+                bail!("Server {no} terminated during setup");
+            }
+        }
+
+        harness.ok()
+    }
+}
+
+/// Peer information handed to a server thread once all servers are listening
+#[derive(Debug)]
+struct HarnessPeerSetup {
+    remote: ServerId,
+    pk: SPk,
+    psk: Option<SymKey>,
+    endpoint: Option<String>,
+    protocol_version: ProtocolVersion,
+}
+
+/// Handle to a single server running in the [Harness]
+#[derive(Debug)]
+struct HarnessServer {
+    id: ServerId,
+    port: u16,
+    /// Indexed by [crate::app_server::AppPeerPtr]
+    peers: Vec<ServerId>,
+    keys: InMemoryKeyStore,
+    events: mpsc::Receiver<AppServerTestEvent>,
+    terminate: mpsc::Sender<()>,
+    waker: Arc<mio::Waker>,
+    thread: Option<JoinHandle<anyhow::Result<()>>>,
+}
+
+impl HarnessServer {
+    /// Start the server thread; the server waits for its peers to be sent through
+    /// the returned channel before it enters the event loop
+    fn spawn(
+        id: ServerId,
+        sk: SSk,
+        pk: SPk,
+        opts: HarnessServerOptions,
+    ) -> anyhow::Result<(Self, mpsc::Sender<Vec<HarnessPeerSetup>>)> {
+        let broker = InMemoryBroker::new();
+        let keys = broker.keys().clone();
+        let (event_tx, events) = mpsc::channel();
+        let (terminate, terminate_rx) = mpsc::channel();
+        let (setup_tx, setup_rx) = mpsc::channel();
+        let (peer_tx, peer_rx) = mpsc::channel::<Vec<HarnessPeerSetup>>();
+
+        let thread = thread::Builder::new()
+            .name(format!("harness-server-{}", id.0))
+            .spawn(move || -> anyhow::Result<()> {
+                let test_helpers = AppServerTest {
+                    enable_dos_permanently: opts.enable_dos_permanently,
+                    termination_handler: Some(terminate_rx),
+                    event_sink: Some(event_tx),
+                };
+                let (sk, oracle) = match opts.key_oracle {
+                    false => (sk, None),
+                    true => {
+                        let (ours, theirs) = UnixStream::pair()?;
+                        thread::Builder::new()
+                            .name(format!("harness-oracle-{}", id.0))
+                            .spawn(move || crate::key_oracle::serve(&sk, theirs))?;
+                        let mut oracle = KeyOracleClient::from_stream(ours, None)?;
+                        oracle.verify(&pk)?;
+                        (SSk::zero(), Some(oracle))
+                    }
+                };
+
+                let addrs = vec![SocketAddr::from((Ipv6Addr::LOCALHOST, 0))];
+                let mut srv =
+                    AppServer::new(Some((sk, pk)), addrs, opts.verbosity, Some(test_helpers))?;
+                srv.on_shutdown = opts.on_shutdown;
+                if let Some(oracle) = oracle {
+                    srv.crypto_server_mut()?.static_key_oracle = Some(Box::new(oracle));
+                }
+                let broker = srv.register_broker(Box::new(broker))?;
+                let waker = srv.register_waker()?;
+                let port = srv.sockets[0].local_addr()?.port();
+
+                setup_tx.send((port, waker))?;
+                let Ok(peers) = peer_rx.recv() else {
+                    // The harness gave up during setup
+                    return Ok(());
+                };
+
+                for peer in peers {
+                    let peer_cfg = InMemoryBrokerCfg {
+                        peer_id: peer.remote.broker_peer_id(),
+                    };
+                    srv.add_peer(
+                        peer.psk,
+                        peer.pk,
+                        None,
+                        Some(BrokerPeer::new(broker.clone(), Box::new(peer_cfg))),
+                        peer.endpoint,
+                        peer.protocol_version,
+                    )?;
+                }
+
+                srv.event_loop()
+            })?;
+
+        let (port, waker) = match setup_rx.recv() {
+            Ok(v) => v,
+            Err(_) => {
+                return match thread.join() {
+                    Ok(Err(e)) => Err(e.context(format!("Could not start server {}", id.0))),
+                    _ => bail!("Could not start server {}", id.0),
+                }
+            }
+        };
+
+        let srv = Self {
+            id,
+            port,
+            peers: Vec::new(),
+            keys,
+            events,
+            terminate,
+            waker,
+            thread: Some(thread),
+        };
+        Ok((srv, peer_tx))
+    }
+
+    /// Ask the server to terminate; does not wait for it to do so
+    fn request_termination(&self) -> anyhow::Result<()> {
+        // The server thread might already be gone; joining will tell us why
+        let _ = self.terminate.send(());
+        self.waker.wake()?;
+        Ok(())
+    }
+
+    /// Wait for the server thread to finish
+    fn join(&mut self) -> anyhow::Result<()> {
+        let Some(thread) = self.thread.take() else {
+            return Ok(());
+        };
+        match thread.join() {
+            Ok(res) => res.with_context(|| format!("Server {} failed", self.id.0)),
+            Err(_) => bail!("Server {} panicked", self.id.0),
+        }
+    }
+}
+
+/// A set of running [AppServer]s; see the [module documentation](self)
+#[derive(Debug)]
+pub struct Harness {
+    servers: Vec<HarnessServer>,
+}
+
+impl Harness {
+    /// The address the given server listens on
+    pub fn addr(&self, server: ServerId) -> SocketAddr {
+        SocketAddr::from((Ipv6Addr::LOCALHOST, self.servers[server.0].port))
+    }
+
+    /// The latest key `server` has output for `peer`, if any
+    pub fn key(&self, server: ServerId, peer: ServerId) -> Option<SymKey> {
+        self.servers[server.0]
+            .keys
+            .lock()
+            .ok()?
+            .get(&peer.broker_peer_id())
+            .cloned()
+    }
+
+    /// Wait for `server` to produce an event matching `pred`
+    ///
+    /// Events that do not match are discarded.
+    pub fn await_event<F>(
+        &mut self,
+        server: ServerId,
+        timeout: Duration,
+        mut pred: F,
+    ) -> anyhow::Result<HarnessEvent>
+    where
+        F: FnMut(&HarnessEvent) -> bool,
+    {
+        let deadline = Instant::now() + timeout;
+        let srv = &self.servers[server.0];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let ev = match srv.events.recv_timeout(remaining) {
+                Ok(ev) => ev,
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    bail!("Timed out waiting for an event from server {}", server.0)
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    bail!("Server {} terminated while waiting for an event", server.0)
+                }
+            };
+
+            let ev = match ev {
+                AppServerTestEvent::KeyOutput { peer, why } => HarnessEvent {
+                    server,
+                    peer: srv.peers[peer.0],
+                    why,
+                },
+            };
+
+            if pred(&ev) {
+                return Ok(ev);
+            }
+        }
+    }
+
+    /// Wait for `server` to exchange a key with `peer` and return that key
+    pub fn await_key_exchange(
+        &mut self,
+        server: ServerId,
+        peer: ServerId,
+        timeout: Duration,
+    ) -> anyhow::Result<SymKey> {
+        self.await_event(server, timeout, |ev| {
+            ev.peer == peer && ev.why == KeyOutputReason::Exchanged
+        })?;
+        self.key(server, peer)
+            .context("Key exchange was reported, but the broker did not receive a key")
+    }
+
+    /// Wait for both servers to exchange a key with each other and for them to agree on it
+    pub fn await_mutual_key_exchange(
+        &mut self,
+        a: ServerId,
+        b: ServerId,
+        timeout: Duration,
+    ) -> anyhow::Result<SymKey> {
+        let deadline = Instant::now() + timeout;
+        let remaining = || deadline.saturating_duration_since(Instant::now());
+        let mut key_a = self.await_key_exchange(a, b, remaining())?;
+        let mut key_b = self.await_key_exchange(b, a, remaining())?;
+        // The other side might have rekeyed in the meantime
+        while !rosenpass_constant_time::memcmp(key_a.secret(), key_b.secret()) {
+            key_a = self.await_key_exchange(a, b, remaining())?;
+            key_b = self.key(b, a).context("Key vanished from the broker")?;
+        }
+        Ok(key_a)
+    }
+
+    /// Terminate a single server and wait for it to finish
+    ///
+    /// Events the server produced while shutting down can still be awaited afterwards.
+    pub fn stop(&mut self, server: ServerId) -> anyhow::Result<()> {
+        let srv = &mut self.servers[server.0];
+        srv.request_termination()?;
+        srv.join()
+    }
+
+    /// Terminate all servers and report any errors they encountered
+    pub fn shutdown(mut self) -> anyhow::Result<()> {
+        self.terminate_servers()
+    }
+
+    /// Used by [Self::shutdown] and when dropping the harness
+    fn terminate_servers(&mut self) -> anyhow::Result<()> {
+        let mut servers = std::mem::take(&mut self.servers);
+        let mut res = Ok(());
+        for srv in servers.iter() {
+            res = res.and(srv.request_termination());
+        }
+        for srv in servers.iter_mut() {
+            res = res.and(srv.join());
+        }
+        res
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        if let Err(e) = self.terminate_servers() {
+            log::error!("Error while shutting down the test harness: {e:?}");
+        }
+    }
+}
@@ -0,0 +1,406 @@
+//! Running external programs when keys are exchanged or go stale
+//!
+//! Besides the `key_out` file and the WireGuard broker, [AppServer::output_key] can notify other
+//! programs about new keys through hooks. A hook is a program executed with these environment
+//! variables:
+//!
+//! - `ROSENPASS_PEER_ID` – the peer id in base64
+//! - `ROSENPASS_PEER_NAME` – the name of the peer, if it has one
+//! - `ROSENPASS_REASON` – `exchanged` or `stale`
+//! - `ROSENPASS_KEY_FILE` – the `key_out` file of the peer, if it has one
+//! - `ROSENPASS_KEY_FD` – with [HookConfig::pass_key], the number of the file descriptor holding
+//!   the key
+//!
+//! With [HookConfig::pass_key], the program inherits a sealed memfd containing the key, encoded
+//! just like in the `key_out` file (see [crate::key_out::KeyOutFormat]); e.g. a shell script can
+//! read it from `/dev/fd/$ROSENPASS_KEY_FD`. Sealed memfds only exist on Linux, so `pass_key` is
+//! rejected on other platforms. Standard input is `/dev/null`. The standard output
+//! of hooks goes to the standard error of rosenpass, so it does not interfere with the
+//! `output-key` lines on standard output.
+//!
+//! Hooks run in the background; the event loop checks on them regularly and kills those that
+//! exceed their [HookConfig::timeout].
+//!
+//! # Examples
+//!
+//! ```toml
+//! # Executed for all peers without a hook of their own
+//! on_key_exchange = { command = ["/usr/local/bin/notify-key", "--verbose"], timeout = 5 }
+//!
+//! [[peers]]
+//! public_key = "/path/to/rp-peer-public-key"
+//! key_out = "/path/to/rp-key-out.txt"
+//! on_key_exchange = { command = ["/usr/local/bin/install-psk"], pass_key = true }
+//! on_key_stale = { command = ["/usr/local/bin/remove-psk"] }
+//! ```
+
+use std::io;
+use std::os::fd::AsFd;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+use anyhow::ensure;
+use serde::{Deserialize, Serialize};
+
+use crate::app_server::{AppPeerPtr, AppServer, KeyOutputReason};
+use crate::key_out::EncodedKey;
+
+/// Maximum number of hooks running at the same time; further hooks are skipped
+const MAX_RUNNING_HOOKS: usize = 64;
+
+/// How often to check whether running hooks have finished
+const REAP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Default for [HookConfig::timeout]
+fn default_hook_timeout() -> u64 {
+    10
+}
+
+/// A program to execute on a key exchange event
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HookConfig {
+    /// The program to execute, followed by its arguments
+    pub command: Vec<String>,
+
+    /// Seconds after which the program is killed
+    #[serde(default = "default_hook_timeout")]
+    pub timeout: u64,
+
+    /// Pass the key to the program on an inherited file descriptor; see [crate::hooks]
+    ///
+    /// Only supported on Linux.
+    #[serde(default)]
+    pub pass_key: bool,
+}
+
+impl HookConfig {
+    /// Check that the configuration is sound
+    pub fn validate(&self) -> anyhow::Result<()> {
+        ensure!(!self.command.is_empty(), "hook command must not be empty");
+        ensure!(self.timeout > 0, "hook timeout must be at least one second");
+        ensure!(
+            cfg!(target_os = "linux") || !self.pass_key,
+            "pass_key is only supported on Linux"
+        );
+        Ok(())
+    }
+}
+
+/// The hooks for the different key exchange events
+///
+/// Used for the whole server (see [AppServer::hooks]) as well as for individual peers (see
+/// [crate::app_server::AppPeer::hooks]); the hook of a peer takes the place of the server's hook
+/// for the same event.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hooks {
+    /// Executed after a key was exchanged with a peer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_key_exchange: Option<HookConfig>,
+
+    /// Executed after the key of a peer was replaced with a random one because no new key
+    /// could be exchanged in time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_key_stale: Option<HookConfig>,
+}
+
+impl Hooks {
+    /// Whether no hook is configured
+    pub fn is_empty(&self) -> bool {
+        self.on_key_exchange.is_none() && self.on_key_stale.is_none()
+    }
+
+    /// The hook for key outputs with the given reason
+    pub fn get(&self, why: KeyOutputReason) -> Option<&HookConfig> {
+        match why {
+            KeyOutputReason::Exchanged => self.on_key_exchange.as_ref(),
+            KeyOutputReason::Stale => self.on_key_stale.as_ref(),
+        }
+    }
+
+    /// Check that the configuration is sound
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for hook in [&self.on_key_exchange, &self.on_key_stale]
+            .into_iter()
+            .flatten()
+        {
+            hook.validate()?;
+        }
+        Ok(())
+    }
+}
+
+/// A hook that was started and has not finished yet
+#[derive(Debug)]
+struct RunningHook {
+    child: Child,
+    /// The peer the hook was started for, for the logs
+    peer: String,
+    /// When to kill the hook
+    deadline: Instant,
+}
+
+/// Keeps track of the hooks running in the background
+///
+/// Part of [AppServer].
+#[derive(Debug, Default)]
+pub struct HookRunner {
+    running: Vec<RunningHook>,
+}
+
+impl HookRunner {
+    /// Number of hooks that have not finished yet
+    pub fn running(&self) -> usize {
+        self.running.len()
+    }
+
+    /// Collect the hooks that finished and kill those that ran for too long
+    ///
+    /// Returns how long it is until the hooks should be checked again.
+    pub fn reap(&mut self) -> Option<Duration> {
+        let now = Instant::now();
+        self.running.retain_mut(|hook| {
+            match hook.child.try_wait() {
+                Ok(Some(status)) if status.success() => return false,
+                Ok(Some(status)) => {
+                    log::warn!(peer = hook.peer; "Hook for peer {} failed: {status}", hook.peer);
+                    return false;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    log::warn!(peer = hook.peer; "Could not check on hook: {e}");
+                    return false;
+                }
+            }
+
+            if now < hook.deadline {
+                return true;
+            }
+
+            log::warn!(peer = hook.peer; "Hook for peer {} timed out", hook.peer);
+            if let Err(e) = hook.child.kill() {
+                log::warn!(peer = hook.peer; "Could not kill hook: {e}");
+            }
+            // Killing is immediate, so this does not block for long
+            let _ = hook.child.wait();
+            false
+        });
+
+        self.running
+            .iter()
+            .map(|hook| hook.deadline.saturating_duration_since(now))
+            .min()
+            .map(|due| due.min(REAP_INTERVAL))
+    }
+
+    /// Start `hook` in the background
+    pub fn spawn(
+        &mut self,
+        hook: &HookConfig,
+        env: &HookEnv,
+        key: &EncodedKey,
+    ) -> anyhow::Result<()> {
+        ensure!(
+            self.running.len() < MAX_RUNNING_HOOKS,
+            "Too many hooks are running already"
+        );
+
+        let (program, args) = hook
+            .command
+            .split_first()
+            .ok_or_else(|| anyhow::anyhow!("hook command must not be empty"))?;
+        let mut cmd = Command::new(program);
+        cmd.args(args)
+            .env("ROSENPASS_PEER_ID", env.peer_id)
+            .env("ROSENPASS_REASON", env.reason.as_str())
+            .stdin(Stdio::null())
+            .stdout(io::stderr().as_fd().try_clone_to_owned()?);
+        if let Some(name) = env.peer_name {
+            cmd.env("ROSENPASS_PEER_NAME", name);
+        }
+        if let Some(key_file) = env.key_file {
+            cmd.env("ROSENPASS_KEY_FILE", key_file);
+        }
+
+        // Stays open in this process until the program was started
+        let key_fd = match hook.pass_key {
+            true => Some(pass_key(&mut cmd, key)?),
+            false => None,
+        };
+
+        let child = cmd.spawn()?;
+        drop(key_fd);
+        self.running.push(RunningHook {
+            child,
+            peer: env.peer_id.to_owned(),
+            deadline: Instant::now() + Duration::from_secs(hook.timeout),
+        });
+
+        Ok(())
+    }
+}
+
+/// What a hook learns about the event it is executed for
+#[derive(Debug)]
+pub struct HookEnv<'a> {
+    /// The peer id in base64
+    pub peer_id: &'a str,
+    /// See [crate::app_server::AppPeer::name]
+    pub peer_name: Option<&'a str>,
+    /// Why the key was output
+    pub reason: KeyOutputReason,
+    /// See [crate::app_server::AppPeer::outfile]
+    pub key_file: Option<&'a Path>,
+}
+
+impl AppServer {
+    /// Run the hook configured for `peer` and `why`, if any; see [crate::hooks]
+    ///
+    /// Failing to start the hook is logged, but not fatal.
+    pub fn run_key_hook(&mut self, peer: AppPeerPtr, why: KeyOutputReason, key: &EncodedKey) {
+        let ap = peer.get_app(self);
+        let Some(hook) = ap.hooks.get(why).or_else(|| self.hooks.get(why)).cloned() else {
+            return;
+        };
+
+        let peer_id = match self.peer_log_id(peer) {
+            Ok(id) => id,
+            Err(e) => {
+                log::warn!("Could not run hook for peer {}: {e:?}", peer.0);
+                return;
+            }
+        };
+        let peer_name = ap.name.clone();
+        let key_file = ap.outfile.clone();
+        let env = HookEnv {
+            peer_id: &peer_id,
+            peer_name: peer_name.as_deref(),
+            reason: why,
+            key_file: key_file.as_deref(),
+        };
+
+        if let Err(e) = self.hook_runner.spawn(&hook, &env, key) {
+            log::warn!(peer = peer_id; "Could not run hook for peer {peer_id}: {e:?}");
+        }
+    }
+}
+
+/// Make the program started by `cmd` inherit a sealed memfd containing `key`
+///
+/// The returned memfd must stay open until the program was started.
+#[cfg(target_os = "linux")]
+fn pass_key(cmd: &mut Command, key: &EncodedKey) -> anyhow::Result<std::fs::File> {
+    use std::os::fd::{AsRawFd, BorrowedFd};
+    use std::os::unix::process::CommandExt;
+
+    let memfd = crate::key_out::sealed_memfd(key)?;
+    let fd = memfd.as_raw_fd();
+    cmd.env("ROSENPASS_KEY_FD", fd.to_string());
+    // The memfd is close-on-exec, so it does not leak into other programs; only the hook
+    // gets to keep it.
+    //
+    // SAFETY: fcntl(2) is async-signal-safe, and `fd` is open until after the spawn
+    unsafe {
+        cmd.pre_exec(move || {
+            let fd = BorrowedFd::borrow_raw(fd);
+            rustix::io::fcntl_setfd(fd, rustix::io::FdFlags::empty())?;
+            Ok(())
+        });
+    }
+    Ok(memfd)
+}
+
+/// See [HookConfig::validate]; sealed memfds only exist on Linux
+#[cfg(not(target_os = "linux"))]
+fn pass_key(_cmd: &mut Command, _key: &EncodedKey) -> anyhow::Result<std::fs::File> {
+    anyhow::bail!("pass_key is only supported on Linux")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use rosenpass_util::file::LoadValueB64;
+
+    use super::*;
+    use crate::app_server::MAX_B64_KEY_SIZE;
+    use crate::key_out::KeyOutFormat;
+    use crate::protocol::SymKey;
+
+    fn wait_for(runner: &mut HookRunner) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while runner.reap().is_some() {
+            assert!(Instant::now() < deadline, "hooks did not finish");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn hooks_get_the_environment_and_key() -> anyhow::Result<()> {
+        rosenpass_secret_memory::secret_policy_use_only_malloc_secrets();
+        let tmp = tempfile::tempdir()?;
+        let out = tmp.path().join("out");
+
+        let hook = HookConfig {
+            command: vec![
+                "sh".to_owned(),
+                "-c".to_owned(),
+                r#"echo "$ROSENPASS_PEER_ID $ROSENPASS_PEER_NAME $ROSENPASS_REASON $ROSENPASS_KEY_FILE" > "$0.env"; cat "/dev/fd/$ROSENPASS_KEY_FD" > "$0.key""#.to_owned(),
+                out.display().to_string(),
+            ],
+            timeout: 10,
+            pass_key: true,
+        };
+        let env = HookEnv {
+            peer_id: "AAAA",
+            peer_name: Some("alice"),
+            reason: KeyOutputReason::Exchanged,
+            key_file: Some(Path::new("/tmp/key")),
+        };
+        let key = SymKey::random();
+        let encoded = EncodedKey::new(&key, KeyOutFormat::Base64, env.peer_id, env.reason)?;
+
+        let mut runner = HookRunner::default();
+        runner.spawn(&hook, &env, &encoded)?;
+        wait_for(&mut runner);
+
+        let env = std::fs::read_to_string(tmp.path().join("out.env"))?;
+        assert_eq!(env, "AAAA alice exchanged /tmp/key\n");
+        let received = SymKey::load_b64::<MAX_B64_KEY_SIZE, _>(tmp.path().join("out.key"))?;
+        assert_eq!(received.secret(), key.secret());
+
+        Ok(())
+    }
+
+    #[test]
+    fn hooks_are_killed_after_their_timeout() -> anyhow::Result<()> {
+        rosenpass_secret_memory::secret_policy_use_only_malloc_secrets();
+        let hook = HookConfig {
+            command: vec!["sleep".to_owned(), "30".to_owned()],
+            timeout: 1,
+            pass_key: false,
+        };
+        let env = HookEnv {
+            peer_id: "AAAA",
+            peer_name: None,
+            reason: KeyOutputReason::Stale,
+            key_file: None,
+        };
+
+        let mut runner = HookRunner::default();
+        let started = Instant::now();
+        let key = EncodedKey::new(
+            &SymKey::random(),
+            KeyOutFormat::Base64,
+            env.peer_id,
+            env.reason,
+        )?;
+        runner.spawn(&hook, &env, &key)?;
+        assert_eq!(runner.running(), 1);
+        wait_for(&mut runner);
+        assert!(started.elapsed() < Duration::from_secs(10));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,305 @@
+//! Keeping the static secret key out of the process that handles network traffic
+//!
+//! The only operation that needs the static secret key `sskm` is decapsulating the
+//! [StaticKem] ciphertexts peers send in [InitHello] and [RespHello]. With the `key_oracle`
+//! option, this happens in a separate process – the key oracle – and [CryptoServer] asks it
+//! through the [StaticKeyOracle] trait. The process parsing network messages never loads the
+//! secret key, so a compromise of the parser does not reveal it.
+//!
+//! - `key_oracle = "spawn"` makes `exchange-config` start `rosenpass key-oracle` as a child
+//!   process and talk to it over a socket pair. The oracle loads the `secret_key` of the
+//!   configuration; it is started before privileges are dropped and the sandbox is set up (see
+//!   [crate::privileges], [crate::landlock] and [crate::seccomp]), so it keeps the access to the
+//!   key the server gives up.
+//! - `key_oracle = { socket = "/run/rosenpass/oracle.sock" }` connects to an oracle that was
+//!   started separately with `rosenpass key-oracle --secret-key <PATH> --listen <SOCKET>`, e.g.
+//!   as a different user. The server then does not need access to the secret key at all.
+//!
+//! At startup, the server checks that the oracle holds the secret key matching its `public_key`.
+//!
+//! The server waits for the oracle while handling a handshake message, so an oracle that does not
+//! answer within [ORACLE_TIMEOUT] counts as failed. A listening oracle is connected to again for
+//! the next message. A spawned oracle cannot be replaced – the server may no longer read the
+//! secret key or start programs – so the server exits with [KeyOracleFailed] instead, to be
+//! restarted by its supervisor (e.g. systemd with `Restart=on-failure`).
+//!
+//! The oracle answers one request at a time: the server sends a ciphertext of
+//! [StaticKem::CT_LEN] bytes and gets back a status byte – zero for success – followed by the
+//! shared key of [StaticKem::SHK_LEN] bytes. A listening oracle serves one connection at a time.
+//!
+//! [InitHello]: crate::msgs::InitHello
+//! [RespHello]: crate::msgs::RespHello
+//! [CryptoServer]: crate::protocol::CryptoServer
+
+use std::io::{self, Read, Write};
+use std::net::Shutdown;
+use std::ops::Deref;
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::time::Duration;
+
+use anyhow::{bail, ensure, Context};
+use log::{info, warn};
+use rosenpass_cipher_traits::primitives::Kem;
+use rosenpass_ciphers::StaticKem;
+use rosenpass_secret_memory::Secret;
+use rosenpass_util::fd::claim_fd;
+use serde::{Deserialize, Serialize};
+
+use crate::credentials::load_secret;
+use crate::protocol::{SPk, SSk, StaticKeyOracle};
+
+/// How long the server waits for an answer from the oracle
+///
+/// Decapsulation takes well under a millisecond; the event loop is blocked while waiting.
+pub const ORACLE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Status byte of a successful decapsulation
+const STATUS_OK: u8 = 0;
+/// Status byte of a failed decapsulation
+const STATUS_ERR: u8 = 1;
+
+/// The spawned key oracle stopped answering, so no handshake can be completed anymore; see
+/// [crate::key_oracle]
+#[derive(thiserror::Error, Debug)]
+#[error("The key oracle stopped answering; rosenpass needs a restart")]
+pub struct KeyOracleFailed;
+
+/// Where the key oracle runs; see [crate::key_oracle]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyOracleConfig {
+    /// Start the oracle as a child process
+    Spawn,
+    /// Connect to an oracle listening on this unix socket
+    Socket(PathBuf),
+}
+
+/// The server side of the connection to a key oracle
+#[derive(Debug)]
+pub struct KeyOracleClient {
+    stream: UnixStream,
+    /// The socket of a listening oracle, to reconnect to
+    path: Option<PathBuf>,
+    /// The oracle process, if it was spawned by us
+    child: Option<Child>,
+    /// Whether a request failed halfway, so requests and responses may be out of step
+    broken: bool,
+}
+
+impl KeyOracleClient {
+    /// Start or connect to the oracle as configured; `secret_key` is what a spawned oracle loads
+    pub fn from_config(config: &KeyOracleConfig, secret_key: &Path) -> anyhow::Result<Self> {
+        match config {
+            KeyOracleConfig::Spawn => Self::spawn(secret_key),
+            KeyOracleConfig::Socket(path) => Self::connect(path),
+        }
+    }
+
+    /// Connect to an oracle listening on `path`
+    pub fn connect(path: &Path) -> anyhow::Result<Self> {
+        let stream = UnixStream::connect(path)
+            .with_context(|| format!("Could not connect to key oracle at {path:?}"))?;
+        let mut client = Self::from_stream(stream, None)?;
+        client.path = Some(path.to_owned());
+        Ok(client)
+    }
+
+    /// Start `rosenpass key-oracle` for `secret_key` as a child process
+    pub fn spawn(secret_key: &Path) -> anyhow::Result<Self> {
+        let (ours, theirs) = UnixStream::pair()?;
+        // The child inherits its end of the socket pair; ours stays private
+        rustix::io::fcntl_setfd(&theirs, rustix::io::FdFlags::empty())?;
+
+        let child = Command::new(std::env::current_exe()?)
+            .arg("key-oracle")
+            .arg("--secret-key")
+            .arg(secret_key)
+            .arg("--stream-fd")
+            .arg(theirs.as_raw_fd().to_string())
+            .spawn()
+            .context("Could not start key oracle")?;
+        drop(theirs);
+
+        info!("Started key oracle as process {}", child.id());
+        Self::from_stream(ours, Some(child))
+    }
+
+    /// Talk to an oracle on `stream`, such as one running [serve] on the other end of a socket
+    /// pair; `child` is the oracle process, if there is one
+    ///
+    /// Without a socket path to reconnect to, a failed request is fatal, as for a spawned oracle.
+    pub fn from_stream(stream: UnixStream, child: Option<Child>) -> anyhow::Result<Self> {
+        stream.set_read_timeout(Some(ORACLE_TIMEOUT))?;
+        stream.set_write_timeout(Some(ORACLE_TIMEOUT))?;
+        Ok(Self {
+            stream,
+            path: None,
+            child,
+            broken: false,
+        })
+    }
+
+    /// Check that the oracle holds the secret key for `pk`
+    pub fn verify(&mut self, pk: &SPk) -> anyhow::Result<()> {
+        let mut shk = Secret::<{ StaticKem::SHK_LEN }>::zero();
+        let mut ct = [0u8; StaticKem::CT_LEN];
+        StaticKem.encaps(shk.secret_mut(), &mut ct, pk.deref())?;
+
+        let mut answer = Secret::<{ StaticKem::SHK_LEN }>::zero();
+        self.decaps(answer.secret_mut(), &ct)?;
+        ensure!(
+            rosenpass_constant_time::memcmp(shk.secret(), answer.secret()),
+            "The key oracle holds a secret key that does not belong to our public key"
+        );
+        Ok(())
+    }
+}
+
+impl StaticKeyOracle for KeyOracleClient {
+    fn decaps(
+        &mut self,
+        shk: &mut [u8; StaticKem::SHK_LEN],
+        ct: &[u8; StaticKem::CT_LEN],
+    ) -> anyhow::Result<()> {
+        if self.broken {
+            let Some(path) = self.path.clone() else {
+                return Err(KeyOracleFailed.into());
+            };
+            *self = Self::connect(&path)?;
+        }
+
+        let mut status = [0u8];
+        let res = self
+            .stream
+            .write_all(ct)
+            .and_then(|()| self.stream.read_exact(&mut status))
+            .and_then(|()| match status[0] {
+                STATUS_OK => self.stream.read_exact(shk),
+                _ => Ok(()),
+            });
+        if let Err(e) = res {
+            self.broken = true;
+            return match self.path {
+                Some(_) => Err(e).context("Could not get an answer from the key oracle"),
+                None => Err(e).context(KeyOracleFailed),
+            };
+        }
+        ensure!(status[0] == STATUS_OK, "Key oracle failed to decapsulate");
+        Ok(())
+    }
+}
+
+impl Drop for KeyOracleClient {
+    fn drop(&mut self) {
+        // A spawned oracle exits once its end of the socket pair is closed
+        let _ = self.stream.shutdown(Shutdown::Both);
+        if let Some(mut child) = self.child.take() {
+            let _ = child.wait();
+        }
+    }
+}
+
+/// Answer decapsulation requests on `stream` until it is closed
+pub fn serve(sk: &SSk, mut stream: UnixStream) -> anyhow::Result<()> {
+    let mut ct = [0u8; StaticKem::CT_LEN];
+    let mut shk = Secret::<{ StaticKem::SHK_LEN }>::zero();
+    loop {
+        match stream.read_exact(&mut ct) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+
+        match StaticKem.decaps(shk.secret_mut(), sk.secret(), &ct) {
+            Ok(()) => {
+                stream.write_all(&[STATUS_OK])?;
+                stream.write_all(shk.secret())?;
+            }
+            Err(e) => {
+                warn!("Could not decapsulate: {e}");
+                stream.write_all(&[STATUS_ERR])?;
+            }
+        }
+    }
+}
+
+/// Run the oracle for the secret key at `secret_key`, either on the inherited socket `stream_fd`
+/// or listening on the unix socket `listen`
+///
+/// Used by the `key-oracle` command.
+pub fn run(
+    secret_key: &Path,
+    listen: Option<&Path>,
+    stream_fd: Option<RawFd>,
+) -> anyhow::Result<()> {
+    let sk: SSk = load_secret(secret_key)?;
+
+    match (listen, stream_fd) {
+        (None, Some(fd)) => {
+            let stream = UnixStream::from(
+                claim_fd(fd).with_context(|| format!("Could not claim file descriptor {fd}"))?,
+            );
+            serve(&sk, stream)
+        }
+        (Some(path), None) => {
+            let listener = UnixListener::bind(path)
+                .with_context(|| format!("Could not listen on {path:?}"))?;
+            info!("Key oracle listening on {path:?}");
+            for stream in listener.incoming() {
+                if let Err(e) = stream
+                    .map_err(anyhow::Error::from)
+                    .and_then(|s| serve(&sk, s))
+                {
+                    warn!("Key oracle connection failed: {e:?}");
+                }
+            }
+            Ok(())
+        }
+        _ => bail!("The key oracle needs either a socket to listen on or a file descriptor"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ops::DerefMut;
+
+    #[test]
+    fn oracle_decapsulates_for_the_server() -> anyhow::Result<()> {
+        rosenpass_secret_memory::secret_policy_use_only_malloc_secrets();
+        let (mut sk, mut pk) = (SSk::zero(), SPk::zero());
+        StaticKem.keygen(sk.secret_mut(), pk.deref_mut())?;
+
+        let (ours, theirs) = UnixStream::pair()?;
+        let oracle = std::thread::spawn(move || serve(&sk, theirs));
+        let mut client = KeyOracleClient::from_stream(ours, None)?;
+        client.verify(&pk)?;
+
+        // A different key pair is detected
+        let (mut other_sk, mut other_pk) = (SSk::zero(), SPk::zero());
+        StaticKem.keygen(other_sk.secret_mut(), other_pk.deref_mut())?;
+        assert!(client.verify(&other_pk).is_err());
+
+        drop(client);
+        oracle.join().unwrap()
+    }
+
+    #[test]
+    fn a_spawned_oracle_that_stops_answering_is_fatal() -> anyhow::Result<()> {
+        rosenpass_secret_memory::secret_policy_use_only_malloc_secrets();
+        let (ours, theirs) = UnixStream::pair()?;
+        let mut client = KeyOracleClient::from_stream(ours, None)?;
+        drop(theirs);
+
+        let mut shk = [0u8; StaticKem::SHK_LEN];
+        let ct = [0u8; StaticKem::CT_LEN];
+        for _ in 0..2 {
+            let err = client.decaps(&mut shk, &ct).unwrap_err();
+            assert!(err.is::<KeyOracleFailed>(), "{err:?}");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,420 @@
+//! Handing exchanged keys to their consumers
+//!
+//! Besides the `key_out` file and the WireGuard broker, [AppServer::output_key] writes the keys
+//! of a peer to the [KeySink]s configured for it (see [RosenpassPeer::key_sinks]):
+//!
+//! - `fd` writes the key to a file descriptor inherited from the process that started
+//!   rosenpass, e.g. a pipe; the consumer needs to keep reading from it
+//! - `fifo` writes the key to a named pipe, if a consumer currently has it open for reading;
+//!   keys are skipped otherwise
+//! - `memfd` (only on Linux, with the `experiment_api` feature) puts every key into a new sealed
+//!   `memfd` and sends it to the unix socket at the given path, along with a line containing the
+//!   peer id and the reason the key was output
+//!
+//! With the `experiment_api` feature, clients of the API can also receive the keys of a peer as
+//! sealed memfds over their API connection on Linux; see
+//! `crate::api::Server::register_key_sink`.
+//!
+//! None of these leave the key in the page cache of a file system. All key outputs of a peer –
+//! including the `key_out` file and the memfd passed to hooks – encode the key in the
+//! [KeyOutFormat] configured for the peer (see [RosenpassPeer::key_out_format]).
+//!
+//! # Examples
+//!
+//! ```toml
+//! [[peers]]
+//! public_key = "/path/to/rp-peer-public-key"
+//! key_out_format = "hex"
+//! key_sinks = [
+//!     { fd = 3 },
+//!     { fifo = "/run/rosenpass/peer.fifo" },
+//!     { memfd = "/run/key-consumer.sock" },
+//! ]
+//! ```
+//!
+//! [RosenpassPeer::key_sinks]: crate::config::RosenpassPeer::key_sinks
+//! [RosenpassPeer::key_out_format]: crate::config::RosenpassPeer::key_out_format
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::os::fd::{AsFd, OwnedFd, RawFd};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context};
+use rosenpass_secret_memory::Secret;
+use rosenpass_util::fd::{claim_fd_inplace, FdIo};
+use rosenpass_util::file::{Encoding, StoreValueEncodedWriter};
+use rustix::fs::{Mode, OFlags};
+use serde::{Deserialize, Serialize};
+
+use crate::app_server::{AppPeerPtr, AppServer, KeyOutputReason};
+use crate::protocol::SymKey;
+
+/// Where to write the keys of a peer; see [crate::key_out]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeySinkConfig {
+    /// Write the keys to this inherited file descriptor
+    ///
+    /// The file descriptor is switched to nonblocking mode; keys the reader is not ready for are
+    /// dropped, just like with [Self::Fifo].
+    Fd(RawFd),
+    /// Write the keys to the named pipe at this path
+    Fifo(PathBuf),
+    /// Send a sealed memfd with the key to the unix socket at this path
+    #[cfg(all(feature = "experiment_api", target_os = "linux"))]
+    Memfd(PathBuf),
+}
+
+impl KeySinkConfig {
+    /// Set up the sink
+    ///
+    /// Inherited file descriptors are claimed the first time they are used; afterwards, all sinks
+    /// for the same file descriptor share it.
+    pub fn open(&self) -> anyhow::Result<KeySink> {
+        Ok(match self {
+            Self::Fd(fd) => KeySink::Fd(*fd, claim_inherited_fd(*fd)?),
+            Self::Fifo(path) => KeySink::Fifo(path.clone()),
+            #[cfg(all(feature = "experiment_api", target_os = "linux"))]
+            Self::Memfd(path) => KeySink::Memfd(path.clone()),
+        })
+    }
+}
+
+/// The file descriptors claimed by [claim_inherited_fd] so far
+fn claimed_fds() -> &'static Mutex<HashMap<RawFd, Arc<OwnedFd>>> {
+    static CLAIMED: OnceLock<Mutex<HashMap<RawFd, Arc<OwnedFd>>>> = OnceLock::new();
+    CLAIMED.get_or_init(Default::default)
+}
+
+/// Take ownership of an inherited file descriptor, or return it if that happened before
+fn claim_inherited_fd(fd: RawFd) -> anyhow::Result<Arc<OwnedFd>> {
+    let mut claimed = claimed_fds().lock().unwrap();
+    if let Some(owned) = claimed.get(&fd) {
+        return Ok(owned.clone());
+    }
+
+    anyhow::ensure!(
+        fd > 2,
+        "Refusing to write keys to standard IO (file descriptor {fd})"
+    );
+    let owned =
+        claim_fd_inplace(fd).with_context(|| format!("Could not claim file descriptor {fd}"))?;
+
+    // A reader that stops reading must not stall the event loop
+    let flags = rustix::fs::fcntl_getfl(&owned)?;
+    rustix::fs::fcntl_setfl(&owned, flags | OFlags::NONBLOCK)
+        .with_context(|| format!("Could not make file descriptor {fd} nonblocking"))?;
+
+    let owned = Arc::new(owned);
+    claimed.insert(fd, owned.clone());
+    Ok(owned)
+}
+
+/// A destination for the keys of a peer, set up from a [KeySinkConfig]
+#[derive(Debug, Clone)]
+pub enum KeySink {
+    /// See [KeySinkConfig::Fd]
+    Fd(RawFd, Arc<OwnedFd>),
+    /// See [KeySinkConfig::Fifo]
+    Fifo(PathBuf),
+    /// See [KeySinkConfig::Memfd]
+    #[cfg(all(feature = "experiment_api", target_os = "linux"))]
+    Memfd(PathBuf),
+}
+
+impl KeySink {
+    /// Hand `key` to the consumer
+    ///
+    /// `peer_id` and `why` are passed along where the sink has room for them.
+    #[cfg_attr(
+        not(all(feature = "experiment_api", target_os = "linux")),
+        allow(unused_variables)
+    )]
+    pub fn write(
+        &self,
+        key: &EncodedKey,
+        peer_id: &str,
+        why: KeyOutputReason,
+    ) -> anyhow::Result<()> {
+        match self {
+            Self::Fd(fd, owned) => {
+                write_nonblocking(owned.as_ref(), key, &format!("file descriptor {fd}"))
+            }
+            Self::Fifo(path) => {
+                // Opening without a reader fails instead of blocking; so does writing to a full
+                // pipe. Writes smaller than PIPE_BUF are atomic, so readers never see partial keys.
+                let fd = rustix::fs::open(
+                    path,
+                    OFlags::WRONLY | OFlags::NONBLOCK | OFlags::CLOEXEC,
+                    Mode::empty(),
+                )
+                .with_context(|| format!("Could not open named pipe {path:?}"))?;
+                write_nonblocking(fd, key, &format!("named pipe {path:?}"))
+            }
+            #[cfg(all(feature = "experiment_api", target_os = "linux"))]
+            Self::Memfd(path) => send_memfd(path, key, peer_id, why)
+                .with_context(|| format!("Could not send key to unix socket {path:?}")),
+        }
+    }
+}
+
+/// Write `key` to the nonblocking file descriptor `fd`, which is called `what` in errors
+///
+/// Fails rather than waiting if the reader is not ready for the key.
+fn write_nonblocking(fd: impl AsFd, key: &EncodedKey, what: &str) -> anyhow::Result<()> {
+    match FdIo(fd).write_all(key.as_bytes()) {
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+            bail!("The reader of {what} is not keeping up; dropping the key")
+        }
+        res => res.with_context(|| format!("Could not write key to {what}")),
+    }
+}
+
+/// Put `key` into a sealed memfd and send it to the unix socket at `path`
+#[cfg(all(feature = "experiment_api", target_os = "linux"))]
+fn send_memfd(
+    path: &std::path::Path,
+    key: &EncodedKey,
+    peer_id: &str,
+    why: KeyOutputReason,
+) -> anyhow::Result<()> {
+    use std::collections::VecDeque;
+
+    use mio::net::UnixStream;
+    use rosenpass_util::mio::WriteWithFileDescriptors;
+
+    let memfd = sealed_memfd(key)?;
+
+    // Connecting to a unix socket does not block; it fails if the listener's backlog is full
+    let socket = UnixStream::connect(path)?;
+    let mut fds = VecDeque::from([&memfd]);
+    let mut writer = WriteWithFileDescriptors::<UnixStream, _, _, _>::new(&socket, &mut fds);
+    writer.write_all(format!("{peer_id} {}\n", why.as_str()).as_bytes())?;
+    anyhow::ensure!(writer.fds().is_empty(), "The memfd was not sent");
+    Ok(())
+}
+
+/// Put `key` into a new memfd that can be neither written to nor resized, positioned at its start
+///
+/// Sealed memfds only exist on Linux.
+#[cfg(target_os = "linux")]
+pub fn sealed_memfd(key: &EncodedKey) -> anyhow::Result<std::fs::File> {
+    use std::fs::File;
+    use std::io::{Seek, SeekFrom};
+
+    use rustix::fs::{fcntl_add_seals, memfd_create, MemfdFlags, SealFlags};
+
+    let mut memfd = File::from(memfd_create(
+        "rosenpass-key",
+        MemfdFlags::CLOEXEC | MemfdFlags::ALLOW_SEALING,
+    )?);
+    memfd.write_all(key.as_bytes())?;
+    memfd.seek(SeekFrom::Start(0))?;
+    fcntl_add_seals(
+        &memfd,
+        SealFlags::SHRINK | SealFlags::GROW | SealFlags::WRITE | SealFlags::SEAL,
+    )?;
+    Ok(memfd)
+}
+
+/// How keys are encoded for output
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyOutFormat {
+    /// Base64, without a trailing newline
+    #[default]
+    Base64,
+    /// Lowercase hexadecimal, without a trailing newline
+    Hex,
+    /// The bytes of the key as they are
+    Raw,
+    /// A `PresharedKey = <base64>` line for a WireGuard configuration file
+    Wireguard,
+    /// A JSON object on a line of its own, with the key in base64 as `key`, the peer id as
+    /// `peer`, the unix time as `timestamp` and the reason the key was output as `reason`
+    Json,
+}
+
+impl KeyOutFormat {
+    /// Whether this is the default format
+    pub fn is_base64(&self) -> bool {
+        *self == Self::Base64
+    }
+}
+
+/// Size of the buffer [EncodedKey] encodes keys into; fits every [KeyOutFormat]
+const MAX_ENCODED_KEY_SIZE: usize = 256;
+
+/// A key encoded for output, as written to `key_out` files, key sinks and hooks
+pub struct EncodedKey {
+    buf: Secret<MAX_ENCODED_KEY_SIZE>,
+    len: usize,
+}
+
+impl EncodedKey {
+    /// Encode `key` in `format`; `peer_id` and `why` are included where the format has room
+    /// for them
+    pub fn new(
+        key: &SymKey,
+        format: KeyOutFormat,
+        peer_id: &str,
+        why: KeyOutputReason,
+    ) -> anyhow::Result<Self> {
+        fn store<W: Write>(key: &SymKey, encoding: Encoding, out: W) -> anyhow::Result<()> {
+            key.store_encoded_writer::<MAX_ENCODED_KEY_SIZE, _>(encoding, out)
+        }
+
+        let mut buf = Secret::zero();
+        let mut out: &mut [u8] = buf.secret_mut();
+        match format {
+            KeyOutFormat::Base64 => store(key, Encoding::Base64, &mut out)?,
+            KeyOutFormat::Hex => store(key, Encoding::Hex, &mut out)?,
+            KeyOutFormat::Raw => store(key, Encoding::Raw, &mut out)?,
+            KeyOutFormat::Wireguard => {
+                out.write_all(b"PresharedKey = ")?;
+                store(key, Encoding::Base64, &mut out)?;
+                out.write_all(b"\n")?;
+            }
+            KeyOutFormat::Json => {
+                // Neither base64 nor the reasons contain characters that need escaping
+                let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+                out.write_all(br#"{"key":""#)?;
+                store(key, Encoding::Base64, &mut out)?;
+                writeln!(
+                    out,
+                    r#"","peer":"{peer_id}","timestamp":{timestamp},"reason":"{}"}}"#,
+                    why.as_str()
+                )?;
+            }
+        }
+
+        let len = MAX_ENCODED_KEY_SIZE - out.len();
+        Ok(Self { buf, len })
+    }
+
+    /// The encoded key
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf.secret()[..self.len]
+    }
+}
+
+impl AppServer {
+    /// Write `key` to all [KeySink]s of `peer` and to the API clients registered as its key sink
+    ///
+    /// Failures are logged, but not fatal; a consumer that is not listening right now should not
+    /// keep the other outputs from getting the key.
+    pub fn write_key_sinks(
+        &mut self,
+        peer: AppPeerPtr,
+        why: KeyOutputReason,
+        key: &EncodedKey,
+    ) -> anyhow::Result<()> {
+        #[cfg(feature = "experiment_api")]
+        self.send_key_to_api(peer, why, key);
+
+        let sinks = &peer.get_app(self).key_sinks;
+        if sinks.is_empty() {
+            return Ok(());
+        }
+
+        let peer_id = self.peer_log_id(peer)?;
+        for sink in sinks {
+            if let Err(e) = sink.write(key, &peer_id, why) {
+                log::warn!(peer = peer_id; "Could not output the key of peer {peer_id}: {e:?}");
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::process::Command;
+
+    use super::*;
+
+    #[test]
+    fn keys_are_encoded_in_the_configured_format() -> anyhow::Result<()> {
+        rosenpass_secret_memory::secret_policy_use_only_malloc_secrets();
+        let key = SymKey::from_slice(&[0xab; 32]);
+        let encode = |format| -> anyhow::Result<Vec<u8>> {
+            let encoded = EncodedKey::new(&key, format, "AAAA", KeyOutputReason::Exchanged)?;
+            Ok(encoded.as_bytes().to_vec())
+        };
+        let b64 = "q6urq6urq6urq6urq6urq6urq6urq6urq6urq6urq6s=";
+
+        assert_eq!(encode(KeyOutFormat::Base64)?, b64.as_bytes());
+        assert_eq!(encode(KeyOutFormat::Hex)?, "ab".repeat(32).as_bytes());
+        assert_eq!(encode(KeyOutFormat::Raw)?, [0xab; 32]);
+        assert_eq!(
+            encode(KeyOutFormat::Wireguard)?,
+            format!("PresharedKey = {b64}\n").as_bytes()
+        );
+
+        let json = String::from_utf8(encode(KeyOutFormat::Json)?)?;
+        let prefix = format!(r#"{{"key":"{b64}","peer":"AAAA","timestamp":"#);
+        assert!(json.starts_with(&prefix), "{json}");
+        assert!(json.ends_with(",\"reason\":\"exchanged\"}\n"), "{json}");
+
+        Ok(())
+    }
+
+    #[test]
+    fn fifo_sinks_only_write_to_listening_consumers() -> anyhow::Result<()> {
+        rosenpass_secret_memory::secret_policy_use_only_malloc_secrets();
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("key.fifo");
+        anyhow::ensure!(Command::new("mkfifo").arg(&path).status()?.success());
+
+        let sink = KeySinkConfig::Fifo(path.clone()).open()?;
+        let key = SymKey::random();
+        let encoded = EncodedKey::new(&key, KeyOutFormat::Base64, "AAAA", KeyOutputReason::Stale)?;
+
+        // Nobody is reading, so the key is not written
+        assert!(sink
+            .write(&encoded, "AAAA", KeyOutputReason::Exchanged)
+            .is_err());
+
+        let reader = rustix::fs::open(
+            &path,
+            OFlags::RDONLY | OFlags::NONBLOCK | OFlags::CLOEXEC,
+            Mode::empty(),
+        )?;
+        sink.write(&encoded, "AAAA", KeyOutputReason::Exchanged)?;
+
+        let mut received = Vec::new();
+        FdIo(&reader).read_to_end(&mut received)?;
+        assert_eq!(received, encoded.as_bytes());
+
+        Ok(())
+    }
+    #[test]
+    fn fd_sinks_drop_keys_instead_of_blocking() -> anyhow::Result<()> {
+        use std::os::fd::IntoRawFd;
+        use std::os::unix::net::UnixStream;
+
+        rosenpass_secret_memory::secret_policy_use_only_malloc_secrets();
+        let (writer, reader) = UnixStream::pair()?;
+        let fd = writer.into_raw_fd();
+        let sink = KeySinkConfig::Fd(fd).open()?;
+        let key = SymKey::random();
+        let encoded = EncodedKey::new(&key, KeyOutFormat::Base64, "AAAA", KeyOutputReason::Stale)?;
+
+        // Nobody reads, so the socket buffer fills up; then keys are dropped
+        let err = (0..1_000_000)
+            .find_map(|_| {
+                sink.write(&encoded, "AAAA", KeyOutputReason::Exchanged)
+                    .err()
+            })
+            .expect("writing to a full socket succeeded");
+        assert!(format!("{err:?}").contains("not keeping up"), "{err:?}");
+
+        drop(reader);
+        claimed_fds().lock().unwrap().remove(&fd);
+        Ok(())
+    }
+}
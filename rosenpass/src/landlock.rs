@@ -0,0 +1,401 @@
+//! Confining the server to the files it needs
+//!
+//! Once the server is set up, it only needs few files: it writes the `key_out` files, key sinks
+//! and the metrics textfile, and it reads the configuration and keys again when reloading. With
+//! `landlock = true`, the `exchange-config` command uses Landlock to restrict access to the file
+//! system to the paths derived from the configuration, right before it enters the event loop:
+//!
+//! - the directories of `key_out` files and the metrics `textfile` can be written to; the files
+//!   are replaced atomically and previous keys are kept next to them
+//! - named pipes used as key sinks can be written to
+//! - the directories holding the configuration file, the keys and the pre-shared keys (including
+//!   the systemd credentials directory) can be read
+//! - sockets can be created and removed in the directories of the API `listen_path`s
+//! - when hooks or `wg` are run, or host names looked up, the system directories (such as `/usr`
+//!   and `/etc`) can be read and programs in them executed, and so can the directories of the
+//!   hook programs
+//!
+//! Rules apply to directories rather than individual files where possible, so files that are
+//! replaced by renaming stay accessible. Connecting to Unix sockets – the PSK broker and key sinks
+//! receiving memfds – is not restricted by Landlock. Files in directories not covered by the rules
+//! cannot be used after a reload either, e.g. the key of a new peer stored elsewhere.
+//!
+//! Landlock only confines the thread that applies it and the threads and processes that thread
+//! starts afterwards. The server therefore starts its background threads – e.g. the one waiting
+//! for a spawned PSK broker and those looking up host names – only once the rules are in place.
+//!
+//! On kernels without Landlock (before Linux 5.13, or with Landlock disabled) and on other
+//! platforms, a warning is logged and file system access is not restricted.
+//!
+//! # Examples
+//!
+//! ```toml
+//! landlock = true
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use crate::config::Rosenpass;
+use crate::credentials::SecretSource;
+use crate::hooks::Hooks;
+
+/// Directories the programs the server runs and the system libraries live in
+const SYSTEM_DIRS: &[&str] = &[
+    "/bin",
+    "/sbin",
+    "/usr",
+    "/lib",
+    "/lib64",
+    "/etc",
+    "/nix/store",
+    "/run/current-system",
+];
+
+/// What the server may do with the files beneath a path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// Read files and list directories
+    Read,
+    /// Read files, list directories and execute programs
+    ReadExecute,
+    /// Read, write, create, replace and remove files
+    Write,
+    /// Write to existing files only, e.g. named pipes and devices
+    WriteExisting,
+    /// Create and remove Unix sockets
+    Sockets,
+}
+
+/// The paths the server may access after [Confinement::apply]; see [crate::landlock]
+#[derive(Debug, Default, Clone)]
+pub struct Confinement {
+    rules: Vec<(PathBuf, Access)>,
+}
+
+/// The directory `path` is in
+fn parent(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_owned(),
+        _ => PathBuf::from("."),
+    }
+}
+
+impl Confinement {
+    /// Derive the paths the server needs from `config`
+    ///
+    /// `runs_wg` tells whether the server runs `wg` itself, i.e. uses the built-in broker for
+    /// peers with a WireGuard device.
+    pub fn from_config(config: &Rosenpass, runs_wg: bool) -> anyhow::Result<Self> {
+        let mut conf = Self::default();
+        let mut needs_system = runs_wg || config.user.is_some();
+
+        conf.allow(parent(&config.config_file_path), Access::Read);
+        if let Some(keypair) = config.keypair.as_ref() {
+            conf.allow(parent(&keypair.public_key), Access::Read);
+            // with a key oracle, the secret key stays out of reach of the server
+            if config.key_oracle.is_none() {
+                conf.allow_secret(&keypair.secret_key)?;
+            }
+        }
+        if let Some(textfile) = config.metrics.as_ref().and_then(|m| m.textfile.as_ref()) {
+            conf.allow(parent(textfile), Access::Write);
+        }
+        #[cfg(feature = "experiment_api")]
+        for path in config.api.listen_path.iter() {
+            conf.allow(parent(path), Access::Sockets);
+        }
+        needs_system |= conf.allow_hooks(&config.hooks);
+
+        for peer in config.peers.iter() {
+            conf.allow(parent(&peer.public_key), Access::Read);
+            if let Some(psk) = peer.pre_shared_key.as_ref() {
+                conf.allow_secret(psk)?;
+            }
+            if let Some(key_out) = peer.key_out.as_ref() {
+                conf.allow(parent(key_out), Access::Write);
+            }
+            for sink in peer.key_sinks.iter() {
+                if let crate::key_out::KeySinkConfig::Fifo(path) = sink {
+                    conf.allow(path, Access::WriteExisting);
+                }
+            }
+            needs_system |= conf.allow_hooks(&peer.hooks);
+            // Host names are looked up again while the server runs; see crate::resolve
+            needs_system |= peer
+                .endpoint
+                .as_deref()
+                .is_some_and(|ep| ep.parse::<std::net::SocketAddr>().is_err());
+        }
+
+        if needs_system {
+            for dir in SYSTEM_DIRS {
+                conf.allow(dir, Access::ReadExecute);
+            }
+            // Child processes get /dev/null as standard input or output
+            conf.allow("/dev/null", Access::Read);
+            conf.allow("/dev/null", Access::WriteExisting);
+        }
+        Ok(conf)
+    }
+
+    /// Allow `access` to `path` and everything beneath it
+    pub fn allow<P: AsRef<Path>>(&mut self, path: P, access: Access) {
+        self.rules.push((path.as_ref().to_owned(), access));
+    }
+
+    /// Allow reading the secret at `path`, if it is stored in a file
+    fn allow_secret(&mut self, path: &Path) -> anyhow::Result<()> {
+        if let Some(file) = SecretSource::parse(path)?.file()? {
+            self.allow(parent(&file), Access::Read);
+        }
+        Ok(())
+    }
+
+    /// Allow running the hook programs; returns whether there are hooks at all
+    fn allow_hooks(&mut self, hooks: &Hooks) -> bool {
+        let programs = [hooks.on_key_exchange.as_ref(), hooks.on_key_stale.as_ref()]
+            .into_iter()
+            .flatten()
+            .filter_map(|hook| hook.command.first())
+            .collect::<Vec<_>>();
+        for program in programs.iter() {
+            // Programs without a directory are looked up in the system directories
+            if program.contains('/') {
+                self.allow(parent(Path::new(program)), Access::ReadExecute);
+            }
+        }
+        !programs.is_empty()
+    }
+
+    /// Restrict the file system access of the calling thread and the threads and processes it
+    /// starts afterwards to the allowed paths
+    ///
+    /// This cannot be undone. Paths that do not exist are skipped. Threads that are running
+    /// already stay unrestricted and share the memory of the calling thread, so this has to be
+    /// applied before any long-lived thread is started.
+    pub fn apply(&self) -> anyhow::Result<()> {
+        match sys::restrict(&self.rules)? {
+            true => log::info!(
+                "Restricted file system access to {} paths with Landlock",
+                self.rules.len()
+            ),
+            false => log::warn!("Landlock is not available; file system access is not restricted"),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    pub(super) fn restrict(_rules: &[(std::path::PathBuf, super::Access)]) -> anyhow::Result<bool> {
+        Ok(false)
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::fs::OpenOptions;
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::path::PathBuf;
+
+    use anyhow::Context;
+
+    use super::Access;
+
+    /// System call numbers, which are the same on all architectures
+    const SYS_LANDLOCK_CREATE_RULESET: libc::c_long = 444;
+    const SYS_LANDLOCK_ADD_RULE: libc::c_long = 445;
+    const SYS_LANDLOCK_RESTRICT_SELF: libc::c_long = 446;
+
+    /// `LANDLOCK_CREATE_RULESET_VERSION`
+    const CREATE_RULESET_VERSION: u32 = 1;
+    /// `LANDLOCK_RULE_PATH_BENEATH`
+    const RULE_PATH_BENEATH: libc::c_int = 1;
+
+    /// `LANDLOCK_ACCESS_FS_*`
+    const EXECUTE: u64 = 1 << 0;
+    const WRITE_FILE: u64 = 1 << 1;
+    const READ_FILE: u64 = 1 << 2;
+    const READ_DIR: u64 = 1 << 3;
+    const REMOVE_FILE: u64 = 1 << 5;
+    const MAKE_REG: u64 = 1 << 8;
+    const MAKE_SOCK: u64 = 1 << 9;
+    /// Since ABI version 2
+    const REFER: u64 = 1 << 13;
+    /// Since ABI version 3
+    const TRUNCATE: u64 = 1 << 14;
+
+    /// All access rights of ABI version 1
+    const ABI_1: u64 = (1 << 13) - 1;
+    /// The access rights that apply to files rather than directories
+    const FILE_RIGHTS: u64 = EXECUTE | WRITE_FILE | READ_FILE | TRUNCATE;
+
+    /// `struct landlock_ruleset_attr`, up to the fields of ABI version 1
+    #[repr(C)]
+    struct RulesetAttr {
+        handled_access_fs: u64,
+    }
+
+    /// `struct landlock_path_beneath_attr`
+    #[repr(C, packed)]
+    struct PathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: i32,
+    }
+
+    impl Access {
+        fn rights(self) -> u64 {
+            match self {
+                Access::Read => READ_FILE | READ_DIR,
+                Access::ReadExecute => READ_FILE | READ_DIR | EXECUTE,
+                Access::Write => {
+                    READ_FILE | READ_DIR | WRITE_FILE | REMOVE_FILE | MAKE_REG | TRUNCATE | REFER
+                }
+                Access::WriteExisting => WRITE_FILE | TRUNCATE,
+                Access::Sockets => MAKE_SOCK | REMOVE_FILE,
+            }
+        }
+    }
+
+    /// Turn the return value of a system call into a result
+    fn check(rc: libc::c_long) -> io::Result<libc::c_long> {
+        match rc {
+            rc if rc < 0 => Err(io::Error::last_os_error()),
+            rc => Ok(rc),
+        }
+    }
+
+    /// Apply the rules to the calling thread; returns false if Landlock is not available
+    pub(super) fn restrict(rules: &[(PathBuf, Access)]) -> anyhow::Result<bool> {
+        // Safety: querying the ABI version takes no pointers
+        let abi = unsafe {
+            libc::syscall(
+                SYS_LANDLOCK_CREATE_RULESET,
+                std::ptr::null::<RulesetAttr>(),
+                0usize,
+                CREATE_RULESET_VERSION,
+            )
+        };
+        let abi = match check(abi) {
+            Ok(abi) => abi,
+            Err(e) if matches!(e.raw_os_error(), Some(libc::ENOSYS | libc::EOPNOTSUPP)) => {
+                return Ok(false)
+            }
+            Err(e) => return Err(e).context("Could not query the Landlock version"),
+        };
+
+        let mut handled = ABI_1;
+        if abi >= 2 {
+            handled |= REFER;
+        }
+        if abi >= 3 {
+            handled |= TRUNCATE;
+        }
+
+        let attr = RulesetAttr {
+            handled_access_fs: handled,
+        };
+        // Safety: attr is a live ruleset attribute of the given size
+        let fd = unsafe {
+            libc::syscall(
+                SYS_LANDLOCK_CREATE_RULESET,
+                &attr as *const RulesetAttr,
+                std::mem::size_of::<RulesetAttr>(),
+                0u32,
+            )
+        };
+        let fd = check(fd).context("Could not create Landlock ruleset")?;
+        // Safety: the kernel just handed us this file descriptor
+        let ruleset = unsafe { OwnedFd::from_raw_fd(fd as libc::c_int) };
+
+        for (path, access) in rules.iter() {
+            let file = match OpenOptions::new()
+                .read(true)
+                .custom_flags(libc::O_PATH | libc::O_CLOEXEC)
+                .open(path)
+            {
+                Ok(file) => file,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    log::debug!("Not adding Landlock rule for {path:?}, which does not exist");
+                    continue;
+                }
+                Err(e) => return Err(e).with_context(|| format!("Could not open {path:?}")),
+            };
+            let mut rights = access.rights() & handled;
+            if !file.metadata()?.is_dir() {
+                rights &= FILE_RIGHTS;
+            }
+
+            let rule = PathBeneathAttr {
+                allowed_access: rights,
+                parent_fd: file.as_raw_fd(),
+            };
+            // Safety: rule is a live path-beneath attribute
+            let rc = unsafe {
+                libc::syscall(
+                    SYS_LANDLOCK_ADD_RULE,
+                    ruleset.as_raw_fd(),
+                    RULE_PATH_BENEATH,
+                    &rule as *const PathBeneathAttr,
+                    0u32,
+                )
+            };
+            check(rc).with_context(|| format!("Could not add Landlock rule for {path:?}"))?;
+        }
+
+        // Safety: plain system calls without pointers; no_new_privs is required to restrict
+        // ourselves without CAP_SYS_ADMIN
+        unsafe {
+            check(libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1 as libc::c_ulong, 0, 0, 0) as _)
+                .context("Could not set no_new_privs")?;
+            check(libc::syscall(
+                SYS_LANDLOCK_RESTRICT_SELF,
+                ruleset.as_raw_fd(),
+                0u32,
+            ))
+            .context("Could not enforce Landlock ruleset")?;
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RosenpassPeer;
+
+    #[test]
+    fn paths_are_derived_from_the_config() -> anyhow::Result<()> {
+        let mut config = Rosenpass::from_sk_pk("/keys/sk", "/keys/pk");
+        config.config_file_path = PathBuf::from("/etc/rosenpass/config.toml");
+        config.peers.push(RosenpassPeer {
+            public_key: PathBuf::from("/peers/a.pk"),
+            key_out: Some(PathBuf::from("/run/rosenpass/a.key")),
+            endpoint: Some("[::1]:9999".to_owned()),
+            ..Default::default()
+        });
+
+        let conf = Confinement::from_config(&config, false)?;
+        let rules = conf
+            .rules
+            .iter()
+            .map(|(p, a)| (p.to_str().unwrap(), *a))
+            .collect::<Vec<_>>();
+        assert!(rules.contains(&("/etc/rosenpass", Access::Read)));
+        assert!(rules.contains(&("/keys", Access::Read)));
+        assert!(rules.contains(&("/peers", Access::Read)));
+        assert!(rules.contains(&("/run/rosenpass", Access::Write)));
+        // No hooks, wg or host names: no system directories
+        assert!(!rules.iter().any(|(p, _)| *p == "/usr"));
+
+        // Running wg needs them
+        let conf = Confinement::from_config(&config, true)?;
+        assert!(conf
+            .rules
+            .contains(&(PathBuf::from("/usr"), Access::ReadExecute)));
+
+        Ok(())
+    }
+}
@@ -5,22 +5,61 @@
 //!   main function quickly hands over to [crate::cli::CliArgs::run] which contains quite a bit
 //!   of our startup logic
 //! - [crate::config] has the code to parse and generate configuration files
+//! - [crate::credentials] loads secrets from systemd credentials, environment variables and file
+//!   descriptors
+//! - [crate::happy_eyeballs] picks the address and socket to reach a peer through, racing IPv6
+//!   against IPv4
 //! - [crate::hash_domains] lists the different hash function domains used in the Rosenpass
 //!   protocol
+//! - [crate::hooks] runs external programs when keys are exchanged or go stale
+//! - [crate::key_oracle] keeps the static secret key in a separate process
+//! - [crate::key_out] hands exchanged keys to consumers through file descriptors, named pipes and
+//!   memfds
+//! - [crate::landlock] confines the server to the files it needs
+//! - [crate::logging] sets up structured logging in text or JSON format
+//! - [crate::metrics] collects statistics about the server and exports them to monitoring systems
 //! - [crate::msgs] provides declarations of the Rosenpass protocol network messages and facilities
 //!   to parse those messages through the [::zerocopy] crate
+//! - [crate::pktinfo] makes replies come from the address the peer sent its message to
+//! - [crate::privileges] drops root privileges once the server is set up
 //! - [crate::protocol] this is where the bulk of our code lives; this module contains the actual
 //!   cryptographic protocol logic
+//! - [crate::resolve] looks up the host names of peer endpoints again while the server runs
+//! - [crate::reload] applies changes to the configuration file to a running server
+//! - [crate::seccomp] restricts the system calls the server may make
+//! - [crate::signals] handles process signals such as SIGHUP for the server
+//! - [crate::socket_options] sets socket options such as a fwmark on the listen sockets
+//! - [crate::systemd] reports readiness to systemd and takes over sockets it passes in
 //! - crate::api implements the Rosenpass unix socket API, if feature "experiment_api" is active
+//! - crate::harness runs several [crate::app_server::AppServer]s in-process for integration
+//!   tests, if feature "internal_testing" is active
 
 #[cfg(feature = "experiment_api")]
 pub mod api;
 pub mod app_server;
 pub mod cli;
 pub mod config;
+pub mod credentials;
+pub mod happy_eyeballs;
+#[cfg(any(test, feature = "internal_testing"))]
+pub mod harness;
 pub mod hash_domains;
+pub mod hooks;
+pub mod key_oracle;
+pub mod key_out;
+pub mod landlock;
+pub mod logging;
+pub mod metrics;
 pub mod msgs;
+pub mod pktinfo;
+pub mod privileges;
 pub mod protocol;
+pub mod reload;
+pub mod resolve;
+pub mod seccomp;
+pub mod signals;
+pub mod socket_options;
+pub mod systemd;
 
 /// Error types used in diverse places across Rosenpass
 #[derive(thiserror::Error, Debug)]
@@ -0,0 +1,408 @@
+//! Structured logging for the Rosenpass server
+//!
+//! Rosenpass logs through the [log] crate; this module provides the logger backend. Log records
+//! carry structured fields in two ways:
+//!
+//! - Key-value pairs attached to individual records, e.g.
+//!   `log::info!(peer:% = peer_id; "Exchanged key")`
+//! - [LogSpan]s, which attach their fields to every record logged while they are active; the
+//!   event loop uses this to mark all records logged while processing a handshake message with
+//!   the endpoint, message type and peer involved
+//!
+//! Records are written to stderr either as human readable text or as one JSON object per line
+//! (see [LogFormat]). The log level is configured through the `RUST_LOG` environment variable or
+//! the command line.
+
+use std::cell::RefCell;
+use std::fmt::{self, Display, Write as _};
+use std::io::Write;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use log::kv::{self, VisitSource};
+use log::{LevelFilter, Record};
+use serde::{Deserialize, Serialize};
+
+/// Output format of the log records
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human readable text, one record per line; fields are appended as `key=value`
+    #[default]
+    Text,
+    /// One JSON object per line, with the fields as top-level keys
+    Json,
+}
+
+impl LogFormat {
+    /// Whether this is the default, human readable format
+    pub fn is_text(&self) -> bool {
+        *self == Self::Text
+    }
+}
+
+/// The format used by the logger, set through [set_format]
+static FORMAT: AtomicU8 = AtomicU8::new(LogFormat::Text as u8);
+
+/// Change the format of all subsequent log records
+///
+/// The logger is set up before the configuration file is read, so the format from the
+/// configuration file is applied through this function once it is known.
+pub fn set_format(format: LogFormat) {
+    FORMAT.store(format as u8, Ordering::Relaxed);
+}
+
+/// The format currently used for log records
+pub fn format() -> LogFormat {
+    match FORMAT.load(Ordering::Relaxed) {
+        x if x == LogFormat::Json as u8 => LogFormat::Json,
+        _ => LogFormat::Text,
+    }
+}
+
+/// Set up the global logger
+///
+/// The level filter is read from the `RUST_LOG` environment variable unless `level` is given.
+pub fn init(level: Option<LevelFilter>, format: LogFormat) {
+    set_format(format);
+
+    let mut builder = env_logger::Builder::from_default_env();
+    if let Some(level) = level {
+        builder.filter_level(level);
+    }
+    builder.format(|buf, record| {
+        let timestamp = buf.timestamp_millis().to_string();
+        match self::format() {
+            LogFormat::Text => {
+                let level = buf.default_styled_level(record.level());
+                write!(buf, "[{timestamp} {level:<5} {}] ", record.target())?;
+                writeln!(buf, "{}", format_text(record))
+            }
+            LogFormat::Json => writeln!(buf, "{}", format_json(&timestamp, record)),
+        }
+    });
+    builder.init();
+}
+
+/// A field value in a log record
+#[derive(Debug, Clone, PartialEq)]
+enum FieldValue {
+    Bool(bool),
+    Int(i128),
+    Float(f64),
+    Str(String),
+}
+
+impl FieldValue {
+    /// Convert a value attached to a record; null values yield `None` and are left out
+    fn from_kv(value: &kv::Value) -> Option<Self> {
+        let mut visitor = FieldVisitor(None);
+        // The visitor never fails
+        let _ = value.visit(&mut visitor);
+        visitor.0
+    }
+
+    /// Write the value as a JSON value
+    fn write_json(&self, out: &mut String) {
+        match self {
+            Self::Bool(v) => write!(out, "{v}").unwrap(),
+            Self::Int(v) => write!(out, "{v}").unwrap(),
+            Self::Float(v) => write!(out, "{v}").unwrap(),
+            Self::Str(v) => write_json_str(out, v),
+        }
+    }
+}
+
+/// Converts a [kv::Value] to a [FieldValue], keeping numbers and booleans intact
+struct FieldVisitor(Option<FieldValue>);
+
+impl<'v> kv::VisitValue<'v> for FieldVisitor {
+    fn visit_any(&mut self, value: kv::Value) -> Result<(), kv::Error> {
+        self.0 = Some(FieldValue::Str(value.to_string()));
+        Ok(())
+    }
+
+    fn visit_null(&mut self) -> Result<(), kv::Error> {
+        self.0 = None;
+        Ok(())
+    }
+
+    fn visit_bool(&mut self, value: bool) -> Result<(), kv::Error> {
+        self.0 = Some(FieldValue::Bool(value));
+        Ok(())
+    }
+
+    fn visit_u64(&mut self, value: u64) -> Result<(), kv::Error> {
+        self.visit_i128(value.into())
+    }
+
+    fn visit_i64(&mut self, value: i64) -> Result<(), kv::Error> {
+        self.visit_i128(value.into())
+    }
+
+    fn visit_i128(&mut self, value: i128) -> Result<(), kv::Error> {
+        self.0 = Some(FieldValue::Int(value));
+        Ok(())
+    }
+
+    fn visit_f64(&mut self, value: f64) -> Result<(), kv::Error> {
+        // JSON has no representation for infinity and NaN
+        self.0 = Some(match value.is_finite() {
+            true => FieldValue::Float(value),
+            false => FieldValue::Str(value.to_string()),
+        });
+        Ok(())
+    }
+}
+
+impl Display for FieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bool(v) => write!(f, "{v}"),
+            Self::Int(v) => write!(f, "{v}"),
+            Self::Float(v) => write!(f, "{v}"),
+            // Quote strings that would otherwise be ambiguous
+            Self::Str(v) if v.is_empty() || v.contains(|c: char| c.is_whitespace() || c == '"') => {
+                write!(f, "{v:?}")
+            }
+            Self::Str(v) => f.write_str(v),
+        }
+    }
+}
+
+/// The fields of an active [LogSpan]
+#[derive(Debug)]
+struct SpanData {
+    name: &'static str,
+    fields: Vec<(&'static str, FieldValue)>,
+}
+
+thread_local! {
+    /// The spans active on this thread, innermost last
+    static SPANS: RefCell<Vec<SpanData>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Context attached to all log records logged on this thread while the span is alive
+///
+/// Spans nest; the fields of inner spans take precedence over the ones of outer spans, and
+/// fields of the record itself take precedence over all span fields.
+///
+/// # Examples
+///
+/// ```
+/// use rosenpass::logging::LogSpan;
+///
+/// let span = LogSpan::enter("handshake").with("endpoint", "[::1]:9999");
+/// log::info!("Processing message"); // carries span=handshake endpoint=[::1]:9999
+/// span.record("peer", "AAAA");
+/// log::info!("Exchanged key"); // additionally carries peer=AAAA
+/// drop(span);
+/// log::info!("Done"); // carries no span fields
+/// ```
+#[must_use = "the span ends when the guard is dropped"]
+#[derive(Debug)]
+pub struct LogSpan {
+    /// Position of this span in [SPANS]
+    depth: usize,
+}
+
+impl LogSpan {
+    /// Start a new span on this thread
+    pub fn enter(name: &'static str) -> Self {
+        let depth = SPANS.with_borrow_mut(|spans| {
+            spans.push(SpanData {
+                name,
+                fields: Vec::new(),
+            });
+            spans.len() - 1
+        });
+        Self { depth }
+    }
+
+    /// Add a field to the span, builder style
+    pub fn with(self, key: &'static str, value: impl Display) -> Self {
+        self.record(key, value);
+        self
+    }
+
+    /// Add a field to the span, replacing any earlier value for the same key
+    pub fn record(&self, key: &'static str, value: impl Display) {
+        let value = FieldValue::Str(value.to_string());
+        SPANS.with_borrow_mut(|spans| {
+            let Some(span) = spans.get_mut(self.depth) else {
+                return;
+            };
+            match span.fields.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, v)) => *v = value,
+                None => span.fields.push((key, value)),
+            }
+        });
+    }
+}
+
+impl Drop for LogSpan {
+    fn drop(&mut self) {
+        SPANS.with_borrow_mut(|spans| spans.truncate(self.depth));
+    }
+}
+
+/// The structured fields of a record: span fields first, then the record's own key-value pairs
+#[derive(Debug, Default)]
+struct Fields {
+    spans: Vec<&'static str>,
+    fields: Vec<(String, FieldValue)>,
+}
+
+impl Fields {
+    fn of(record: &Record) -> Self {
+        let mut res = Self::default();
+        SPANS.with_borrow(|spans| {
+            for span in spans {
+                res.spans.push(span.name);
+                for (key, value) in span.fields.iter() {
+                    res.insert(key.to_string(), value.clone());
+                }
+            }
+        });
+        // Visiting the key-value pairs of a record only fails if the visitor fails
+        let _ = record.key_values().visit(&mut res);
+        res
+    }
+
+    fn insert(&mut self, key: String, value: FieldValue) {
+        match self.fields.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, v)) => *v = value,
+            None => self.fields.push((key, value)),
+        }
+    }
+}
+
+impl<'kvs> VisitSource<'kvs> for Fields {
+    fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        if let Some(value) = FieldValue::from_kv(&value) {
+            self.insert(key.as_str().to_owned(), value);
+        }
+        Ok(())
+    }
+}
+
+/// Format a record as text, without the timestamp, level and target
+fn format_text(record: &Record) -> String {
+    let fields = Fields::of(record);
+    let mut out = record.args().to_string();
+    if !fields.spans.is_empty() {
+        write!(out, " span={}", fields.spans.join(":")).unwrap();
+    }
+    for (key, value) in fields.fields.iter() {
+        write!(out, " {key}={value}").unwrap();
+    }
+    out
+}
+
+/// Format a record as a single line JSON object
+fn format_json(timestamp: &str, record: &Record) -> String {
+    let fields = Fields::of(record);
+    let mut out = String::from("{");
+    let entry = |out: &mut String, key: &str| {
+        if out.len() > 1 {
+            out.push(',');
+        }
+        write_json_str(out, key);
+        out.push(':');
+    };
+
+    entry(&mut out, "timestamp");
+    write_json_str(&mut out, timestamp);
+    entry(&mut out, "level");
+    write_json_str(&mut out, record.level().as_str());
+    entry(&mut out, "target");
+    write_json_str(&mut out, record.target());
+    entry(&mut out, "message");
+    write_json_str(&mut out, &record.args().to_string());
+    if !fields.spans.is_empty() {
+        entry(&mut out, "span");
+        write_json_str(&mut out, &fields.spans.join(":"));
+    }
+    for (key, value) in fields.fields.iter() {
+        // Record fields must not shadow the fixed keys
+        if matches!(
+            key.as_str(),
+            "timestamp" | "level" | "target" | "message" | "span"
+        ) {
+            continue;
+        }
+        entry(&mut out, key);
+        value.write_json(&mut out);
+    }
+    out.push('}');
+    out
+}
+
+/// Write `s` as a quoted JSON string
+fn write_json_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record_json(f: impl FnOnce(&Record) -> String) -> String {
+        let record = Record::builder()
+            .args(format_args!("say \"hi\"\n"))
+            .level(log::Level::Info)
+            .target("rosenpass::test")
+            .build();
+        f(&record)
+    }
+
+    #[test]
+    fn json_records_are_escaped_and_carry_span_fields() {
+        let span = LogSpan::enter("handshake").with("endpoint", "[::1]:9999");
+        span.record("peer", "AA==");
+        {
+            let _inner = LogSpan::enter("inner").with("peer", "BB==");
+            assert_eq!(
+                record_json(|r| format_json("now", r)),
+                r#"{"timestamp":"now","level":"INFO","target":"rosenpass::test","message":"say \"hi\"\n","span":"handshake:inner","endpoint":"[::1]:9999","peer":"BB=="}"#
+            );
+        }
+        assert_eq!(
+            record_json(format_text),
+            "say \"hi\"\n span=handshake endpoint=[::1]:9999 peer=AA=="
+        );
+
+        drop(span);
+        assert_eq!(record_json(format_text), "say \"hi\"\n");
+    }
+
+    #[test]
+    fn field_values_keep_their_json_type() {
+        let mut out = String::new();
+        for value in [
+            FieldValue::from_kv(&kv::Value::from(true)),
+            FieldValue::from_kv(&kv::Value::from(-3i32)),
+            FieldValue::from_kv(&kv::Value::from(1.5f64)),
+            FieldValue::from_kv(&kv::Value::from("a\tb")),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            value.write_json(&mut out);
+            out.push(' ');
+        }
+        assert_eq!(out, r#"true -3 1.5 "a\tb" "#);
+        assert_eq!(FieldValue::from_kv(&kv::Value::null()), None);
+    }
+}
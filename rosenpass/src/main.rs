@@ -59,23 +59,11 @@ pub fn main() {
         SM::secret_policy_use_only_malloc_secrets();
     }
 
-    // init logging
-    {
-        let mut log_builder = env_logger::Builder::from_default_env(); // sets log level filter from environment (or defaults)
-        if let Some(level) = args.get_log_level() {
-            log::debug!("setting log level to {:?} (set via CLI parameter)", level);
-            log_builder.filter_level(level); // set log level filter from CLI args if available
-        }
-        log_builder.init();
-
-        // // check the effectiveness of the log level filter with the following lines:
-        // use log::{debug, error, info, trace, warn};
-        // trace!("trace dummy");
-        // debug!("debug dummy");
-        // info!("info dummy");
-        // warn!("warn dummy");
-        // error!("error dummy");
-    }
+    // init logging; the format may still change once the configuration file is read
+    rosenpass::logging::init(
+        args.get_log_level(),
+        args.get_log_format().unwrap_or_default(),
+    );
 
     let broker_interface = args.get_broker_interface();
     match args.run(broker_interface, None) {
@@ -0,0 +1,81 @@
+use std::net::{SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{ensure, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::app_server::AppServer;
+
+/// Default for [MetricsConfig::textfile_interval]
+fn default_textfile_interval() -> u64 {
+    15
+}
+
+/// Configuration of the metrics exporter (the `[metrics]` section of the configuration file)
+///
+/// Both ways of exporting metrics can be used at the same time.
+///
+/// # Examples
+///
+/// ```toml
+/// [metrics]
+/// listen = "127.0.0.1:9464"
+/// textfile = "/var/lib/prometheus/node-exporter/rosenpass.prom"
+/// textfile_interval = 15
+/// ```
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct MetricsConfig {
+    /// Serve the metrics over HTTP at `/metrics` on this address
+    ///
+    /// There is no authentication, so this should be a local address.
+    #[serde(default)]
+    pub listen: Option<SocketAddr>,
+
+    /// Periodically write the metrics to this file, for instance for the textfile collector of
+    /// the Prometheus node exporter
+    ///
+    /// The file is replaced atomically.
+    #[serde(default)]
+    pub textfile: Option<PathBuf>,
+
+    /// How often to write [Self::textfile], in seconds
+    #[serde(default = "default_textfile_interval")]
+    pub textfile_interval: u64,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            listen: None,
+            textfile: None,
+            textfile_interval: default_textfile_interval(),
+        }
+    }
+}
+
+impl MetricsConfig {
+    /// Check that the configuration is sound
+    pub fn validate(&self) -> anyhow::Result<()> {
+        ensure!(
+            self.textfile.is_none() || self.textfile_interval > 0,
+            "metrics textfile_interval must be at least one second"
+        );
+        Ok(())
+    }
+
+    /// Open the HTTP listener and set up the textfile as configured
+    pub fn apply_to_app_server(&self, srv: &mut AppServer) -> anyhow::Result<()> {
+        if let Some(addr) = self.listen {
+            let listener = TcpListener::bind(addr)
+                .with_context(|| format!("Could not listen for metrics requests on {addr}"))?;
+            srv.add_metrics_listener(listener)?;
+        }
+
+        if let Some(path) = self.textfile.as_ref() {
+            srv.set_metrics_textfile(path.clone(), Duration::from_secs(self.textfile_interval));
+        }
+
+        Ok(())
+    }
+}
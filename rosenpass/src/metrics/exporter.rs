@@ -0,0 +1,292 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use mio::net::{TcpListener, TcpStream};
+use mio::{Interest, Token};
+use rosenpass_util::file::{store_atomic, Visibility};
+
+use crate::app_server::{AppServer, AppServerIoSource};
+
+use super::MetricsFormat;
+
+/// Maximum number of metrics HTTP connections served at the same time
+const MAX_CONNECTIONS: usize = 16;
+
+/// Maximum size of an HTTP request head; scrapers send a few hundred bytes at most
+const MAX_REQUEST_SIZE: usize = 8192;
+
+/// Connections that did not complete their exchange in this time are closed
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Serves [super::Metrics] over HTTP and writes them to a file periodically
+///
+/// Part of [AppServer]; configured through [super::config::MetricsConfig].
+#[derive(Debug, Default)]
+pub struct MetricsExporter {
+    /// Listener for HTTP connections
+    listener: Option<TcpListener>,
+    /// Open HTTP connections; closed connections leave an empty slot
+    connections: Vec<Option<HttpConnection>>,
+    /// Where and how often to write the metrics
+    textfile: Option<(PathBuf, Duration)>,
+    /// When to write [Self::textfile] next
+    next_textfile_write: Option<Instant>,
+}
+
+/// Points at a particular source of IO events inside [MetricsExporter]
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum MetricsIoSource {
+    /// The HTTP listener
+    Listener,
+    /// The Nth HTTP connection
+    Connection(usize),
+}
+
+/// A single HTTP connection from a metrics scraper
+///
+/// The connection reads one request, sends one response and is closed afterwards.
+#[derive(Debug)]
+struct HttpConnection {
+    stream: TcpStream,
+    mio_token: Token,
+    opened: Instant,
+    /// The request head read so far
+    request: Vec<u8>,
+    /// The response, once the request head is complete
+    response: Option<Vec<u8>>,
+    /// Number of bytes of [Self::response] written so far
+    written: usize,
+}
+
+impl HttpConnection {
+    /// Make as much progress as possible without blocking; returns true once the connection
+    /// should be closed
+    fn advance(&mut self, srv: &AppServer) -> io::Result<bool> {
+        if self.response.is_none() {
+            let mut buf = [0u8; 1024];
+            loop {
+                match self.stream.read(&mut buf) {
+                    // Closed before sending a complete request
+                    Ok(0) => return Ok(true),
+                    Ok(n) => self.request.extend_from_slice(&buf[..n]),
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(false),
+                    Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                }
+
+                if self.request.windows(4).any(|w| w == b"\r\n\r\n") {
+                    break;
+                }
+                if self.request.len() > MAX_REQUEST_SIZE {
+                    return Ok(true);
+                }
+            }
+            self.response = Some(respond(&self.request, srv));
+        }
+
+        let response = self.response.as_deref().unwrap_or_default();
+        while self.written < response.len() {
+            match self.stream.write(&response[self.written..]) {
+                Ok(0) => return Ok(true),
+                Ok(n) => self.written += n,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(false),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+/// Produce the HTTP response to a metrics request
+fn respond(request: &[u8], srv: &AppServer) -> Vec<u8> {
+    let request = String::from_utf8_lossy(request);
+    let mut lines = request.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default();
+    let path = request_line.next().unwrap_or_default();
+    let path = path.split('?').next().unwrap_or_default();
+
+    let format = match lines
+        .filter_map(|l| l.split_once(':'))
+        .filter(|(name, _)| name.trim().eq_ignore_ascii_case("accept"))
+        .any(|(_, value)| value.contains("application/openmetrics-text"))
+    {
+        true => MetricsFormat::OpenMetrics,
+        false => MetricsFormat::Prometheus,
+    };
+
+    let (status, content_type, body) = match (method, path) {
+        ("GET" | "HEAD", "/metrics") => (
+            "200 OK",
+            format.content_type(),
+            srv.metrics.render(srv, format),
+        ),
+        ("GET" | "HEAD", _) => ("404 Not Found", "text/plain", "Not Found\n".to_owned()),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "Method Not Allowed\n".to_owned(),
+        ),
+    };
+
+    let mut response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    );
+    if method != "HEAD" {
+        response.push_str(&body);
+    }
+    response.into_bytes()
+}
+
+impl AppServer {
+    /// Serve metrics over HTTP on the given listener; see [super::config::MetricsConfig::listen]
+    pub fn add_metrics_listener(&mut self, listener: std::net::TcpListener) -> io::Result<()> {
+        if self.metrics_exporter.listener.is_some() {
+            return Err(io::Error::new(
+                ErrorKind::AlreadyExists,
+                "A metrics listener was already added",
+            ));
+        }
+
+        listener.set_nonblocking(true)?;
+        let mut listener = TcpListener::from_std(listener);
+        let mio_token = self.mio_token_dispenser.dispense();
+        self.mio_poll
+            .registry()
+            .register(&mut listener, mio_token, Interest::READABLE)?;
+        self.register_io_source(
+            mio_token,
+            AppServerIoSource::Metrics(MetricsIoSource::Listener),
+        );
+        self.metrics_exporter.listener = Some(listener);
+        Ok(())
+    }
+
+    /// Write the metrics to `path` every `interval`, starting right away
+    pub fn set_metrics_textfile(&mut self, path: PathBuf, interval: Duration) {
+        self.metrics_exporter.textfile = Some((path, interval));
+        self.metrics_exporter.next_textfile_write = Some(Instant::now());
+    }
+
+    /// Write the metrics textfile if it is due and return how long it is until the next write
+    ///
+    /// Failing to write the file is logged, but not fatal.
+    pub fn write_metrics_textfile_if_due(&mut self) -> Option<Duration> {
+        let (path, interval) = self.metrics_exporter.textfile.as_ref()?;
+        let next = self.metrics_exporter.next_textfile_write?;
+
+        let now = Instant::now();
+        if now < next {
+            return Some(next - now);
+        }
+
+        // Readers such as the node exporter must never see a partial file
+        let body = self.metrics.render(self, MetricsFormat::Prometheus);
+        let res = store_atomic(path, Visibility::Public, |f| f.write_all(body.as_bytes()));
+        if let Err(e) = res {
+            log::warn!("Could not write the metrics to {path:?}: {e}");
+        }
+
+        let interval = *interval;
+        self.metrics_exporter.next_textfile_write = Some(now + interval);
+        Some(interval)
+    }
+
+    /// Handle IO on a particular [MetricsIoSource]
+    pub(crate) fn poll_metrics_io_source(&mut self, io_source: MetricsIoSource) -> io::Result<()> {
+        match io_source {
+            MetricsIoSource::Listener => self.accept_metrics_connections(),
+            MetricsIoSource::Connection(idx) => {
+                self.poll_metrics_connection(idx);
+                Ok(())
+            }
+        }
+    }
+
+    /// Check for new connections and make progress on all open connections
+    pub(crate) fn poll_metrics(&mut self) -> io::Result<()> {
+        self.accept_metrics_connections()?;
+        for idx in 0..self.metrics_exporter.connections.len() {
+            self.poll_metrics_connection(idx);
+        }
+        Ok(())
+    }
+
+    /// Accept connections until the listener would block
+    fn accept_metrics_connections(&mut self) -> io::Result<()> {
+        loop {
+            let Some(listener) = self.metrics_exporter.listener.as_ref() else {
+                return Ok(());
+            };
+            let mut stream = match listener.accept() {
+                Ok((stream, _addr)) => stream,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                // The client might have given up already; this should not stop the server
+                Err(e) => {
+                    log::debug!("Could not accept metrics connection: {e}");
+                    return Ok(());
+                }
+            };
+
+            let conns = &mut self.metrics_exporter.connections;
+            let idx = match conns.iter().position(Option::is_none) {
+                Some(idx) => idx,
+                None if conns.len() < MAX_CONNECTIONS => {
+                    conns.push(None);
+                    conns.len() - 1
+                }
+                // Too many connections; dropping the stream closes it
+                None => continue,
+            };
+
+            let mio_token = self.mio_token_dispenser.dispense();
+            self.mio_poll.registry().register(
+                &mut stream,
+                mio_token,
+                Interest::READABLE | Interest::WRITABLE,
+            )?;
+            self.register_io_source(
+                mio_token,
+                AppServerIoSource::Metrics(MetricsIoSource::Connection(idx)),
+            );
+            self.metrics_exporter.connections[idx] = Some(HttpConnection {
+                stream,
+                mio_token,
+                opened: Instant::now(),
+                request: Vec::new(),
+                response: None,
+                written: 0,
+            });
+        }
+    }
+
+    /// Make progress on a single connection, closing it when done
+    fn poll_metrics_connection(&mut self, idx: usize) {
+        let Some(mut conn) = self.metrics_exporter.connections[idx].take() else {
+            return;
+        };
+
+        let done = match conn.advance(self) {
+            Ok(done) => done || conn.opened.elapsed() > CONNECTION_TIMEOUT,
+            Err(e) => {
+                log::debug!("Error on metrics connection: {e}");
+                true
+            }
+        };
+
+        if !done {
+            self.metrics_exporter.connections[idx] = Some(conn);
+            return;
+        }
+
+        if let Err(e) = self.mio_poll.registry().deregister(&mut conn.stream) {
+            log::warn!("Could not deregister metrics connection: {e}");
+        }
+        self.unregister_io_source(conn.mio_token);
+    }
+}
@@ -0,0 +1,578 @@
+//! Metrics about a running [AppServer] in the OpenMetrics format
+//!
+//! Every [AppServer] keeps a [Metrics] instance up to date as it processes messages and
+//! timer events; collecting the numbers is cheap, so this always happens. The
+//! [exporter](MetricsExporter) makes them available to Prometheus and compatible systems,
+//! either over HTTP on a local address or through a file written periodically for the
+//! textfile collector of the node exporter. See [config::MetricsConfig] for the configuration.
+//!
+//! The following metrics are exported:
+//!
+//! - `rosenpass_handshakes_total{role, outcome}` – handshake messages processed, by the role
+//!   of this server (`initiator` or `responder`) and whether processing succeeded in completing
+//!   the key exchange (`success`) or failed (`failure`)
+//! - `rosenpass_messages_received_total{type}` – network messages received by [MsgType]
+//! - `rosenpass_messages_rejected_total{reason}` – network messages that could not be processed
+//!   by [RejectReason]
+//! - `rosenpass_cookie_replies_sent_total` – cookie replies sent while under load
+//! - `rosenpass_under_load` – whether the server currently considers itself under load
+//!   (see [crate::app_server::DoSOperation])
+//! - `rosenpass_retransmissions_total` – handshake messages retransmitted
+//! - `rosenpass_seconds_since_last_key_exchange{peer}` – per peer, only once a key was exchanged
+//! - `rosenpass_broker_failures_total` – failed attempts to talk to a WireGuard PSK broker
+//! - `rosenpass_event_loop_latency_seconds{event}` – histogram of the time spent handling one
+//!   event in the event loop by [LoopEvent]
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Write};
+use std::time::{Duration, Instant};
+
+use rosenpass_util::b64::B64Display;
+
+use crate::app_server::{AppPeerPtr, AppServer, DoSOperation};
+use crate::msgs::MsgType;
+use crate::protocol::HandleMsgResult;
+use crate::RosenpassError;
+
+pub mod config;
+mod exporter;
+
+pub use exporter::*;
+
+/// Role of this server in a handshake
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HandshakeRole {
+    /// This server started the handshake
+    Initiator,
+    /// The peer started the handshake
+    Responder,
+}
+
+impl HandshakeRole {
+    /// The role this server plays when receiving a message of the given type
+    pub fn receiving(msg_type: MsgType) -> Self {
+        match msg_type {
+            MsgType::InitHello | MsgType::InitConf => Self::Responder,
+            MsgType::RespHello | MsgType::EmptyData | MsgType::CookieReply => Self::Initiator,
+        }
+    }
+
+    /// Label value used in the exported metrics
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Initiator => "initiator",
+            Self::Responder => "responder",
+        }
+    }
+}
+
+/// Outcome of processing a handshake message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HandshakeOutcome {
+    /// A key was exchanged
+    Success,
+    /// The message was rejected
+    Failure,
+}
+
+impl HandshakeOutcome {
+    /// Label value used in the exported metrics
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Success => "success",
+            Self::Failure => "failure",
+        }
+    }
+}
+
+/// Why a network message was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RejectReason {
+    /// The first byte is not a known [MsgType]
+    UnknownType,
+    /// The message does not have the size required by its type
+    Malformed,
+    /// Messages of this type are not processed while the server is under load
+    UnderLoad,
+    /// Any other failure, e.g. a message that fails authentication or refers to an unknown peer
+    Invalid,
+}
+
+impl RejectReason {
+    /// Classify the error returned while processing a message
+    ///
+    /// `msg_type` is the type of the message as determined by [Metrics::record_message].
+    pub fn classify(
+        msg_type: Option<MsgType>,
+        under_load: DoSOperation,
+        e: &anyhow::Error,
+    ) -> Self {
+        let under_load = matches!(under_load, DoSOperation::UnderLoad);
+        match msg_type {
+            None => Self::UnknownType,
+            Some(MsgType::InitHello | MsgType::InitConf) => Self::classify_error(e),
+            Some(_) if under_load => Self::UnderLoad,
+            Some(_) => Self::classify_error(e),
+        }
+    }
+
+    /// Classify an error returned while processing a message of a known type
+    fn classify_error(e: &anyhow::Error) -> Self {
+        match e.downcast_ref::<RosenpassError>() {
+            Some(RosenpassError::BufferSizeMismatch) => Self::Malformed,
+            Some(RosenpassError::InvalidMessageType(_)) => Self::UnknownType,
+            _ => Self::Invalid,
+        }
+    }
+
+    /// Label value used in the exported metrics
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::UnknownType => "unknown_type",
+            Self::Malformed => "malformed",
+            Self::UnderLoad => "under_load",
+            Self::Invalid => "invalid",
+        }
+    }
+}
+
+/// What the event loop handled in one iteration; see [Metrics::event_loop_latency]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum LoopEvent {
+    /// A network message was received
+    Message,
+    /// A handshake was initiated
+    Initiation,
+    /// A handshake message was retransmitted
+    Retransmission,
+    /// A key went stale and was erased
+    DeleteKey,
+    /// Anything else, such as a configuration reload
+    Other,
+}
+
+impl LoopEvent {
+    /// Label value used in the exported metrics
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Message => "message",
+            Self::Initiation => "initiation",
+            Self::Retransmission => "retransmission",
+            Self::DeleteKey => "delete_key",
+            Self::Other => "other",
+        }
+    }
+}
+
+/// Upper bounds of the buckets of [Histogram], in seconds
+pub const LATENCY_BUCKETS: [f64; 10] = [
+    0.000_1, 0.000_25, 0.000_5, 0.001, 0.002_5, 0.005, 0.01, 0.025, 0.1, 1.0,
+];
+
+/// A latency histogram with the buckets from [LATENCY_BUCKETS]
+#[derive(Debug, Clone, Default)]
+pub struct Histogram {
+    /// Number of observations that fell into each bucket (not cumulative)
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    /// Total number of observations, including those larger than the largest bucket
+    count: u64,
+    /// Sum of all observations in seconds
+    sum: f64,
+}
+
+impl Histogram {
+    /// Record a single observation
+    pub fn observe(&mut self, value: Duration) {
+        let secs = value.as_secs_f64();
+        if let Some(idx) = LATENCY_BUCKETS.iter().position(|le| secs <= *le) {
+            self.buckets[idx] += 1;
+        }
+        self.count += 1;
+        self.sum += secs;
+    }
+
+    /// Total number of observations
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Sum of all observations in seconds
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    /// Pairs of bucket upper bound and cumulative count, excluding the `+Inf` bucket
+    pub fn cumulative_buckets(&self) -> impl Iterator<Item = (f64, u64)> + '_ {
+        LATENCY_BUCKETS
+            .iter()
+            .zip(self.buckets.iter())
+            .scan(0u64, |acc, (le, n)| {
+                *acc += n;
+                Some((*le, *acc))
+            })
+    }
+}
+
+/// Text format to render [Metrics] in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MetricsFormat {
+    /// The Prometheus text exposition format (version 0.0.4); understood by every Prometheus
+    /// version and by the textfile collector of the node exporter
+    #[default]
+    Prometheus,
+    /// OpenMetrics 1.0
+    OpenMetrics,
+}
+
+impl MetricsFormat {
+    /// Value for the HTTP `Content-Type` header
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Prometheus => "text/plain; version=0.0.4; charset=utf-8",
+            Self::OpenMetrics => "application/openmetrics-text; version=1.0.0; charset=utf-8",
+        }
+    }
+}
+
+/// Things that went wrong with one particular peer
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PeerCounters {
+    /// Handshake messages retransmitted to the peer
+    pub retransmissions: u64,
+    /// Failed attempts to hand the peer's key to its WireGuard PSK broker
+    pub broker_failures: u64,
+    /// Keys that went stale because no new key was exchanged in time
+    pub keys_stale: u64,
+}
+
+/// Counters and statistics about an [AppServer]; see the [module documentation](self)
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Handshake messages processed by role and outcome
+    pub handshakes: BTreeMap<(HandshakeRole, HandshakeOutcome), u64>,
+    /// Network messages received; messages of unknown type are counted under `None`
+    pub messages_received: BTreeMap<Option<MsgType>, u64>,
+    /// Network messages rejected by reason
+    pub messages_rejected: BTreeMap<RejectReason, u64>,
+    /// Cookie replies sent while under load
+    pub cookie_replies_sent: u64,
+    /// Handshake messages retransmitted
+    pub retransmissions: u64,
+    /// Failed attempts to talk to a WireGuard PSK broker
+    pub broker_failures: u64,
+    /// Time of the last key exchange, indexed by [AppPeerPtr]
+    pub last_key_exchange: HashMap<usize, Instant>,
+    /// Per-peer counters, indexed by [AppPeerPtr]; reported through the API, not exported
+    pub peers: HashMap<usize, PeerCounters>,
+    /// Time spent handling one event in the event loop
+    pub event_loop_latency: BTreeMap<LoopEvent, Histogram>,
+}
+
+impl Metrics {
+    /// Count a network message before it is processed and return its type, if known
+    pub fn record_message(&mut self, msg: &[u8]) -> Option<MsgType> {
+        let msg_type = msg.first().and_then(|t| MsgType::try_from(*t).ok());
+        *self.messages_received.entry(msg_type).or_default() += 1;
+        msg_type
+    }
+
+    /// Record the result of processing a network message counted with [Self::record_message]
+    ///
+    /// `tx` is the buffer the response, if any, was written to.
+    pub fn record_message_result(
+        &mut self,
+        msg_type: Option<MsgType>,
+        under_load: DoSOperation,
+        result: &anyhow::Result<HandleMsgResult>,
+        tx: &[u8],
+    ) {
+        let role = msg_type.map(HandshakeRole::receiving);
+
+        match result {
+            Err(e) => {
+                let reason = RejectReason::classify(msg_type, under_load, e);
+                *self.messages_rejected.entry(reason).or_default() += 1;
+                if let Some(role) = role {
+                    self.record_handshake(role, HandshakeOutcome::Failure);
+                }
+            }
+            Ok(HandleMsgResult {
+                exchanged_with,
+                resp,
+            }) => {
+                let sent_cookie =
+                    resp.is_some() && tx.first() == Some(&(MsgType::CookieReply as u8));
+                if matches!(under_load, DoSOperation::UnderLoad) && sent_cookie {
+                    self.cookie_replies_sent += 1;
+                }
+                if let (Some(peer), Some(role)) = (exchanged_with, role) {
+                    self.record_handshake(role, HandshakeOutcome::Success);
+                    self.last_key_exchange
+                        .insert(AppPeerPtr::lift(*peer).0, Instant::now());
+                }
+            }
+        }
+    }
+
+    /// Count a handshake message processed
+    pub fn record_handshake(&mut self, role: HandshakeRole, outcome: HandshakeOutcome) {
+        *self.handshakes.entry((role, outcome)).or_default() += 1;
+    }
+
+    /// Record the time it took to handle one event in the event loop
+    pub fn record_latency(&mut self, event: LoopEvent, latency: Duration) {
+        self.event_loop_latency
+            .entry(event)
+            .or_default()
+            .observe(latency);
+    }
+
+    /// The counters of one peer, for updating them
+    pub fn peer_mut(&mut self, peer: AppPeerPtr) -> &mut PeerCounters {
+        self.peers.entry(peer.0).or_default()
+    }
+
+    /// Drop the per-peer statistics of a peer that was removed
+    pub fn forget_peer(&mut self, peer: AppPeerPtr) {
+        self.last_key_exchange.remove(&peer.0);
+        self.peers.remove(&peer.0);
+    }
+
+    /// Render all metrics of `srv` in the given format
+    pub fn render(&self, srv: &AppServer, format: MetricsFormat) -> String {
+        let mut w = MetricsWriter::new(format);
+
+        w.family(
+            "rosenpass_handshakes",
+            Kind::Counter,
+            "Handshake messages processed by role of this server and outcome",
+        );
+        for ((role, outcome), n) in self.handshakes.iter() {
+            w.sample(
+                "rosenpass_handshakes_total",
+                &[("role", role.label()), ("outcome", outcome.label())],
+                n,
+            );
+        }
+
+        w.family(
+            "rosenpass_messages_received",
+            Kind::Counter,
+            "Network messages received by message type",
+        );
+        for (msg_type, n) in self.messages_received.iter() {
+            let label = match msg_type {
+                Some(t) => format!("{t:?}"),
+                None => "unknown".to_owned(),
+            };
+            w.sample("rosenpass_messages_received_total", &[("type", &label)], n);
+        }
+
+        w.family(
+            "rosenpass_messages_rejected",
+            Kind::Counter,
+            "Network messages that could not be processed by reason",
+        );
+        for (reason, n) in self.messages_rejected.iter() {
+            w.sample(
+                "rosenpass_messages_rejected_total",
+                &[("reason", reason.label())],
+                n,
+            );
+        }
+
+        w.family(
+            "rosenpass_cookie_replies_sent",
+            Kind::Counter,
+            "Cookie replies sent while under load",
+        );
+        w.sample(
+            "rosenpass_cookie_replies_sent_total",
+            &[],
+            self.cookie_replies_sent,
+        );
+
+        w.family(
+            "rosenpass_under_load",
+            Kind::Gauge,
+            "Whether the server considers itself to be under load",
+        );
+        let under_load = matches!(srv.under_load, DoSOperation::UnderLoad);
+        w.sample("rosenpass_under_load", &[], u8::from(under_load));
+
+        w.family(
+            "rosenpass_retransmissions",
+            Kind::Counter,
+            "Handshake messages retransmitted",
+        );
+        w.sample("rosenpass_retransmissions_total", &[], self.retransmissions);
+
+        w.family(
+            "rosenpass_seconds_since_last_key_exchange",
+            Kind::Gauge,
+            "Time since the last key exchange with each peer",
+        );
+        if let Some(crypto) = srv.crypto_site.product_ref() {
+            let mut peers = self.last_key_exchange.iter().collect::<Vec<_>>();
+            peers.sort_unstable_by_key(|(no, _)| **no);
+            for (no, at) in peers {
+                let Ok(peer_id) = AppPeerPtr(*no).lower().get(crypto).pidt() else {
+                    continue;
+                };
+                let peer_id = peer_id.fmt_b64::<{ crate::app_server::MAX_B64_PEER_ID_SIZE }>();
+                w.sample(
+                    "rosenpass_seconds_since_last_key_exchange",
+                    &[("peer", &peer_id.to_string())],
+                    at.elapsed().as_secs_f64(),
+                );
+            }
+        }
+
+        w.family(
+            "rosenpass_broker_failures",
+            Kind::Counter,
+            "Failed attempts to talk to a WireGuard PSK broker",
+        );
+        w.sample("rosenpass_broker_failures_total", &[], self.broker_failures);
+
+        w.family(
+            "rosenpass_event_loop_latency_seconds",
+            Kind::Histogram,
+            "Time spent handling one event in the event loop",
+        );
+        for (event, hist) in self.event_loop_latency.iter() {
+            let event = ("event", event.label());
+            for (le, n) in hist.cumulative_buckets() {
+                w.sample(
+                    "rosenpass_event_loop_latency_seconds_bucket",
+                    &[event, ("le", &le.to_string())],
+                    n,
+                );
+            }
+            w.sample(
+                "rosenpass_event_loop_latency_seconds_bucket",
+                &[event, ("le", "+Inf")],
+                hist.count(),
+            );
+            w.sample(
+                "rosenpass_event_loop_latency_seconds_sum",
+                &[event],
+                hist.sum(),
+            );
+            w.sample(
+                "rosenpass_event_loop_latency_seconds_count",
+                &[event],
+                hist.count(),
+            );
+        }
+
+        w.finish()
+    }
+}
+
+/// Metric types used by [MetricsWriter::family]
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+/// Helper for [Metrics::render] taking care of the differences between the [MetricsFormat]s
+struct MetricsWriter {
+    format: MetricsFormat,
+    out: String,
+}
+
+impl MetricsWriter {
+    fn new(format: MetricsFormat) -> Self {
+        let out = String::new();
+        Self { format, out }
+    }
+
+    /// Start a metric family; counter families are named without the `_total` suffix
+    fn family(&mut self, name: &str, kind: Kind, help: &str) {
+        let (kind, suffix) = match (kind, self.format) {
+            (Kind::Counter, MetricsFormat::Prometheus) => ("counter", "_total"),
+            (Kind::Counter, MetricsFormat::OpenMetrics) => ("counter", ""),
+            (Kind::Gauge, _) => ("gauge", ""),
+            (Kind::Histogram, _) => ("histogram", ""),
+        };
+        // Writing to a String cannot fail
+        let _ = writeln!(self.out, "# TYPE {name}{suffix} {kind}");
+        let _ = writeln!(self.out, "# HELP {name}{suffix} {help}.");
+    }
+
+    /// Add a sample to the current family; label values must not need escaping
+    fn sample<V: Display>(&mut self, name: &str, labels: &[(&str, &str)], value: V) {
+        self.out.push_str(name);
+        if !labels.is_empty() {
+            self.out.push('{');
+            for (i, (k, v)) in labels.iter().enumerate() {
+                if i > 0 {
+                    self.out.push(',');
+                }
+                let _ = write!(self.out, "{k}=\"{v}\"");
+            }
+            self.out.push('}');
+        }
+        let _ = writeln!(self.out, " {value}");
+    }
+
+    fn finish(mut self) -> String {
+        if self.format == MetricsFormat::OpenMetrics {
+            self.out.push_str("# EOF\n");
+        }
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let mut hist = Histogram::default();
+        hist.observe(Duration::from_micros(50));
+        hist.observe(Duration::from_millis(3));
+        hist.observe(Duration::from_secs(2));
+
+        let buckets = hist.cumulative_buckets().collect::<Vec<_>>();
+        assert_eq!(buckets.first(), Some(&(0.0001, 1)));
+        assert_eq!(
+            buckets.iter().find(|(le, _)| *le == 0.005),
+            Some(&(0.005, 2))
+        );
+        assert_eq!(buckets.last(), Some(&(1.0, 2)));
+        assert_eq!(hist.count(), 3);
+    }
+
+    #[test]
+    fn counter_families_follow_the_format() {
+        for (format, family, eof) in [
+            (
+                MetricsFormat::Prometheus,
+                "rosenpass_retransmissions_total",
+                false,
+            ),
+            (
+                MetricsFormat::OpenMetrics,
+                "rosenpass_retransmissions",
+                true,
+            ),
+        ] {
+            let mut w = MetricsWriter::new(format);
+            w.family(
+                "rosenpass_retransmissions",
+                Kind::Counter,
+                "Retransmissions",
+            );
+            w.sample("rosenpass_retransmissions_total", &[("a", "b")], 3);
+            let out = w.finish();
+            assert!(out.starts_with(&format!("# TYPE {family} counter\n")));
+            assert!(out.contains("rosenpass_retransmissions_total{a=\"b\"} 3\n"));
+            assert_eq!(out.ends_with("# EOF\n"), eof);
+        }
+    }
+}
@@ -0,0 +1,356 @@
+//! Replying from the address a message was received on
+//!
+//! A listen socket bound to a wildcard address (`0.0.0.0` or `[::]`) receives messages sent to
+//! any address of the host. When sending, the kernel picks the source address by routing,
+//! which on a host with several addresses need not be the one the peer sent its message to;
+//! stateful firewalls and NAT in front of the peer then drop the reply.
+//!
+//! On Linux, listen sockets therefore ask for `IP_PKTINFO`/`IPV6_RECVPKTINFO` control messages.
+//! [recv_from] returns the local address a message was sent to, which is kept in the
+//! [crate::app_server::SocketBoundEndpoint] of the peer, and [send_to] pins replies and
+//! subsequent initiations to that source address. On other platforms, the kernel keeps picking
+//! source addresses.
+
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+
+use mio::net::UdpSocket;
+
+/// The local address a message was received on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalAddr {
+    /// The destination address of the message
+    pub ip: IpAddr,
+    /// The interface the message arrived on
+    pub ifindex: u32,
+}
+
+impl std::fmt::Display for LocalAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.ip)
+    }
+}
+
+pub use imp::{enable, recv_from, send_to};
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod imp {
+    use std::mem::{size_of, size_of_val, zeroed};
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6};
+    use std::os::fd::{AsRawFd, RawFd};
+    use std::ptr::{read_unaligned, write_unaligned};
+
+    use super::*;
+
+    /// Room for one `IP_PKTINFO` or `IPV6_PKTINFO` control message; `u64` for alignment
+    type CmsgBuffer = [u64; 8];
+
+    fn setsockopt_int(fd: RawFd, level: libc::c_int, name: libc::c_int) -> io::Result<()> {
+        let value: libc::c_int = 1;
+        // Safety: the option value points to a live c_int of the given size
+        let res = unsafe {
+            libc::setsockopt(
+                fd,
+                level,
+                name,
+                (&value as *const libc::c_int).cast(),
+                size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        match res {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        }
+    }
+
+    /// Ask the kernel to report the local address of received messages on `sock`
+    pub fn enable(sock: &UdpSocket) -> io::Result<()> {
+        let fd = sock.as_raw_fd();
+        match sock.local_addr()? {
+            SocketAddr::V4(_) => setsockopt_int(fd, libc::IPPROTO_IP, libc::IP_PKTINFO),
+            SocketAddr::V6(_) => {
+                setsockopt_int(fd, libc::IPPROTO_IPV6, libc::IPV6_RECVPKTINFO)?;
+                // For IPv4 messages on dual-stack sockets; IPv6-only sockets do not need it
+                let _ = setsockopt_int(fd, libc::IPPROTO_IP, libc::IP_PKTINFO);
+                Ok(())
+            }
+        }
+    }
+
+    fn sockaddr_from_std(addr: SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+        // Safety: all-zero is a valid sockaddr_storage
+        let mut storage: libc::sockaddr_storage = unsafe { zeroed() };
+        let ptr = (&mut storage as *mut libc::sockaddr_storage).cast::<u8>();
+        // Safety: sockaddr_storage is large enough for either address type
+        let len = unsafe {
+            match addr {
+                SocketAddr::V4(a) => {
+                    let sin = libc::sockaddr_in {
+                        sin_family: libc::AF_INET as libc::sa_family_t,
+                        sin_port: a.port().to_be(),
+                        sin_addr: libc::in_addr {
+                            s_addr: u32::from(*a.ip()).to_be(),
+                        },
+                        sin_zero: [0; 8],
+                    };
+                    write_unaligned(ptr.cast(), sin);
+                    size_of::<libc::sockaddr_in>()
+                }
+                SocketAddr::V6(a) => {
+                    let sin6 = libc::sockaddr_in6 {
+                        sin6_family: libc::AF_INET6 as libc::sa_family_t,
+                        sin6_port: a.port().to_be(),
+                        sin6_flowinfo: a.flowinfo(),
+                        sin6_addr: libc::in6_addr {
+                            s6_addr: a.ip().octets(),
+                        },
+                        sin6_scope_id: a.scope_id(),
+                    };
+                    write_unaligned(ptr.cast(), sin6);
+                    size_of::<libc::sockaddr_in6>()
+                }
+            }
+        };
+        (storage, len as libc::socklen_t)
+    }
+
+    fn sockaddr_to_std(storage: &libc::sockaddr_storage) -> io::Result<SocketAddr> {
+        let ptr = (storage as *const libc::sockaddr_storage).cast::<u8>();
+        // Safety: the kernel filled in an address of the family it reports
+        unsafe {
+            match storage.ss_family as libc::c_int {
+                libc::AF_INET => {
+                    let sin: libc::sockaddr_in = read_unaligned(ptr.cast());
+                    let ip = Ipv4Addr::from(u32::from_be(sin.sin_addr.s_addr));
+                    Ok(SocketAddrV4::new(ip, u16::from_be(sin.sin_port)).into())
+                }
+                libc::AF_INET6 => {
+                    let sin6: libc::sockaddr_in6 = read_unaligned(ptr.cast());
+                    let ip = Ipv6Addr::from(sin6.sin6_addr.s6_addr);
+                    let port = u16::from_be(sin6.sin6_port);
+                    Ok(SocketAddrV6::new(ip, port, sin6.sin6_flowinfo, sin6.sin6_scope_id).into())
+                }
+                family => Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Unexpected address family {family}"),
+                )),
+            }
+        }
+    }
+
+    /// Find the packet info among the control messages of `msg`
+    ///
+    /// # Safety
+    ///
+    /// `msg` must have been filled in by `recvmsg`.
+    unsafe fn local_from_cmsgs(msg: &libc::msghdr) -> Option<LocalAddr> {
+        let mut cmsg = libc::CMSG_FIRSTHDR(msg);
+        while !cmsg.is_null() {
+            let data = libc::CMSG_DATA(cmsg);
+            match ((*cmsg).cmsg_level, (*cmsg).cmsg_type) {
+                (libc::IPPROTO_IP, libc::IP_PKTINFO) => {
+                    let info: libc::in_pktinfo = read_unaligned(data.cast());
+                    return Some(LocalAddr {
+                        ip: Ipv4Addr::from(u32::from_be(info.ipi_addr.s_addr)).into(),
+                        ifindex: info.ipi_ifindex as u32,
+                    });
+                }
+                (libc::IPPROTO_IPV6, libc::IPV6_PKTINFO) => {
+                    let info: libc::in6_pktinfo = read_unaligned(data.cast());
+                    return Some(LocalAddr {
+                        ip: Ipv6Addr::from(info.ipi6_addr.s6_addr).into(),
+                        ifindex: info.ipi6_ifindex,
+                    });
+                }
+                _ => {}
+            }
+            cmsg = libc::CMSG_NXTHDR(msg, cmsg);
+        }
+        None
+    }
+
+    /// Receive a message like [UdpSocket::recv_from], along with the local address it was sent
+    /// to if [enable] was called on `sock`
+    pub fn recv_from(
+        sock: &UdpSocket,
+        buf: &mut [u8],
+    ) -> io::Result<(usize, SocketAddr, Option<LocalAddr>)> {
+        // Safety: all-zero is a valid sockaddr_storage and msghdr
+        let mut addr: libc::sockaddr_storage = unsafe { zeroed() };
+        let mut msg: libc::msghdr = unsafe { zeroed() };
+        let mut cmsgs: CmsgBuffer = Default::default();
+        let mut iov = libc::iovec {
+            iov_base: buf.as_mut_ptr().cast(),
+            iov_len: buf.len(),
+        };
+        msg.msg_name = (&mut addr as *mut libc::sockaddr_storage).cast();
+        msg.msg_namelen = size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = cmsgs.as_mut_ptr().cast();
+        msg.msg_controllen = size_of_val(&cmsgs) as _;
+
+        // Safety: all buffers msg points to are live and as large as it claims
+        let len = unsafe { libc::recvmsg(sock.as_raw_fd(), &mut msg, 0) };
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let peer = sockaddr_to_std(&addr)?;
+        // Safety: recvmsg succeeded
+        let local = unsafe { local_from_cmsgs(&msg) };
+        Ok((len as usize, peer, local))
+    }
+
+    /// Send a message like [UdpSocket::send_to], from the address `local` if given
+    ///
+    /// If the host no longer has the address `local`, the kernel picks the source address.
+    pub fn send_to(
+        sock: &UdpSocket,
+        buf: &[u8],
+        addr: SocketAddr,
+        local: Option<&LocalAddr>,
+    ) -> io::Result<usize> {
+        let Some(local) = local else {
+            return sock.send_to(buf, addr);
+        };
+
+        let (mut name, namelen) = sockaddr_from_std(addr);
+        // Safety: all-zero is a valid msghdr
+        let mut msg: libc::msghdr = unsafe { zeroed() };
+        let mut cmsgs: CmsgBuffer = Default::default();
+        let mut iov = libc::iovec {
+            iov_base: buf.as_ptr() as *mut libc::c_void,
+            iov_len: buf.len(),
+        };
+        msg.msg_name = (&mut name as *mut libc::sockaddr_storage).cast();
+        msg.msg_namelen = namelen;
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = cmsgs.as_mut_ptr().cast();
+
+        // Safety: the control message fits into cmsgs, which msg points to
+        unsafe {
+            let (level, kind, len) = match local.ip {
+                IpAddr::V4(_) => (
+                    libc::IPPROTO_IP,
+                    libc::IP_PKTINFO,
+                    size_of::<libc::in_pktinfo>(),
+                ),
+                IpAddr::V6(_) => (
+                    libc::IPPROTO_IPV6,
+                    libc::IPV6_PKTINFO,
+                    size_of::<libc::in6_pktinfo>(),
+                ),
+            };
+            msg.msg_controllen = libc::CMSG_SPACE(len as u32) as _;
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = level;
+            (*cmsg).cmsg_type = kind;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(len as u32) as _;
+            let data = libc::CMSG_DATA(cmsg);
+            match local.ip {
+                IpAddr::V4(ip) => write_unaligned(
+                    data.cast(),
+                    libc::in_pktinfo {
+                        ipi_ifindex: 0,
+                        ipi_spec_dst: libc::in_addr {
+                            s_addr: u32::from(ip).to_be(),
+                        },
+                        ipi_addr: libc::in_addr { s_addr: 0 },
+                    },
+                ),
+                IpAddr::V6(ip) => write_unaligned(
+                    data.cast(),
+                    libc::in6_pktinfo {
+                        ipi6_addr: libc::in6_addr {
+                            s6_addr: ip.octets(),
+                        },
+                        // Link-local addresses are only unique together with their interface
+                        ipi6_ifindex: match ip.segments()[0] & 0xffc0 == 0xfe80 {
+                            true => local.ifindex,
+                            false => 0,
+                        },
+                    },
+                ),
+            }
+        }
+
+        // Safety: all buffers msg points to are live and as large as it claims
+        let len = unsafe { libc::sendmsg(sock.as_raw_fd(), &msg, 0) };
+        if len >= 0 {
+            return Ok(len as usize);
+        }
+        let err = io::Error::last_os_error();
+        match err.raw_os_error() {
+            Some(libc::EINVAL) | Some(libc::EADDRNOTAVAIL) => sock.send_to(buf, addr),
+            _ => Err(err),
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+mod imp {
+    use super::*;
+
+    /// Does nothing; local addresses of received messages are only reported on Linux
+    pub fn enable(_sock: &UdpSocket) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Receive a message; the local address it was sent to is not known here
+    pub fn recv_from(
+        sock: &UdpSocket,
+        buf: &mut [u8],
+    ) -> io::Result<(usize, SocketAddr, Option<LocalAddr>)> {
+        let (len, addr) = sock.recv_from(buf)?;
+        Ok((len, addr, None))
+    }
+
+    /// Send a message, letting the kernel pick the source address
+    pub fn send_to(
+        sock: &UdpSocket,
+        buf: &[u8],
+        addr: SocketAddr,
+        _local: Option<&LocalAddr>,
+    ) -> io::Result<usize> {
+        sock.send_to(buf, addr)
+    }
+}
+
+#[cfg(all(test, any(target_os = "linux", target_os = "android")))]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn replies_come_from_the_address_messages_were_sent_to() -> anyhow::Result<()> {
+        let server = std::net::UdpSocket::bind("0.0.0.0:0")?;
+        server.set_read_timeout(Some(Duration::from_secs(10)))?;
+        let server = UdpSocket::from_std(server);
+        enable(&server)?;
+        let port = server.local_addr()?.port();
+
+        let client = std::net::UdpSocket::bind("127.0.0.1:0")?;
+        client.set_read_timeout(Some(Duration::from_secs(10)))?;
+
+        // All of 127.0.0.0/8 is local, so this works like a second address of the host
+        let target: SocketAddr = format!("127.0.0.2:{port}").parse()?;
+        client.send_to(b"hello", target)?;
+
+        let mut buf = [0u8; 16];
+        let (len, from, local) = recv_from(&server, &mut buf)?;
+        assert_eq!(&buf[..len], b"hello");
+        assert_eq!(from, client.local_addr()?);
+        let local = local.expect("no packet info received");
+        assert_eq!(local.ip, target.ip());
+
+        send_to(&server, b"reply", from, Some(&local))?;
+        let (len, reply_from) = client.recv_from(&mut buf)?;
+        assert_eq!(&buf[..len], b"reply");
+        assert_eq!(reply_from, target);
+
+        Ok(())
+    }
+}
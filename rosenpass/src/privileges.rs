@@ -0,0 +1,349 @@
+//! Dropping privileges once the server is set up
+//!
+//! Binding to privileged ports, reading the secret key and setting socket options may require
+//! root; exchanging keys afterwards does not. With the `user` (and optionally `group`) options,
+//! the `exchange-config` command switches to that user once all listen sockets are bound, the
+//! keys are loaded and the brokers are connected:
+//!
+//! - Supplementary groups are cleared, then the group and the user are switched.
+//! - On Linux, all capabilities are dropped, including from the bounding set, except for those
+//!   the configured broker needs: with the built-in broker, which runs `wg set` for peers with a
+//!   WireGuard `device`, `CAP_NET_ADMIN` is kept and passed on to `wg` as an ambient capability.
+//!   Brokers running in a separate process are started before privileges are dropped and keep
+//!   theirs.
+//! - If any step fails, or root privileges could be regained afterwards, rosenpass exits instead
+//!   of running with more privileges than asked for.
+//!
+//! After the switch, everything rosenpass touches at runtime must be accessible to the user:
+//! `key_out` files and their directories, hooks, and – for reloading – the configuration file,
+//! the keys and pre-shared keys. Sockets for addresses added to `listen` by a reload are bound as
+//! the user as well, so they cannot use privileged ports.
+//!
+//! # Examples
+//!
+//! ```toml
+//! user = "rosenpass"
+//! group = "rosenpass" # defaults to the primary group of the user
+//! ```
+
+use std::ffi::CString;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{bail, ensure, Context};
+
+/// Set once privileges were dropped
+static DROPPED: AtomicBool = AtomicBool::new(false);
+
+/// Whether [PrivilegeDrop::apply] dropped the privileges of this process
+pub fn dropped() -> bool {
+    DROPPED.load(Ordering::Relaxed)
+}
+
+/// A capability the server may keep after dropping privileges
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    /// `CAP_NET_ADMIN`, needed to configure WireGuard devices
+    NetAdmin,
+}
+
+impl Capability {
+    /// The number of the capability in the Linux capability sets
+    #[cfg(target_os = "linux")]
+    fn number(self) -> u32 {
+        match self {
+            Capability::NetAdmin => 12,
+        }
+    }
+}
+
+/// The user and group to switch to, and the capabilities to keep; see [crate::privileges]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrivilegeDrop {
+    /// Name or number of the user
+    pub user: String,
+    /// Numeric user id
+    pub uid: libc::uid_t,
+    /// Numeric group id
+    pub gid: libc::gid_t,
+    /// Capabilities to keep
+    pub retain: Vec<Capability>,
+}
+
+/// Size of the buffer for getpwnam_r(3) and friends
+const PASSWD_BUFFER_SIZE: usize = 16384;
+
+/// Look up the user id and primary group of `user`, a user name or number
+fn lookup_user(user: &str) -> anyhow::Result<(libc::uid_t, Option<libc::gid_t>)> {
+    let cname = CString::new(user)?;
+    let numeric = user.parse::<libc::uid_t>().ok();
+    // Safety: all-zero is a valid passwd
+    let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buf = vec![0 as libc::c_char; PASSWD_BUFFER_SIZE];
+    let mut result = std::ptr::null_mut();
+    // Safety: all pointers refer to live buffers of the given size
+    let rc = unsafe {
+        match numeric {
+            Some(uid) => libc::getpwuid_r(uid, &mut pwd, buf.as_mut_ptr(), buf.len(), &mut result),
+            None => libc::getpwnam_r(
+                cname.as_ptr(),
+                &mut pwd,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut result,
+            ),
+        }
+    };
+    if rc != 0 {
+        return Err(io::Error::from_raw_os_error(rc))
+            .with_context(|| format!("Could not look up user {user:?}"));
+    }
+
+    match (result.is_null(), numeric) {
+        (false, _) => Ok((pwd.pw_uid, Some(pwd.pw_gid))),
+        // Numeric ids need not have an entry in the user database
+        (true, Some(uid)) => Ok((uid, None)),
+        (true, None) => bail!("No such user {user:?}"),
+    }
+}
+
+/// Look up the group id of `group`, a group name or number
+fn lookup_group(group: &str) -> anyhow::Result<libc::gid_t> {
+    if let Ok(gid) = group.parse::<libc::gid_t>() {
+        return Ok(gid);
+    }
+
+    let cname = CString::new(group)?;
+    // Safety: all-zero is a valid group
+    let mut grp: libc::group = unsafe { std::mem::zeroed() };
+    let mut buf = vec![0 as libc::c_char; PASSWD_BUFFER_SIZE];
+    let mut result = std::ptr::null_mut();
+    // Safety: all pointers refer to live buffers of the given size
+    let rc = unsafe {
+        libc::getgrnam_r(
+            cname.as_ptr(),
+            &mut grp,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut result,
+        )
+    };
+    if rc != 0 {
+        return Err(io::Error::from_raw_os_error(rc))
+            .with_context(|| format!("Could not look up group {group:?}"));
+    }
+    ensure!(!result.is_null(), "No such group {group:?}");
+    Ok(grp.gr_gid)
+}
+
+/// Turn the return value of a libc function into a result
+fn check(rc: libc::c_int, what: &str) -> anyhow::Result<()> {
+    if rc == 0 {
+        return Ok(());
+    }
+    let err = io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::EPERM) => Err(err).with_context(|| {
+            format!("Could not {what}; dropping privileges requires starting rosenpass as root")
+        }),
+        _ => Err(err).with_context(|| format!("Could not {what}")),
+    }
+}
+
+impl PrivilegeDrop {
+    /// Look up `user` and `group`; the group defaults to the primary group of the user
+    pub fn lookup(
+        user: &str,
+        group: Option<&str>,
+        retain: Vec<Capability>,
+    ) -> anyhow::Result<Self> {
+        let (uid, primary_gid) = lookup_user(user)?;
+        let gid = match group {
+            Some(group) => lookup_group(group)?,
+            None => primary_gid.with_context(|| {
+                format!("User {user:?} has no entry in the user database; please set a group")
+            })?,
+        };
+        Ok(Self {
+            user: user.to_owned(),
+            uid,
+            gid,
+            retain,
+        })
+    }
+
+    /// Switch to the user and group and drop all capabilities but [Self::retain]
+    ///
+    /// The user and group change for all threads, but capabilities are per thread: only the
+    /// calling thread keeps [Self::retain], so programs that need them must be started from it.
+    pub fn apply(&self) -> anyhow::Result<()> {
+        #[cfg(target_os = "linux")]
+        linux::prepare(&self.retain)?;
+        #[cfg(not(target_os = "linux"))]
+        ensure!(
+            self.retain.is_empty(),
+            "Keeping capabilities is only supported on Linux"
+        );
+
+        // Safety: plain system calls without pointers, or with a null pointer for zero groups
+        unsafe {
+            check(
+                libc::setgroups(0, std::ptr::null()),
+                "clear supplementary groups",
+            )?;
+            check(libc::setgid(self.gid), "switch group")?;
+            check(libc::setuid(self.uid), "switch user")?;
+        }
+
+        #[cfg(target_os = "linux")]
+        linux::restrict(&self.retain)?;
+
+        self.verify()?;
+        DROPPED.store(true, Ordering::Relaxed);
+        log::info!(
+            "Dropped privileges to user {:?} (uid {}, gid {})",
+            self.user,
+            self.uid,
+            self.gid
+        );
+        Ok(())
+    }
+
+    /// Check that the switch worked and cannot be undone
+    fn verify(&self) -> anyhow::Result<()> {
+        // Safety: plain system calls without pointers
+        unsafe {
+            ensure!(
+                libc::getuid() == self.uid && libc::geteuid() == self.uid,
+                "The user was not switched"
+            );
+            ensure!(
+                libc::getgid() == self.gid && libc::getegid() == self.gid,
+                "The group was not switched"
+            );
+            ensure!(
+                libc::getgroups(0, std::ptr::null_mut()) <= 1,
+                "Supplementary groups were not cleared"
+            );
+            ensure!(
+                self.uid == 0 || libc::setuid(0) != 0,
+                "Root privileges could be regained after dropping them"
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::*;
+
+    /// `_LINUX_CAPABILITY_VERSION_3`, for 64 bit capability sets
+    const CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+
+    /// `struct __user_cap_header_struct`
+    #[repr(C)]
+    struct CapHeader {
+        version: u32,
+        pid: libc::c_int,
+    }
+
+    /// `struct __user_cap_data_struct`
+    #[repr(C)]
+    #[derive(Clone, Copy, Default)]
+    struct CapData {
+        effective: u32,
+        permitted: u32,
+        inheritable: u32,
+    }
+
+    /// Drop capabilities from the bounding set and keep the rest across the user switch
+    pub(super) fn prepare(retain: &[Capability]) -> anyhow::Result<()> {
+        for cap in 0..64 {
+            if retain.iter().any(|c| c.number() == cap) {
+                continue;
+            }
+            // Safety: plain system call without pointers
+            let rc = unsafe { libc::prctl(libc::PR_CAPBSET_DROP, cap as libc::c_ulong, 0, 0, 0) };
+            if rc != 0 {
+                match io::Error::last_os_error().raw_os_error() {
+                    // Past the last capability the kernel knows
+                    Some(libc::EINVAL) => break,
+                    // Without CAP_SETPCAP; the capability sets are still cleared below
+                    Some(libc::EPERM) => break,
+                    _ => check(rc, "drop capabilities from the bounding set")?,
+                }
+            }
+        }
+
+        if !retain.is_empty() {
+            // Safety: plain system call without pointers
+            check(
+                unsafe { libc::prctl(libc::PR_SET_KEEPCAPS, 1 as libc::c_ulong, 0, 0, 0) },
+                "keep capabilities across the user switch",
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Reduce the capability sets to `retain` and make them ambient, so programs the server
+    /// starts (such as `wg`) get them as well
+    pub(super) fn restrict(retain: &[Capability]) -> anyhow::Result<()> {
+        let mut data = [CapData::default(); 2];
+        for cap in retain.iter().map(|c| c.number()) {
+            let set = &mut data[(cap / 32) as usize];
+            let bit = 1 << (cap % 32);
+            set.effective |= bit;
+            set.permitted |= bit;
+            set.inheritable |= bit;
+        }
+        let header = CapHeader {
+            version: CAPABILITY_VERSION_3,
+            pid: 0,
+        };
+        // Safety: header and data have the layout capset(2) expects
+        let rc = unsafe { libc::syscall(libc::SYS_capset, &header, data.as_ptr()) };
+        check(rc as libc::c_int, "set capabilities")?;
+
+        // Safety: plain system calls without pointers
+        unsafe {
+            check(
+                libc::prctl(libc::PR_SET_KEEPCAPS, 0 as libc::c_ulong, 0, 0, 0),
+                "reset keeping capabilities",
+            )?;
+            for cap in retain.iter().map(|c| c.number()) {
+                check(
+                    libc::prctl(
+                        libc::PR_CAP_AMBIENT,
+                        libc::PR_CAP_AMBIENT_RAISE as libc::c_ulong,
+                        cap as libc::c_ulong,
+                        0,
+                        0,
+                    ),
+                    "pass capabilities on to child processes",
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn users_and_groups_are_looked_up() -> anyhow::Result<()> {
+        let root = PrivilegeDrop::lookup("root", None, vec![])?;
+        assert_eq!((root.uid, root.gid), (0, 0));
+
+        let numeric = PrivilegeDrop::lookup("65534", Some("65534"), vec![])?;
+        assert_eq!((numeric.uid, numeric.gid), (65534, 65534));
+
+        assert!(PrivilegeDrop::lookup("no-such-user-for-rosenpass", None, vec![]).is_err());
+        assert!(
+            PrivilegeDrop::lookup("root", Some("no-such-group-for-rosenpass"), vec![]).is_err()
+        );
+        Ok(())
+    }
+}
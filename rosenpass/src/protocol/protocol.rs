@@ -179,6 +179,20 @@ pub type MsgBuf = Public<MAX_MESSAGE_LEN>;
 /// Server-local peer number; this is just the index in [CryptoServer::peers]
 pub type PeerNo = usize;
 
+/// Decapsulation with the static secret key, performed outside of the [CryptoServer]
+///
+/// When [CryptoServer::static_key_oracle] is set, the server uses it instead of
+/// [CryptoServer::sskm], which may then be zero. See [crate::key_oracle] for an implementation
+/// that runs in a separate process.
+pub trait StaticKeyOracle: Debug + Send {
+    /// Decapsulate `ct` with the static secret key, writing the shared key to `shk`
+    fn decaps(
+        &mut self,
+        shk: &mut [u8; StaticKem::SHK_LEN],
+        ct: &[u8; StaticKem::CT_LEN],
+    ) -> anyhow::Result<()>;
+}
+
 /// This is the implementation of our cryptographic protocol.
 ///
 /// The scope of this is:
@@ -210,6 +224,8 @@ pub struct CryptoServer {
     pub sskm: SSk,
     /// Static Public Key Mine (our public key)
     pub spkm: SPk,
+    /// Performs the operations with [Self::sskm] elsewhere, if set
+    pub static_key_oracle: Option<Box<dyn StaticKeyOracle>>,
     /// Counter used to fill the [Biscuit::biscuit_no] field for biscuits issued.
     ///
     /// Every [Biscuit] issued contains a biscuit number; this is the counter used to generate
@@ -466,6 +482,9 @@ pub struct Peer {
     /// [CryptoServer::initiate_handshake] (and by proxy [CryptoServer::handle_initiation]),
     /// on its own accord. Instead, it will issue a
     pub initiation_requested: bool,
+    /// Set by [CryptoServer::initiate_now] to have [CryptoServer::poll] issue a
+    /// [PollResult::SendInitiation] right away, even if the current session is still young
+    pub initiate_now: bool,
     /// Stores a known response for a [Envelope]<[InitConf]> message, i.e. a
     /// [Envelope]<[EmptyData]>.
     ///
@@ -479,6 +498,12 @@ pub struct Peer {
 
     /// The protocol version used by with this peer.
     pub protocol_version: ProtocolVersion,
+
+    /// Set by [CryptoServer::remove_peer].
+    ///
+    /// Removed peers keep their slot in [CryptoServer::peers] so that [PeerPtr]s to other peers
+    /// stay valid, but they hold no state and take no part in the protocol anymore.
+    pub removed: bool,
 }
 
 impl Peer {
@@ -501,9 +526,11 @@ impl Peer {
             biscuit_used: BiscuitId::zero(),
             session: None,
             initiation_requested: false,
+            initiate_now: false,
             handshake: None,
             known_init_conf_response: None,
             protocol_version,
+            removed: false,
         }
     }
 }
@@ -1394,6 +1421,7 @@ impl CryptoServer {
         CryptoServer {
             sskm: sk,
             spkm: pk,
+            static_key_oracle: None,
 
             // Defaults
             timebase: tb,
@@ -1474,7 +1502,9 @@ impl CryptoServer {
             handshake: None,
             known_init_conf_response: None,
             initiation_requested: false,
+            initiate_now: false,
             protocol_version,
+            removed: false,
         };
         let peerid = peer.pidt()?;
         let peerno = self.peers.len();
@@ -1489,6 +1519,67 @@ impl CryptoServer {
         Ok(PeerPtr(peerno))
     }
 
+    /// Remove a peer added through [Self::add_peer].
+    ///
+    /// Any established session, ongoing handshake and cached response for the peer is erased
+    /// and the peer is removed from [Self::index], so messages referring to it are rejected
+    /// from now on.
+    ///
+    /// Since peers are referred to by their position in [Self::peers], the peer keeps its
+    /// slot; it is marked as [Peer::removed] and produces no further events in [Self::poll].
+    /// Adding a peer with the same public key afterwards creates a fresh slot.
+    ///
+    /// ```
+    /// use std::ops::DerefMut;
+    /// use rosenpass::protocol::{SSk, SPk, CryptoServer, ProtocolVersion};
+    /// use rosenpass_ciphers::StaticKem;
+    /// use rosenpass_cipher_traits::primitives::Kem;
+    ///
+    /// rosenpass_secret_memory::secret_policy_try_use_memfd_secrets();
+    ///
+    /// let (mut sskm, mut spkm) = (SSk::zero(), SPk::zero());
+    /// StaticKem.keygen(sskm.secret_mut(), spkm.deref_mut())?;
+    /// let mut srv = CryptoServer::new(sskm, spkm);
+    ///
+    /// let (mut sskt, mut spkt) = (SSk::zero(), SPk::zero());
+    /// StaticKem.keygen(sskt.secret_mut(), spkt.deref_mut())?;
+    ///
+    /// let peer = srv.add_peer(None, spkt.clone(), ProtocolVersion::V03)?;
+    /// let peerid = peer.get(&srv).pidt()?;
+    /// assert!(srv.find_peer(peerid).is_some());
+    ///
+    /// srv.remove_peer(peer)?;
+    /// assert!(peer.get(&srv).removed);
+    /// assert!(srv.find_peer(peerid).is_none());
+    ///
+    /// // The peer can be added again; it gets a new slot
+    /// let peer2 = srv.add_peer(None, spkt, ProtocolVersion::V03)?;
+    /// assert_ne!(peer, peer2);
+    ///
+    /// Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn remove_peer(&mut self, peer: PeerPtr) -> Result<()> {
+        ensure!(
+            !peer.get(self).removed,
+            "Peer {} has already been removed",
+            peer.0
+        );
+
+        let peerid = peer.get(self).pidt()?;
+        peer.session().take(self);
+        peer.hs().take(self);
+        peer.known_init_conf_response().remove(self);
+        self.index.remove(&IndexKey::Peer(peerid));
+
+        let p = peer.get_mut(self);
+        p.psk = SymKey::zero();
+        p.initiation_requested = false;
+        p.initiate_now = false;
+        p.removed = true;
+
+        Ok(())
+    }
+
     /// Register a new session
     ///
     /// Used in [SessionPtr::insert] and [IniHsPtr::insert].
@@ -1671,7 +1762,9 @@ impl Peer {
             handshake: None,
             known_init_conf_response: None,
             initiation_requested: false,
+            initiate_now: false,
             protocol_version,
+            removed: false,
         }
     }
 
@@ -2071,6 +2164,11 @@ impl CryptoServer {
         // TODO move retransmission storage to io server
         //
         // Envelope::<InitHello>::default(); // TODO
+        ensure!(
+            !peer.get(self).removed,
+            "Can not initiate a handshake with removed peer {}",
+            peer.0
+        );
         let mut msg = truncating_cast_into::<Envelope<InitHello>>(tx_buf)?;
         self.handle_initiation(peer, &mut msg.payload)?;
         let len = self.seal_and_commit_msg(peer, MsgType::InitHello, &mut msg)?;
@@ -2080,6 +2178,54 @@ impl CryptoServer {
     }
 }
 
+impl CryptoServer {
+    /// Have the next call to [Self::poll] issue a [PollResult::SendInitiation] for `peer`.
+    ///
+    /// Normally, [Self::poll] only asks for a new handshake once the current session is about to
+    /// go stale. This forces a fresh key exchange right away, e.g. to test connectivity. The
+    /// current session and key stay valid until the new handshake completes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::ops::DerefMut;
+    ///
+    /// use rosenpass::protocol::{CryptoServer, PollResult, SSk, SPk, ProtocolVersion};
+    /// use rosenpass_cipher_traits::primitives::Kem;
+    /// use rosenpass_ciphers::StaticKem;
+    ///
+    /// rosenpass_secret_memory::secret_policy_try_use_memfd_secrets();
+    ///
+    /// let keypair = || {
+    ///     let (mut sk, mut pk) = (SSk::zero(), SPk::zero());
+    ///     StaticKem.keygen(sk.secret_mut(), pk.deref_mut()).unwrap();
+    ///     (sk, pk)
+    /// };
+    /// let ((sk, pk), (_, peer_pk)) = (keypair(), keypair());
+    /// let mut srv = CryptoServer::new(sk, pk);
+    /// let peer = srv.add_peer(None, peer_pk, ProtocolVersion::V03)?;
+    ///
+    /// // Without a session, the server asks for a handshake on its own
+    /// assert!(matches!(srv.poll()?, PollResult::SendInitiation(p) if p == peer));
+    /// assert!(matches!(srv.poll()?, PollResult::Sleep(_)));
+    ///
+    /// // The handshake was requested already, but we can ask for another one
+    /// srv.initiate_now(peer)?;
+    /// assert!(matches!(srv.poll()?, PollResult::SendInitiation(p) if p == peer));
+    /// assert!(matches!(srv.poll()?, PollResult::Sleep(_)));
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn initiate_now(&mut self, peer: PeerPtr) -> Result<()> {
+        ensure!(
+            !peer.get(self).removed,
+            "Can not initiate a handshake with removed peer {}",
+            peer.0
+        );
+        peer.get_mut(self).initiate_now = true;
+        Ok(())
+    }
+}
+
 /// The type returned by [CryptoServer::handle_msg]
 #[derive(Debug)]
 pub struct HandleMsgResult {
@@ -2560,6 +2706,21 @@ impl Wait {
         }
     }
 
+    /// Equivalent to [Self::immediate] or [Self::hibernate], depending
+    /// the given condition; the opposite of [Self::immediate_unless]
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rosenpass::protocol::{Wait, UNENDING};
+    ///
+    /// assert_eq!(Wait::immediate_if(true).0, 0.0);
+    /// assert_eq!(Wait::immediate_if(false).0, UNENDING);
+    /// ```
+    pub fn immediate_if(cond: bool) -> Self {
+        Self::immediate_unless(!cond)
+    }
+
     /// Use the given timing value or hibernate if None
     ///
     /// # Examples
@@ -3056,6 +3217,10 @@ impl Pollable for ServerCookieSecretPtr {
 
 impl Pollable for PeerPtr {
     fn poll(&self, srv: &mut CryptoServer) -> Result<PollResult> {
+        if self.get(srv).removed {
+            return Ok(PollResult::hibernate());
+        }
+
         let (ses, hs) = (self.session(), self.hs());
         begin_poll()
             .sched(hs.life_left(srv), void_poll(|| hs.take(srv))) // Silently erase old handshakes
@@ -3064,6 +3229,13 @@ impl Pollable for PeerPtr {
                 ses.take(srv);
                 PollResult::DeleteKey(*self)
             })
+            // Initiate right away if asked to through CryptoServer::initiate_now
+            .sched(Wait::immediate_if(self.get(srv).initiate_now), || {
+                let peer = self.get_mut(srv);
+                peer.initiate_now = false;
+                peer.initiation_requested = true;
+                PollResult::SendInitiation(*self)
+            })
             // Initialize the handshake
             // IF if initiation hasn't been requested (consumer of the API is free to
             // ignore the request hence there is a need to do record keeping on that)
@@ -3595,6 +3767,26 @@ impl CryptoServer {
         Ok(peer)
     }
 
+    /// Decapsulate `ct` with our static secret key and mix it into `core`, just like
+    /// [HandshakeState::decaps_and_mix]; through [Self::static_key_oracle] if there is one
+    fn decaps_static_and_mix(
+        &mut self,
+        core: &mut HandshakeState,
+        ct: &[u8; StaticKem::CT_LEN],
+    ) -> Result<()> {
+        match self.static_key_oracle.as_mut() {
+            None => {
+                core.decaps_and_mix(&StaticKem, self.sskm.secret(), self.spkm.deref(), ct)?;
+            }
+            Some(oracle) => {
+                let mut shk = Secret::<{ StaticKem::SHK_LEN }>::zero();
+                oracle.decaps(shk.secret_mut(), ct)?;
+                core.mix(self.spkm.deref())?.mix(shk.secret())?.mix(ct)?;
+            }
+        }
+        Ok(())
+    }
+
     /// Core cryptographic protocol implementation: Parses an [InitHello] message and produces a
     /// [RespHello] message on the responder side.
     pub fn handle_init_hello(
@@ -3614,7 +3806,7 @@ impl CryptoServer {
         core.mix(&ih.sidi)?.mix(&ih.epki)?;
 
         // IHR5
-        core.decaps_and_mix(&StaticKem, self.sskm.secret(), self.spkm.deref(), &ih.sctr)?;
+        self.decaps_static_and_mix(&mut core, &ih.sctr)?;
 
         // IHR6
         let peer = {
@@ -3711,7 +3903,7 @@ impl CryptoServer {
         )?;
 
         // RHI5
-        core.decaps_and_mix(&StaticKem, self.sskm.secret(), self.spkm.deref(), &rh.scti)?;
+        self.decaps_static_and_mix(&mut core, &rh.scti)?;
 
         // RHI6
         core.mix(&rh.biscuit)?;
@@ -0,0 +1,690 @@
+//! Reloading the configuration file of a running [AppServer]
+//!
+//! The `exchange-config` command remembers the configuration it was started with in a
+//! [ConfigReload]. [AppServer::reload_config] – triggered through SIGHUP or the API – re-reads
+//! the configuration file and applies the difference to the running server:
+//!
+//! - Peers are matched by their public key. Peers whose pre-shared key or protocol version changed
+//!   are re-created, which ends their session. Peers whose endpoint, name, hooks or key outputs
+//!   changed are updated in place and keep their session; so do peers that did not change at all.
+//! - Peers that are no longer configured are removed; peers that are new are added.
+//! - Listen sockets are opened and closed to match the `listen` list. The sockets opened by default
+//!   when no listen address is configured are never closed.
+//! - Changed `listen_options` are applied to all listen sockets. Failing to apply them to a socket
+//!   that is already open is logged, but does not stop the reload.
+//!
+//! Everything that can fail because of the new configuration (parsing, loading keys, resolving
+//! endpoints, binding sockets, clashing with peers added through the API) happens before the
+//! running server is modified; if any of it fails, the running configuration stays in effect.
+//! Should applying the changes fail midway nonetheless, the changes applied so far stay in effect
+//! and the next reload picks up from there.
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use anyhow::{bail, ensure, Context};
+use log::{error, info, warn};
+use rosenpass_ciphers::KEY_LEN;
+use rosenpass_util::file::LoadValue;
+use rosenpass_wireguard_broker::brokers::native_unix::{
+    NativeUnixBrokerConfigBaseBuilder, NativeUnixBrokerConfigBaseBuilderError,
+};
+
+use crate::app_server::{
+    AppPeerPtr, AppServer, BrokerPeer, BrokerStorePtr, Endpoint, KeyOutputReason,
+};
+use crate::config::{self, ProtocolVersion, RosenpassPeer};
+use crate::credentials::load_secret_b64;
+use crate::hooks::Hooks;
+use crate::key_out::{KeyOutFormat, KeySink, KeySinkConfig};
+use crate::protocol::{SPk, SymKey};
+
+/// Maximum size of a pre-shared key file
+const MAX_PSK_SIZE: usize = 1000;
+
+/// A peer from the configuration file with all its files loaded and its endpoint resolved
+#[derive(Debug)]
+pub struct LoadedPeer {
+    /// The pre-shared key read from [RosenpassPeer::pre_shared_key]
+    pub psk: Option<SymKey>,
+    /// The public key read from [RosenpassPeer::public_key]
+    pub pk: SPk,
+    /// See [RosenpassPeer::key_out]
+    pub outfile: Option<PathBuf>,
+    /// See [RosenpassPeer::key_out_previous]
+    pub outfile_previous: usize,
+    /// See [RosenpassPeer::key_out_format]
+    pub key_format: KeyOutFormat,
+    /// Broker configuration built from [RosenpassPeer::wg]
+    pub broker_peer: Option<BrokerPeer>,
+    /// Endpoint resolved from [RosenpassPeer::endpoint]
+    pub endpoint: Option<Endpoint>,
+    /// See [RosenpassPeer::protocol_version]
+    pub protocol_version: ProtocolVersion,
+    /// See [RosenpassPeer::name]
+    pub name: Option<String>,
+    /// See [RosenpassPeer::hooks]
+    pub hooks: Hooks,
+    /// Sinks set up from [RosenpassPeer::key_sinks]
+    pub key_sinks: Vec<KeySink>,
+}
+
+impl LoadedPeer {
+    /// Load the files referenced by `cfg` and resolve its endpoint; `broker` is used for peers
+    /// with a WireGuard configuration
+    pub fn load(cfg: &RosenpassPeer, broker: &BrokerStorePtr) -> anyhow::Result<Self> {
+        fn cfg_err_map(e: NativeUnixBrokerConfigBaseBuilderError) -> anyhow::Error {
+            anyhow::Error::msg(format!("NativeUnixBrokerConfigBaseBuilderError: {:?}", e))
+        }
+
+        let broker_peer = match &cfg.wg {
+            Some(wg) => {
+                let peer_cfg = NativeUnixBrokerConfigBaseBuilder::default()
+                    .peer_id_b64(&wg.peer)?
+                    .interface(wg.device.clone())
+                    .extra_params_ser(&wg.extra_params)?
+                    .build()
+                    .map_err(cfg_err_map)?;
+                Some(BrokerPeer::new(broker.clone(), Box::new(peer_cfg)))
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            psk: cfg
+                .pre_shared_key
+                .as_deref()
+                .map(load_secret_b64::<MAX_PSK_SIZE, KEY_LEN>)
+                .transpose()?,
+            pk: SPk::load(&cfg.public_key)?,
+            outfile: cfg.key_out.clone(),
+            outfile_previous: cfg.key_out_previous,
+            key_format: cfg.key_out_format,
+            broker_peer,
+            endpoint: cfg
+                .endpoint
+                .clone()
+                .map(Endpoint::discovery_from_hostname)
+                .transpose()?,
+            protocol_version: cfg.protocol_version,
+            name: cfg.name.clone(),
+            hooks: cfg.hooks.clone(),
+            key_sinks: cfg
+                .key_sinks
+                .iter()
+                .map(KeySinkConfig::open)
+                .collect::<anyhow::Result<_>>()?,
+        })
+    }
+
+    /// Add this peer to `srv`
+    fn add_to(self, srv: &mut AppServer) -> anyhow::Result<RunningPeer> {
+        let (pk, psk) = (self.pk.clone(), self.psk.clone());
+        let ptr = srv.add_peer_with_endpoint(
+            self.psk,
+            self.pk,
+            self.outfile,
+            self.broker_peer,
+            self.endpoint,
+            self.protocol_version,
+        )?;
+        let ap = ptr.get_app_mut(srv);
+        ap.outfile_previous = self.outfile_previous;
+        ap.key_format = self.key_format;
+        ap.name = self.name;
+        ap.hooks = self.hooks;
+        ap.key_sinks = self.key_sinks;
+        Ok(RunningPeer { ptr, pk, psk })
+    }
+
+    /// Replace endpoint, name, hooks and key outputs of the existing peer `ptr` with the ones
+    /// from this peer
+    ///
+    /// The destinations the peer's key was written to so far receive a random key, so no
+    /// stale key is left behind in places rosenpass does not manage anymore; if there is an
+    /// active session, its key is handed to the new destinations right away.
+    fn update(self, srv: &mut AppServer, ptr: AppPeerPtr, outputs_changed: bool) -> RunningPeer {
+        let have_crypto = srv.crypto_site.is_available();
+
+        if outputs_changed && have_crypto {
+            if let Err(e) = srv.output_key(ptr, KeyOutputReason::Stale, &SymKey::random()) {
+                warn!(
+                    "Could not erase the key of peer {} from its previous key outputs: {e:?}",
+                    ptr.0
+                );
+            }
+        }
+
+        let ap = ptr.get_app_mut(srv);
+        ap.outfile = self.outfile;
+        ap.outfile_previous = self.outfile_previous;
+        ap.key_format = self.key_format;
+        ap.broker_peer = self.broker_peer;
+        // The current endpoint is where the peer was last heard from; keep using it
+        ap.initial_endpoint = self.endpoint;
+        ap.name = self.name;
+        ap.hooks = self.hooks;
+        ap.key_sinks = self.key_sinks;
+
+        if outputs_changed && have_crypto {
+            let osk = srv.crypto_server().and_then(|c| c.osk(ptr.lower()));
+            if let Ok(osk) = osk {
+                if let Err(e) = srv.output_key(ptr, KeyOutputReason::Exchanged, &osk) {
+                    warn!(
+                        "Could not write the key of peer {} to its new key outputs: {e:?}",
+                        ptr.0
+                    );
+                }
+            }
+        }
+
+        RunningPeer {
+            ptr,
+            pk: self.pk,
+            psk: self.psk,
+        }
+    }
+}
+
+/// A peer added to the [AppServer] from the configuration
+#[derive(Debug)]
+struct RunningPeer {
+    /// Where the peer lives in the [AppServer]
+    ptr: AppPeerPtr,
+    /// The peer's public key; used to match peers across reloads
+    pk: SPk,
+    /// The pre-shared key the peer was added with
+    psk: Option<SymKey>,
+}
+
+impl RunningPeer {
+    /// Whether `psk` matches the pre-shared key this peer was added with
+    fn same_psk(&self, psk: &Option<SymKey>) -> bool {
+        match (&self.psk, psk) {
+            (None, None) => true,
+            (Some(a), Some(b)) => rosenpass_constant_time::memcmp(a.secret(), b.secret()),
+            _ => false,
+        }
+    }
+}
+
+/// What [ConfigReload] needs to do about a peer in the new configuration
+#[derive(Debug)]
+enum PeerChange {
+    /// Nothing changed
+    Keep(usize),
+    /// Endpoint, name, hooks or key outputs changed; the index refers to [ConfigReload::peers]
+    Update { old: usize, outputs_changed: bool },
+    /// Pre-shared key or protocol version changed; the peer needs to be re-created
+    Replace(usize),
+    /// The peer is new
+    Add,
+}
+
+/// Everything needed to apply a new configuration, prepared by [ConfigReload::prepare]
+struct ReloadPlan {
+    /// The new configuration
+    config: config::Rosenpass,
+    /// One entry for every peer in [Self::config]
+    peers: Vec<(PeerChange, LoadedPeer)>,
+    /// Indices into [ConfigReload::peers] of peers missing from the new configuration
+    removed_peers: Vec<usize>,
+    /// Newly opened sockets for addresses added to the listen list
+    new_sockets: Vec<(SocketAddr, mio::net::UdpSocket)>,
+    /// Local addresses of the sockets for addresses removed from the listen list
+    removed_sockets: Vec<SocketAddr>,
+}
+
+/// What changed during [AppServer::reload_config]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ReloadSummary {
+    /// Peers that were not configured before
+    pub peers_added: usize,
+    /// Peers that are not configured anymore
+    pub peers_removed: usize,
+    /// Peers whose endpoint or key outputs changed; their sessions were kept
+    pub peers_updated: usize,
+    /// Peers whose pre-shared key or protocol version changed; their sessions were dropped
+    pub peers_replaced: usize,
+    /// Peers left as they were
+    pub peers_unchanged: usize,
+    /// Listen sockets opened
+    pub listen_added: usize,
+    /// Listen sockets closed
+    pub listen_removed: usize,
+}
+
+impl fmt::Display for ReloadSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "peers: {} added, {} removed, {} updated, {} replaced, {} unchanged; \
+            listen sockets: {} added, {} removed",
+            self.peers_added,
+            self.peers_removed,
+            self.peers_updated,
+            self.peers_replaced,
+            self.peers_unchanged,
+            self.listen_added,
+            self.listen_removed
+        )
+    }
+}
+
+/// Errors raised by [AppServer::reload_config]
+#[derive(thiserror::Error, Debug)]
+pub enum ReloadError {
+    /// The server was not configured through a configuration file
+    #[error("The server was not started from a configuration file; there is nothing to reload")]
+    NotSupported,
+    /// The new configuration could not be loaded; the server is unchanged
+    #[error("Invalid configuration, keeping the running configuration: {0:#}")]
+    InvalidConfig(anyhow::Error),
+    /// Applying the new configuration failed midway
+    #[error(
+        "Failed to apply the new configuration; the server may be partially reconfigured: {0:#}"
+    )]
+    Apply(anyhow::Error),
+}
+
+/// The configuration a running [AppServer] was set up from; see the [module documentation](self)
+#[derive(Debug)]
+pub struct ConfigReload {
+    /// The configuration currently in effect
+    config: config::Rosenpass,
+    /// The broker used for peers with a WireGuard configuration
+    broker: BrokerStorePtr,
+    /// `peers[i]` was created from `config.peers[i]`
+    peers: Vec<RunningPeer>,
+    /// `listen[i]` is the local address of the socket opened for `config.listen[i]`
+    listen: Vec<SocketAddr>,
+    /// Number of steps [Self::commit] may take before failing; see [Self::fail_after_steps]
+    #[cfg(feature = "internal_testing")]
+    steps_until_failure: Option<usize>,
+}
+
+impl ConfigReload {
+    /// Add the peers from `config` to `srv` and remember the configuration for later reloads
+    ///
+    /// `srv` must have been created with [config::Rosenpass::listen] as listen addresses.
+    pub fn apply(
+        srv: &mut AppServer,
+        config: config::Rosenpass,
+        broker: BrokerStorePtr,
+    ) -> anyhow::Result<Self> {
+        let listen = srv.sockets[..config.listen.len()]
+            .iter()
+            .map(|sock| sock.local_addr())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut peers = Vec::with_capacity(config.peers.len());
+        for cfg_peer in config.peers.iter() {
+            peers.push(LoadedPeer::load(cfg_peer, &broker)?.add_to(srv)?);
+        }
+
+        Ok(Self {
+            config,
+            broker,
+            peers,
+            listen,
+            #[cfg(feature = "internal_testing")]
+            steps_until_failure: None,
+        })
+    }
+
+    /// Make the next reload fail after applying `steps` changes to the server, as if the server
+    /// had refused the next one
+    #[cfg(feature = "internal_testing")]
+    pub fn fail_after_steps(&mut self, steps: usize) {
+        self.steps_until_failure = Some(steps);
+    }
+
+    /// Called by [Self::commit] before each change to the server
+    fn step(&mut self) -> anyhow::Result<()> {
+        #[cfg(feature = "internal_testing")]
+        if let Some(steps) = self.steps_until_failure.as_mut() {
+            ensure!(*steps > 0, "Failure injected through fail_after_steps");
+            *steps -= 1;
+        }
+        Ok(())
+    }
+
+    /// Index into [Self::peers] of the peer `ptr`
+    fn tracked_peer(&self, ptr: AppPeerPtr) -> anyhow::Result<usize> {
+        self.peers
+            .iter()
+            .position(|p| p.ptr.0 == ptr.0)
+            .with_context(|| format!("Peer {} is not part of the configuration", ptr.0))
+    }
+
+    /// Stop tracking the peer `ptr`, which was removed from the server by other means, e.g.
+    /// through the API
+    ///
+    /// If the peer is still in the configuration file, the next reload adds it again.
+    pub fn forget_peer(&mut self, ptr: AppPeerPtr) {
+        if let Some(i) = self.peers.iter().position(|p| p.ptr.0 == ptr.0) {
+            self.peers.remove(i);
+            self.config.peers.remove(i);
+        }
+    }
+
+    /// Re-read the configuration file and apply it to `srv`
+    pub fn reload(&mut self, srv: &mut AppServer) -> Result<ReloadSummary, ReloadError> {
+        if self.config.config_file_path.as_os_str().is_empty() {
+            return Err(ReloadError::NotSupported);
+        }
+
+        let plan = self.prepare(srv).map_err(ReloadError::InvalidConfig)?;
+        self.commit(srv, plan).map_err(ReloadError::Apply)
+    }
+
+    /// Load the new configuration and work out what needs to change, without modifying `srv`
+    fn prepare(&self, srv: &AppServer) -> anyhow::Result<ReloadPlan> {
+        let mut config = config::Rosenpass::load(&self.config.config_file_path)?;
+        config.validate()?;
+
+        ensure!(
+            config.keypair == self.config.keypair,
+            "Changing the server keypair requires a restart"
+        );
+        #[cfg(feature = "experiment_api")]
+        {
+            // The running API configuration may contain command line overrides; keep it as is
+            if config.api != self.config.api {
+                warn!("The API configuration differs from the running one; changes to it take effect after a restart");
+            }
+            config.api = self.config.api.clone();
+        }
+        if config.metrics != self.config.metrics {
+            warn!("Changes to the metrics configuration take effect after a restart");
+            config.metrics.clone_from(&self.config.metrics);
+        }
+        if (&config.user, &config.group) != (&self.config.user, &self.config.group) {
+            warn!("Changes to the user and group take effect after a restart");
+            config.user.clone_from(&self.config.user);
+            config.group.clone_from(&self.config.group);
+        }
+        if config.seccomp != self.config.seccomp {
+            warn!("Changes to the seccomp mode take effect after a restart");
+            config.seccomp = self.config.seccomp;
+        }
+        ensure!(
+            !(config.has_hooks() && crate::seccomp::blocks_processes()),
+            "The seccomp filter keeps hooks from running; adding hooks requires a restart"
+        );
+        if config.landlock != self.config.landlock {
+            warn!("Changes to the landlock option take effect after a restart");
+            config.landlock = self.config.landlock;
+        }
+        if config.key_oracle != self.config.key_oracle {
+            warn!("Changes to the key oracle take effect after a restart");
+            config.key_oracle = self.config.key_oracle.clone();
+        }
+        // Like the API configuration, the log format may come from the command line
+        if config.log_format != self.config.log_format {
+            warn!("The log format differs from the running one; changes to it take effect after a restart");
+            config.log_format = self.config.log_format;
+        }
+
+        let mut loaded = Vec::with_capacity(config.peers.len());
+        for (i, cfg_peer) in config.peers.iter().enumerate() {
+            let peer = LoadedPeer::load(cfg_peer, &self.broker)
+                .with_context(|| format!("Could not load peer {i}"))?;
+            ensure!(
+                !loaded.iter().any(|p: &LoadedPeer| p.pk == peer.pk),
+                "Peer {i} uses the same public key as another peer"
+            );
+            loaded.push(peer);
+        }
+
+        let mut still_configured = vec![false; self.peers.len()];
+        let mut peers = Vec::with_capacity(loaded.len());
+        for (cfg_peer, peer) in config.peers.iter().zip(loaded) {
+            let change = match self.peers.iter().position(|p| p.pk == peer.pk) {
+                None => PeerChange::Add,
+                Some(old) => {
+                    still_configured[old] = true;
+                    let old_cfg = &self.config.peers[old];
+                    let outputs_changed = old_cfg.key_out != cfg_peer.key_out
+                        || old_cfg.key_out_previous != cfg_peer.key_out_previous
+                        || old_cfg.key_out_format != cfg_peer.key_out_format
+                        || old_cfg.key_sinks != cfg_peer.key_sinks
+                        || old_cfg.wg != cfg_peer.wg;
+                    if !self.peers[old].same_psk(&peer.psk)
+                        || old_cfg.protocol_version != cfg_peer.protocol_version
+                    {
+                        PeerChange::Replace(old)
+                    } else if outputs_changed
+                        || old_cfg.endpoint != cfg_peer.endpoint
+                        || old_cfg.name != cfg_peer.name
+                        || old_cfg.hooks != cfg_peer.hooks
+                    {
+                        PeerChange::Update {
+                            old,
+                            outputs_changed,
+                        }
+                    } else {
+                        PeerChange::Keep(old)
+                    }
+                }
+            };
+            peers.push((change, peer));
+        }
+
+        let removed_peers: Vec<usize> = (0..self.peers.len())
+            .filter(|&i| !still_configured[i])
+            .collect();
+
+        let must_remove = !removed_peers.is_empty()
+            || peers
+                .iter()
+                .any(|(change, _)| matches!(change, PeerChange::Replace(_)));
+        if must_remove && !srv.crypto_site.is_available() {
+            bail!("Peers can only be removed or changed once the server keypair is known");
+        }
+
+        // What adding and removing peers in the server would reject
+        if let Some(crypto) = srv.crypto_site.product_ref() {
+            let replaced = peers.iter().filter_map(|(change, _)| match change {
+                PeerChange::Replace(old) => Some(*old),
+                _ => None,
+            });
+            for old in removed_peers.iter().copied().chain(replaced) {
+                ensure!(
+                    !self.peers[old].ptr.lower().get(crypto).removed,
+                    "Peer {} of the running configuration was removed by other means",
+                    self.peers[old].ptr.0
+                );
+            }
+
+            let untracked = srv
+                .live_peers()
+                .into_iter()
+                .filter(|ptr| !self.peers.iter().any(|p| p.ptr.0 == ptr.0))
+                .map(|ptr| &ptr.lower().get(crypto).spkt)
+                .collect::<Vec<_>>();
+            for (i, (change, peer)) in peers.iter().enumerate() {
+                ensure!(
+                    !matches!(change, PeerChange::Add) || !untracked.contains(&&peer.pk),
+                    "Peer {i} uses the same public key as a peer added through the API"
+                );
+            }
+        }
+
+        let mut new_sockets = Vec::new();
+        for addr in config.listen.iter() {
+            if !self.config.listen.contains(addr) {
+                let sock = mio::net::UdpSocket::bind(*addr)
+                    .with_context(|| format!("Could not listen on {addr}"))?;
+                config
+                    .listen_options
+                    .apply(&sock)
+                    .with_context(|| format!("Could not set up listen socket {addr}"))?;
+                new_sockets.push((*addr, sock));
+            }
+        }
+
+        let removed_sockets: Vec<SocketAddr> = self
+            .config
+            .listen
+            .iter()
+            .zip(self.listen.iter())
+            .filter(|(addr, _)| !config.listen.contains(addr))
+            .map(|(_, local)| *local)
+            .collect();
+        for local in removed_sockets.iter() {
+            ensure!(
+                srv.sockets
+                    .iter()
+                    .any(|sock| sock.local_addr().ok() == Some(*local)),
+                "No listen socket bound to {local}"
+            );
+        }
+
+        ensure!(
+            srv.sockets.len() + new_sockets.len() > removed_sockets.len(),
+            "The new configuration leaves no socket to listen on"
+        );
+
+        Ok(ReloadPlan {
+            config,
+            peers,
+            removed_peers,
+            new_sockets,
+            removed_sockets,
+        })
+    }
+
+    /// Apply a plan produced by [Self::prepare]
+    ///
+    /// [Self::peers], [Self::listen] and [Self::config] are updated after every change to `srv`,
+    /// so they describe the running server even if a change fails.
+    fn commit(&mut self, srv: &mut AppServer, plan: ReloadPlan) -> anyhow::Result<ReloadSummary> {
+        let ReloadPlan {
+            mut config,
+            peers,
+            removed_peers,
+            new_sockets,
+            removed_sockets,
+        } = plan;
+        let mut summary = ReloadSummary::default();
+
+        // The indices in the plan refer to the peers before any of them are removed
+        let old_ptrs = self.peers.iter().map(|p| p.ptr).collect::<Vec<_>>();
+
+        // Remove old peers first so their replacements can be added under the same public key
+        let replaced = peers.iter().filter_map(|(change, _)| match change {
+            PeerChange::Replace(old) => Some(*old),
+            _ => None,
+        });
+        let remove = removed_peers
+            .iter()
+            .copied()
+            .chain(replaced)
+            .map(|old| old_ptrs[old])
+            .collect::<Vec<_>>();
+        for ptr in remove {
+            self.step()?;
+            srv.remove_peer(ptr)?;
+            self.forget_peer(ptr);
+        }
+        summary.peers_removed = removed_peers.len();
+
+        for ((change, peer), cfg_peer) in peers.into_iter().zip(config.peers.iter()) {
+            self.step()?;
+            match change {
+                PeerChange::Keep(old) => {
+                    summary.peers_unchanged += 1;
+                    let idx = self.tracked_peer(old_ptrs[old])?;
+                    self.config.peers[idx] = cfg_peer.clone();
+                }
+                PeerChange::Update {
+                    old,
+                    outputs_changed,
+                } => {
+                    summary.peers_updated += 1;
+                    let idx = self.tracked_peer(old_ptrs[old])?;
+                    self.peers[idx] = peer.update(srv, old_ptrs[old], outputs_changed);
+                    self.config.peers[idx] = cfg_peer.clone();
+                }
+                PeerChange::Replace(_) => {
+                    summary.peers_replaced += 1;
+                    self.peers.push(peer.add_to(srv)?);
+                    self.config.peers.push(cfg_peer.clone());
+                }
+                PeerChange::Add => {
+                    summary.peers_added += 1;
+                    self.peers.push(peer.add_to(srv)?);
+                    self.config.peers.push(cfg_peer.clone());
+                }
+            }
+        }
+
+        // Before registering new sockets, which get the current listen options applied
+        if config.listen_options != srv.listen_options {
+            self.step()?;
+            srv.set_listen_options(config.listen_options.clone())?;
+            self.config.listen_options = config.listen_options.clone();
+        }
+
+        for (addr, sock) in new_sockets {
+            self.step()?;
+            let local = sock.local_addr()?;
+            srv.register_listen_socket(sock)?;
+            self.config.listen.push(addr);
+            self.listen.push(local);
+            summary.listen_added += 1;
+        }
+
+        for local in removed_sockets {
+            self.step()?;
+            let idx = srv
+                .sockets
+                .iter()
+                .position(|sock| sock.local_addr().ok() == Some(local))
+                .with_context(|| format!("No listen socket bound to {local}"))?;
+            srv.remove_listen_socket(idx)?;
+            let i = self
+                .listen
+                .iter()
+                .position(|l| *l == local)
+                .expect("taken from self.listen");
+            self.listen.remove(i);
+            self.config.listen.remove(i);
+            summary.listen_removed += 1;
+        }
+
+        srv.verbosity = config.verbosity;
+        srv.on_shutdown = config.on_shutdown;
+        srv.hooks = config.hooks.clone();
+
+        // Peers and listen addresses were taken over one by one above
+        config.peers = std::mem::take(&mut self.config.peers);
+        config.listen = std::mem::take(&mut self.config.listen);
+        self.config = config;
+
+        Ok(summary)
+    }
+}
+
+impl AppServer {
+    /// Re-read the configuration file and apply the changes to the running server
+    ///
+    /// See the [module documentation](self) for details.
+    pub fn reload_config(&mut self) -> Result<ReloadSummary, ReloadError> {
+        let mut reload = self.config_reload.take().ok_or(ReloadError::NotSupported)?;
+        let res = reload.reload(self);
+        self.config_reload = Some(reload);
+        res
+    }
+
+    /// Reload the configuration, logging the outcome; used when SIGHUP is received
+    pub fn reload_config_and_log(&mut self) {
+        info!("Reloading the configuration file");
+        match self.reload_config() {
+            Ok(summary) => info!("Configuration reloaded; {summary}"),
+            Err(e) => error!("{e}"),
+        }
+    }
+}
@@ -0,0 +1,326 @@
+//! Looking up the host names of peer endpoints again while the server runs
+//!
+//! The `endpoint` of a peer may be a host name; it is resolved once when the peer is added (see
+//! [HostPathDiscoveryEndpoint::lookup]), or right afterwards in the background for peers added
+//! through the API (see [AppServer::resolve_endpoint]). Peers with dynamic IP addresses would
+//! become unreachable once their address changes, so the host name is looked up again
+//!
+//! - every [RESOLVE_INTERVAL] and
+//! - after [RETRANSMISSIONS_BEFORE_RESOLVE] retransmissions to the peer without a completed
+//!   handshake, but at most once per [MIN_RESOLVE_INTERVAL].
+//!
+//! Lookups run on background threads, so a slow resolver does not hold up the event loop. When
+//! the addresses changed, they replace the ones in [AppPeer::initial_endpoint] and the change is
+//! logged; if the peer is currently being contacted through addresses that are no longer among
+//! them, the new addresses are merged into the set [HostPathDiscoveryEndpoint::send_scouting]
+//! tries.
+//!
+//! [AppPeer::initial_endpoint]: crate::app_server::AppPeer::initial_endpoint
+//! [HostPathDiscoveryEndpoint::lookup]: crate::app_server::HostPathDiscoveryEndpoint::lookup
+//! [HostPathDiscoveryEndpoint::send_scouting]: crate::app_server::HostPathDiscoveryEndpoint::send_scouting
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+
+use crate::app_server::{AppPeerPtr, AppServer, Endpoint};
+
+/// How often host names are looked up again
+pub const RESOLVE_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Minimum time between two lookups of the same host name
+pub const MIN_RESOLVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Number of retransmissions after which the host name of a peer is looked up early
+pub const RETRANSMISSIONS_BEFORE_RESOLVE: u32 = 3;
+
+/// How often to check whether running lookups have finished
+const PENDING_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What [Resolver] keeps track of for every peer with a host name
+#[derive(Debug)]
+struct PeerState {
+    /// When the host name was last looked up
+    last_lookup: Instant,
+    /// Retransmissions since the last handshake or lookup
+    retransmissions: u32,
+    /// Whether a lookup is running
+    pending: bool,
+}
+
+impl PeerState {
+    /// When the host name should be looked up next
+    fn due(&self) -> Instant {
+        match self.retransmissions >= RETRANSMISSIONS_BEFORE_RESOLVE {
+            true => self.last_lookup + MIN_RESOLVE_INTERVAL,
+            false => self.last_lookup + RESOLVE_INTERVAL,
+        }
+    }
+}
+
+/// The result of a lookup running in the background
+#[derive(Debug)]
+struct Lookup {
+    peer: AppPeerPtr,
+    hostname: String,
+    result: io::Result<Vec<SocketAddr>>,
+}
+
+/// Schedules host name lookups for peer endpoints and runs them in the background
+///
+/// Part of [AppServer] (see [AppServer::resolver]).
+#[derive(Debug)]
+pub struct Resolver {
+    peers: HashMap<usize, PeerState>,
+    results_tx: mpsc::Sender<Lookup>,
+    results_rx: mpsc::Receiver<Lookup>,
+    /// When to check again whether a lookup is due
+    next_check: Instant,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        let (results_tx, results_rx) = mpsc::channel();
+        Self {
+            peers: HashMap::new(),
+            results_tx,
+            results_rx,
+            next_check: Instant::now(),
+        }
+    }
+}
+
+impl Resolver {
+    /// The state of `peer`; peers seen for the first time were looked up when they were added
+    fn state(&mut self, peer: AppPeerPtr) -> &mut PeerState {
+        self.peers.entry(peer.0).or_insert_with(|| PeerState {
+            last_lookup: Instant::now(),
+            retransmissions: 0,
+            pending: false,
+        })
+    }
+
+    /// Number of lookups that have not finished yet
+    pub fn pending(&self) -> usize {
+        self.peers.values().filter(|state| state.pending).count()
+    }
+
+    /// Record that a handshake message to `peer` had to be retransmitted
+    pub fn note_retransmission(&mut self, peer: AppPeerPtr) {
+        let state = self.state(peer);
+        state.retransmissions += 1;
+        if state.retransmissions >= RETRANSMISSIONS_BEFORE_RESOLVE {
+            let due = state.due();
+            self.next_check = self.next_check.min(due);
+        }
+    }
+
+    /// Record that a handshake with `peer` completed
+    pub fn note_exchange(&mut self, peer: AppPeerPtr) {
+        if let Some(state) = self.peers.get_mut(&peer.0) {
+            state.retransmissions = 0;
+        }
+    }
+
+    /// Look up `hostname` for `peer` on a background thread
+    fn start(&mut self, peer: AppPeerPtr, hostname: String) {
+        let results_tx = self.results_tx.clone();
+        let spawned = thread::Builder::new()
+            .name("rosenpass-resolve".to_owned())
+            .spawn(move || {
+                let result = hostname.to_socket_addrs().map(Iterator::collect);
+                // The server may be gone already; then nobody cares about the result
+                let _ = results_tx.send(Lookup {
+                    peer,
+                    hostname,
+                    result,
+                });
+            });
+
+        let state = self.state(peer);
+        state.last_lookup = Instant::now();
+        state.retransmissions = 0;
+        match spawned {
+            Ok(_) => state.pending = true,
+            Err(e) => warn!("Could not start host name lookup for peer {}: {e}", peer.0),
+        }
+    }
+}
+
+impl AppServer {
+    /// Look up the host name of the endpoint of `peer` in the background right away, if it has one
+    pub fn resolve_endpoint(&mut self, peer: AppPeerPtr) {
+        let Some(Endpoint::Discovery(ep)) = peer.get_app(self).initial_endpoint.as_ref() else {
+            return;
+        };
+        let Some(hostname) = ep.hostname().map(str::to_owned) else {
+            return;
+        };
+        self.resolver.start(peer, hostname);
+    }
+
+    /// Start the host name lookups that are due and apply the results of finished ones; see
+    /// [crate::resolve]
+    ///
+    /// Returns how long it is until this should be called again.
+    pub fn resolve_endpoints_if_due(&mut self) -> Option<Duration> {
+        while let Ok(lookup) = self.resolver.results_rx.try_recv() {
+            self.apply_lookup(lookup);
+        }
+
+        let now = Instant::now();
+        if now >= self.resolver.next_check {
+            let mut next_check = now + RESOLVE_INTERVAL;
+            for no in 0..self.peers.len() {
+                let peer = AppPeerPtr(no);
+                let Some(Endpoint::Discovery(ep)) = peer.get_app(self).initial_endpoint.as_ref()
+                else {
+                    continue;
+                };
+                let Some(hostname) = ep.hostname().map(str::to_owned) else {
+                    continue;
+                };
+
+                let state = self.resolver.state(peer);
+                if state.pending {
+                    continue;
+                }
+                let due = state.due();
+                if due <= now {
+                    self.resolver.start(peer, hostname);
+                } else {
+                    next_check = next_check.min(due);
+                }
+            }
+            self.resolver.next_check = next_check;
+        }
+
+        let due = self.resolver.next_check.saturating_duration_since(now);
+        match self.resolver.pending() {
+            0 => Some(due),
+            _ => Some(due.min(PENDING_CHECK_INTERVAL)),
+        }
+    }
+
+    /// Update the endpoints of a peer with the addresses a lookup found
+    fn apply_lookup(&mut self, lookup: Lookup) {
+        let Lookup {
+            peer,
+            hostname,
+            result,
+        } = lookup;
+        self.resolver.state(peer).pending = false;
+        let peer_id = self
+            .peer_log_id(peer)
+            .unwrap_or_else(|_| peer.0.to_string());
+
+        let addresses = match result {
+            Ok(addresses) if !addresses.is_empty() => addresses,
+            Ok(_) => {
+                warn!(
+                    peer = peer_id;
+                    "Host name {hostname} of peer {peer_id} resolved to no addresses; \
+                     keeping the previous ones"
+                );
+                return;
+            }
+            Err(e) => {
+                warn!(
+                    peer = peer_id;
+                    "Could not resolve host name {hostname} of peer {peer_id}: {e}"
+                );
+                return;
+            }
+        };
+
+        let ap = peer.get_app_mut(self);
+        // The endpoint may have been changed (e.g. by a reload) while the lookup ran
+        let Some(Endpoint::Discovery(ep)) = ap.initial_endpoint.as_mut() else {
+            return;
+        };
+        if ep.hostname() != Some(hostname.as_str()) {
+            return;
+        }
+
+        let old = ep.addresses().iter().copied().collect::<HashSet<_>>();
+        let new = addresses.iter().copied().collect::<HashSet<_>>();
+        if old == new {
+            return;
+        }
+        let added = addresses
+            .iter()
+            .filter(|a| !old.contains(a))
+            .collect::<Vec<_>>();
+        let removed = ep
+            .addresses()
+            .iter()
+            .filter(|a| !new.contains(a))
+            .collect::<Vec<_>>();
+        info!(
+            peer = peer_id;
+            "Addresses of {hostname} (peer {peer_id}) changed; added {added:?}, removed {removed:?}"
+        );
+        ep.set_addresses(addresses);
+
+        // Keep contacting the peer where it was last heard from, unless that address is gone
+        let current_is_stale = ap
+            .current_endpoint
+            .as_ref()
+            .is_some_and(|cur| cur.addresses().iter().any(|a| !new.contains(a)));
+        if current_is_stale {
+            ap.current_endpoint = Endpoint::discovery_from_multiple_sources(
+                ap.initial_endpoint.as_ref(),
+                ap.current_endpoint.as_ref(),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookups_are_scheduled_by_retransmissions() {
+        let mut resolver = Resolver::default();
+        let peer = AppPeerPtr(0);
+
+        resolver.note_retransmission(peer);
+        let due = resolver.state(peer).due();
+        assert!(due > Instant::now() + MIN_RESOLVE_INTERVAL);
+
+        for _ in 1..RETRANSMISSIONS_BEFORE_RESOLVE {
+            resolver.note_retransmission(peer);
+        }
+        let early = resolver.state(peer).due();
+        assert!(early < due);
+        assert!(resolver.next_check <= early);
+
+        resolver.note_exchange(peer);
+        assert_eq!(resolver.state(peer).due(), due);
+    }
+
+    #[test]
+    fn lookups_run_in_the_background() {
+        let mut resolver = Resolver::default();
+        let peer = AppPeerPtr(3);
+        resolver.start(peer, "127.0.0.1:9999".to_owned());
+        assert_eq!(resolver.pending(), 1);
+
+        let lookup = resolver
+            .results_rx
+            .recv_timeout(Duration::from_secs(10))
+            .unwrap();
+        assert_eq!(lookup.peer.0, 3);
+        assert_eq!(lookup.hostname, "127.0.0.1:9999");
+        assert_eq!(
+            lookup.result.unwrap(),
+            vec!["127.0.0.1:9999".parse::<SocketAddr>().unwrap()]
+        );
+    }
+}
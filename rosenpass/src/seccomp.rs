@@ -0,0 +1,491 @@
+//! Restricting the system calls the server may make
+//!
+//! The server parses untrusted network input while it holds the long-term secret key. With the
+//! `seccomp` option, the `exchange-config` command installs a seccomp-bpf filter once the server
+//! is set up and privileges are dropped (see [crate::privileges]), right before it enters the
+//! event loop. The filter allows the system calls the event loop, the secret memory allocator,
+//! key outputs, hooks, metrics, reloading and the API need; what happens on any other system call
+//! depends on the mode:
+//!
+//! - `"off"` – no filter is installed (the default)
+//! - `"log"` – the system call goes through, but the kernel logs it (`SECCOMP_RET_LOG`); look for
+//!   `type=1326` audit messages in the kernel log to find system calls missing from the filter
+//! - `"enforce"` – the process is killed (`SECCOMP_RET_KILL_PROCESS`)
+//!
+//! Starting programs (`execve`, `fork` and the like, and `kill` to stop them) is only allowed if
+//! hooks or the built-in WireGuard broker, which runs `wg`, are configured at startup; otherwise,
+//! `clone` may only create threads. Hooks cannot be added by reloading the configuration then.
+//! `prctl` is limited to the options the server and the C library use, and `socket` to Unix,
+//! IPv4, IPv6 and netlink sockets. `connect` remains allowed for the API, key sinks, brokers and
+//! host name lookups.
+//!
+//! The filter applies to all threads and is inherited by child processes, i.e. by `wg` when the
+//! built-in broker is used and by hooks. Try new setups in log mode first.
+//!
+//! Seccomp filters are supported on Linux on x86_64 and aarch64.
+//!
+//! # Examples
+//!
+//! ```toml
+//! seccomp = "enforce"
+//! ```
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+/// What the seccomp filter does with system calls it does not allow; see [crate::seccomp]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Copy, Clone, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SeccompMode {
+    /// Do not install a filter
+    #[default]
+    Off,
+    /// Let the system call through and have the kernel log it
+    Log,
+    /// Kill the process
+    Enforce,
+}
+
+impl SeccompMode {
+    /// Whether no filter is installed
+    pub fn is_off(&self) -> bool {
+        *self == Self::Off
+    }
+}
+
+/// Whether seccomp filters are supported on this platform
+pub const SUPPORTED: bool = cfg!(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+));
+
+/// Set once a filter was installed that kills the process when it starts a program
+static BLOCKS_PROCESSES: AtomicBool = AtomicBool::new(false);
+
+/// Whether the installed filter keeps the server from starting programs, such as hooks
+pub fn blocks_processes() -> bool {
+    BLOCKS_PROCESSES.load(Ordering::Relaxed)
+}
+
+/// Install the seccomp filter for `mode`; see [crate::seccomp]
+///
+/// `processes` tells whether the server starts programs, i.e. runs hooks or `wg`.
+///
+/// This cannot be undone. Any system call the process makes afterwards must be allowed by the
+/// filter, so this should be the last thing done before entering the event loop.
+pub fn install(mode: SeccompMode, processes: bool) -> anyhow::Result<()> {
+    if mode.is_off() {
+        return Ok(());
+    }
+    filter::install_for(mode, processes)?;
+    BLOCKS_PROCESSES.store(
+        mode == SeccompMode::Enforce && !processes,
+        Ordering::Relaxed,
+    );
+    log::info!("Installed seccomp filter in {mode:?} mode");
+    Ok(())
+}
+
+#[cfg(not(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+)))]
+mod filter {
+    pub(super) fn install_for(_mode: super::SeccompMode, _processes: bool) -> anyhow::Result<()> {
+        anyhow::bail!("Seccomp filters are not supported on this platform")
+    }
+}
+
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+mod filter {
+    use std::io;
+
+    use anyhow::{ensure, Context};
+
+    /// `SECCOMP_SET_MODE_FILTER`
+    const SET_MODE_FILTER: libc::c_ulong = 1;
+    /// `SECCOMP_FILTER_FLAG_TSYNC`, to apply the filter to all threads
+    const FILTER_FLAG_TSYNC: libc::c_ulong = 1;
+
+    /// `SECCOMP_RET_KILL_PROCESS`
+    const RET_KILL_PROCESS: u32 = 0x8000_0000;
+    /// `SECCOMP_RET_ERRNO`, to be combined with the error number
+    const RET_ERRNO: u32 = 0x0005_0000;
+    /// `SECCOMP_RET_LOG`
+    const RET_LOG: u32 = 0x7ffc_0000;
+    /// `SECCOMP_RET_ALLOW`
+    const RET_ALLOW: u32 = 0x7fff_0000;
+
+    /// `AUDIT_ARCH_*` of the architecture the filter is built for
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: u32 = 0xc000_003e;
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+    /// Offsets of `nr`, `arch` and the lower half of `args[0]` in `struct seccomp_data`; both
+    /// supported architectures are little endian
+    const DATA_NR: u32 = 0;
+    const DATA_ARCH: u32 = 4;
+    const DATA_ARG0: u32 = 16;
+
+    /// Classic BPF opcodes: `BPF_LD | BPF_W | BPF_ABS`, `BPF_JMP | BPF_JEQ | BPF_K`,
+    /// `BPF_JMP | BPF_JSET | BPF_K` and `BPF_RET | BPF_K`
+    const BPF_LD_W_ABS: u16 = 0x20;
+    const BPF_JMP_JEQ_K: u16 = 0x15;
+    const BPF_JMP_JSET_K: u16 = 0x45;
+    const BPF_RET_K: u16 = 0x06;
+
+    /// Most instructions a filter may have (`BPF_MAXINSNS`)
+    const MAX_INSTRUCTIONS: usize = 4096;
+
+    /// `memfd_secret(2)`, which has the same number on all supported architectures
+    const SYS_MEMFD_SECRET: libc::c_long = 447;
+
+    /// System calls the filter allows on all supported architectures
+    const ALLOWED: &[libc::c_long] = &[
+        // Files: key outputs, reloading, loading keys and PSKs
+        libc::SYS_read,
+        libc::SYS_write,
+        libc::SYS_readv,
+        libc::SYS_writev,
+        libc::SYS_pread64,
+        libc::SYS_pwrite64,
+        libc::SYS_openat,
+        libc::SYS_close,
+        libc::SYS_close_range,
+        libc::SYS_fstat,
+        libc::SYS_newfstatat,
+        libc::SYS_statx,
+        libc::SYS_lseek,
+        libc::SYS_fcntl,
+        libc::SYS_ioctl,
+        libc::SYS_flock,
+        libc::SYS_dup,
+        libc::SYS_dup3,
+        libc::SYS_pipe2,
+        libc::SYS_getdents64,
+        libc::SYS_readlinkat,
+        libc::SYS_faccessat,
+        libc::SYS_renameat,
+        libc::SYS_renameat2,
+        libc::SYS_unlinkat,
+        libc::SYS_mkdirat,
+        libc::SYS_fsync,
+        libc::SYS_fdatasync,
+        libc::SYS_ftruncate,
+        libc::SYS_fchmod,
+        libc::SYS_fchmodat,
+        libc::SYS_fchown,
+        libc::SYS_umask,
+        libc::SYS_getcwd,
+        // Memory, including the secret memory allocator
+        libc::SYS_brk,
+        libc::SYS_mmap,
+        libc::SYS_munmap,
+        libc::SYS_mremap,
+        libc::SYS_mprotect,
+        libc::SYS_madvise,
+        libc::SYS_mlock,
+        libc::SYS_munlock,
+        libc::SYS_memfd_create,
+        SYS_MEMFD_SECRET,
+        // The event loop
+        libc::SYS_epoll_create1,
+        libc::SYS_epoll_ctl,
+        libc::SYS_epoll_pwait,
+        libc::SYS_eventfd2,
+        libc::SYS_ppoll,
+        libc::SYS_pselect6,
+        // Networking: listen sockets, the API, metrics, brokers and host name lookups; `socket`
+        // is limited to the families in SOCKET_FAMILIES
+        libc::SYS_socketpair,
+        libc::SYS_bind,
+        libc::SYS_connect,
+        libc::SYS_listen,
+        libc::SYS_accept,
+        libc::SYS_accept4,
+        libc::SYS_sendto,
+        libc::SYS_recvfrom,
+        libc::SYS_sendmsg,
+        libc::SYS_recvmsg,
+        libc::SYS_sendmmsg,
+        libc::SYS_recvmmsg,
+        libc::SYS_getsockname,
+        libc::SYS_getpeername,
+        libc::SYS_setsockopt,
+        libc::SYS_getsockopt,
+        libc::SYS_shutdown,
+        // Time
+        libc::SYS_clock_gettime,
+        libc::SYS_clock_getres,
+        libc::SYS_clock_nanosleep,
+        libc::SYS_nanosleep,
+        libc::SYS_gettimeofday,
+        // Threads and signals
+        libc::SYS_futex,
+        libc::SYS_set_robust_list,
+        libc::SYS_rseq,
+        libc::SYS_set_tid_address,
+        libc::SYS_sched_yield,
+        libc::SYS_sched_getaffinity,
+        libc::SYS_rt_sigaction,
+        libc::SYS_rt_sigprocmask,
+        libc::SYS_rt_sigreturn,
+        libc::SYS_sigaltstack,
+        libc::SYS_restart_syscall,
+        libc::SYS_tgkill,
+        libc::SYS_exit,
+        libc::SYS_exit_group,
+        // Waiting for children, including spawned brokers, is always allowed; `prctl` is limited
+        // to PRCTL_OPTIONS, `clone` to threads unless PROCESSES are allowed
+        libc::SYS_wait4,
+        libc::SYS_waitid,
+        libc::SYS_prlimit64,
+        // Process information and randomness
+        libc::SYS_getpid,
+        libc::SYS_getppid,
+        libc::SYS_gettid,
+        libc::SYS_getuid,
+        libc::SYS_geteuid,
+        libc::SYS_getgid,
+        libc::SYS_getegid,
+        libc::SYS_getresuid,
+        libc::SYS_getresgid,
+        libc::SYS_getgroups,
+        libc::SYS_getrandom,
+        libc::SYS_uname,
+        libc::SYS_sysinfo,
+    ];
+
+    /// System calls for starting programs – hooks and `wg` – and stopping them
+    const PROCESSES: &[libc::c_long] = &[
+        libc::SYS_clone,
+        libc::SYS_clone3,
+        libc::SYS_execve,
+        libc::SYS_kill,
+        libc::SYS_pidfd_open,
+        libc::SYS_setsid,
+        libc::SYS_setpgid,
+    ];
+
+    /// The `prctl` options allowed: naming threads, as done when they are started, and naming
+    /// memory mappings, as recent C libraries do
+    const PRCTL_OPTIONS: &[u32] = &[
+        libc::PR_SET_NAME as u32,
+        libc::PR_GET_NAME as u32,
+        PR_SET_VMA,
+    ];
+
+    /// `PR_SET_VMA`, which the libc crate does not define for all targets
+    const PR_SET_VMA: u32 = 0x5356_4d41;
+
+    /// The address families sockets may be created for: Unix sockets for the API, key sinks
+    /// and brokers, IP for listen sockets and host name lookups, netlink for the latter
+    const SOCKET_FAMILIES: &[u32] = &[
+        libc::AF_UNIX as u32,
+        libc::AF_INET as u32,
+        libc::AF_INET6 as u32,
+        libc::AF_NETLINK as u32,
+    ];
+
+    /// Legacy variants of the system calls above, which only x86_64 has
+    #[cfg(target_arch = "x86_64")]
+    const ALLOWED_ARCH: &[libc::c_long] = &[
+        libc::SYS_open,
+        libc::SYS_stat,
+        libc::SYS_lstat,
+        libc::SYS_access,
+        libc::SYS_readlink,
+        libc::SYS_rename,
+        libc::SYS_unlink,
+        libc::SYS_mkdir,
+        libc::SYS_getdents,
+        libc::SYS_dup2,
+        libc::SYS_pipe,
+        libc::SYS_poll,
+        libc::SYS_select,
+        libc::SYS_epoll_wait,
+        libc::SYS_getrlimit,
+        libc::SYS_arch_prctl,
+    ];
+    #[cfg(target_arch = "aarch64")]
+    const ALLOWED_ARCH: &[libc::c_long] = &[];
+
+    /// Legacy variants of [PROCESSES], which only x86_64 has
+    #[cfg(target_arch = "x86_64")]
+    const PROCESSES_ARCH: &[libc::c_long] = &[libc::SYS_fork, libc::SYS_vfork];
+    #[cfg(target_arch = "aarch64")]
+    const PROCESSES_ARCH: &[libc::c_long] = &[];
+
+    fn insn(code: u16, jt: u8, jf: u8, k: u32) -> libc::sock_filter {
+        libc::sock_filter { code, jt, jf, k }
+    }
+
+    /// Append instructions returning `action` for the system call `nr`
+    ///
+    /// Expects the system call number to be loaded, and leaves it loaded.
+    fn push_nr(prog: &mut Vec<libc::sock_filter>, nr: libc::c_long, action: u32) {
+        prog.push(insn(BPF_JMP_JEQ_K, 0, 1, nr as u32));
+        prog.push(insn(BPF_RET_K, 0, 0, action));
+    }
+
+    /// Append instructions allowing the system call `nr` if the lower half of its first
+    /// argument is one of `values`, and applying `default_action` otherwise
+    ///
+    /// Expects the system call number to be loaded, and leaves it loaded for other system calls.
+    fn push_arg_values(
+        prog: &mut Vec<libc::sock_filter>,
+        nr: libc::c_long,
+        values: &[u32],
+        default_action: u32,
+    ) {
+        prog.push(insn(
+            BPF_JMP_JEQ_K,
+            0,
+            2 + 2 * values.len() as u8,
+            nr as u32,
+        ));
+        prog.push(insn(BPF_LD_W_ABS, 0, 0, DATA_ARG0));
+        for &value in values {
+            prog.push(insn(BPF_JMP_JEQ_K, 0, 1, value));
+            prog.push(insn(BPF_RET_K, 0, 0, RET_ALLOW));
+        }
+        prog.push(insn(BPF_RET_K, 0, 0, default_action));
+    }
+
+    /// Build a filter that allows [ALLOWED] and [ALLOWED_ARCH], as well as [PROCESSES] and
+    /// [PROCESSES_ARCH] if `processes` is set, and applies `default_action` to all other system
+    /// calls
+    ///
+    /// System calls made through another ABI (e.g. 32 bit x86 on x86_64) kill the process.
+    fn build(default_action: u32, processes: bool) -> Vec<libc::sock_filter> {
+        let mut prog = vec![
+            insn(BPF_LD_W_ABS, 0, 0, DATA_ARCH),
+            insn(BPF_JMP_JEQ_K, 1, 0, AUDIT_ARCH),
+            insn(BPF_RET_K, 0, 0, RET_KILL_PROCESS),
+            insn(BPF_LD_W_ABS, 0, 0, DATA_NR),
+        ];
+        for &nr in ALLOWED.iter().chain(ALLOWED_ARCH) {
+            push_nr(&mut prog, nr, RET_ALLOW);
+        }
+
+        if processes {
+            for &nr in PROCESSES.iter().chain(PROCESSES_ARCH) {
+                push_nr(&mut prog, nr, RET_ALLOW);
+            }
+        } else {
+            // The arguments of clone3 cannot be inspected; the C library falls back to clone
+            push_nr(&mut prog, libc::SYS_clone3, RET_ERRNO | libc::ENOSYS as u32);
+            // clone, only for threads
+            prog.push(insn(BPF_JMP_JEQ_K, 0, 4, libc::SYS_clone as u32));
+            prog.push(insn(BPF_LD_W_ABS, 0, 0, DATA_ARG0));
+            prog.push(insn(BPF_JMP_JSET_K, 0, 1, libc::CLONE_THREAD as u32));
+            prog.push(insn(BPF_RET_K, 0, 0, RET_ALLOW));
+            prog.push(insn(BPF_RET_K, 0, 0, default_action));
+        }
+
+        push_arg_values(&mut prog, libc::SYS_prctl, PRCTL_OPTIONS, default_action);
+        push_arg_values(&mut prog, libc::SYS_socket, SOCKET_FAMILIES, default_action);
+        prog.push(insn(BPF_RET_K, 0, 0, default_action));
+        prog
+    }
+
+    /// Install the filter for `mode` for all threads of the process
+    pub(super) fn install_for(mode: super::SeccompMode, processes: bool) -> anyhow::Result<()> {
+        let default_action = match mode {
+            super::SeccompMode::Log => RET_LOG,
+            _ => RET_KILL_PROCESS,
+        };
+        install(&build(default_action, processes))
+    }
+
+    /// Install `prog` for all threads of the process
+    fn install(prog: &[libc::sock_filter]) -> anyhow::Result<()> {
+        ensure!(prog.len() <= MAX_INSTRUCTIONS, "Seccomp filter is too long");
+        let fprog = libc::sock_fprog {
+            len: prog.len() as libc::c_ushort,
+            filter: prog.as_ptr() as *mut libc::sock_filter,
+        };
+
+        // Safety: plain system call without pointers; required to install a filter without
+        // CAP_SYS_ADMIN, and keeps programs started later from gaining privileges
+        let rc = unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1 as libc::c_ulong, 0, 0, 0) };
+        if rc != 0 {
+            return Err(io::Error::last_os_error()).context("Could not set no_new_privs");
+        }
+
+        // Safety: fprog points to prog, which outlives the call; the kernel copies the filter
+        let rc = unsafe {
+            libc::syscall(
+                libc::SYS_seccomp,
+                SET_MODE_FILTER,
+                FILTER_FLAG_TSYNC,
+                &fprog as *const libc::sock_fprog,
+            )
+        };
+        match rc {
+            0 => Ok(()),
+            // With TSYNC, a positive value is the id of a thread the filter could not be applied to
+            rc if rc > 0 => anyhow::bail!(
+                "Could not install seccomp filter: thread {rc} is in an incompatible state"
+            ),
+            _ => Err(io::Error::last_os_error()).context("Could not install seccomp filter"),
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        /// The system calls `prog` allows unconditionally
+        fn allowed(prog: &[libc::sock_filter]) -> Vec<libc::c_long> {
+            // Every such system call is compared right after loading the number, or after
+            // the previous such comparison, and followed by the instruction allowing it
+            let mut nr_loaded = false;
+            let mut allowed = Vec::new();
+            for w in prog.windows(2) {
+                if (w[0].code, w[0].k) == (BPF_LD_W_ABS, DATA_NR) {
+                    nr_loaded = true;
+                } else if w[0].code == BPF_LD_W_ABS {
+                    nr_loaded = false;
+                } else if nr_loaded && w[0].code == BPF_JMP_JEQ_K && w[1].k == RET_ALLOW {
+                    allowed.push(w[0].k as libc::c_long);
+                }
+            }
+            allowed
+        }
+
+        #[test]
+        fn filter_checks_arch_and_ends_with_default_action() {
+            let prog = build(RET_LOG, false);
+            assert!(prog.len() <= MAX_INSTRUCTIONS);
+            assert_eq!(prog[1].k, AUDIT_ARCH);
+
+            let last = prog.last().unwrap();
+            assert_eq!((last.code, last.k), (BPF_RET_K, RET_LOG));
+
+            let allowed = allowed(&prog);
+            assert!(allowed.contains(&libc::SYS_recvmsg));
+            assert!(allowed.contains(&libc::SYS_epoll_pwait));
+            assert!(!allowed.contains(&libc::SYS_ptrace));
+            assert!(!allowed.contains(&libc::SYS_prctl));
+            assert!(!allowed.contains(&libc::SYS_socket));
+        }
+
+        #[test]
+        fn programs_can_only_be_started_if_needed() {
+            let without = allowed(&build(RET_KILL_PROCESS, false));
+            let with = allowed(&build(RET_KILL_PROCESS, true));
+            for nr in [libc::SYS_execve, libc::SYS_kill, libc::SYS_clone3] {
+                assert!(!without.contains(&nr));
+                assert!(with.contains(&nr));
+            }
+            assert!(!without.contains(&libc::SYS_mknodat));
+            assert!(!without.contains(&libc::SYS_chdir));
+        }
+    }
+}
@@ -0,0 +1,118 @@
+//! Handling of process signals for [AppServer](crate::app_server::AppServer)
+//!
+//! Signal handlers are very restricted in what they may do, so the handlers registered here
+//! just set a flag and write a byte into a socket pair. The read end of that socket pair is
+//! registered with the [mio] poll of the server (see [AppServer::enable_signal_handling](crate::app_server::AppServer::enable_signal_handling)),
+//! so a signal wakes the event loop up, which then checks the flags and does the actual work
+//! outside of signal context.
+
+use std::io::{self, ErrorKind, Read};
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+use signal_hook::SigId;
+
+/// Signal handlers registered for an [AppServer](crate::app_server::AppServer)
+///
+/// The handlers are unregistered when this struct is dropped.
+#[derive(Debug)]
+pub struct SignalHandler {
+    /// Set when SIGHUP was received; see [Self::take_reload_request]
+    reload: Arc<AtomicBool>,
+    /// Set when SIGTERM or SIGINT was received; see [Self::terminate_requested]
+    terminate: Arc<AtomicBool>,
+    /// Read end of the socket pair the signal handlers write to in order to wake
+    /// up the event loop
+    wakeup: mio::net::UnixStream,
+    /// The handlers registered with [signal_hook]
+    registered: Vec<SigId>,
+}
+
+impl SignalHandler {
+    /// Register the signal handlers
+    pub fn new() -> anyhow::Result<Self> {
+        let (rx, tx) = UnixStream::pair()?;
+        rx.set_nonblocking(true)?;
+        tx.set_nonblocking(true)?;
+
+        let mut handler = Self {
+            reload: Arc::new(AtomicBool::new(false)),
+            terminate: Arc::new(AtomicBool::new(false)),
+            wakeup: mio::net::UnixStream::from_std(rx),
+            registered: Vec::new(),
+        };
+
+        // Flag first, so the flag is guaranteed to be set when the event loop wakes up
+        handler
+            .registered
+            .push(signal_hook::flag::register(SIGHUP, handler.reload.clone())?);
+        handler
+            .registered
+            .push(signal_hook::low_level::pipe::register(
+                SIGHUP,
+                tx.try_clone()?,
+            )?);
+
+        for sig in [SIGTERM, SIGINT] {
+            // A second signal while we are still shutting down exits right away, so a stuck
+            // shutdown can still be interrupted
+            handler
+                .registered
+                .push(signal_hook::flag::register_conditional_shutdown(
+                    sig,
+                    1,
+                    handler.terminate.clone(),
+                )?);
+            handler
+                .registered
+                .push(signal_hook::flag::register(sig, handler.terminate.clone())?);
+            handler
+                .registered
+                .push(signal_hook::low_level::pipe::register(
+                    sig,
+                    tx.try_clone()?,
+                )?);
+        }
+
+        Ok(handler)
+    }
+
+    /// Register the wakeup socket with [mio]
+    pub fn register(&mut self, registry: &mio::Registry, token: mio::Token) -> io::Result<()> {
+        registry.register(&mut self.wakeup, token, mio::Interest::READABLE)
+    }
+
+    /// Read all pending wakeup notifications so the socket stops being reported as readable
+    pub fn drain(&mut self) -> io::Result<()> {
+        let mut buf = [0u8; 16];
+        loop {
+            match self.wakeup.read(&mut buf) {
+                Ok(0) => return Ok(()),
+                Ok(_) => continue,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Check whether a configuration reload was requested through SIGHUP since the last call
+    pub fn take_reload_request(&self) -> bool {
+        self.reload.swap(false, Ordering::Relaxed)
+    }
+
+    /// Check whether SIGTERM or SIGINT was received; unlike reload requests, this stays set
+    pub fn terminate_requested(&self) -> bool {
+        self.terminate.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for SignalHandler {
+    fn drop(&mut self) {
+        for id in self.registered.drain(..) {
+            signal_hook::low_level::unregister(id);
+        }
+    }
+}
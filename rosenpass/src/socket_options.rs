@@ -0,0 +1,330 @@
+//! Options for the UDP sockets rosenpass listens on
+//!
+//! In policy-routed setups, the handshake traffic of rosenpass usually must not go through the
+//! WireGuard tunnel whose keys it exchanges. The `[listen_options]` section of the configuration
+//! sets socket options on all listen sockets – the configured ones, the ones opened by default,
+//! socket-activated ones and those added through the API:
+//!
+//! - `bind_to_device` – send and receive only through this network device (`SO_BINDTODEVICE`)
+//! - `fwmark` – mark outgoing packets for policy routing (`SO_MARK`); needs `CAP_NET_ADMIN`
+//! - `dscp` – the differentiated services code point of outgoing packets (`IP_TOS`,
+//!   `IPV6_TCLASS`), 0 to 63
+//! - `recv_buffer`, `send_buffer` – socket buffer sizes in bytes; without `CAP_NET_ADMIN`, the
+//!   kernel limits them to `net.core.rmem_max` and `net.core.wmem_max`
+//!
+//! Binding to a device and setting a fwmark are only supported on Linux. Options that cannot be
+//! applied, e.g. for lack of privileges, make starting the server or reloading the configuration
+//! fail; validating the configuration only checks the values. When reloading removes the device,
+//! fwmark or DSCP, they are reset on the running sockets; buffer sizes stay as they are.
+//!
+//! # Examples
+//!
+//! ```toml
+//! [listen_options]
+//! bind_to_device = "eth0"
+//! fwmark = 0xca6c
+//! dscp = 46
+//! ```
+
+use std::io;
+use std::mem::size_of;
+use std::net::SocketAddr;
+use std::os::fd::{AsRawFd, RawFd};
+
+use anyhow::{ensure, Context};
+use mio::net::UdpSocket;
+use serde::{Deserialize, Serialize};
+
+/// Longest network device name Linux accepts (`IFNAMSIZ` without the terminating zero)
+const MAX_DEVICE_NAME_LEN: usize = 15;
+
+/// Options applied to all listen sockets; see [crate::socket_options]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SocketOptions {
+    /// Network device to bind the sockets to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bind_to_device: Option<String>,
+
+    /// Firewall mark for outgoing packets
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fwmark: Option<u32>,
+
+    /// Differentiated services code point for outgoing packets
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dscp: Option<u8>,
+
+    /// Size of the receive buffer in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recv_buffer: Option<usize>,
+
+    /// Size of the send buffer in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub send_buffer: Option<usize>,
+}
+
+impl SocketOptions {
+    /// Whether no option is set
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Check that the option values are sound
+    ///
+    /// Whether the options can be applied with the privileges at hand only shows once they are
+    /// applied to the listen sockets.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if let Some(dev) = self.bind_to_device.as_deref() {
+            ensure!(
+                !dev.is_empty() && dev.len() <= MAX_DEVICE_NAME_LEN && !dev.contains(['/', '\0']),
+                "Invalid network device name {dev:?}"
+            );
+        }
+        if let Some(dscp) = self.dscp {
+            ensure!(dscp < 64, "DSCP values range from 0 to 63, not {dscp}");
+        }
+        for size in [self.recv_buffer, self.send_buffer].into_iter().flatten() {
+            ensure!(
+                size > 0 && size <= libc::c_int::MAX as usize / 2,
+                "Invalid socket buffer size {size}"
+            );
+        }
+        Ok(())
+    }
+
+    /// Set the options on `sock`
+    pub fn apply(&self, sock: &UdpSocket) -> anyhow::Result<()> {
+        let fd = sock.as_raw_fd();
+
+        if let Some(dev) = self.bind_to_device.as_deref() {
+            bind_to_device(fd, dev)
+                .with_context(|| format!("Could not bind listen socket to device {dev:?}"))?;
+        }
+
+        if let Some(mark) = self.fwmark {
+            set_fwmark(fd, mark)
+                .with_context(|| format!("Could not set fwmark {mark:#x} on listen socket"))?;
+        }
+
+        if let Some(dscp) = self.dscp {
+            set_dscp(sock, dscp)
+                .with_context(|| format!("Could not set DSCP {dscp} on listen socket"))?;
+        }
+
+        if let Some(size) = self.recv_buffer {
+            set_buffer(fd, Buffer::Recv, size)
+                .context("Could not set the receive buffer size of listen socket")?;
+        }
+        if let Some(size) = self.send_buffer {
+            set_buffer(fd, Buffer::Send, size)
+                .context("Could not set the send buffer size of listen socket")?;
+        }
+
+        Ok(())
+    }
+
+    /// Set the options on `sock`, which has the `previous` options applied
+    ///
+    /// The device, fwmark and DSCP set in `previous` but not in `self` are reset to their
+    /// defaults.
+    pub fn reapply(&self, previous: &SocketOptions, sock: &UdpSocket) -> anyhow::Result<()> {
+        let fd = sock.as_raw_fd();
+
+        if previous.bind_to_device.is_some() && self.bind_to_device.is_none() {
+            bind_to_device(fd, "").context("Could not unbind listen socket from its device")?;
+        }
+        if previous.fwmark.is_some() && self.fwmark.is_none() {
+            set_fwmark(fd, 0).context("Could not clear the fwmark of listen socket")?;
+        }
+        if previous.dscp.is_some() && self.dscp.is_none() {
+            set_dscp(sock, 0).context("Could not clear the DSCP of listen socket")?;
+        }
+
+        self.apply(sock)
+    }
+}
+
+/// Set the differentiated services code point of the packets sent through `sock`
+fn set_dscp(sock: &UdpSocket, dscp: u8) -> anyhow::Result<()> {
+    let fd = sock.as_raw_fd();
+    // The lower two bits are for explicit congestion notification
+    let tos = (dscp as libc::c_int) << 2;
+    match sock.local_addr()? {
+        SocketAddr::V4(_) => set_int(fd, libc::IPPROTO_IP, libc::IP_TOS, tos)?,
+        SocketAddr::V6(_) => {
+            // For IPv4 traffic on dual-stack sockets; IPv6-only sockets do not need it
+            let _ = set_int(fd, libc::IPPROTO_IP, libc::IP_TOS, tos);
+            set_int(fd, libc::IPPROTO_IPV6, libc::IPV6_TCLASS, tos)?
+        }
+    };
+    Ok(())
+}
+
+/// Call setsockopt(2) with the option value `value`
+fn set_raw(fd: RawFd, level: libc::c_int, name: libc::c_int, value: &[u8]) -> io::Result<()> {
+    // Safety: the option value points to a live buffer of the given length
+    let res = unsafe {
+        libc::setsockopt(
+            fd,
+            level,
+            name,
+            value.as_ptr().cast(),
+            value.len() as libc::socklen_t,
+        )
+    };
+    match res {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Set a socket option that takes an integer
+fn set_int(fd: RawFd, level: libc::c_int, name: libc::c_int, value: libc::c_int) -> io::Result<()> {
+    set_raw(fd, level, name, &value.to_ne_bytes())
+}
+
+/// Read a socket option that is an integer
+fn get_int(fd: RawFd, level: libc::c_int, name: libc::c_int) -> io::Result<libc::c_int> {
+    let mut value: libc::c_int = 0;
+    let mut len = size_of::<libc::c_int>() as libc::socklen_t;
+    // Safety: value and len point to live variables of the given size
+    let res = unsafe {
+        libc::getsockopt(
+            fd,
+            level,
+            name,
+            (&mut value as *mut libc::c_int).cast(),
+            &mut len,
+        )
+    };
+    match res {
+        0 => Ok(value),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Turn "operation not permitted" into an error naming the capability that is missing
+fn needs_capability(err: io::Error, capability: &str) -> anyhow::Error {
+    match err.raw_os_error() {
+        Some(libc::EPERM) => anyhow::anyhow!("{err}; this requires the {capability} capability"),
+        _ => err.into(),
+    }
+}
+
+/// Bind the socket to the network device `dev`; the empty name unbinds it
+#[cfg(target_os = "linux")]
+fn bind_to_device(fd: RawFd, dev: &str) -> anyhow::Result<()> {
+    // Before Linux 5.7, binding to a device needs CAP_NET_RAW; so does changing the binding
+    set_raw(fd, libc::SOL_SOCKET, libc::SO_BINDTODEVICE, dev.as_bytes())
+        .map_err(|e| needs_capability(e, "CAP_NET_RAW"))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn bind_to_device(_fd: RawFd, _dev: &str) -> anyhow::Result<()> {
+    anyhow::bail!("Binding sockets to devices is only supported on Linux")
+}
+
+#[cfg(target_os = "linux")]
+fn set_fwmark(fd: RawFd, mark: u32) -> anyhow::Result<()> {
+    set_int(fd, libc::SOL_SOCKET, libc::SO_MARK, mark as libc::c_int)
+        .map_err(|e| needs_capability(e, "CAP_NET_ADMIN"))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_fwmark(_fd: RawFd, _mark: u32) -> anyhow::Result<()> {
+    anyhow::bail!("Setting a fwmark is only supported on Linux")
+}
+
+/// One of the socket buffers
+#[derive(Debug, Clone, Copy)]
+enum Buffer {
+    Recv,
+    Send,
+}
+
+/// Set the size of a socket buffer
+///
+/// On Linux, the size is forced past the system limit if the process may do so; otherwise, the
+/// size may end up smaller than requested, which is logged.
+fn set_buffer(fd: RawFd, buffer: Buffer, size: usize) -> anyhow::Result<()> {
+    let (name, what, sysctl) = match buffer {
+        Buffer::Recv => (libc::SO_RCVBUF, "receive", "net.core.rmem_max"),
+        Buffer::Send => (libc::SO_SNDBUF, "send", "net.core.wmem_max"),
+    };
+    let value = size as libc::c_int;
+
+    #[cfg(target_os = "linux")]
+    {
+        let force = match buffer {
+            Buffer::Recv => libc::SO_RCVBUFFORCE,
+            Buffer::Send => libc::SO_SNDBUFFORCE,
+        };
+        match set_int(fd, libc::SOL_SOCKET, force, value) {
+            Ok(()) => return Ok(()),
+            Err(e) if e.raw_os_error() == Some(libc::EPERM) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    set_int(fd, libc::SOL_SOCKET, name, value)?;
+
+    // Linux reports twice the size set, to account for its bookkeeping overhead
+    let reported = match cfg!(target_os = "linux") {
+        true => size * 2,
+        false => size,
+    };
+    let actual = get_int(fd, libc::SOL_SOCKET, name)?;
+    if (actual as usize) < reported {
+        log::warn!(
+            "The {what} buffer of the listen socket is smaller than the {size} bytes \
+             configured; raise {sysctl} or grant rosenpass CAP_NET_ADMIN"
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn invalid_options_are_rejected() {
+        let dscp = SocketOptions {
+            dscp: Some(64),
+            ..Default::default()
+        };
+        assert!(dscp.validate().is_err());
+
+        let device = SocketOptions {
+            bind_to_device: Some("a-name-that-is-too-long".to_owned()),
+            ..Default::default()
+        };
+        assert!(device.validate().is_err());
+
+        let buffer = SocketOptions {
+            recv_buffer: Some(0),
+            ..Default::default()
+        };
+        assert!(buffer.validate().is_err());
+    }
+
+    #[test]
+    fn unprivileged_options_are_applied() -> anyhow::Result<()> {
+        let opts = SocketOptions {
+            dscp: Some(46),
+            send_buffer: Some(4096),
+            ..Default::default()
+        };
+        opts.validate()?;
+
+        let sock = UdpSocket::bind(SocketAddr::from((Ipv4Addr::LOCALHOST, 0)))?;
+        opts.apply(&sock)?;
+        let fd = sock.as_raw_fd();
+        assert_eq!(get_int(fd, libc::IPPROTO_IP, libc::IP_TOS)?, 46 << 2);
+
+        // Removing the DSCP resets it
+        SocketOptions::default().reapply(&opts, &sock)?;
+        assert_eq!(get_int(fd, libc::IPPROTO_IP, libc::IP_TOS)?, 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,339 @@
+//! Integration with the systemd service manager
+//!
+//! - **Readiness and status:** When started with `Type=notify`, rosenpass reports `READY=1` once
+//!   its event loop runs and keeps the `STATUS=` line shown by `systemctl status` up to date with
+//!   the number of peers that have a live session (see sd_notify(3)). `STOPPING=1` is sent when
+//!   the server shuts down.
+//! - **Watchdog:** If the unit sets `WatchdogSec=`, the event loop sends `WATCHDOG=1` every half
+//!   interval; a hung event loop thus gets the service restarted.
+//! - **Socket activation:** UDP sockets and API unix listeners passed in through `LISTEN_FDS`
+//!   (see sd_listen_fds(3)) are used in addition to the configured ones. Sockets named `listen` or
+//!   `api` through `FileDescriptorName=` are used as such; other sockets are told apart by their
+//!   type.
+//!
+//! Outside of systemd, none of the environment variables involved are set and all of this does
+//! nothing. The variables are removed from the environment once read, so hooks and other child
+//! processes do not mistake them for their own.
+//!
+//! # Examples
+//!
+//! ```ini
+//! # rosenpass@.socket
+//! [Socket]
+//! ListenDatagram=9999
+//! FileDescriptorName=listen
+//!
+//! # rosenpass@.service
+//! [Service]
+//! Type=notify
+//! WatchdogSec=30
+//! ExecStart=rosenpass exchange-config /etc/rosenpass/%i.toml
+//! ```
+
+use std::env;
+use std::io;
+use std::os::fd::{OwnedFd, RawFd};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::net::{SocketAddr as UnixSocketAddr, UnixDatagram};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, ensure, Context};
+use rosenpass_util::fd::{claim_fd, GetSocketType};
+
+use crate::app_server::{AppPeerPtr, AppServer};
+
+/// The first file descriptor passed through socket activation
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+/// Sends notifications to the service manager; see [crate::systemd]
+///
+/// Part of [AppServer] (see [AppServer::systemd]).
+#[derive(Debug)]
+pub struct SystemdNotifier {
+    socket: UnixDatagram,
+    addr: UnixSocketAddr,
+    /// How often to send `WATCHDOG=1`
+    watchdog_interval: Option<Duration>,
+    next_watchdog: Instant,
+    /// Whether `READY=1` was sent
+    ready: bool,
+    /// The number of live sessions last reported through `STATUS=`
+    reported_sessions: Option<usize>,
+}
+
+impl SystemdNotifier {
+    /// Set up notifications to the socket in `NOTIFY_SOCKET`, if the variable is set
+    ///
+    /// The watchdog interval is read from `WATCHDOG_USEC`, unless `WATCHDOG_PID` names a
+    /// different process.
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+            return Ok(None);
+        };
+        let watchdog_usec = env::var("WATCHDOG_USEC").ok();
+        let watchdog_pid = env::var("WATCHDOG_PID").ok();
+        for var in ["NOTIFY_SOCKET", "WATCHDOG_USEC", "WATCHDOG_PID"] {
+            env::remove_var(var);
+        }
+
+        let path = path.into_vec();
+        let addr = match path.strip_prefix(b"@") {
+            #[cfg(target_os = "linux")]
+            Some(name) => {
+                use std::os::linux::net::SocketAddrExt;
+                UnixSocketAddr::from_abstract_name(name)?
+            }
+            #[cfg(not(target_os = "linux"))]
+            Some(_) => bail!("Abstract notification sockets are only supported on Linux"),
+            None => UnixSocketAddr::from_pathname(std::ffi::OsStr::from_bytes(&path))?,
+        };
+
+        let for_us = match watchdog_pid {
+            Some(pid) => pid.parse::<u32>().ok() == Some(std::process::id()),
+            None => true,
+        };
+        let watchdog_interval = match watchdog_usec {
+            Some(usec) if for_us => {
+                let usec: u64 = usec
+                    .parse()
+                    .with_context(|| format!("Invalid WATCHDOG_USEC {usec:?}"))?;
+                ensure!(usec > 0, "WATCHDOG_USEC must not be zero");
+                // Notify twice per interval, as sd_watchdog_enabled(3) recommends
+                Some(Duration::from_micros(usec) / 2)
+            }
+            _ => None,
+        };
+
+        Ok(Some(Self {
+            socket: UnixDatagram::unbound()?,
+            addr,
+            watchdog_interval,
+            next_watchdog: Instant::now(),
+            ready: false,
+            reported_sessions: None,
+        }))
+    }
+
+    /// Send `state`, one `KEY=value` assignment per line, to the service manager
+    pub fn notify(&self, state: &str) -> io::Result<()> {
+        self.socket.send_to_addr(state.as_bytes(), &self.addr)?;
+        Ok(())
+    }
+
+    /// Report readiness and the number of live sessions, and pet the watchdog if it is due
+    ///
+    /// Returns how long it is until the watchdog is due again.
+    pub fn update(&mut self, live_sessions: usize) -> Option<Duration> {
+        if self.reported_sessions != Some(live_sessions) {
+            let mut state = format!("STATUS={live_sessions} peers with live sessions\n");
+            if !self.ready {
+                state.insert_str(0, "READY=1\n");
+            }
+            match self.notify(&state) {
+                Ok(()) => {
+                    self.ready = true;
+                    self.reported_sessions = Some(live_sessions);
+                }
+                Err(e) => log::warn!("Could not notify the service manager: {e}"),
+            }
+        }
+
+        let interval = self.watchdog_interval?;
+        let now = Instant::now();
+        if now >= self.next_watchdog {
+            if let Err(e) = self.notify("WATCHDOG=1") {
+                log::warn!("Could not notify the service manager watchdog: {e}");
+            }
+            self.next_watchdog = now + interval;
+        }
+        Some(self.next_watchdog - now)
+    }
+}
+
+/// Sockets passed in by the service manager through socket activation
+#[derive(Debug, Default)]
+pub struct ListenFds {
+    /// UDP sockets to receive handshake messages on
+    pub udp: Vec<mio::net::UdpSocket>,
+    /// Unix sockets to accept API connections on
+    pub api: Vec<mio::net::UnixListener>,
+}
+
+impl ListenFds {
+    /// Claim the file descriptors passed through `LISTEN_FDS`, if they are meant for this process
+    ///
+    /// The file descriptors are claimed with [claim_fd], so the numbers they were passed with
+    /// point to `/dev/null` afterwards and cannot be mistaken for the sockets.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let Ok(pid) = env::var("LISTEN_PID") else {
+            return Ok(Self::default());
+        };
+        let count = env::var("LISTEN_FDS").ok();
+        let names = env::var("LISTEN_FDNAMES").ok();
+        for var in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+            env::remove_var(var);
+        }
+
+        let Some(count) = count else {
+            return Ok(Self::default());
+        };
+        if pid.parse::<u32>().ok() != Some(std::process::id()) {
+            return Ok(Self::default());
+        }
+        let count: RawFd = count
+            .parse()
+            .with_context(|| format!("Invalid LISTEN_FDS {count:?}"))?;
+        let names = names.unwrap_or_default();
+        let mut names = names.split(':');
+
+        let mut fds = Self::default();
+        for fd in SD_LISTEN_FDS_START..SD_LISTEN_FDS_START + count {
+            let name = names.next().unwrap_or_default();
+            let owned = claim_fd(fd).with_context(|| {
+                format!("Could not claim socket-activated file descriptor {fd}")
+            })?;
+            fds.add(owned, name)
+                .with_context(|| format!("Socket-activated file descriptor {fd} ({name:?})"))?;
+        }
+        Ok(fds)
+    }
+
+    /// Sort `fd` into [Self::udp] or [Self::api]
+    fn add(&mut self, fd: OwnedFd, name: &str) -> anyhow::Result<()> {
+        use rustix::net::AddressFamily as AF;
+
+        // Asking the socket for its protocol or domain only works on Linux; the socket type
+        // together with the family of the bound address works everywhere
+        let family = rustix::net::getsockname(&fd)?.address_family();
+        let is_udp = fd.is_datagram_socket()? && [AF::INET, AF::INET6].contains(&family);
+        let is_stream = fd.is_stream_socket()? && family == AF::UNIX;
+        match (name, is_udp, is_stream) {
+            ("listen", true, _) | ("api", _, true) => {}
+            ("listen", false, _) => bail!("Sockets named `listen` must be UDP sockets"),
+            ("api", _, false) => bail!("Sockets named `api` must be unix stream sockets"),
+            _ => {}
+        }
+
+        if is_udp {
+            let sock = std::net::UdpSocket::from(fd);
+            sock.set_nonblocking(true)?;
+            self.udp.push(mio::net::UdpSocket::from_std(sock));
+        } else if is_stream {
+            let sock = std::os::unix::net::UnixListener::from(fd);
+            sock.set_nonblocking(true)?;
+            self.api.push(mio::net::UnixListener::from_std(sock));
+        } else {
+            bail!("Neither a UDP socket nor a stream socket");
+        }
+        Ok(())
+    }
+
+    /// Add [Self::api] to `srv`
+    ///
+    /// [Self::udp] has to be passed to [AppServer::with_sockets] instead, before `srv` exists.
+    pub fn apply_to_app_server(self, srv: &mut AppServer) -> anyhow::Result<()> {
+        ensure!(
+            self.udp.is_empty(),
+            "Socket-activated UDP sockets must be passed to AppServer::with_sockets"
+        );
+
+        #[cfg(feature = "experiment_api")]
+        for listener in self.api {
+            srv.add_api_listener(listener)?;
+        }
+
+        #[cfg(not(feature = "experiment_api"))]
+        {
+            let _ = srv;
+            ensure!(
+                self.api.is_empty(),
+                "API sockets were passed in, but rosenpass was built without API support"
+            );
+        }
+
+        Ok(())
+    }
+}
+
+impl AppServer {
+    /// Number of peers that currently have a session with an established key
+    pub fn live_sessions(&self) -> usize {
+        let Some(crypto) = self.crypto_site.product_ref() else {
+            return 0;
+        };
+        (0..self.peers.len())
+            .map(|no| AppPeerPtr(no).lower())
+            .filter(|peer| peer.session().get(crypto).is_some())
+            .count()
+    }
+
+    /// Keep the service manager posted, if there is one; see [SystemdNotifier::update]
+    pub fn notify_systemd_if_due(&mut self) -> Option<Duration> {
+        let live_sessions = self.live_sessions();
+        self.systemd.as_mut()?.update(live_sessions)
+    }
+
+    /// Tell the service manager that the server is shutting down, if there is one
+    pub fn notify_systemd_stopping(&self) {
+        if let Some(notifier) = self.systemd.as_ref() {
+            if let Err(e) = notifier.notify("STOPPING=1") {
+                log::warn!("Could not notify the service manager: {e}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notifications_reach_the_socket() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let path = tmp.path().join("notify");
+        let receiver = UnixDatagram::bind(&path)?;
+        receiver.set_read_timeout(Some(Duration::from_secs(10)))?;
+
+        let mut notifier = SystemdNotifier {
+            socket: UnixDatagram::unbound()?,
+            addr: UnixSocketAddr::from_pathname(&path)?,
+            watchdog_interval: Some(Duration::from_secs(3600)),
+            next_watchdog: Instant::now(),
+            ready: false,
+            reported_sessions: None,
+        };
+        let recv = || -> anyhow::Result<String> {
+            let mut buf = [0u8; 256];
+            let len = receiver.recv(&mut buf)?;
+            Ok(String::from_utf8(buf[..len].to_vec())?)
+        };
+
+        let due = notifier.update(0);
+        assert_eq!(recv()?, "READY=1\nSTATUS=0 peers with live sessions\n");
+        assert_eq!(recv()?, "WATCHDOG=1");
+        assert!(due.is_some_and(|due| due > Duration::from_secs(3000)));
+
+        // Nothing changed and the watchdog is not due, so nothing is sent
+        notifier.update(0);
+        notifier.update(2);
+        assert_eq!(recv()?, "STATUS=2 peers with live sessions\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn activated_sockets_are_sorted_by_type() -> anyhow::Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let udp = std::net::UdpSocket::bind("127.0.0.1:0")?;
+        let unix = std::os::unix::net::UnixListener::bind(tmp.path().join("api"))?;
+        let tcp = std::net::TcpListener::bind("127.0.0.1:0")?;
+
+        let mut fds = ListenFds::default();
+        fds.add(udp.into(), "listen")?;
+        fds.add(unix.into(), "api")?;
+        assert!(fds.add(tcp.into(), "").is_err());
+        assert_eq!((fds.udp.len(), fds.api.len()), (1, 1));
+
+        Ok(())
+    }
+}
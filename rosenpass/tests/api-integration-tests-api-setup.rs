@@ -89,7 +89,17 @@ fn api_integration_api_setup(protocol_version: ProtocolVersion) -> anyhow::Resul
         config_file_path: tempfile!("a.config"),
         keypair: None,
         listen: vec![], // TODO: This could collide by accident
+        listen_options: Default::default(),
+        user: None,
+        group: None,
+        seccomp: Default::default(),
+        landlock: false,
+        key_oracle: None,
         verbosity: config::Verbosity::Verbose,
+        log_format: rosenpass::logging::LogFormat::Text,
+        on_shutdown: config::ShutdownPolicy::Keep,
+        metrics: None,
+        hooks: Default::default(),
         api: api::config::ApiConfig {
             listen_path: vec![tempfile!("a.sock")],
             listen_fd: vec![],
@@ -97,7 +107,11 @@ fn api_integration_api_setup(protocol_version: ProtocolVersion) -> anyhow::Resul
         },
         peers: vec![config::RosenpassPeer {
             public_key: tempfile!("b.pk"),
+            name: None,
             key_out: None,
+            key_out_previous: 0,
+            key_out_format: Default::default(),
+            key_sinks: vec![],
             endpoint: None,
             pre_shared_key: None,
             wg: Some(config::WireGuard {
@@ -106,6 +120,7 @@ fn api_integration_api_setup(protocol_version: ProtocolVersion) -> anyhow::Resul
                 extra_params: vec![],
             }),
             protocol_version: protocol_version.clone(),
+            hooks: Default::default(),
         }],
     };
 
@@ -114,7 +129,17 @@ fn api_integration_api_setup(protocol_version: ProtocolVersion) -> anyhow::Resul
         config_file_path: tempfile!("b.config"),
         keypair: Some(peer_b_keypair.clone()),
         listen: vec![],
+        listen_options: Default::default(),
+        user: None,
+        group: None,
+        seccomp: Default::default(),
+        landlock: false,
+        key_oracle: None,
         verbosity: config::Verbosity::Verbose,
+        log_format: rosenpass::logging::LogFormat::Text,
+        on_shutdown: config::ShutdownPolicy::Keep,
+        metrics: None,
+        hooks: Default::default(),
         api: api::config::ApiConfig {
             listen_path: vec![tempfile!("b.sock")],
             listen_fd: vec![],
@@ -122,11 +147,16 @@ fn api_integration_api_setup(protocol_version: ProtocolVersion) -> anyhow::Resul
         },
         peers: vec![config::RosenpassPeer {
             public_key: tempfile!("a.pk"),
+            name: None,
             key_out: Some(peer_b_osk.clone()),
+            key_out_previous: 0,
+            key_out_format: Default::default(),
+            key_sinks: vec![],
             endpoint: Some(peer_a_endpoint.to_owned()),
             pre_shared_key: None,
             wg: None,
             protocol_version: protocol_version.clone(),
+            hooks: Default::default(),
         }],
     };
 
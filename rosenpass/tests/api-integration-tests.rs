@@ -2,7 +2,9 @@ use std::{
     io::{BufRead, BufReader},
     net::ToSocketAddrs,
     os::unix::net::UnixStream,
-    process::Stdio,
+    process::{Child, Stdio},
+    thread::sleep,
+    time::Duration,
 };
 
 use anyhow::{bail, Context};
@@ -14,7 +16,7 @@ use rosenpass_util::{
 };
 use rosenpass_util::{mem::DiscardResultExt, zerocopy::ZerocopySliceExt};
 use tempfile::TempDir;
-use zerocopy::AsBytes;
+use zerocopy::{AsBytes, FromBytes};
 
 use rosenpass::config::ProtocolVersion;
 use rosenpass::protocol::SymKey;
@@ -37,6 +39,26 @@ impl Drop for KillChild {
     }
 }
 
+/// Send an API request and read the response
+fn request<Res: FromBytes + Copy>(conn: &UnixStream, req: &[u8]) -> anyhow::Result<Res> {
+    LengthPrefixEncoder::from_message(req).write_all_to_stdio(conn)?;
+    let mut decoder = LengthPrefixDecoder::new([0u8; api::MAX_RESPONSE_LEN]);
+    let res = decoder.read_all_from_stdio(conn)?;
+    Ok(*res.zk_parse::<Res>()?)
+}
+
+/// Wait for a rosenpass process to exit on its own
+fn wait_for_exit(child: &mut Child) -> anyhow::Result<()> {
+    for _ in 0..500 {
+        if let Some(status) = child.try_wait()? {
+            anyhow::ensure!(status.success(), "rosenpass exited with {status}");
+            return Ok(());
+        }
+        sleep(Duration::from_millis(20));
+    }
+    bail!("rosenpass did not exit")
+}
+
 #[test]
 fn api_integration_test_v02() -> anyhow::Result<()> {
     api_integration_test(ProtocolVersion::V02)
@@ -70,7 +92,17 @@ fn api_integration_test(protocol_version: ProtocolVersion) -> anyhow::Result<()>
         config_file_path: tempfile!("a.config"),
         keypair: Some(peer_a_keypair.clone()),
         listen: peer_a_endpoint.to_socket_addrs()?.collect(), // TODO: This could collide by accident
+        listen_options: Default::default(),
+        user: None,
+        group: None,
+        seccomp: Default::default(),
+        landlock: false,
+        key_oracle: None,
         verbosity: config::Verbosity::Verbose,
+        log_format: rosenpass::logging::LogFormat::Text,
+        on_shutdown: config::ShutdownPolicy::Keep,
+        metrics: None,
+        hooks: Default::default(),
         api: api::config::ApiConfig {
             listen_path: vec![tempfile!("a.sock")],
             listen_fd: vec![],
@@ -78,11 +110,16 @@ fn api_integration_test(protocol_version: ProtocolVersion) -> anyhow::Result<()>
         },
         peers: vec![config::RosenpassPeer {
             public_key: tempfile!("b.pk"),
+            name: None,
             key_out: Some(peer_a_osk.clone()),
+            key_out_previous: 0,
+            key_out_format: Default::default(),
+            key_sinks: vec![],
             endpoint: None,
             pre_shared_key: None,
             wg: None,
             protocol_version: protocol_version.clone(),
+            hooks: Default::default(),
         }],
     };
 
@@ -91,7 +128,17 @@ fn api_integration_test(protocol_version: ProtocolVersion) -> anyhow::Result<()>
         config_file_path: tempfile!("b.config"),
         keypair: Some(peer_b_keypair.clone()),
         listen: vec![],
+        listen_options: Default::default(),
+        user: None,
+        group: None,
+        seccomp: Default::default(),
+        landlock: false,
+        key_oracle: None,
         verbosity: config::Verbosity::Verbose,
+        log_format: rosenpass::logging::LogFormat::Text,
+        on_shutdown: config::ShutdownPolicy::Keep,
+        metrics: None,
+        hooks: Default::default(),
         api: api::config::ApiConfig {
             listen_path: vec![tempfile!("b.sock")],
             listen_fd: vec![],
@@ -99,11 +146,16 @@ fn api_integration_test(protocol_version: ProtocolVersion) -> anyhow::Result<()>
         },
         peers: vec![config::RosenpassPeer {
             public_key: tempfile!("a.pk"),
+            name: None,
             key_out: Some(peer_b_osk.clone()),
+            key_out_previous: 0,
+            key_out_format: Default::default(),
+            key_sinks: vec![],
             endpoint: Some(peer_a_endpoint.to_owned()),
             pre_shared_key: None,
             wg: None,
             protocol_version: protocol_version.clone(),
+            hooks: Default::default(),
         }],
     };
 
@@ -197,7 +249,7 @@ fn api_integration_test(protocol_version: ProtocolVersion) -> anyhow::Result<()>
     let api_a = UnixStream::connect(&peer_a.api.listen_path[0])?;
     let api_b = UnixStream::connect(&peer_b.api.listen_path[0])?;
 
-    for conn in ([api_a, api_b]).iter() {
+    for conn in [&api_a, &api_b] {
         let mut echo = [0u8; 256];
         copy_slice_least_src("Hello World".as_bytes()).to(&mut echo);
 
@@ -210,5 +262,62 @@ fn api_integration_test(protocol_version: ProtocolVersion) -> anyhow::Result<()>
         assert_eq!(*res, api::PingResponse::new(echo));
     }
 
+    // Both servers report this build
+    for conn in [&api_a, &api_b] {
+        let res: api::VersionResponse = request(conn, api::VersionRequest::new().as_bytes())?;
+        let version = res.payload;
+        let (status, features) = (version.status, version.features);
+        assert_eq!(status, api::version_response_status::OK);
+        assert_eq!(version.version_str()?, env!("CARGO_PKG_VERSION"));
+        assert_ne!(features & api::version_features::EXPERIMENT_API, 0);
+        assert!(version.supports_protocol_version(api::add_peer_protocol_version::V02));
+        assert!(version.supports_protocol_version(api::add_peer_protocol_version::V03));
+        assert!(!version.supports_protocol_version(1));
+    }
+
+    // Reloading the unchanged configuration keeps the peer
+    let res: api::ReloadConfigResponse =
+        request(&api_b, api::ReloadConfigRequest::new().as_bytes())?;
+    let (status, unchanged) = (res.payload.status, res.payload.peers_unchanged);
+    assert_eq!(status, api::reload_config_response_status::OK);
+    assert_eq!(unchanged, 1);
+
+    // Shut down peer a, retiring its key even though the configuration says to keep it
+    let res: api::ShutdownResponse = request(
+        &api_a,
+        api::ShutdownRequest::new(api::shutdown_key_policy::RETIRE).as_bytes(),
+    )?;
+    assert_eq!(
+        res,
+        api::ShutdownResponse::new(api::shutdown_response_status::OK)
+    );
+    wait_for_exit(&mut proc_a.0)?;
+    let retired = out_a.collect::<Result<Vec<_>, _>>()?;
+    assert!(
+        retired.iter().any(|line| line.ends_with(" stale")),
+        "{retired:?}"
+    );
+    let osk_a = SymKey::load_b64::<64, _>(peer_a_osk.clone())?;
+    let osk_b = SymKey::load_b64::<64, _>(peer_b_osk.clone())?;
+    assert_ne!(osk_a.secret(), osk_b.secret());
+
+    // Peer b keeps its key as configured
+    let res: api::ShutdownResponse = request(&api_b, api::ShutdownRequest::new(42).as_bytes())?;
+    assert_eq!(
+        res,
+        api::ShutdownResponse::new(api::shutdown_response_status::INVALID_REQUEST)
+    );
+    let res: api::ShutdownResponse = request(
+        &api_b,
+        api::ShutdownRequest::new(api::shutdown_key_policy::CONFIGURED).as_bytes(),
+    )?;
+    assert_eq!(
+        res,
+        api::ShutdownResponse::new(api::shutdown_response_status::OK)
+    );
+    wait_for_exit(&mut proc_b.0)?;
+    let kept = SymKey::load_b64::<64, _>(peer_b_osk.clone())?;
+    assert_eq!(kept.secret(), osk_b.secret());
+
     Ok(())
 }
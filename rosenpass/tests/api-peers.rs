@@ -0,0 +1,405 @@
+use std::{
+    collections::VecDeque,
+    fs,
+    io::{BufRead, BufReader, Read},
+    net::UdpSocket,
+    os::{
+        fd::{AsFd, BorrowedFd, OwnedFd},
+        unix::net::UnixStream,
+    },
+    path::Path,
+    process::{Command, Stdio},
+    thread::sleep,
+    time::Duration,
+};
+
+use anyhow::ensure;
+use rosenpass::api::{
+    self, add_peer_protocol_version, add_peer_response_status, get_peer_status_response_status,
+    key_sink_reason, register_key_sink_response_status, rekey_response_status,
+    remove_peer_response_status, subscribe_events_event_kind, subscribe_events_response_status,
+    AddPeerRequest, AddPeerResponse, GetPeerStatusRequest, GetPeerStatusResponse, ListPeersRequest,
+    ListPeersResponse, RegisterKeySinkRequest, RegisterKeySinkResponse, RekeyRequest,
+    RekeyResponse, RemovePeerRequest, RemovePeerResponse, SubscribeEventsRequest,
+    SubscribeEventsResponse, EVENT_NO_PEER, PEER_STATUS_NEVER,
+};
+use rosenpass::config::ProtocolVersion;
+use rosenpass_util::{
+    length_prefix_encoding::{decoder::LengthPrefixDecoder, encoder::LengthPrefixEncoder},
+    mio::{ReadWithFileDescriptors, WriteWithFileDescriptors},
+    zerocopy::ZerocopySliceExt,
+};
+use zerocopy::{AsBytes, FromBytes};
+
+mod common;
+use common::{free_udp_port, gen_keypair, KillChild};
+
+/// Start a server without peers on the given UDP address, listening for API connections
+fn start_server(dir: &Path, listen: &str) -> anyhow::Result<(KillChild, UnixStream)> {
+    let api_path = dir.join("api.sock");
+    let toml = format!(
+        "public_key = {:?}\nsecret_key = {:?}\nlisten = [{:?}]\nverbosity = \"Quiet\"\n\n\
+         [api]\nlisten_path = [{:?}]\nlisten_fd = []\nstream_fd = []\n",
+        dir.join("server-pk"),
+        dir.join("server-sk"),
+        listen,
+        api_path,
+    );
+    fs::write(dir.join("server.toml"), toml)?;
+
+    let mut child = KillChild(
+        Command::new(env!("CARGO_BIN_EXE_rosenpass"))
+            .arg("exchange-config")
+            .arg(dir.join("server.toml"))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()?,
+    );
+
+    for _ in 0..250 {
+        if api_path.exists() {
+            return Ok((child, UnixStream::connect(&api_path)?));
+        }
+        if let Some(status) = child.0.try_wait()? {
+            let out = BufReader::new(child.0.stdout.take().unwrap()).lines();
+            anyhow::bail!(
+                "rosenpass exited with {status}: {:?}",
+                out.collect::<Vec<_>>()
+            );
+        }
+        sleep(Duration::from_millis(20));
+    }
+    anyhow::bail!("The API socket was not created")
+}
+
+/// Send an API request with the given file descriptors and read the response
+fn request<Res: FromBytes + Copy>(
+    api: &UnixStream,
+    req: &[u8],
+    fds: Vec<BorrowedFd<'_>>,
+) -> anyhow::Result<Res> {
+    let mut fds: VecDeque<_> = fds.into();
+    {
+        let mut api = WriteWithFileDescriptors::<UnixStream, _, _, _>::new(api, &mut fds);
+        LengthPrefixEncoder::from_message(req).write_all_to_stdio(&mut api)?;
+    }
+    ensure!(fds.is_empty(), "Failed to write all file descriptors");
+
+    receive(api)
+}
+
+/// Read one message from the API
+fn receive<Res: FromBytes + Copy>(api: &UnixStream) -> anyhow::Result<Res> {
+    let mut decoder = LengthPrefixDecoder::new([0u8; api::MAX_RESPONSE_LEN]);
+    let res = decoder.read_all_from_stdio(api)?;
+    Ok(*res.zk_parse::<Res>()?)
+}
+
+/// Read one message from the API along with the file descriptors that came with it
+fn receive_with_fds<Res: FromBytes + Copy>(
+    api: &UnixStream,
+) -> anyhow::Result<(Res, VecDeque<OwnedFd>)> {
+    let mut fds = VecDeque::new();
+    let mut decoder = LengthPrefixDecoder::new([0u8; api::MAX_RESPONSE_LEN]);
+    let res = decoder.read_all_from_stdio(ReadWithFileDescriptors::<
+        { api::MAX_RESPONSE_FDS },
+        UnixStream,
+        _,
+        _,
+    >::new(api, &mut fds))?;
+    Ok((*res.zk_parse::<Res>()?, fds))
+}
+
+#[test]
+fn api_adds_lists_and_removes_peers() -> anyhow::Result<()> {
+    rosenpass_secret_memory::secret_policy_use_only_malloc_secrets();
+
+    let tmp = tempfile::tempdir()?;
+    let dir = tmp.path();
+    gen_keypair(dir, "server")?;
+    gen_keypair(dir, "a")?;
+    fs::write(dir.join("a-psk"), [0x42u8; 32])?;
+    fs::write(dir.join("garbage"), b"not a public key")?;
+
+    let (_server, api) = start_server(dir, "[::1]:0")?;
+    let pk_a = fs::File::open(dir.join("a-pk"))?;
+    let psk_a = fs::File::open(dir.join("a-psk"))?;
+    let add_a = AddPeerRequest::new(ProtocolVersion::V03).with_endpoint("[::1]:9")?;
+
+    // Add a peer with endpoint and pre-shared key
+    let res: AddPeerResponse = request(&api, add_a.as_bytes(), vec![pk_a.as_fd(), psk_a.as_fd()])?;
+    assert_eq!(res, AddPeerResponse::new(add_peer_response_status::OK, 0));
+
+    // The same peer can not be added twice
+    let pk_a = fs::File::open(dir.join("a-pk"))?;
+    let res: AddPeerResponse = request(&api, add_a.as_bytes(), vec![pk_a.as_fd()])?;
+    let status = res.payload.status;
+    assert_eq!(status, add_peer_response_status::PEER_ALREADY_EXISTS);
+
+    // Malformed requests are rejected
+    let garbage = fs::File::open(dir.join("garbage"))?;
+    let res: AddPeerResponse = request(&api, add_a.as_bytes(), vec![garbage.as_fd()])?;
+    let status = res.payload.status;
+    assert_eq!(status, add_peer_response_status::INVALID_REQUEST);
+
+    let pk_a = fs::File::open(dir.join("a-pk"))?;
+    let no_port = AddPeerRequest::new(ProtocolVersion::V03).with_endpoint("localhost")?;
+    let res: AddPeerResponse = request(&api, no_port.as_bytes(), vec![pk_a.as_fd()])?;
+    let status = res.payload.status;
+    assert_eq!(status, add_peer_response_status::INVALID_REQUEST);
+
+    let pk_a = fs::File::open(dir.join("a-pk"))?;
+    let unknown_broker =
+        AddPeerRequest::new(ProtocolVersion::V03).with_wireguard("wg0", [0u8; 32], 17)?;
+    let res: AddPeerResponse = request(&api, unknown_broker.as_bytes(), vec![pk_a.as_fd()])?;
+    let status = res.payload.status;
+    assert_eq!(status, add_peer_response_status::INVALID_REQUEST);
+
+    // The peer is listed and its status can be queried
+    let res: ListPeersResponse = request(&api, ListPeersRequest::new(0).as_bytes(), vec![])?;
+    let total = res.payload.total;
+    assert_eq!((total, res.peer_ids()), (1, vec![0]));
+    let res: ListPeersResponse = request(&api, ListPeersRequest::new(1).as_bytes(), vec![])?;
+    assert!(res.peer_ids().is_empty());
+
+    let res: GetPeerStatusResponse =
+        request(&api, GetPeerStatusRequest::new(0).as_bytes(), vec![])?;
+    let status = res.payload;
+    assert_eq!(
+        (status.status, status.peer_id, status.protocol_version),
+        (
+            get_peer_status_response_status::OK,
+            0,
+            add_peer_protocol_version::V03
+        )
+    );
+    let endpoint = status.configured_endpoint;
+    assert!(String::from_utf8_lossy(&endpoint).contains("::1"));
+    // There is nobody listening on the endpoint, so no session
+    let (session_age, since_key_exchange) = (status.session_age_ms, status.since_key_exchange_ms);
+    assert_eq!(session_age, PEER_STATUS_NEVER);
+    assert_eq!(since_key_exchange, PEER_STATUS_NEVER);
+
+    // Remove the peer; afterwards, it is gone
+    let remove = RemovePeerRequest::new(0);
+    let res: RemovePeerResponse = request(&api, remove.as_bytes(), vec![])?;
+    assert_eq!(
+        res,
+        RemovePeerResponse::new(remove_peer_response_status::OK)
+    );
+    let res: RemovePeerResponse = request(&api, remove.as_bytes(), vec![])?;
+    assert_eq!(
+        res,
+        RemovePeerResponse::new(remove_peer_response_status::NO_SUCH_PEER)
+    );
+    let res: RemovePeerResponse = request(&api, RemovePeerRequest::new(17).as_bytes(), vec![])?;
+    assert_eq!(
+        res,
+        RemovePeerResponse::new(remove_peer_response_status::NO_SUCH_PEER)
+    );
+
+    let res: ListPeersResponse = request(&api, ListPeersRequest::new(0).as_bytes(), vec![])?;
+    assert!(res.peer_ids().is_empty());
+    let res: GetPeerStatusResponse =
+        request(&api, GetPeerStatusRequest::new(0).as_bytes(), vec![])?;
+    assert_eq!(
+        res,
+        GetPeerStatusResponse::new(get_peer_status_response_status::NO_SUCH_PEER)
+    );
+
+    // A removed peer can be added again and gets a new id
+    let pk_a = fs::File::open(dir.join("a-pk"))?;
+    let res: AddPeerResponse = request(&api, add_a.as_bytes(), vec![pk_a.as_fd()])?;
+    assert_eq!(res, AddPeerResponse::new(add_peer_response_status::OK, 1));
+
+    Ok(())
+}
+
+#[test]
+fn api_pushes_events_to_subscribers() -> anyhow::Result<()> {
+    rosenpass_secret_memory::secret_policy_use_only_malloc_secrets();
+
+    let tmp = tempfile::tempdir()?;
+    let dir = tmp.path();
+    gen_keypair(dir, "server")?;
+
+    let port = free_udp_port()?;
+    let (_server, api) = start_server(dir, &format!("[::1]:{port}"))?;
+    api.set_read_timeout(Some(Duration::from_secs(10)))?;
+
+    let res: SubscribeEventsResponse =
+        request(&api, SubscribeEventsRequest::new().as_bytes(), vec![])?;
+    let r = res.payload;
+    assert_eq!(
+        (r.status, r.event, r.peer_id, r.dropped, r.under_load),
+        (
+            subscribe_events_response_status::OK,
+            subscribe_events_event_kind::SUBSCRIBED,
+            EVENT_NO_PEER,
+            0,
+            0
+        )
+    );
+
+    // Garbage sent to the server makes the handshake fail
+    let client = UdpSocket::bind("[::1]:0")?;
+    client.send_to(b"not a rosenpass message", ("::1", port))?;
+
+    let res: SubscribeEventsResponse = receive(&api)?;
+    let r = res.payload;
+    assert_eq!(
+        (r.event, r.peer_id),
+        (subscribe_events_event_kind::HANDSHAKE_FAILED, EVENT_NO_PEER)
+    );
+    let endpoint = r.endpoint;
+    let endpoint = String::from_utf8_lossy(&endpoint);
+    assert_eq!(
+        endpoint.trim_end_matches('\0'),
+        client.local_addr()?.to_string()
+    );
+
+    // Failures right after that one are not reported; anyone can send garbage
+    client.send_to(b"not a rosenpass message", ("::1", port))?;
+    api.set_read_timeout(Some(Duration::from_millis(500)))?;
+    assert!(receive::<SubscribeEventsResponse>(&api).is_err());
+
+    Ok(())
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn api_key_sinks_receive_keys_as_memfds() -> anyhow::Result<()> {
+    use rustix::fs::{fcntl_get_seals, SealFlags};
+
+    rosenpass_secret_memory::secret_policy_use_only_malloc_secrets();
+
+    let tmp = tempfile::tempdir()?;
+    let (dir_a, dir_b) = (tmp.path().join("a"), tmp.path().join("b"));
+    for dir in [&dir_a, &dir_b] {
+        fs::create_dir(dir)?;
+        gen_keypair(dir, "server")?;
+    }
+    let (port_a, port_b) = (free_udp_port()?, free_udp_port()?);
+    let (_server_a, api_a) = start_server(&dir_a, &format!("[::1]:{port_a}"))?;
+    let (_server_b, api_b) = start_server(&dir_b, &format!("[::1]:{port_b}"))?;
+
+    // There are no peers yet
+    let sink_a = UnixStream::connect(dir_a.join("api.sock"))?;
+    sink_a.set_read_timeout(Some(Duration::from_secs(30)))?;
+    let res: RegisterKeySinkResponse =
+        request(&sink_a, RegisterKeySinkRequest::new(0).as_bytes(), vec![])?;
+    let status = res.payload.status;
+    assert_eq!(status, register_key_sink_response_status::NO_SUCH_PEER);
+
+    // Server b does not know server a yet, so no key is exchanged before we register as sink
+    let pk_b = fs::File::open(dir_b.join("server-pk"))?;
+    let add_b =
+        AddPeerRequest::new(ProtocolVersion::V03).with_endpoint(&format!("[::1]:{port_b}"))?;
+    let res: AddPeerResponse = request(&api_a, add_b.as_bytes(), vec![pk_b.as_fd()])?;
+    assert_eq!(res, AddPeerResponse::new(add_peer_response_status::OK, 0));
+
+    let res: RegisterKeySinkResponse =
+        request(&sink_a, RegisterKeySinkRequest::new(0).as_bytes(), vec![])?;
+    assert_eq!(
+        res,
+        RegisterKeySinkResponse::new(
+            register_key_sink_response_status::OK,
+            0,
+            key_sink_reason::REGISTERED
+        )
+    );
+
+    let pk_a = fs::File::open(dir_a.join("server-pk"))?;
+    let add_a =
+        AddPeerRequest::new(ProtocolVersion::V03).with_endpoint(&format!("[::1]:{port_a}"))?;
+    let res: AddPeerResponse = request(&api_b, add_a.as_bytes(), vec![pk_a.as_fd()])?;
+    assert_eq!(res, AddPeerResponse::new(add_peer_response_status::OK, 0));
+
+    // The key arrives as a sealed memfd
+    let (res, mut fds): (RegisterKeySinkResponse, _) = receive_with_fds(&sink_a)?;
+    assert_eq!(
+        res,
+        RegisterKeySinkResponse::new(
+            register_key_sink_response_status::OK,
+            0,
+            key_sink_reason::EXCHANGED
+        )
+    );
+    assert_eq!(fds.len(), 1);
+    let memfd = fs::File::from(fds.pop_front().unwrap());
+    assert!(fcntl_get_seals(&memfd)?.contains(SealFlags::WRITE | SealFlags::SEAL));
+
+    // Keys are in base64 by default
+    let mut key = String::new();
+    (&memfd).read_to_string(&mut key)?;
+    assert_eq!(key.len(), 44, "{key:?}");
+
+    Ok(())
+}
+
+#[test]
+fn api_rekey_exchanges_a_new_key_right_away() -> anyhow::Result<()> {
+    rosenpass_secret_memory::secret_policy_use_only_malloc_secrets();
+
+    let tmp = tempfile::tempdir()?;
+    let (dir_a, dir_b) = (tmp.path().join("a"), tmp.path().join("b"));
+    for dir in [&dir_a, &dir_b] {
+        fs::create_dir(dir)?;
+        gen_keypair(dir, "server")?;
+    }
+    gen_keypair(tmp.path(), "c")?;
+    let (port_a, port_b) = (free_udp_port()?, free_udp_port()?);
+    let (_server_a, api_a) = start_server(&dir_a, &format!("[::1]:{port_a}"))?;
+    let (_server_b, api_b) = start_server(&dir_b, &format!("[::1]:{port_b}"))?;
+
+    let res: RekeyResponse = request(&api_a, RekeyRequest::new(0, 0).as_bytes(), vec![])?;
+    assert_eq!(
+        res,
+        RekeyResponse::new(rekey_response_status::NO_SUCH_PEER, 0)
+    );
+
+    // Without an endpoint, there is nobody to send the initiation to
+    let pk_c = fs::File::open(tmp.path().join("c-pk"))?;
+    let add_c = AddPeerRequest::new(ProtocolVersion::V03);
+    let res: AddPeerResponse = request(&api_a, add_c.as_bytes(), vec![pk_c.as_fd()])?;
+    assert_eq!(res, AddPeerResponse::new(add_peer_response_status::OK, 0));
+
+    let res: RekeyResponse = request(&api_a, RekeyRequest::new(0, 0).as_bytes(), vec![])?;
+    assert_eq!(
+        res,
+        RekeyResponse::new(rekey_response_status::NO_ENDPOINT, 0)
+    );
+
+    let pk_b = fs::File::open(dir_b.join("server-pk"))?;
+    let add_b =
+        AddPeerRequest::new(ProtocolVersion::V03).with_endpoint(&format!("[::1]:{port_b}"))?;
+    let res: AddPeerResponse = request(&api_a, add_b.as_bytes(), vec![pk_b.as_fd()])?;
+    assert_eq!(res, AddPeerResponse::new(add_peer_response_status::OK, 1));
+
+    let pk_a = fs::File::open(dir_a.join("server-pk"))?;
+    let add_a =
+        AddPeerRequest::new(ProtocolVersion::V03).with_endpoint(&format!("[::1]:{port_a}"))?;
+    let res: AddPeerResponse = request(&api_b, add_a.as_bytes(), vec![pk_a.as_fd()])?;
+    assert_eq!(res, AddPeerResponse::new(add_peer_response_status::OK, 0));
+
+    // The command line client waits for the exchange to succeed
+    let out = Command::new(env!("CARGO_BIN_EXE_rosenpass"))
+        .arg("rekey")
+        .arg("--api")
+        .arg(dir_a.join("api.sock"))
+        .args(["--wait", "30", "1"])
+        .stdin(Stdio::null())
+        .output()?;
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(out.status.success(), "{out:?}");
+    assert!(
+        stdout.contains("Exchanged a new key with peer 1"),
+        "{stdout:?}"
+    );
+
+    // The API connection keeps working after the held back response
+    let res: RekeyResponse = request(&api_a, RekeyRequest::new(1, 30_000).as_bytes(), vec![])?;
+    assert_eq!(res, RekeyResponse::new(rekey_response_status::OK, 1));
+
+    Ok(())
+}
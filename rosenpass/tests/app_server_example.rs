@@ -126,6 +126,7 @@ impl TestServer {
         let test_helpers = Some(AppServerTest {
             enable_dos_permanently: false,
             termination_handler: Some(termination_queue),
+            event_sink: None,
         });
 
         let app_srv = AppServer::new(keypair, addrs, verbosity, test_helpers)?;
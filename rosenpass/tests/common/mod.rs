@@ -0,0 +1,39 @@
+//! Fixtures shared by the integration tests
+//!
+//! Each test binary includes this through `mod common;` and uses only part of it.
+#![allow(dead_code)]
+
+use std::net::UdpSocket;
+use std::ops::DerefMut;
+use std::path::Path;
+use std::process::Child;
+
+use rosenpass::protocol::{SPk, SSk};
+use rosenpass_cipher_traits::primitives::Kem;
+use rosenpass_ciphers::StaticKem;
+use rosenpass_secret_memory::file::StoreSecret;
+use rosenpass_util::file::StoreValue;
+
+/// Kills the rosenpass process started by a test once the test is done with it
+pub struct KillChild(pub Child);
+
+impl Drop for KillChild {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+/// Store a new keypair as `<name>-sk` and `<name>-pk` in `dir`
+pub fn gen_keypair(dir: &Path, name: &str) -> anyhow::Result<()> {
+    let (mut sk, mut pk) = (SSk::zero(), SPk::zero());
+    StaticKem.keygen(sk.secret_mut(), pk.deref_mut())?;
+    sk.store_secret(dir.join(format!("{name}-sk")))?;
+    pk.store(dir.join(format!("{name}-pk")))?;
+    Ok(())
+}
+
+/// A UDP port on the loopback interface that nobody listens on right now
+pub fn free_udp_port() -> anyhow::Result<u16> {
+    Ok(UdpSocket::bind("[::1]:0")?.local_addr()?.port())
+}
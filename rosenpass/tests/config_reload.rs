@@ -0,0 +1,208 @@
+use std::fs;
+use std::path::Path;
+
+use rosenpass::app_server::AppServer;
+use rosenpass::config::{self, Verbosity};
+use rosenpass::harness::InMemoryBroker;
+use rosenpass::protocol::{SPk, SSk, SymKey};
+use rosenpass::reload::{ConfigReload, ReloadError, ReloadSummary};
+use rosenpass_util::file::{LoadValue, LoadValueB64};
+
+mod common;
+use common::gen_keypair;
+
+fn peer_toml(dir: &Path, name: &str, key_out: &str) -> String {
+    format!(
+        "[[peers]]\npublic_key = {:?}\nkey_out = {:?}\n\n",
+        dir.join(format!("{name}-pk")),
+        dir.join(key_out)
+    )
+}
+
+/// A key exported earlier, base64 encoded; 32 times the byte 7
+const OLD_KEY: &str = "BwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwc=";
+
+/// Check that the key output at `path` no longer holds [OLD_KEY], but a different valid key
+fn assert_key_replaced(path: &Path) -> anyhow::Result<()> {
+    let key = SymKey::load_b64::<64, _>(path)?;
+    assert_ne!(key.secret(), &[7u8; 32], "{path:?} still holds the old key");
+    assert_ne!(key.secret(), &[0u8; 32], "{path:?} holds an all-zero key");
+    Ok(())
+}
+
+fn write_config(dir: &Path, listen: &str, peers: &[String]) -> anyhow::Result<()> {
+    let mut toml = format!(
+        "public_key = {:?}\nsecret_key = {:?}\nlisten = {listen}\nverbosity = \"Quiet\"\n\n",
+        dir.join("server-pk"),
+        dir.join("server-sk")
+    );
+    peers.iter().for_each(|p| toml.push_str(p));
+    fs::write(dir.join("config.toml"), toml)?;
+    Ok(())
+}
+
+/// Start a server from the configuration written by [write_config], ready to reload it
+fn load_server(dir: &Path) -> anyhow::Result<AppServer> {
+    let config = config::Rosenpass::load(dir.join("config.toml"))?;
+    let keypair = (
+        SSk::load(dir.join("server-sk"))?,
+        SPk::load(dir.join("server-pk"))?,
+    );
+    let mut srv = AppServer::new(Some(keypair), config.listen.clone(), Verbosity::Quiet, None)?;
+    let broker = srv.register_broker(Box::new(InMemoryBroker::new()))?;
+    srv.config_reload = Some(ConfigReload::apply(&mut srv, config, broker)?);
+    Ok(srv)
+}
+
+#[test]
+fn reload_applies_changes_and_keeps_config_on_errors() -> anyhow::Result<()> {
+    rosenpass_secret_memory::secret_policy_use_only_malloc_secrets();
+
+    let tmp = tempfile::tempdir()?;
+    let dir = tmp.path();
+    for name in ["server", "a", "b", "c"] {
+        gen_keypair(dir, name)?;
+    }
+
+    write_config(
+        dir,
+        r#"["[::1]:0"]"#,
+        &[peer_toml(dir, "a", "a-out"), peer_toml(dir, "b", "b-out")],
+    )?;
+
+    let mut srv = load_server(dir)?;
+    assert_eq!(srv.peers.len(), 2);
+    assert_eq!(srv.sockets.len(), 1);
+
+    // Nothing changed
+    let summary = srv.reload_config()?;
+    assert_eq!(
+        summary,
+        ReloadSummary {
+            peers_unchanged: 2,
+            ..Default::default()
+        }
+    );
+
+    // Pretend a and b exchanged keys before
+    fs::write(dir.join("a-out"), OLD_KEY)?;
+    fs::write(dir.join("b-out"), OLD_KEY)?;
+
+    // Peer a writes its key elsewhere, b goes, c comes and we listen on a second socket
+    write_config(
+        dir,
+        r#"["[::1]:0", "127.0.0.1:0"]"#,
+        &[peer_toml(dir, "a", "a-out-2"), peer_toml(dir, "c", "c-out")],
+    )?;
+    let summary = srv.reload_config()?;
+    assert_eq!(
+        summary,
+        ReloadSummary {
+            peers_added: 1,
+            peers_removed: 1,
+            peers_updated: 1,
+            listen_added: 1,
+            ..Default::default()
+        }
+    );
+    assert_eq!(srv.sockets.len(), 2);
+    // Removed peers and replaced key outputs get a random key, erasing the previous one
+    assert_key_replaced(&dir.join("b-out"))?;
+    assert_key_replaced(&dir.join("a-out"))?;
+    assert_ne!(fs::read(dir.join("a-out"))?, fs::read(dir.join("b-out"))?);
+    // Without a session, there is no key to write to the new output yet
+    assert!(!dir.join("a-out-2").exists());
+
+    // A broken configuration is rejected as a whole
+    write_config(
+        dir,
+        r#"["[::1]:0"]"#,
+        &[peer_toml(dir, "does-not-exist", "x-out")],
+    )?;
+    assert!(matches!(
+        srv.reload_config(),
+        Err(ReloadError::InvalidConfig(_))
+    ));
+    assert_eq!(srv.sockets.len(), 2);
+
+    // Closing the second socket again
+    write_config(
+        dir,
+        r#"["[::1]:0"]"#,
+        &[peer_toml(dir, "a", "a-out-2"), peer_toml(dir, "c", "c-out")],
+    )?;
+    let summary = srv.reload_config()?;
+    assert_eq!(
+        summary,
+        ReloadSummary {
+            peers_unchanged: 2,
+            listen_removed: 1,
+            ..Default::default()
+        }
+    );
+    assert_eq!(srv.sockets.len(), 1);
+
+    Ok(())
+}
+
+#[cfg(feature = "internal_testing")]
+#[test]
+fn reload_continues_after_failing_midway() -> anyhow::Result<()> {
+    rosenpass_secret_memory::secret_policy_use_only_malloc_secrets();
+
+    let tmp = tempfile::tempdir()?;
+    let dir = tmp.path();
+    for name in ["server", "a", "b", "c"] {
+        gen_keypair(dir, name)?;
+    }
+
+    write_config(
+        dir,
+        r#"["[::1]:0"]"#,
+        &[peer_toml(dir, "a", "a-out"), peer_toml(dir, "b", "b-out")],
+    )?;
+    let mut srv = load_server(dir)?;
+
+    // Removing b works, then the server refuses to go on
+    write_config(
+        dir,
+        r#"["[::1]:0"]"#,
+        &[peer_toml(dir, "a", "a-out"), peer_toml(dir, "c", "c-out")],
+    )?;
+    srv.config_reload.as_mut().unwrap().fail_after_steps(1);
+    assert!(matches!(srv.reload_config(), Err(ReloadError::Apply(_))));
+    assert_eq!(srv.live_peers().len(), 1);
+
+    // The next reload knows b is gone and finishes the job
+    let summary = srv.reload_config()?;
+    assert_eq!(
+        summary,
+        ReloadSummary {
+            peers_added: 1,
+            peers_unchanged: 1,
+            ..Default::default()
+        }
+    );
+    assert_eq!(srv.live_peers().len(), 2);
+
+    // And from then on, the configuration is in sync again
+    assert_eq!(
+        srv.reload_config()?,
+        ReloadSummary {
+            peers_unchanged: 2,
+            ..Default::default()
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn reload_without_config_file_is_not_supported() -> anyhow::Result<()> {
+    let mut srv = AppServer::new(None, vec![], Verbosity::Quiet, None)?;
+    assert!(matches!(
+        srv.reload_config(),
+        Err(ReloadError::NotSupported)
+    ));
+    Ok(())
+}
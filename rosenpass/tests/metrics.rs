@@ -0,0 +1,89 @@
+use std::io::{Read, Write};
+use std::net::{Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rosenpass::app_server::{AppServer, AppServerTest};
+use rosenpass::config::Verbosity;
+
+/// Fetch `path` from the metrics endpoint with the given `Accept` header
+fn get(addr: SocketAddr, path: &str, accept: &str) -> anyhow::Result<String> {
+    let mut stream = TcpStream::connect(addr)?;
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    write!(
+        stream,
+        "GET {path} HTTP/1.1\r\nHost: localhost\r\nAccept: {accept}\r\n\r\n"
+    )?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    Ok(response)
+}
+
+#[test]
+fn metrics_are_served_over_http_and_written_to_a_file() -> anyhow::Result<()> {
+    rosenpass_secret_memory::secret_policy_use_only_malloc_secrets();
+
+    let tmp = tempfile::tempdir()?;
+    let textfile = tmp.path().join("rosenpass.prom");
+
+    let (terminate, termination_handler) = mpsc::channel();
+    let (setup_tx, setup_rx) = mpsc::channel();
+    let server_textfile = textfile.clone();
+    let server = thread::spawn(move || -> anyhow::Result<()> {
+        let test_helpers = AppServerTest {
+            enable_dos_permanently: false,
+            termination_handler: Some(termination_handler),
+            event_sink: None,
+        };
+        let addrs = vec![SocketAddr::from((Ipv6Addr::LOCALHOST, 0))];
+        let mut srv = AppServer::new(None, addrs, Verbosity::Quiet, Some(test_helpers))?;
+
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        srv.add_metrics_listener(listener)?;
+        srv.set_metrics_textfile(server_textfile, Duration::from_secs(3600));
+        let waker = srv.register_waker()?;
+
+        setup_tx.send((addr, waker))?;
+        srv.event_loop()
+    });
+    let (addr, waker) = setup_rx.recv()?;
+
+    let prometheus = get(addr, "/metrics", "text/plain")?;
+    assert!(prometheus.starts_with("HTTP/1.1 200 OK\r\n"));
+    assert!(prometheus.contains("Content-Type: text/plain; version=0.0.4"));
+    assert!(prometheus.contains("# TYPE rosenpass_retransmissions_total counter\n"));
+    assert!(prometheus.contains("\nrosenpass_under_load "));
+    assert!(!prometheus.contains("# EOF"));
+
+    let openmetrics = get(
+        addr,
+        "/metrics",
+        "application/openmetrics-text; version=1.0.0",
+    )?;
+    assert!(openmetrics.contains("Content-Type: application/openmetrics-text"));
+    assert!(openmetrics.contains("# TYPE rosenpass_retransmissions counter\n"));
+    assert!(openmetrics.ends_with("# EOF\n"));
+
+    let not_found = get(addr, "/", "text/plain")?;
+    assert!(not_found.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+    // The textfile is written right away
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !textfile.is_file() {
+        assert!(
+            Instant::now() < deadline,
+            "metrics textfile was not written"
+        );
+        thread::sleep(Duration::from_millis(10));
+    }
+    let contents = std::fs::read_to_string(&textfile)?;
+    assert!(contents.contains("\nrosenpass_under_load "));
+
+    terminate.send(())?;
+    waker.wake()?;
+    server.join().unwrap()?;
+
+    Ok(())
+}
@@ -0,0 +1,78 @@
+#![cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::Context;
+use rosenpass::protocol::SymKey;
+use rosenpass_util::file::LoadValueB64;
+
+mod common;
+use common::{free_udp_port, gen_keypair, KillChild};
+
+fn write_config(dir: &Path, me: &str, port: u16, peer: &str, peer_port: u16) -> anyhow::Result<()> {
+    let toml = format!(
+        "public_key = {:?}\nsecret_key = {:?}\nlisten = [\"[::1]:{port}\"]\n\
+         verbosity = \"Quiet\"\nseccomp = \"enforce\"\n\n\
+         [[peers]]\npublic_key = {:?}\nendpoint = \"[::1]:{peer_port}\"\nkey_out = {:?}\n",
+        dir.join(format!("{me}-pk")),
+        dir.join(format!("{me}-sk")),
+        dir.join(format!("{peer}-pk")),
+        dir.join(format!("{me}-out")),
+    );
+    fs::write(dir.join(format!("{me}.toml")), toml)?;
+    Ok(())
+}
+
+/// Start `rosenpass exchange-config` and wait until it reports an exchanged key
+fn exchange(dir: &Path, me: &str) -> anyhow::Result<KillChild> {
+    let mut child = KillChild(
+        Command::new(env!("CARGO_BIN_EXE_rosenpass"))
+            .arg("exchange-config")
+            .arg(dir.join(format!("{me}.toml")))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()?,
+    );
+    let stdout = child.0.stdout.take().context("no stdout")?;
+    let mut lines = BufReader::new(stdout).lines();
+    let line = lines
+        .next()
+        .with_context(|| format!("{me} exited; was it killed by the seccomp filter?"))??;
+    anyhow::ensure!(line.ends_with(" exchanged"), "Unexpected output: {line}");
+    Ok(child)
+}
+
+#[test]
+fn handshake_completes_under_seccomp_filter() -> anyhow::Result<()> {
+    rosenpass_secret_memory::secret_policy_use_only_malloc_secrets();
+
+    let tmp = tempfile::tempdir()?;
+    let dir = tmp.path();
+    gen_keypair(dir, "a")?;
+    gen_keypair(dir, "b")?;
+
+    let (port_a, port_b) = (free_udp_port()?, free_udp_port()?);
+    write_config(dir, "a", port_a, "b", port_b)?;
+    write_config(dir, "b", port_b, "a", port_a)?;
+
+    let a = std::thread::spawn({
+        let dir = dir.to_owned();
+        move || exchange(&dir, "a")
+    });
+    let _b = exchange(dir, "b")?;
+    let _a = a.join().unwrap()?;
+
+    let key_a = SymKey::load_b64::<64, _>(dir.join("a-out"))?;
+    let key_b = SymKey::load_b64::<64, _>(dir.join("b-out"))?;
+    assert!(rosenpass_constant_time::memcmp(
+        key_a.secret(),
+        key_b.secret()
+    ));
+    Ok(())
+}
@@ -10,10 +10,11 @@ use zeroize::{Zeroize, ZeroizeOnDrop};
 
 use rosenpass_util::b64::{b64_decode, b64_encode};
 use rosenpass_util::file::{
-    fopen_r, LoadValue, LoadValueB64, ReadExactToEnd, ReadSliceToEnd, StoreValueB64,
-    StoreValueB64Writer,
+    fopen_r, Encoding, LoadValue, LoadValueB64, ReadExactToEnd, ReadSliceToEnd, StoreValueB64,
+    StoreValueB64Writer, StoreValueEncodedWriter,
 };
 use rosenpass_util::functional::mutating;
+use rosenpass_util::hex::hex_encode;
 
 use crate::alloc::{secret_box, SecretBox, SecretVec};
 use crate::file::StoreSecret;
@@ -361,6 +362,35 @@ impl<const N: usize> StoreValueB64Writer for Secret<N> {
     }
 }
 
+impl<const N: usize> StoreValueEncodedWriter for Secret<N> {
+    type Error = anyhow::Error;
+
+    // No extra documentation here because the Trait already provides a good documentation.
+    fn store_encoded_writer<const F: usize, W: Write>(
+        &self,
+        encoding: Encoding,
+        mut writer: W,
+    ) -> anyhow::Result<()> {
+        match encoding {
+            Encoding::Base64 => self.store_b64_writer::<F, W>(writer),
+            Encoding::Hex => {
+                let mut f: Secret<F> = Secret::random();
+                let encoded_str = hex_encode(self.secret(), f.secret_mut())
+                    .with_context(|| "Could not encode secret to hex")?;
+
+                writer
+                    .write_all(encoded_str.as_bytes())
+                    .with_context(|| "Could not write hex to writer")?;
+                f.zeroize();
+                Ok(())
+            }
+            Encoding::Raw => writer
+                .write_all(self.secret())
+                .with_context(|| "Could not write secret to writer"),
+        }
+    }
+}
+
 impl<const N: usize> StoreSecret for Secret<N> {
     type Error = anyhow::Error;
 
@@ -527,6 +557,24 @@ mod test {
         });
     }
 
+    #[test]
+    fn test_secret_store_encoded() {
+        test_spawn_process_provided_policies!({
+            let secret = Secret::<4>::from_slice(&[0x00, 0x1f, 0xa0, 0xff]);
+            let encoded = |encoding| {
+                let mut out = Vec::new();
+                secret
+                    .store_encoded_writer::<16, _>(encoding, &mut out)
+                    .unwrap();
+                out
+            };
+
+            assert_eq!(encoded(Encoding::Base64), b"AB+g/w==");
+            assert_eq!(encoded(Encoding::Hex), b"001fa0ff");
+            assert_eq!(encoded(Encoding::Raw), secret.secret());
+        });
+    }
+
     /// Test the creation of a [ZeroizingSecretBox] using its [new](ZeroizingSecretBox::new)
     /// function.
     #[test]
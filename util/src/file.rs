@@ -1,9 +1,11 @@
 //! Helpers for working with files
 
 use anyhow::ensure;
+use std::ffi::OsString;
 use std::fs::File;
-use std::io::Read;
-use std::os::unix::fs::OpenOptionsExt;
+use std::io::{self, Read};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::PathBuf;
 use std::{fs::OpenOptions, path::Path};
 
 /// Level of secrecy applied for a file
@@ -14,6 +16,16 @@ pub enum Visibility {
     Secret,
 }
 
+impl Visibility {
+    /// The permissions of files with this visibility
+    fn mode(&self) -> u32 {
+        match self {
+            Visibility::Public => 0o644,
+            Visibility::Secret => 0o600,
+        }
+    }
+}
+
 /// Open a file writeably, truncating the file.
 ///
 /// Sensible default permissions are chosen based on the value of `visibility`
@@ -42,11 +54,12 @@ pub enum Visibility {
 /// ```
 pub fn fopen_w<P: AsRef<Path>>(path: P, visibility: Visibility) -> std::io::Result<File> {
     let mut options = OpenOptions::new();
-    options.create(true).write(true).read(false).truncate(true);
-    match visibility {
-        Visibility::Public => options.mode(0o644),
-        Visibility::Secret => options.mode(0o600),
-    };
+    options
+        .create(true)
+        .write(true)
+        .read(false)
+        .truncate(true)
+        .mode(visibility.mode());
     options.open(path)
 }
 
@@ -64,6 +77,145 @@ pub fn fopen_r<P: AsRef<Path>>(path: P) -> std::io::Result<File> {
         .open(path)
 }
 
+/// The directory containing `path`; `.` for bare file names
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+/// The file name of `path` with `suffix` appended, hidden by a leading dot if `hidden` is set
+fn sibling_name(path: &Path, hidden: bool, suffix: &str) -> io::Result<OsString> {
+    let name = path.file_name().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{path:?} does not name a file"),
+        )
+    })?;
+    let mut sibling = OsString::from(if hidden { "." } else { "" });
+    sibling.push(name);
+    sibling.push(suffix);
+    Ok(sibling)
+}
+
+/// A file next to `path`, named as by [sibling_name]
+fn sibling_path(path: &Path, hidden: bool, suffix: &str) -> io::Result<PathBuf> {
+    Ok(parent_dir(path).join(sibling_name(path, hidden, suffix)?))
+}
+
+/// Replace the file at `path` atomically and durably
+///
+/// `write` fills a temporary file in the same directory as `path`, which is created with the
+/// permissions for `visibility`. The temporary file is synced to disk and renamed to `path`, and
+/// the directory is synced too. Readers of `path` thus see either the old or the new contents,
+/// never a partially written file; not even after a crash.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::Write;
+/// use rosenpass_util::file::{store_atomic, Visibility};
+///
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join("key");
+///
+/// store_atomic(&path, Visibility::Secret, |f| f.write_all(b"old"))?;
+/// store_atomic(&path, Visibility::Secret, |f| f.write_all(b"new"))?;
+/// assert_eq!(std::fs::read(&path)?, b"new");
+///
+/// Ok::<(), std::io::Error>(())
+/// ```
+pub fn store_atomic<P, F>(path: P, visibility: Visibility, write: F) -> io::Result<()>
+where
+    P: AsRef<Path>,
+    F: FnOnce(&mut File) -> io::Result<()>,
+{
+    let path = path.as_ref();
+    let dir = parent_dir(path);
+    let mut tmp = tempfile::Builder::new()
+        .prefix(&sibling_name(path, true, ".")?)
+        .suffix(".tmp")
+        .permissions(std::fs::Permissions::from_mode(visibility.mode()))
+        .tempfile_in(dir)?;
+
+    write(tmp.as_file_mut())?;
+    tmp.as_file().sync_all()?;
+    tmp.persist(path).map_err(|e| e.error)?;
+    File::open(dir)?.sync_all()
+}
+
+/// Where [rotate_previous] keeps the `n`th most recent previous version of `path`
+///
+/// That is `<path>.prev` for `n = 1` and `<path>.prev.<n>` for older versions.
+pub fn previous_path<P: AsRef<Path>>(path: P, n: usize) -> io::Result<PathBuf> {
+    match n {
+        1 => sibling_path(path.as_ref(), false, ".prev"),
+        n => sibling_path(path.as_ref(), false, &format!(".prev.{n}")),
+    }
+}
+
+/// Keep the current contents of `path` around as a previous version, dropping versions beyond
+/// the `keep` most recent ones
+///
+/// The versions are stored at [previous_path]. `path` itself stays in place, so it can be
+/// replaced by [store_atomic] right after; the previous version is a hard link, so `path` must
+/// only ever be replaced, not modified in place. Nothing happens if `keep` is zero or `path` does
+/// not exist.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::Write;
+/// use rosenpass_util::file::{previous_path, rotate_previous, store_atomic, Visibility};
+///
+/// let dir = tempfile::tempdir()?;
+/// let path = dir.path().join("key");
+///
+/// for contents in [b"one", b"two", b"tri"] {
+///     rotate_previous(&path, 1)?;
+///     store_atomic(&path, Visibility::Secret, |f| f.write_all(contents))?;
+/// }
+/// assert_eq!(std::fs::read(&path)?, b"tri");
+/// assert_eq!(std::fs::read(previous_path(&path, 1)?)?, b"two");
+/// assert!(!previous_path(&path, 2)?.exists());
+///
+/// Ok::<(), std::io::Error>(())
+/// ```
+pub fn rotate_previous<P: AsRef<Path>>(path: P, keep: usize) -> io::Result<()> {
+    let path = path.as_ref();
+    if keep == 0 || !path.try_exists()? {
+        return Ok(());
+    }
+
+    for n in (2..=keep).rev() {
+        match std::fs::rename(previous_path(path, n - 1)?, previous_path(path, n)?) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            res => res?,
+        }
+    }
+
+    // Link to a temporary name first, so the most recent previous version is replaced atomically
+    let tmp = sibling_path(path, true, ".prev.tmp")?;
+    match std::fs::remove_file(&tmp) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        res => res?,
+    }
+    std::fs::hard_link(path, &tmp)?;
+    std::fs::rename(&tmp, previous_path(path, 1)?)
+}
+
+/// Remove the `keep` most recent previous versions of `path` stored by [rotate_previous]
+pub fn remove_previous<P: AsRef<Path>>(path: P, keep: usize) -> io::Result<()> {
+    for n in 1..=keep {
+        match std::fs::remove_file(previous_path(path.as_ref(), n)?) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            res => res?,
+        }
+    }
+    Ok(())
+}
+
 /// Extension trait for [std::io::Read] adding [read_slice_to_end]
 pub trait ReadSliceToEnd {
     /// Error type returned by functions in this trait
@@ -355,6 +507,34 @@ pub trait StoreValueB64Writer {
     ) -> Result<(), Self::Error>;
 }
 
+/// Encodings supported by [StoreValueEncodedWriter]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// Base64, as written by [StoreValueB64Writer]
+    #[default]
+    Base64,
+    /// Lowercase hexadecimal
+    Hex,
+    /// The bytes of the value as they are
+    Raw,
+}
+
+/// Store a value to a writable stream in an [Encoding] chosen at runtime
+pub trait StoreValueEncodedWriter {
+    /// Error type returned
+    type Error;
+
+    /// Store a value to a writable stream in the given encoding
+    ///
+    /// `F` is the size of the buffer used for encoding, like for
+    /// [StoreValueB64Writer::store_b64_writer]; it must fit the hex encoding of the value.
+    fn store_encoded_writer<const F: usize, W: std::io::Write>(
+        &self,
+        encoding: Encoding,
+        writer: W,
+    ) -> Result<(), Self::Error>;
+}
+
 /// Store a value in a file
 pub trait StoreValue {
     /// Error type returned
@@ -410,6 +590,39 @@ mod tests {
         assert_eq!(contents, "test");
     }
 
+    #[test]
+    fn test_store_atomic_secret() {
+        let tmp_dir = tempdir().unwrap();
+        let path = tmp_dir.path().join("test");
+        store_atomic(&path, Visibility::Secret, |f| f.write_all(b"test")).unwrap();
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(metadata.permissions().mode(), 0o100600);
+        assert_eq!(std::fs::read(&path).unwrap(), b"test");
+        // No temporary files are left behind
+        assert_eq!(std::fs::read_dir(tmp_dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_rotate_and_remove_previous() {
+        let tmp_dir = tempdir().unwrap();
+        let path = tmp_dir.path().join("test");
+        for contents in ["1", "2", "3", "4"] {
+            rotate_previous(&path, 2).unwrap();
+            store_atomic(&path, Visibility::Public, |f| {
+                f.write_all(contents.as_bytes())
+            })
+            .unwrap();
+        }
+        let read = |n| std::fs::read_to_string(previous_path(&path, n).unwrap()).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "4");
+        assert_eq!(read(1), "3");
+        assert_eq!(read(2), "2");
+        assert!(!previous_path(&path, 3).unwrap().exists());
+
+        remove_previous(&path, 2).unwrap();
+        assert_eq!(std::fs::read_dir(tmp_dir.path()).unwrap().count(), 1);
+    }
+
     #[test]
     fn test_read_slice_to_end() {
         let tmp_dir = tempdir().unwrap();
@@ -0,0 +1,61 @@
+//! Utilities for working with hexadecimal encoding
+
+use anyhow::ensure;
+
+/// Encode one nibble as a lowercase hex digit without branching on its value
+fn hex_digit(nibble: u8) -> u8 {
+    let nibble = nibble as i16;
+    // All ones if the nibble is greater than nine, zero otherwise
+    let letter = (9 - nibble) >> 8;
+    (nibble + b'0' as i16 + (letter & (b'a' as i16 - b'0' as i16 - 10))) as u8
+}
+
+/// Encode a value as lowercase hexadecimal
+///
+/// The encoding does not branch on or index by the input, so it is suitable for secrets.
+///
+/// # Examples
+///
+/// ```
+/// use rosenpass_util::hex::hex_encode;
+///
+/// let mut buffer = [0u8; 64];
+/// assert_eq!(hex_encode(&[0x00, 0x9f, 0xa0, 0xff], &mut buffer)?, "009fa0ff");
+///
+/// Ok::<(), anyhow::Error>(())
+/// ```
+pub fn hex_encode<'o>(input: &[u8], output: &'o mut [u8]) -> anyhow::Result<&'o str> {
+    ensure!(
+        output.len() >= input.len() * 2,
+        "Output buffer too small for hex encoding"
+    );
+
+    let output = &mut output[..input.len() * 2];
+    for (byte, digits) in input.iter().zip(output.chunks_exact_mut(2)) {
+        digits[0] = hex_digit(byte >> 4);
+        digits[1] = hex_digit(byte & 0x0f);
+    }
+
+    // Only ever contains ASCII digits and letters
+    Ok(std::str::from_utf8(output)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_encode_all_bytes() {
+        let input: Vec<u8> = (0..=255).collect();
+        let mut output = [0u8; 512];
+        let encoded = hex_encode(&input, &mut output).unwrap();
+        let expected: String = input.iter().map(|b| format!("{b:02x}")).collect();
+        assert_eq!(encoded, expected);
+    }
+
+    #[test]
+    fn test_hex_encode_small_buffer() {
+        let mut output = [0u8; 3];
+        assert!(hex_encode(b"ab", &mut output).is_err());
+    }
+}
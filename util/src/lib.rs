@@ -16,6 +16,8 @@ pub mod fd;
 pub mod file;
 /// Functional programming utilities.
 pub mod functional;
+/// Hexadecimal encoding functionality.
+pub mod hex;
 /// Input/output operations.
 pub mod io;
 /// Length prefix encoding schemes implementation.